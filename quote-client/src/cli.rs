@@ -1,15 +1,26 @@
+use std::collections::HashSet;
 use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
 use std::path::PathBuf;
+use std::time::Duration;
 
 use anyhow::{Context, Result, bail};
-use clap::{ArgGroup, Parser};
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
+use quote_core::tickers::TickerCase;
+use quote_core::wire::WireFormat;
+
+use crate::dedup::DedupWindow;
+use crate::udp::QuoteOutput;
 
 /// Quote Client - подписка на котировки через quote-server.
 ///
 /// TCP используется один раз: отправляем STREAM и ждём OK/ERR.
 /// Дальше принимаем котировки по UDP и шлём Ping keep-alive.
+///
+/// `quote-client --server HOST:PORT check` - только проверить доступность сервера.
+/// `quote-client ... snapshot` - напечатать текущие цены один раз и выйти.
 #[derive(Parser, Debug, Clone)]
 #[command(name = "quote-client", version, about)]
+#[command(subcommand_negates_reqs = true)]
 #[command(
     group(
         ArgGroup::new("tickers_source")
@@ -18,18 +29,34 @@ use clap::{ArgGroup, Parser};
     )
 )]
 pub(crate) struct Args {
+    /// Подкоманда; без неё - обычный стриминг котировок
+    #[command(subcommand)]
+    pub(crate) command: Option<ClientCommand>,
+
     /// TCP адрес quote-server, например 127.0.0.1:5555 или host.example.com:5555
     #[arg(long)]
     pub(crate) server: String,
 
     /// Локальный UDP порт, на который будут приходить котировки
-    #[arg(long, value_parser = clap::value_parser!(u16).range(1..=65535))]
-    pub(crate) udp_port: u16,
+    /// (обязателен для стриминга)
+    #[arg(long, required = true, value_parser = clap::value_parser!(u16).range(1..=65535))]
+    pub(crate) udp_port: Option<u16>,
 
     /// IP, который клиент объявляет серверу в udp://IP:PORT
-    /// (обычно 127.0.0.1 для локального запуска; в проде — реальный IP интерфейса)
-    #[arg(long, default_value = "127.0.0.1")]
-    pub(crate) bind_ip: IpAddr,
+    /// (обычно 127.0.0.1 для локального запуска; в проде — реальный IP интерфейса).
+    /// Имеет приоритет над --advertise
+    #[arg(long)]
+    pub(crate) bind_ip: Option<IpAddr>,
+
+    /// Локальный IP, на котором слушается UDP-порт (на машине с несколькими
+    /// интерфейсами). Не влияет на IP, который видит сервер (--bind-ip / --advertise)
+    #[arg(long, default_value = "0.0.0.0")]
+    pub(crate) udp_bind_ip: IpAddr,
+
+    /// Как выбрать IP для udp://IP:PORT без --bind-ip:
+    /// `loopback` - 127.0.0.1, `auto` - IP интерфейса, через который виден сервер
+    #[arg(long, value_enum, default_value_t = AdvertiseMode::Loopback)]
+    pub(crate) advertise: AdvertiseMode,
 
     /// Файл тикеров (по одному на строку). Нельзя вместе с --tickers
     #[arg(long, conflicts_with = "tickers")]
@@ -39,6 +66,105 @@ pub(crate) struct Args {
     /// Нельзя вместе с --tickers-file
     #[arg(long, conflicts_with = "tickers_file")]
     pub(crate) tickers: Option<String>,
+
+    /// Подписаться только на первые N тикеров (после нормализации и сортировки)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub(crate) tickers_count: Option<u32>,
+
+    /// Печатать котировки только для этих тикеров, например "AAPL,TSLA".
+    /// Подписка (--tickers / --tickers-file) не меняется - это фильтр вывода
+    #[arg(long)]
+    pub(crate) watch: Option<String>,
+
+    /// Формат строки котировки в выводе: text (`AAPL price=1.00 ...`),
+    /// csv (`ticker,price,volume,timestamp_ms`) или json (как `StockQuote::to_json`)
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub(crate) output: OutputFormat,
+
+    /// Добавлять к каждой котировке локальное время её приёма в мс от эпохи -
+    /// для анализа задержек по логам: text - префикс `recv_ms=`, csv - первая
+    /// колонка, json - поле `recv_ts`
+    #[arg(long)]
+    pub(crate) with_recv_ts: bool,
+
+    /// Не печатать котировку, полностью совпадающую (тикер, цена, объём, время)
+    /// с выведенной за последние N мс: переотправки сервера и дубли в сети
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub(crate) dedup_window_ms: Option<u64>,
+
+    /// Стабильный id клиента (`ID=` в STREAM); без флага генерируется при старте.
+    /// Сервер по нему узнаёт переподключившегося клиента
+    #[arg(long)]
+    pub(crate) client_id: Option<String>,
+
+    /// Попросить у сервера id сессии (`SID=1`) и писать его в лог:
+    /// по нему сессию легко найти в логах сервера
+    #[arg(long)]
+    pub(crate) session_id: bool,
+
+    /// Сообщать серверу, сколько контрольных точек доставки дошло (`ACK=1`):
+    /// клиент отвечает на них полем `ack_seq` в ping, а сервер
+    /// по итогам сессии пишет в лог оценку потерь UDP
+    #[arg(long)]
+    pub(crate) report_loss: bool,
+
+    /// Не приводить тикеры к верхнему регистру (для сервера с --preserve-case)
+    #[arg(long)]
+    pub(crate) preserve_case: bool,
+
+    /// Строгая проверка `--tickers`: неверный элемент (пробел внутри, длиннее 16 байт)
+    /// - ошибка с его именем, а не молчаливая подписка на него
+    #[arg(long, requires = "tickers")]
+    pub(crate) strict_tickers: bool,
+
+    /// Просить сервер слать котировки в фиксированной бинарной раскладке
+    /// (`WIRE=FIXED`: меньше накладных расходов, тикер обрезается до 8 байт)
+    #[arg(long)]
+    pub(crate) wire_fixed: bool,
+
+    /// С `--wire-fixed`: время котировок в секундах (`WIRE=FIXED-SECS`,
+    /// пакет на 4 байта короче, миллисекунды теряются)
+    #[arg(long, requires = "wire_fixed")]
+    pub(crate) ts_seconds: bool,
+
+    /// Сколько раз переподключаться (заново STREAM), если сервер пропал:
+    /// недоступен, перестал слать Pong, занят. После лимита клиент
+    /// завершается с ошибкой; 0 - не переподключаться
+    #[arg(long, default_value_t = 0)]
+    pub(crate) max_reconnects: u32,
+
+    /// Проверить wire-формат (encode/decode) при старте и упасть при ошибке
+    #[arg(long)]
+    pub(crate) self_test: bool,
+
+    /// Писать лог JSON-строками (`{"level":..,"ts":..,"msg":..}`) вместо текста
+    #[arg(long, global = true)]
+    pub(crate) log_json: bool,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AdvertiseMode {
+    Loopback,
+    Auto,
+}
+
+/// Формат строки котировки (`--output`)
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OutputFormat {
+    #[default]
+    Text,
+    Csv,
+    Json,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub(crate) enum ClientCommand {
+    /// Отправить STATUS, напечатать ответ и выйти: код 0 - сервер доступен
+    /// (для liveness-проб контейнеров)
+    Check,
+    /// Получить текущие цены один раз: STREAM, первая котировка каждого тикера,
+    /// печать и выход без ping (нужны те же --udp-port и тикеры, что для стриминга)
+    Snapshot,
 }
 
 impl Args {
@@ -51,6 +177,15 @@ impl Args {
             bail!("--server must look like HOST:PORT (got: {})", self.server);
         }
 
+        // `check` использует только --server
+        if matches!(self.command, Some(ClientCommand::Check)) {
+            return Ok(());
+        }
+
+        if self.udp_port.is_none() {
+            bail!("--udp-port is required");
+        }
+
         if let Some(path) = &self.tickers_file {
             let md = std::fs::metadata(path)
                 .with_context(|| format!("tickers file not found: {:?}", path))?;
@@ -71,12 +206,70 @@ impl Args {
         Ok(())
     }
 
+    /// Фильтр вывода из --watch (None = печатать всё)
+    pub(crate) fn watch_set(&self) -> Option<HashSet<String>> {
+        self.watch.as_deref().map(|raw| {
+            quote_core::tickers::parse_tickers_csv_with_case(raw, self.ticker_case())
+                .into_iter()
+                .collect()
+        })
+    }
+
+    /// Настройки вывода котировок: --watch, --output, --with-recv-ts, --dedup-window-ms
+    pub(crate) fn quote_output(&self) -> QuoteOutput {
+        QuoteOutput {
+            watch: self.watch_set(),
+            format: self.output,
+            with_recv_ts: self.with_recv_ts,
+            dedup: self
+                .dedup_window_ms
+                .map(|ms| DedupWindow::new(Duration::from_millis(ms))),
+        }
+    }
+
+    /// Нормализация регистра тикеров по флагу `--preserve-case`
+    pub(crate) fn ticker_case(&self) -> TickerCase {
+        if self.preserve_case {
+            TickerCase::Preserve
+        } else {
+            TickerCase::Upper
+        }
+    }
+
+    /// `--client-id` или случайный id процесса (16 hex-символов)
+    pub(crate) fn client_id(&self) -> String {
+        self.client_id.clone().unwrap_or_else(generate_client_id)
+    }
+
+    /// Формат котировок, который просим у сервера
+    pub(crate) fn wire_format(&self) -> WireFormat {
+        if self.wire_fixed && self.ts_seconds {
+            WireFormat::FixedSecs
+        } else if self.wire_fixed {
+            WireFormat::Fixed
+        } else {
+            WireFormat::Postcard
+        }
+    }
+
     pub(crate) fn tcp_server(&self) -> &str {
         self.server.as_str()
     }
 
-    pub(crate) fn advertise_ip(&self) -> IpAddr {
-        self.bind_ip
+    /// Локальный адрес UDP-сокета котировок: `--udp-bind-ip` + `--udp-port`
+    pub(crate) fn udp_bind_addr(&self, udp_port: u16) -> SocketAddr {
+        SocketAddr::new(self.udp_bind_ip, udp_port)
+    }
+
+    /// `--bind-ip`, иначе по `--advertise`
+    pub(crate) fn advertise_ip(&self) -> std::io::Result<IpAddr> {
+        if let Some(ip) = self.bind_ip {
+            return Ok(ip);
+        }
+        match self.advertise {
+            AdvertiseMode::Loopback => Ok(IpAddr::from([127, 0, 0, 1])),
+            AdvertiseMode::Auto => crate::udp::detect_outbound_ip(self.server_socket_addr()?),
+        }
     }
 
     pub(crate) fn server_socket_addr(&self) -> std::io::Result<SocketAddr> {
@@ -86,3 +279,61 @@ impl Args {
         })
     }
 }
+
+/// Случайный id без внешних зависимостей: `RandomState` засевается случайно
+fn generate_client_id() -> String {
+    use std::hash::BuildHasher;
+
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let hash = std::collections::hash_map::RandomState::new().hash_one((nanos, std::process::id()));
+    format!("{hash:016x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn udp_bind_ip_is_independent_of_advertised_ip() {
+        let args = Args::try_parse_from([
+            "quote-client",
+            "--server",
+            "127.0.0.1:5555",
+            "--udp-port",
+            "6001",
+            "--tickers",
+            "AAPL",
+            "--udp-bind-ip",
+            "127.0.0.1",
+            "--bind-ip",
+            "192.0.2.10",
+        ])
+        .unwrap();
+
+        assert_eq!(args.udp_bind_addr(0), "127.0.0.1:0".parse().unwrap());
+        assert_eq!(args.advertise_ip().unwrap(), IpAddr::from([192, 0, 2, 10]));
+
+        // сокет действительно поднимается на выбранном интерфейсе
+        let sock = std::net::UdpSocket::bind(args.udp_bind_addr(0)).unwrap();
+        assert!(sock.local_addr().unwrap().ip().is_loopback());
+    }
+
+    #[test]
+    fn udp_bind_ip_defaults_to_all_interfaces() {
+        let args = Args::try_parse_from([
+            "quote-client",
+            "--server",
+            "127.0.0.1:5555",
+            "--udp-port",
+            "6001",
+            "--tickers",
+            "AAPL",
+        ])
+        .unwrap();
+
+        assert_eq!(args.udp_bind_addr(6001), "0.0.0.0:6001".parse().unwrap());
+    }
+}
@@ -0,0 +1,136 @@
+//! `--dedup-window-ms`: подавление повторов котировок на клиенте.
+//!
+//! Повтор - котировка с теми же `(ticker, price, volume, timestamp_ms)`, что уже
+//! выводилась не раньше чем `window` назад (переотправка сервером, дубли в сети).
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use quote_core::StockQuote;
+
+/// Идентичность котировки для дедупликации: `(ticker, price, volume, timestamp_ms)`.
+/// Храним поля целиком, а не хэш: коллизия хэша подавила бы другую котировку
+type QuoteKey = (String, i64, u32, u128);
+
+/// Окно недавно выведенных котировок
+#[derive(Debug)]
+pub(crate) struct DedupWindow {
+    window: Duration,
+    /// котировка -> когда видели последний раз
+    seen: HashMap<QuoteKey, Instant>,
+    /// очередь на вытеснение в порядке поступления
+    order: VecDeque<(Instant, QuoteKey)>,
+}
+
+impl DedupWindow {
+    pub(crate) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// true - котировку нужно вывести; false - повтор в пределах окна
+    pub(crate) fn admit(&mut self, q: &StockQuote, now: Instant) -> bool {
+        self.evict(now);
+
+        let key = quote_key(q);
+        if self.seen.contains_key(&key) {
+            return false;
+        }
+        self.seen.insert(key.clone(), now);
+        self.order.push_back((now, key));
+        true
+    }
+
+    /// Убирает записи старше окна
+    fn evict(&mut self, now: Instant) {
+        while let Some(&(at, _)) = self.order.front() {
+            if now.saturating_duration_since(at) < self.window {
+                break;
+            }
+            let Some((_, key)) = self.order.pop_front() else {
+                break;
+            };
+            if self.seen.get(&key) == Some(&at) {
+                self.seen.remove(&key);
+            }
+        }
+    }
+}
+
+fn quote_key(q: &StockQuote) -> QuoteKey {
+    (q.ticker.clone(), q.price, q.volume, q.timestamp_ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(ticker: &str, price: i64) -> StockQuote {
+        StockQuote {
+            ticker: ticker.to_string(),
+            price,
+            volume: 10,
+            timestamp_ms: 1,
+            side: None,
+            sent_ms: None,
+        }
+    }
+
+    #[test]
+    fn duplicate_within_window_is_suppressed_and_passes_after_it() {
+        let mut d = DedupWindow::new(Duration::from_millis(100));
+        let t0 = Instant::now();
+
+        assert!(d.admit(&quote("AAPL", 100), t0));
+        assert!(!d.admit(&quote("AAPL", 100), t0 + Duration::from_millis(50)));
+        // другая цена - не повтор
+        assert!(d.admit(&quote("AAPL", 101), t0 + Duration::from_millis(60)));
+
+        // окно первой котировки истекло
+        assert!(d.admit(&quote("AAPL", 100), t0 + Duration::from_millis(150)));
+        assert_eq!(d.seen.len(), 2, "expired entries are evicted");
+    }
+
+    #[test]
+    fn quotes_differing_in_any_key_field_are_all_admitted() {
+        let mut d = DedupWindow::new(Duration::from_secs(1));
+        let t0 = Instant::now();
+        let base = quote("AAPL", 100);
+
+        assert!(d.admit(&base, t0));
+        let variants = [
+            StockQuote {
+                ticker: "MSFT".to_string(),
+                ..base.clone()
+            },
+            StockQuote {
+                volume: 11,
+                ..base.clone()
+            },
+            StockQuote {
+                timestamp_ms: 2,
+                ..base.clone()
+            },
+        ];
+        for q in &variants {
+            assert!(d.admit(q, t0), "{q} is not a duplicate");
+        }
+        assert_eq!(d.seen.len(), 4);
+    }
+
+    #[test]
+    fn sent_ms_and_side_do_not_affect_identity() {
+        let mut d = DedupWindow::new(Duration::from_secs(1));
+        let t0 = Instant::now();
+
+        let mut resent = quote("TSLA", 5);
+        assert!(d.admit(&resent, t0));
+        resent.sent_ms = Some(42);
+        assert!(!d.admit(&resent, t0));
+        resent.side = Some(quote_core::Side::Sell);
+        assert!(!d.admit(&resent, t0));
+    }
+}
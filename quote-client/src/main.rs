@@ -5,21 +5,40 @@
 //! - одноразовый TCP-запрос `STREAM` и ожидание `OK/ERR`
 //! - запуск UDP-цикла приёма котировок
 //! - запуск keep-alive ping в отдельном потоке с того же UDP-порта
+//! - `snapshot`: первая котировка каждого тикера и выход, без ping
+//! - переподключение (STREAM заново) при потере сервера, до `--max-reconnects` раз
 //! - корректная остановка по `Ctrl+C`
 
 mod cli;
+mod dedup;
+mod reconnect;
+mod snapshot;
 mod tcp;
 mod tickers;
 mod udp;
-use std::net::SocketAddr;
+use std::net::{SocketAddr, UdpSocket};
 use std::sync::{Arc, atomic::AtomicBool, atomic::Ordering};
+use std::time::Duration;
 
+use anyhow::Context;
 use clap::Parser;
 use log::info;
+use quote_core::logfmt;
+
+/// Пауза перед переподключением (если сервер не попросил другую)
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Сколько `snapshot` ждёт котировки (несколько тиков генератора сервера)
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(5);
 
 fn main() -> anyhow::Result<()> {
+    let args = cli::Args::parse();
     // Логи через RUST_LOG=info/trace
-    env_logger::init();
+    let mut logger = env_logger::Builder::from_default_env();
+    if args.log_json {
+        logger.format(logfmt::write_json_record);
+    }
+    logger.init();
 
     let shutdown = Arc::new(AtomicBool::new(false));
 
@@ -32,30 +51,91 @@ fn main() -> anyhow::Result<()> {
         })?;
     }
 
-    let args = cli::Args::parse();
     args.validate()?; // оставляем как есть, если validate() у тебя на anyhow::Result
 
+    if args.self_test {
+        quote_core::wire::self_test()?;
+        info!("wire self-test passed");
+    }
+
+    if let Some(cli::ClientCommand::Check) = args.command {
+        // ошибка из main => ненулевой код выхода
+        let status = tcp::check_status(args.server_socket_addr()?)?;
+        println!("OK {status}");
+        return Ok(());
+    }
+
+    let udp_port = args.udp_port.context("--udp-port is required")?;
+    let client_id = args.client_id();
+
     let tickers = tickers::load_tickers(&args).map_err(|e| anyhow::anyhow!(e))?;
+    let advertise_ip = args
+        .advertise_ip()
+        .context("failed to detect advertise ip")?;
 
     info!(
-        "Starting quote-client: server_tcp={}, udp_port={}, advertise_ip={}, tickers={}",
+        "Starting quote-client: id={client_id}, server_tcp={}, udp_port={}, advertise_ip={}, tickers={}",
         args.tcp_server(),
-        args.udp_port,
-        args.advertise_ip(),
+        udp_port,
+        advertise_ip,
         tickers.join(",")
     );
 
-    let udp_advertise_addr = SocketAddr::new(args.advertise_ip(), args.udp_port);
-    let udp_bind_addr = SocketAddr::from(([0, 0, 0, 0], args.udp_port));
+    let udp_advertise_addr = SocketAddr::new(advertise_ip, udp_port);
+    let udp_bind_addr = args.udp_bind_addr(udp_port);
+
+    if let Some(cli::ClientCommand::Snapshot) = args.command {
+        // сокет до STREAM: первые котировки приходят сразу после OK
+        let sock = UdpSocket::bind(udp_bind_addr)?;
+        snapshot::run_snapshot(
+            &sock,
+            || {
+                tcp::send_stream_command(
+                    args.server_socket_addr()?,
+                    udp_advertise_addr,
+                    tickers.as_slice(),
+                    args.wire_format(),
+                    &client_id,
+                    args.session_id,
+                    false,
+                )
+            },
+            tickers.as_slice(),
+            SNAPSHOT_TIMEOUT,
+            &mut std::io::stdout().lock(),
+        )?;
+        return Ok(());
+    }
 
-    // запрос на стрим
-    tcp::send_stream_command(
-        args.server_socket_addr()?,
-        udp_advertise_addr,
-        tickers.as_slice(),
-    )?;
+    let policy = reconnect::ReconnectPolicy {
+        max_reconnects: args.max_reconnects,
+        delay: RECONNECT_DELAY,
+    };
+    reconnect::run_supervised(policy, &shutdown, |attempt| {
+        if attempt > 0 {
+            info!("reconnecting to {} (attempt {attempt})", args.tcp_server());
+        }
 
-    udp::run_udp_receiver(udp_bind_addr, shutdown)?;
+        // запрос на стрим; тот же ID= - сервер заменит прежнюю сессию
+        let reply = tcp::send_stream_command(
+            args.server_socket_addr()?,
+            udp_advertise_addr,
+            tickers.as_slice(),
+            args.wire_format(),
+            &client_id,
+            args.session_id,
+            args.report_loss,
+        )?;
+        if let Some(id) = reply.session_id {
+            info!("server session id={id} (client id {client_id})");
+        }
 
-    Ok(())
+        udp::run_udp_receiver(
+            udp_bind_addr,
+            reply.token,
+            args.quote_output(),
+            args.report_loss,
+            shutdown.clone(),
+        )
+    })
 }
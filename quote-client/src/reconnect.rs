@@ -0,0 +1,166 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use anyhow::bail;
+use log::warn;
+
+use crate::tcp::ClientError;
+
+/// Шаг сна между проверками shutdown
+const SHUTDOWN_POLL_STEP: Duration = Duration::from_millis(100);
+
+/// Сколько раз и с какой паузой переподключаться (`--max-reconnects`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ReconnectPolicy {
+    /// 0 - не переподключаться: первая же ошибка завершает клиента
+    pub(crate) max_reconnects: u32,
+    pub(crate) delay: Duration,
+}
+
+/// Запускает `session` и перезапускает её после ошибки, пока не исчерпан
+/// лимит переподключений. `session` получает номер переподключения
+/// (0 - первое подключение).
+///
+/// `ClientError::Rejected` (сервер отказал по существу запроса) не
+/// повторяется; для `ClientError::Busy` пауза - `retry_after` сервера.
+/// Ok - сессия завершилась штатно (Ctrl+C).
+pub(crate) fn run_supervised<F>(
+    policy: ReconnectPolicy,
+    shutdown: &AtomicBool,
+    mut session: F,
+) -> anyhow::Result<()>
+where
+    F: FnMut(u32) -> anyhow::Result<()>,
+{
+    let mut reconnects = 0;
+    loop {
+        let err = match session(reconnects) {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+        if shutdown.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let delay = match err.downcast_ref::<ClientError>() {
+            Some(ClientError::Rejected(_)) => return Err(err),
+            Some(ClientError::Busy { retry_after, .. }) => *retry_after,
+            None => policy.delay,
+        };
+        if reconnects >= policy.max_reconnects {
+            if policy.max_reconnects == 0 {
+                return Err(err);
+            }
+            bail!("giving up after {reconnects} reconnect attempts: {err:#}");
+        }
+
+        reconnects += 1;
+        warn!(
+            "connection lost: {err:#}; reconnect {reconnects}/{} in {delay:?}",
+            policy.max_reconnects
+        );
+
+        let mut slept = Duration::ZERO;
+        while slept < delay {
+            if shutdown.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            let step = (delay - slept).min(SHUTDOWN_POLL_STEP);
+            std::thread::sleep(step);
+            slept += step;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tcp::send_stream_command;
+    use quote_core::wire::WireFormat;
+    use std::net::{SocketAddr, TcpListener};
+
+    fn policy(max_reconnects: u32) -> ReconnectPolicy {
+        ReconnectPolicy {
+            max_reconnects,
+            delay: Duration::from_millis(10),
+        }
+    }
+
+    /// Адрес, на котором гарантированно никто не слушает
+    fn dead_server_addr() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    #[test]
+    fn gives_up_after_max_reconnects_when_server_never_returns() {
+        let server = dead_server_addr();
+        let shutdown = AtomicBool::new(false);
+        let mut attempts = Vec::new();
+
+        let err = run_supervised(policy(3), &shutdown, |n| {
+            attempts.push(n);
+            send_stream_command(
+                server,
+                "127.0.0.1:34254".parse().unwrap(),
+                &["AAPL".to_string()],
+                WireFormat::Postcard,
+                "c1",
+                false,
+                false,
+            )
+            .map(|_| ())
+        })
+        .unwrap_err();
+
+        assert_eq!(attempts, vec![0, 1, 2, 3]);
+        assert!(
+            err.to_string()
+                .contains("giving up after 3 reconnect attempts"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn zero_limit_returns_first_error_unchanged() {
+        let shutdown = AtomicBool::new(false);
+        let mut calls = 0;
+
+        let err = run_supervised(policy(0), &shutdown, |_| {
+            calls += 1;
+            bail!("boom")
+        })
+        .unwrap_err();
+
+        assert_eq!(calls, 1);
+        assert_eq!(err.to_string(), "boom");
+    }
+
+    #[test]
+    fn rejected_stream_is_not_retried() {
+        let shutdown = AtomicBool::new(false);
+        let mut calls = 0;
+
+        let err = run_supervised(policy(5), &shutdown, |_| {
+            calls += 1;
+            Err(ClientError::Rejected("unknown tickers".to_string()).into())
+        })
+        .unwrap_err();
+
+        assert_eq!(calls, 1);
+        assert!(err.downcast_ref::<ClientError>().is_some());
+    }
+
+    #[test]
+    fn recovers_when_server_comes_back() {
+        let shutdown = AtomicBool::new(false);
+
+        let mut calls = 0;
+        run_supervised(policy(5), &shutdown, |n| {
+            calls += 1;
+            if n < 2 { bail!("down") } else { Ok(()) }
+        })
+        .unwrap();
+        assert_eq!(calls, 3);
+    }
+}
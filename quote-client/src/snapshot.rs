@@ -0,0 +1,202 @@
+//! Подкоманда `snapshot`: текущие цены один раз, без стриминга.
+//!
+//! Отдельного snapshot-пакета в протоколе нет: клиент отправляет обычный `STREAM`,
+//! берёт первую котировку каждого тикера и выходит, не запуская ping.
+//! Сессия на сервере без ping завершается сама через `PING_TIMEOUT`.
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::net::UdpSocket;
+use std::time::{Duration, Instant};
+
+use log::debug;
+use quote_core::StockQuote;
+use quote_core::protocol::OkReply;
+use quote_core::wire::{UdpPacketV1, decode_any};
+
+/// Шаг ожидания в recv (проверка дедлайна)
+const RECV_TICK: Duration = Duration::from_millis(200);
+
+/// Отправляет `STREAM` через `send_stream`, собирает снимок с `sock` и печатает
+/// его в `out` (котировка на строку, по возрастанию тикера).
+///
+/// `sock` должен быть привязан до `STREAM`, чтобы не потерять первые пакеты.
+/// Возвращает число напечатанных котировок; ни одной за `timeout` - ошибка.
+pub(crate) fn run_snapshot<F, W>(
+    sock: &UdpSocket,
+    send_stream: F,
+    tickers: &[String],
+    timeout: Duration,
+    out: &mut W,
+) -> anyhow::Result<usize>
+where
+    F: FnOnce() -> anyhow::Result<OkReply>,
+    W: Write,
+{
+    send_stream()?;
+
+    let quotes = collect_snapshot(sock, tickers, timeout)?;
+    if quotes.is_empty() {
+        anyhow::bail!("no quotes received within {timeout:?}");
+    }
+
+    for quote in &quotes {
+        writeln!(out, "{quote}")?;
+    }
+    Ok(quotes.len())
+}
+
+/// Первая котировка каждого тикера.
+///
+/// Снимок готов, когда пришли все запрошенные тикеры или какой-то тикер пришёл
+/// повторно (сервер начал следующий тик - так работают `*` и `@группа`,
+/// для которых итоговый список заранее неизвестен). Иначе - что успело прийти за `timeout`.
+fn collect_snapshot(
+    sock: &UdpSocket,
+    tickers: &[String],
+    timeout: Duration,
+) -> io::Result<Vec<StockQuote>> {
+    let expected = tickers
+        .iter()
+        .all(|t| !t.contains('*') && !t.starts_with('@'))
+        .then_some(tickers.len());
+
+    let deadline = Instant::now() + timeout;
+    let mut seen: BTreeMap<String, StockQuote> = BTreeMap::new();
+    let mut buf = [0u8; 2048];
+
+    loop {
+        if expected.is_some_and(|n| seen.len() >= n) {
+            break;
+        }
+
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        sock.set_read_timeout(Some(left.min(RECV_TICK)))?;
+
+        let n = match sock.recv(&mut buf) {
+            Ok(n) => n,
+            Err(e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        match decode_any(&buf[..n]) {
+            Ok(UdpPacketV1::Quote(quote)) => {
+                if seen.contains_key(&quote.ticker) {
+                    break;
+                }
+                seen.insert(quote.ticker.clone(), quote);
+            }
+            Ok(other) => debug!("ignoring packet: {other}"),
+            Err(e) => debug!("bad udp packet: {e}"),
+        }
+    }
+
+    Ok(seen.into_values().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tcp::send_stream_command;
+    use quote_core::Command;
+    use quote_core::wire::{WireFormat, encode_v1};
+    use std::io::{BufRead, BufReader};
+    use std::net::{SocketAddr, TcpListener};
+    use std::thread;
+
+    /// Отвечает `OK` на STREAM и шлёт `quotes` на UDP-адрес из команды
+    fn fake_server(quotes: &'static [&'static str]) -> (SocketAddr, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let h = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut line = String::new();
+            BufReader::new(stream.try_clone().unwrap())
+                .read_line(&mut line)
+                .unwrap();
+            let Command::Stream { udp_target, .. } =
+                quote_core::protocol::parse_command(line.trim()).unwrap()
+            else {
+                panic!("expected STREAM, got {line:?}");
+            };
+            stream.write_all(b"OK\n").unwrap();
+
+            let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+            for (i, ticker) in quotes.iter().enumerate() {
+                let pkt = UdpPacketV1::Quote(StockQuote {
+                    ticker: ticker.to_string(),
+                    price: 100 + i as i64,
+                    volume: 1,
+                    timestamp_ms: 1,
+                    side: None,
+                    sent_ms: None,
+                });
+                udp.send_to(&encode_v1(&pkt).unwrap(), udp_target).unwrap();
+            }
+        });
+
+        (addr, h)
+    }
+
+    fn snapshot(server: SocketAddr, tickers: &[String]) -> (anyhow::Result<usize>, String) {
+        let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let target = sock.local_addr().unwrap();
+        let mut out = Vec::new();
+
+        let res = run_snapshot(
+            &sock,
+            || {
+                send_stream_command(
+                    server,
+                    target,
+                    tickers,
+                    WireFormat::Postcard,
+                    "c1",
+                    false,
+                    false,
+                )
+            },
+            tickers,
+            Duration::from_secs(5),
+            &mut out,
+        );
+        (res, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn snapshot_prints_one_quote_per_ticker_and_returns() {
+        let (server, h) = fake_server(&["TSLA", "AAPL"]);
+        let tickers = vec!["AAPL".to_string(), "TSLA".to_string()];
+
+        let started = Instant::now();
+        let (res, out) = snapshot(server, &tickers);
+
+        assert_eq!(res.unwrap(), 2);
+        assert_eq!(
+            out,
+            "AAPL price=1.01 volume=1 ts_ms=1\nTSLA price=1.00 volume=1 ts_ms=1\n"
+        );
+        // не ждали таймаут: все тикеры пришли
+        assert!(started.elapsed() < Duration::from_secs(5));
+        h.join().unwrap();
+    }
+
+    #[test]
+    fn wildcard_snapshot_ends_when_a_ticker_repeats() {
+        let (server, h) = fake_server(&["AAPL", "TSLA", "AAPL", "MSFT"]);
+
+        let (res, out) = snapshot(server, &["*".to_string()]);
+
+        assert_eq!(res.unwrap(), 2);
+        assert!(!out.contains("MSFT"), "{out}");
+        h.join().unwrap();
+    }
+}
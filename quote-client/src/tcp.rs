@@ -1,16 +1,74 @@
-use quote_core::protocol::format_stream_command_line;
-use std::io::{BufRead, BufReader, Write};
+use quote_core::protocol::{
+    OkReply, REPLY_ERR_PREFIX, REPLY_OK, format_status_command_line, format_stream_command,
+    parse_err_reply, parse_ok_reply,
+};
+use quote_core::wire::WireFormat;
+use std::io::{self, BufRead, BufReader, Read, Write};
 use std::net::{SocketAddr, TcpStream};
 use std::time::Duration;
+use thiserror::Error;
 
 const TCP_READ_TIMEOUT_S: u64 = 5;
 const TCP_WRITE_TIMEOUT_S: u64 = 5;
 
+/// Отказы сервера на `STREAM`
+#[derive(Debug, Error)]
+pub(crate) enum ClientError {
+    /// Сервер временно не может принять клиента и просит повторить позже
+    #[error("server busy ({code}), retry after {retry_after:?}")]
+    Busy { code: String, retry_after: Duration },
+
+    #[error("server error: {0}")]
+    Rejected(String),
+}
+
+/// Отправляет STATUS и возвращает ответ сервера без `OK`.
+/// Ошибка - сервер недоступен или ответил не `OK`.
+pub(crate) fn check_status(server_tcp_addr: SocketAddr) -> anyhow::Result<String> {
+    let mut stream =
+        TcpStream::connect_timeout(&server_tcp_addr, Duration::from_secs(TCP_READ_TIMEOUT_S))?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(TCP_READ_TIMEOUT_S)))
+        .ok();
+    stream
+        .set_write_timeout(Some(Duration::from_secs(TCP_WRITE_TIMEOUT_S)))
+        .ok();
+
+    stream.write_all(format_status_command_line().as_bytes())?;
+    stream.flush()?;
+
+    let Some(line) = read_reply_line(&mut stream)? else {
+        anyhow::bail!("server closed connection without response");
+    };
+
+    let resp = line.as_str();
+    if resp == REPLY_OK {
+        return Ok(String::new());
+    }
+    if let Some(rest) = resp
+        .strip_prefix(REPLY_OK)
+        .and_then(|r| r.strip_prefix(' '))
+    {
+        return Ok(rest.to_string());
+    }
+    if let Some(rest) = resp.strip_prefix(REPLY_ERR_PREFIX) {
+        anyhow::bail!("server error: {}", rest.trim());
+    }
+
+    anyhow::bail!("unexpected server response: {:?}", resp);
+}
+
+/// Отправляет STREAM и ждёт `OK [<token>] [id=<n>]` / `ERR ...`.
+/// `want_session_id` - попросить id сессии (`SID=1`).
 pub(crate) fn send_stream_command(
     server_tcp_addr: SocketAddr,
     udp_target: SocketAddr,
     tickers: &[String],
-) -> anyhow::Result<()> {
+    wire: WireFormat,
+    client_id: &str,
+    want_session_id: bool,
+    want_ack: bool,
+) -> anyhow::Result<OkReply> {
     let mut stream = TcpStream::connect(server_tcp_addr)?;
 
     stream.set_nodelay(true).ok();
@@ -22,30 +80,227 @@ pub(crate) fn send_stream_command(
         .ok();
 
     // отправляем команду
-    let cmd = format_stream_command_line(udp_target, tickers);
+    let mut cmd = format_stream_command(udp_target, tickers)?;
+    match wire {
+        WireFormat::Postcard => {}
+        WireFormat::Fixed => cmd.push_str(" WIRE=FIXED"),
+        WireFormat::FixedSecs => cmd.push_str(" WIRE=FIXED-SECS"),
+    }
+    cmd.push_str(" ID=");
+    cmd.push_str(client_id);
+    if want_session_id {
+        cmd.push_str(" SID=1");
+    }
+    if want_ack {
+        cmd.push_str(" ACK=1");
+    }
+    cmd.push('\n');
 
     stream.write_all(cmd.as_bytes())?;
     stream.flush()?;
 
     // обрабатываем ответ
-    let mut reader = BufReader::new(&mut stream);
+    let Some(line) = read_reply_line(&mut stream)? else {
+        anyhow::bail!("server closed connection without response");
+    };
 
-    let mut line = String::new();
-    let n = reader.read_line(&mut line)?;
+    let resp = line.as_str();
 
-    if n == 0 {
-        anyhow::bail!("server closed connection without response");
+    if let Some(reply) = parse_ok_reply(resp) {
+        return Ok(reply);
+    }
+    if resp.starts_with(REPLY_OK) {
+        anyhow::bail!("bad OK reply from server: {:?}", resp);
+    }
+
+    if let Some(reply) = parse_err_reply(resp) {
+        return Err(match reply.retry_after {
+            Some(retry_after) => ClientError::Busy {
+                code: reply.code,
+                retry_after,
+            },
+            None => ClientError::Rejected(resp[REPLY_ERR_PREFIX.len()..].trim().to_string()),
+        }
+        .into());
     }
 
-    let resp = line.trim_end_matches(&['\r', '\n'][..]);
+    anyhow::bail!("unexpected server response: {:?}", resp);
+}
+
+/// Строка ответа сервера без `\r\n`; None - соединение закрыто без ответа.
+///
+/// Ответ может прийти несколькими TCP-сегментами: дочитываем до `\n` или
+/// настоящего EOF (тогда ответом считается то, что успело прийти), а не
+/// до первого короткого `read`. Таймаут чтения сокета - ошибка.
+fn read_reply_line<R: Read>(stream: R) -> anyhow::Result<Option<String>> {
+    let mut reader = BufReader::new(stream);
+    let mut buf = Vec::new();
 
-    if resp == "OK" {
-        return Ok(());
+    loop {
+        match reader.read_until(b'\n', &mut buf) {
+            Ok(0) => break,
+            Ok(_) if buf.ends_with(b"\n") => break,
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                anyhow::bail!(
+                    "timed out waiting for server reply (partial: {:?})",
+                    String::from_utf8_lossy(&buf)
+                );
+            }
+            Err(e) => return Err(e.into()),
+        }
     }
 
-    if let Some(rest) = resp.strip_prefix("ERR") {
-        anyhow::bail!("server error: {}", rest.trim());
+    if buf.is_empty() {
+        return Ok(None);
     }
 
-    anyhow::bail!("unexpected server response: {:?}", resp);
+    let line = String::from_utf8_lossy(&buf);
+    Ok(Some(line.trim_end_matches(&['\r', '\n'][..]).to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Фейковый сервер: читает одну команду и отвечает `reply`
+    fn fake_server(reply: &'static str) -> (SocketAddr, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let h = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut cmd = String::new();
+            BufReader::new(stream.try_clone().unwrap())
+                .read_line(&mut cmd)
+                .unwrap();
+            stream.write_all(reply.as_bytes()).unwrap();
+            cmd
+        });
+
+        (addr, h)
+    }
+
+    #[test]
+    fn check_status_sends_status_and_returns_reply() {
+        let (addr, h) = fake_server("OK sessions=3\n");
+
+        assert_eq!(check_status(addr).unwrap(), "sessions=3");
+        assert_eq!(h.join().unwrap(), "STATUS\n");
+    }
+
+    #[test]
+    fn check_status_fails_on_err_reply() {
+        let (addr, h) = fake_server("ERR E_BAD_COMMAND unknown command: STATUS\n");
+
+        let err = check_status(addr).unwrap_err();
+        assert!(err.to_string().contains("unknown command"), "{err}");
+        h.join().unwrap();
+    }
+
+    #[test]
+    fn stream_busy_reply_surfaces_typed_error() {
+        let (addr, h) =
+            fake_server("ERR E_SERVER_FULL retry_after_ms=1500 server is at capacity\n");
+
+        let err = send_stream_command(
+            addr,
+            "127.0.0.1:34254".parse().unwrap(),
+            &["AAPL".to_string()],
+            WireFormat::Postcard,
+            "c1",
+            false,
+            false,
+        )
+        .unwrap_err();
+
+        match err.downcast_ref::<ClientError>() {
+            Some(ClientError::Busy { code, retry_after }) => {
+                assert_eq!(code, "E_SERVER_FULL");
+                assert_eq!(*retry_after, Duration::from_millis(1500));
+            }
+            other => panic!("expected Busy, got {other:?}"),
+        }
+        h.join().unwrap();
+    }
+
+    #[test]
+    fn stream_reply_split_across_delayed_writes_is_read_whole() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let h = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut cmd = String::new();
+            BufReader::new(stream.try_clone().unwrap())
+                .read_line(&mut cmd)
+                .unwrap();
+
+            stream.write_all(b"OK").unwrap();
+            stream.flush().unwrap();
+            thread::sleep(Duration::from_millis(100));
+            stream.write_all(b" 42\r\n").unwrap();
+        });
+
+        let reply = send_stream_command(
+            addr,
+            "127.0.0.1:34254".parse().unwrap(),
+            &["AAPL".to_string()],
+            WireFormat::Postcard,
+            "c1",
+            false,
+            false,
+        )
+        .unwrap();
+        assert_eq!(reply.token, Some(42));
+        h.join().unwrap();
+    }
+
+    #[test]
+    fn stream_requests_and_parses_session_id() {
+        let (addr, h) = fake_server("OK 7 id=42\n");
+
+        let reply = send_stream_command(
+            addr,
+            "127.0.0.1:34254".parse().unwrap(),
+            &["AAPL".to_string()],
+            WireFormat::Postcard,
+            "c1",
+            true,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            reply,
+            OkReply::new().with_token(Some(7)).with_session_id(Some(42))
+        );
+        assert!(h.join().unwrap().ends_with(" ID=c1 SID=1\n"));
+    }
+
+    #[test]
+    fn reply_without_newline_before_eof_is_accepted() {
+        let (addr, h) = fake_server("OK sessions=0");
+
+        assert_eq!(check_status(addr).unwrap(), "sessions=0");
+        h.join().unwrap();
+    }
+
+    #[test]
+    fn check_status_fails_when_server_is_unreachable() {
+        // порт освобождён сразу после bind
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        assert!(check_status(addr).is_err());
+    }
 }
@@ -1,3 +1,5 @@
+use quote_core::InvalidTicker;
+use quote_core::tickers::TickerCase;
 use std::path::{Path, PathBuf};
 use thiserror::Error;
 
@@ -15,6 +17,9 @@ pub(crate) enum TickersError {
     #[error("tickers list is empty (--tickers value: {raw:?})")]
     EmptyFromArg { raw: String },
 
+    #[error("invalid --tickers value: {}", quote_core::tickers::describe_invalid_tickers(.rejects))]
+    InvalidInArg { rejects: Vec<InvalidTicker> },
+
     #[error("failed to read tickers file: {path:?}")]
     ReadFile {
         path: PathBuf,
@@ -28,24 +33,37 @@ pub(crate) type Result<T> = std::result::Result<T, TickersError>;
 /// Загружает тикеры из источника, выбранного в CLI:
 /// - `--tickers-file` -> quote_core::tickers::read_tickers_from_path
 /// - `--tickers`      -> quote_core::tickers::parse_tickers_csv
+///   (с `--strict-tickers` - сначала quote_core::tickers::check_tickers_csv)
+///
+/// `--tickers-count` применяется после нормализации.
 pub(crate) fn load_tickers(args: &Args) -> Result<Vec<String>> {
-    if let Some(path) = &args.tickers_file {
-        load_from_file(path)
+    let tickers = if let Some(path) = &args.tickers_file {
+        load_from_file(path, args.ticker_case())?
     } else if let Some(raw) = &args.tickers {
-        load_from_arg(raw)
+        load_from_arg(raw, args.ticker_case(), args.strict_tickers)?
     } else {
-        Err(TickersError::MissingSource)
-    }
+        return Err(TickersError::MissingSource);
+    };
+
+    Ok(quote_core::tickers::limit_tickers(
+        tickers,
+        args.tickers_count.map(|n| n as usize),
+    ))
 }
 
-fn load_from_file(path: impl AsRef<Path>) -> Result<Vec<String>> {
+fn load_from_file(path: impl AsRef<Path>, case: TickerCase) -> Result<Vec<String>> {
     let path = path.as_ref().to_path_buf();
 
-    let tickers =
-        quote_core::tickers::read_tickers_from_path(&path).map_err(|e| TickersError::ReadFile {
+    let (tickers, report) = std::fs::File::open(&path)
+        .and_then(|f| quote_core::tickers::read_tickers_with_report(f, case))
+        .map_err(|e| TickersError::ReadFile {
             path: path.clone(),
             source: e,
         })?;
+    log::info!("tickers file {path:?}: {report}");
+    for t in &report.invalid {
+        log::warn!("tickers file {path:?}: invalid ticker {t:?} dropped");
+    }
 
     if tickers.is_empty() {
         return Err(TickersError::EmptyFromFile { path });
@@ -54,8 +72,13 @@ fn load_from_file(path: impl AsRef<Path>) -> Result<Vec<String>> {
     Ok(tickers)
 }
 
-fn load_from_arg(raw: &str) -> Result<Vec<String>> {
-    let tickers = quote_core::tickers::parse_tickers_csv(raw);
+fn load_from_arg(raw: &str, case: TickerCase, strict: bool) -> Result<Vec<String>> {
+    if strict {
+        quote_core::tickers::check_tickers_csv(raw)
+            .map_err(|rejects| TickersError::InvalidInArg { rejects })?;
+    }
+
+    let tickers = quote_core::tickers::parse_tickers_csv_with_case(raw, case);
 
     if tickers.is_empty() {
         return Err(TickersError::EmptyFromArg {
@@ -65,3 +88,64 @@ fn load_from_arg(raw: &str) -> Result<Vec<String>> {
 
     Ok(tickers)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn tickers_count_keeps_first_n_sorted() {
+        // 100 символов в обратном порядке: T099, T098, ..., T000
+        let raw = (0..100)
+            .rev()
+            .map(|i| format!("t{i:03}"))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let args = Args::try_parse_from([
+            "quote-client",
+            "--server",
+            "127.0.0.1:5555",
+            "--udp-port",
+            "34254",
+            "--tickers",
+            &raw,
+            "--tickers-count",
+            "10",
+        ])
+        .unwrap();
+
+        let got = load_tickers(&args).unwrap();
+        let want: Vec<String> = (0..10).map(|i| format!("T{i:03}")).collect();
+        assert_eq!(got, want);
+    }
+
+    #[test]
+    fn strict_tickers_error_names_rejected_token() {
+        let args = Args::try_parse_from([
+            "quote-client",
+            "--server",
+            "127.0.0.1:5555",
+            "--udp-port",
+            "34254",
+            "--tickers",
+            "AAPL,TOO_LONG_SYMBOL_NAME,TSLA",
+            "--strict-tickers",
+        ])
+        .unwrap();
+
+        let err = load_tickers(&args).unwrap_err();
+        assert!(matches!(err, TickersError::InvalidInArg { .. }));
+        assert_eq!(
+            err.to_string(),
+            "invalid --tickers value: invalid ticker \"TOO_LONG_SYMBOL_NAME\": longer than 16 bytes"
+        );
+    }
+
+    #[test]
+    fn without_strict_long_ticker_is_accepted() {
+        let got = load_from_arg("AAPL,TOO_LONG_SYMBOL_NAME", TickerCase::Upper, false).unwrap();
+        assert_eq!(got, vec!["AAPL", "TOO_LONG_SYMBOL_NAME"]);
+    }
+}
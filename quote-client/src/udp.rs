@@ -1,21 +1,51 @@
-use std::net::{SocketAddr, UdpSocket};
+use std::collections::HashSet;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
 use std::sync::{
     Arc,
-    atomic::{AtomicBool, Ordering},
+    atomic::{AtomicBool, AtomicU64, Ordering},
 };
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use log::{debug, info, warn};
 
 use crossbeam_channel::{Receiver, Sender, TrySendError};
-use quote_core::PING_INTERVAL;
-use quote_core::wire::{UdpPacketV1, decode, encode_v1};
+use quote_core::wire::{UdpPacketV1, WIRE_VERSION, decode_any, encode_v1};
+use quote_core::{PING_INTERVAL, PING_TIMEOUT, StockQuote, WireError};
 use std::thread;
 
+use crate::cli::OutputFormat;
+use crate::dedup::DedupWindow;
+
 const TICK_RATE_MS: u64 = 200;
 
+/// IP интерфейса, через который ОС отправила бы пакет серверу (`--advertise auto`).
+/// `connect` у UDP-сокета ничего не шлёт, но выбирает маршрут,
+/// после чего `local_addr()` показывает адрес исходящего интерфейса.
+pub(crate) fn detect_outbound_ip(server: SocketAddr) -> io::Result<IpAddr> {
+    let unspecified: IpAddr = match server {
+        SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    };
+
+    let sock = UdpSocket::bind((unspecified, 0))?;
+    sock.connect(server)?;
+
+    let ip = sock.local_addr()?.ip();
+    if ip.is_unspecified() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            format!("no outbound interface for {server}"),
+        ));
+    }
+    Ok(ip)
+}
+
 pub(crate) fn run_udp_receiver(
     bind_addr: SocketAddr,
+    session_token: Option<u64>,
+    mut output: QuoteOutput,
+    report_loss: bool,
     shutdown: Arc<AtomicBool>,
 ) -> anyhow::Result<()> {
     let sock = UdpSocket::bind(bind_addr)?;
@@ -27,12 +57,21 @@ pub(crate) fn run_udp_receiver(
 
     let mut buf = [0u8; 2048];
     let mut connected = false;
+    let mut liveness = ServerLiveness::default();
+    let mut mismatches = WireVersionMismatches::default();
 
     let (tx, rx): (Sender<SocketAddr>, Receiver<SocketAddr>) = crossbeam_channel::bounded(1);
 
-    let sd = shutdown.clone();
+    // число полученных Checkpoint; ping отправляет его серверу как ack_seq
+    let checkpoints = Arc::new(AtomicU64::new(0));
+    let ping_acks = report_loss.then(|| checkpoints.clone());
+
+    // свой флаг: при потере сервера ping останавливается, а общий
+    // shutdown остаётся за Ctrl+C (клиент может переподключиться)
+    let ping_stop = Arc::new(AtomicBool::new(false));
+    let sd = ping_stop.clone();
     let h = thread::spawn(move || {
-        if let Err(e) = run_ping(ping_sock, rx, PING_INTERVAL, sd) {
+        if let Err(e) = run_ping(ping_sock, rx, PING_INTERVAL, session_token, ping_acks, sd) {
             warn!("keep-alive error: {e}");
         }
     });
@@ -43,14 +82,23 @@ pub(crate) fn run_udp_receiver(
             break Ok(());
         }
 
+        if liveness.server_dead(Instant::now(), PING_TIMEOUT) {
+            break Err(anyhow::anyhow!(
+                "no pong from server for more than {PING_TIMEOUT:?}; server seems dead"
+            ));
+        }
+
         if !connected {
             // первый пакет
             match sock.recv_from(&mut buf) {
                 Ok((n, src)) => {
-                    match decode(&buf[..n]) {
+                    match decode_any(&buf[..n]) {
                         Ok(pkt) => {
-                            if let Err(e) = sock.connect(src) {
-                                break Err(e.into());
+                            if !connect_to_server(|addr| sock.connect(addr), src) {
+                                // временная ошибка не должна ронять клиента:
+                                // пакет обработаем, connect повторим на следующем
+                                handle_pkt(pkt, &mut output, &mut liveness, &checkpoints);
+                                continue;
                             }
                             connected = true;
                             match tx.try_send(src) {
@@ -62,10 +110,12 @@ pub(crate) fn run_udp_receiver(
                                     warn!("ping channel disconnected; keep-alive will not be sent");
                                 }
                             };
-                            handle_pkt(pkt);
+                            handle_pkt(pkt, &mut output, &mut liveness, &checkpoints);
                         }
                         Err(e) => {
-                            debug!("bad udp packet from {src}: {e}");
+                            if !mismatches.observe(&e, src) {
+                                debug!("bad udp packet from {src}: {e}");
+                            }
                         }
                     }
                 }
@@ -83,12 +133,14 @@ pub(crate) fn run_udp_receiver(
         } else {
             // sock.connect уже выполнен
             match sock.recv(&mut buf) {
-                Ok(n) => match decode(&buf[..n]) {
+                Ok(n) => match decode_any(&buf[..n]) {
                     Ok(pkt) => {
-                        handle_pkt(pkt);
+                        handle_pkt(pkt, &mut output, &mut liveness, &checkpoints);
                     }
                     Err(e) => {
-                        warn!("error decoding packet: {e}")
+                        if !mismatches.observe(&e, server_addr_hint(&sock)) {
+                            warn!("error decoding packet: {e}")
+                        }
                     }
                 },
                 Err(e)
@@ -105,25 +157,203 @@ pub(crate) fn run_udp_receiver(
         }
     };
 
-    shutdown.store(true, Ordering::Relaxed);
+    ping_stop.store(true, Ordering::Relaxed);
     drop(tx);
     let _ = h.join();
+    if mismatches.count > 0 {
+        warn!("{} packets with unsupported wire version", mismatches.count);
+    }
     result
 }
 
-fn handle_pkt(pkt: UdpPacketV1) {
+/// Адрес сервера у подключённого сокета (для логов)
+fn server_addr_hint(sock: &UdpSocket) -> SocketAddr {
+    sock.peer_addr()
+        .unwrap_or_else(|_| SocketAddr::from(([0, 0, 0, 0], 0)))
+}
+
+/// Пакеты с чужой версией wire-протокола: первый - заметный warn
+/// (клиент и сервер разных версий), дальше - только счётчик.
+#[derive(Debug, Default)]
+struct WireVersionMismatches {
+    count: u64,
+}
+
+impl WireVersionMismatches {
+    /// true - ошибка была несовпадением версии и уже учтена
+    fn observe(&mut self, err: &WireError, src: SocketAddr) -> bool {
+        let WireError::UnsupportedWireVersion(ver) = err else {
+            return false;
+        };
+
+        self.count += 1;
+        if self.count == 1 {
+            warn!(
+                "WIRE VERSION MISMATCH: {src} sent v{ver}, client speaks v{WIRE_VERSION}; \
+                 client and server versions differ?"
+            );
+        } else {
+            debug!("wire version mismatch from {src}: v{ver}");
+        }
+        true
+    }
+}
+
+/// Привязывает сокет к адресу сервера. Ошибка логируется, а не пробрасывается:
+/// false - остаёмся в режиме `recv_from` и пробуем снова на следующем пакете.
+fn connect_to_server<F>(connect: F, src: SocketAddr) -> bool
+where
+    F: FnOnce(SocketAddr) -> std::io::Result<()>,
+{
+    match connect(src) {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("failed to connect udp socket to {src}: {e}; will retry on next packet");
+            false
+        }
+    }
+}
+
+/// Отслеживание Pong от сервера (сервер запущен с --enable-pong).
+/// Пока не пришёл ни один Pong, проверка не активна.
+#[derive(Debug, Default)]
+struct ServerLiveness {
+    last_pong: Option<Instant>,
+}
+
+impl ServerLiveness {
+    fn on_pkt(&mut self, pkt: &UdpPacketV1, now: Instant) {
+        if matches!(pkt, UdpPacketV1::Pong) {
+            self.last_pong = Some(now);
+        }
+    }
+
+    fn server_dead(&self, now: Instant, timeout: Duration) -> bool {
+        self.last_pong
+            .is_some_and(|t| now.saturating_duration_since(t) > timeout)
+    }
+}
+
+/// Что и как печатать из принятых котировок
+#[derive(Debug, Default)]
+pub(crate) struct QuoteOutput {
+    /// Фильтр `--watch` (None - все тикеры)
+    pub(crate) watch: Option<HashSet<String>>,
+    /// `--output`: формат строки котировки
+    pub(crate) format: OutputFormat,
+    /// `--with-recv-ts`: время приёма в строке котировки
+    pub(crate) with_recv_ts: bool,
+    /// `--dedup-window-ms`: подавление повторов
+    pub(crate) dedup: Option<DedupWindow>,
+}
+
+fn handle_pkt(
+    pkt: UdpPacketV1,
+    output: &mut QuoteOutput,
+    liveness: &mut ServerLiveness,
+    checkpoints: &AtomicU64,
+) {
+    // время приёма - до любой обработки пакета
+    let recv_ms = now_ms();
+    liveness.on_pkt(&pkt, Instant::now());
+    if let UdpPacketV1::Checkpoint(seq) = pkt {
+        checkpoints.fetch_add(1, Ordering::Relaxed);
+        debug!("checkpoint seq={seq}");
+        return;
+    }
+    if let UdpPacketV1::TickerRemoved(ticker) = &pkt {
+        warn!("server removed ticker {ticker}: no more quotes for it");
+        return;
+    }
+    if let Some(quote) = printable_quote(pkt, output.watch.as_ref()) {
+        if let Some(dedup) = &mut output.dedup
+            && !dedup.admit(&quote, Instant::now())
+        {
+            debug!("duplicate quote suppressed: {quote}");
+            return;
+        }
+        info!(
+            "{}",
+            quote_line(
+                &quote,
+                output.format,
+                output.with_recv_ts.then_some(recv_ms)
+            )
+        );
+        if let Some(sent_ms) = quote.sent_ms {
+            debug!(
+                "{} transit_ms={}",
+                quote.ticker,
+                recv_ms.saturating_sub(sent_ms)
+            );
+        }
+    }
+}
+
+/// Строка вывода котировки в формате `--output`; с `--with-recv-ts` - с локальным
+/// временем приёма пакета: text - префикс `recv_ms=<мс>`, csv - первая колонка,
+/// json - поле `recv_ts` первым в объекте
+fn quote_line(quote: &StockQuote, format: OutputFormat, recv_ms: Option<u64>) -> String {
+    let line = match format {
+        OutputFormat::Text => quote.to_string(),
+        OutputFormat::Csv => format!(
+            "{},{},{},{}",
+            quote.ticker, quote.price, quote.volume, quote.timestamp_ms
+        ),
+        OutputFormat::Json => quote.to_json(),
+    };
+    match (format, recv_ms) {
+        (_, None) => line,
+        (OutputFormat::Text, Some(ms)) => format!("recv_ms={ms} {line}"),
+        (OutputFormat::Csv, Some(ms)) => format!("{ms},{line}"),
+        // to_json всегда начинается с `{"ticker":`
+        (OutputFormat::Json, Some(ms)) => format!("{{\"recv_ts\":{ms},{}", &line[1..]),
+    }
+}
+
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Котировка, которую нужно вывести (с учётом фильтра --watch)
+fn printable_quote(pkt: UdpPacketV1, watch: Option<&HashSet<String>>) -> Option<StockQuote> {
     match pkt {
-        UdpPacketV1::Ping => {}
-        UdpPacketV1::Quote(quote) => {
-            info!("{}", quote);
+        UdpPacketV1::Quote(quote) => match watch {
+            Some(w) if !w.contains(&quote.ticker) => None,
+            _ => Some(quote),
+        },
+        UdpPacketV1::Ping | UdpPacketV1::Pong => None,
+        other => {
+            // служебные пакеты и варианты из более новых версий протокола
+            debug!("ignoring packet: {other}");
+            None
         }
     }
 }
 
+/// Ping для текущего тика: с `--report-loss` - PingAck с числом
+/// полученных контрольных точек, иначе обычный Ping/PingToken.
+/// Если сервер выдал токен - пингуем с ним (переживает NAT)
+fn ping_packet(session_token: Option<u64>, acks: Option<&AtomicU64>) -> UdpPacketV1 {
+    match (acks, session_token) {
+        (Some(acks), token) => UdpPacketV1::PingAck {
+            token,
+            ack_seq: acks.load(Ordering::Relaxed),
+        },
+        (None, Some(token)) => UdpPacketV1::PingToken(token),
+        (None, None) => UdpPacketV1::Ping,
+    }
+}
+
 fn run_ping(
     sock: UdpSocket,
     rx: Receiver<SocketAddr>,
     interval: Duration,
+    session_token: Option<u64>,
+    acks: Option<Arc<AtomicU64>>,
     shutdown: Arc<AtomicBool>,
 ) -> anyhow::Result<()> {
     // Ждём адрес сервера (полученный из udp recv_from)
@@ -142,13 +372,12 @@ fn run_ping(
         }
     };
 
-    let bytes = encode_v1(&UdpPacketV1::Ping)?;
-
     let tick = Duration::from_millis(TICK_RATE_MS);
 
     while !shutdown.load(Ordering::Relaxed) {
-        sock.send_to(&bytes, server_addr)?;
-        debug!("PING");
+        let ping = ping_packet(session_token, acks.as_deref());
+        sock.send_to(&encode_v1(&ping)?, server_addr)?;
+        debug!("{ping}");
 
         let mut slept = Duration::ZERO;
         while slept < interval && !shutdown.load(Ordering::Relaxed) {
@@ -160,3 +389,179 @@ fn run_ping(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(ticker: &str) -> UdpPacketV1 {
+        UdpPacketV1::Quote(StockQuote {
+            ticker: ticker.to_string(),
+            price: 100,
+            volume: 1,
+            timestamp_ms: 1,
+            side: None,
+            sent_ms: None,
+        })
+    }
+
+    #[test]
+    fn wire_version_mismatch_is_counted_separately_from_garbage() {
+        let mut m = WireVersionMismatches::default();
+        let src: SocketAddr = "127.0.0.1:5556".parse().unwrap();
+
+        let mut future = encode_v1(&quote("AAPL")).unwrap();
+        future[0] = 42; // неизвестная версия (не v1 и не fixed)
+        let err = decode_any(&future).unwrap_err();
+        assert!(m.observe(&err, src));
+        assert!(m.observe(&err, src));
+
+        assert!(!m.observe(&WireError::PacketTooShort, src));
+        assert_eq!(m.count, 2);
+    }
+
+    #[test]
+    fn detect_outbound_ip_to_loopback_server_is_loopback() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let ip = detect_outbound_ip(server.local_addr().unwrap()).unwrap();
+        assert!(ip.is_loopback(), "unexpected outbound ip: {ip}");
+    }
+
+    #[test]
+    fn watch_filter_suppresses_non_watched_tickers() {
+        let watch: HashSet<String> = ["AAPL".to_string(), "TSLA".to_string()].into();
+
+        assert!(printable_quote(quote("AAPL"), Some(&watch)).is_some());
+        assert!(printable_quote(quote("TSLA"), Some(&watch)).is_some());
+        assert!(printable_quote(quote("MSFT"), Some(&watch)).is_none());
+    }
+
+    #[test]
+    fn unhandled_packet_variant_is_ignored() {
+        // клиент не обрабатывает PingToken явно - попадает в catch-all
+        let bytes = encode_v1(&UdpPacketV1::PingToken(1)).unwrap();
+        let pkt = decode_any(&bytes).unwrap();
+
+        assert!(printable_quote(pkt, None).is_none());
+    }
+
+    #[test]
+    fn connect_failure_is_not_fatal_and_retries() {
+        let src: SocketAddr = "127.0.0.1:5555".parse().unwrap();
+        let mut attempts = Vec::new();
+
+        // первая попытка падает, вторая проходит
+        let mut results = vec![
+            Ok(()),
+            Err(std::io::Error::new(
+                std::io::ErrorKind::AddrNotAvailable,
+                "transient",
+            )),
+        ];
+        let mut connect = |addr| {
+            attempts.push(addr);
+            results.pop().unwrap()
+        };
+
+        assert!(!connect_to_server(&mut connect, src));
+        assert!(connect_to_server(&mut connect, src));
+        assert_eq!(attempts, vec![src, src]);
+    }
+
+    #[test]
+    fn liveness_detects_missing_pong_only_after_first_pong() {
+        let mut l = ServerLiveness::default();
+        let t0 = Instant::now();
+        let timeout = Duration::from_secs(5);
+
+        // сервер без --enable-pong: никогда не считаем его мёртвым
+        assert!(!l.server_dead(t0 + Duration::from_secs(60), timeout));
+
+        l.on_pkt(&UdpPacketV1::Pong, t0);
+        assert!(!l.server_dead(t0 + Duration::from_secs(4), timeout));
+        assert!(l.server_dead(t0 + Duration::from_secs(6), timeout));
+    }
+
+    #[test]
+    fn no_watch_filter_prints_all_quotes_but_not_pings() {
+        assert!(printable_quote(quote("MSFT"), None).is_some());
+        assert!(printable_quote(UdpPacketV1::Ping, None).is_none());
+    }
+
+    #[test]
+    fn recv_ts_prefixes_quote_line_only_when_enabled() {
+        let UdpPacketV1::Quote(q) = quote("AAPL") else {
+            unreachable!()
+        };
+
+        assert_eq!(
+            quote_line(&q, OutputFormat::Text, None),
+            "AAPL price=1.00 volume=1 ts_ms=1"
+        );
+        assert_eq!(
+            quote_line(&q, OutputFormat::Text, Some(1_700_000_000_123)),
+            "recv_ms=1700000000123 AAPL price=1.00 volume=1 ts_ms=1"
+        );
+    }
+
+    #[test]
+    fn recv_ts_is_first_csv_column_only_when_enabled() {
+        let UdpPacketV1::Quote(q) = quote("AAPL") else {
+            unreachable!()
+        };
+
+        assert_eq!(quote_line(&q, OutputFormat::Csv, None), "AAPL,100,1,1");
+        assert_eq!(
+            quote_line(&q, OutputFormat::Csv, Some(1_700_000_000_123)),
+            "1700000000123,AAPL,100,1,1"
+        );
+    }
+
+    #[test]
+    fn recv_ts_is_json_field_only_when_enabled() {
+        let UdpPacketV1::Quote(q) = quote("AAPL") else {
+            unreachable!()
+        };
+
+        assert_eq!(quote_line(&q, OutputFormat::Json, None), q.to_json());
+        let line = quote_line(&q, OutputFormat::Json, Some(1_700_000_000_123));
+        assert_eq!(
+            line,
+            r#"{"recv_ts":1700000000123,"ticker":"AAPL","price":100,"volume":1,"timestamp_ms":1}"#
+        );
+        // остальное - та же котировка
+        assert_eq!(StockQuote::from_json(&line).unwrap(), q);
+    }
+
+    #[test]
+    fn checkpoints_are_counted_and_echoed_in_ping_ack() {
+        let mut l = ServerLiveness::default();
+        let acks = AtomicU64::new(0);
+
+        handle_pkt(
+            UdpPacketV1::Checkpoint(1),
+            &mut QuoteOutput::default(),
+            &mut l,
+            &acks,
+        );
+        handle_pkt(quote("AAPL"), &mut QuoteOutput::default(), &mut l, &acks);
+        handle_pkt(
+            UdpPacketV1::Checkpoint(2),
+            &mut QuoteOutput::default(),
+            &mut l,
+            &acks,
+        );
+
+        assert_eq!(
+            ping_packet(Some(7), Some(&acks)),
+            UdpPacketV1::PingAck {
+                token: Some(7),
+                ack_seq: 2
+            }
+        );
+        // без --report-loss ping прежний
+        assert_eq!(ping_packet(Some(7), None), UdpPacketV1::PingToken(7));
+        assert_eq!(ping_packet(None, None), UdpPacketV1::Ping);
+    }
+}
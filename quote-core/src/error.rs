@@ -47,9 +47,61 @@ pub enum ProtocolError {
     #[error("tickers list is empty")]
     EmptyTickers,
 
+    /// Неверное значение опции RATE=
+    #[error("invalid RATE value: {0}")]
+    InvalidRate(String),
+
+    /// Неверное значение опции BURST=
+    #[error("invalid BURST value: {0}")]
+    InvalidBurst(String),
+
+    /// Неверное значение опции ID=
+    #[error("invalid client ID: {0:?} (1-64 chars of [A-Za-z0-9_-])")]
+    InvalidClientId(String),
+
+    /// Неверное значение опции WIRE=
+    #[error("invalid WIRE value: {0} (expected postcard or fixed)")]
+    InvalidWireFormat(String),
+
+    /// Неверное значение опции ENC=
+    #[error("invalid ENC value: {0} (expected v1, fixed, batch or text)")]
+    InvalidEncoding(String),
+
+    /// ENC= и WIRE= в одной команде
+    #[error("ENC= and WIRE= are mutually exclusive")]
+    EncodingWithWire,
+
+    /// Тикер нельзя передать в STREAM: пустой, с пробелом или разделителем
+    #[error("ticker {0:?} cannot be sent in STREAM (empty, whitespace, ',', '#' or '=')")]
+    UnformattableTicker(String),
+
+    /// `ALL` вместо `*` в списке тикеров
+    #[error("{0:?} is not a wildcard: use `*` to subscribe to all tickers")]
+    NonCanonicalAllTickers(String),
+
+    /// Неверное значение опции SID=
+    #[error("invalid SID value: {0} (expected 0 or 1)")]
+    InvalidSid(String),
+
+    /// Неверное значение опции ACK=
+    #[error("invalid ACK value: {0} (expected 0 or 1)")]
+    InvalidAck(String),
+
+    /// Неизвестная опция KEY=value в STREAM
+    #[error("unknown option: {0}")]
+    UnknownOption(String),
+
+    /// Бинарная команда не разбирается
+    #[error("malformed binary command: {0}")]
+    MalformedBinary(String),
+
     /// Лишние аргументы
     #[error("unexpected extra arguments")]
     ExtraArgs,
+
+    /// `DEBUG` без темы или с неизвестной темой
+    #[error("unknown DEBUG topic: {0:?} (expected pings)")]
+    UnknownDebugTopic(String),
 }
 
 /// Ошибки сериализации
@@ -59,6 +111,10 @@ pub enum WireError {
     #[error("packet too short")]
     PacketTooShort,
 
+    /// Есть байт версии, но нет payload
+    #[error("missing payload after version byte")]
+    MissingPayload,
+
     /// Неверная версия протокола
     #[error("unsupported wire version: {0}")]
     UnsupportedWireVersion(u8),
@@ -66,4 +122,87 @@ pub enum WireError {
     /// Ошибка сериализации/десериализации
     #[error("postcard encode/decode error: {0}")]
     Postcard(#[from] postcard::Error),
+
+    /// Неизвестный тип пакета в заголовке (см. `wire::peek_kind`)
+    #[error("unknown packet kind: {0}")]
+    UnknownPacketKind(u8),
+
+    /// Фиксированный формат: неверная длина пакета
+    #[error("fixed packet has wrong length: {0} bytes")]
+    FixedLength(usize),
+
+    /// Фиксированный формат: timestamp не помещается в u64
+    #[error("timestamp {0} does not fit fixed layout (u64)")]
+    FixedTimestampOverflow(u128),
+
+    /// Фиксированный формат: тикер не в UTF-8
+    #[error("fixed packet ticker is not valid utf-8")]
+    FixedInvalidTicker,
+
+    /// Фиксированный формат: неизвестный код side
+    #[error("fixed packet has invalid side code: {0}")]
+    FixedInvalidSide(u8),
+
+    /// Self-test: пакет после encode/decode не совпал с исходным
+    #[error("wire self-test failed: round-trip mismatch")]
+    SelfTestMismatch,
+}
+
+/// Тикер, отклонённый [`crate::tickers::canonicalize`]
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("invalid ticker {raw:?}: {reason}")]
+pub struct InvalidTicker {
+    /// Исходная строка (как передана, без trim)
+    pub raw: String,
+    /// Причина отказа
+    pub reason: InvalidTickerReason,
+}
+
+/// Почему тикер отклонён
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum InvalidTickerReason {
+    /// Пустая строка (или одни пробелы)
+    #[error("empty")]
+    Empty,
+
+    /// Длиннее допустимого
+    #[error("longer than {max} bytes")]
+    TooLong {
+        /// допустимый максимум
+        max: usize,
+    },
+
+    /// Пробел, не-ASCII или разделитель (`,`, `#`)
+    #[error("bad character {0:?}")]
+    BadChar(char),
+}
+
+/// Ошибки [`crate::StockQuote::from_json`]
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum QuoteParseError {
+    /// Текст - не JSON-объект
+    #[error("invalid JSON at byte {offset}: expected {expected}")]
+    Syntax {
+        /// Смещение в байтах, где разбор остановился
+        offset: usize,
+        /// Что ожидалось в этом месте
+        expected: &'static str,
+    },
+
+    /// Нет обязательного поля
+    #[error("missing field {0:?}")]
+    MissingField(&'static str),
+
+    /// Поле указано дважды
+    #[error("duplicate field {0:?}")]
+    DuplicateField(&'static str),
+
+    /// Значение поля неверного типа или вне диапазона
+    #[error("invalid value for field {field:?}: {value}")]
+    InvalidField {
+        /// Имя поля
+        field: &'static str,
+        /// Значение, как оно записано в JSON
+        value: String,
+    },
 }
@@ -0,0 +1,384 @@
+//! Минимальный JSON-парсер и запись строк - единственный в крате:
+//! - плоский объект для [`StockQuote::from_json`](crate::StockQuote::from_json):
+//!   значения полей котировки - строки, целые и `null`; значения неизвестных
+//!   полей (в том числе вложенные объекты и массивы) проверяются и пропускаются;
+//! - массив строк для JSON-файлов тикеров ([`crate::tickers::read_tickers_auto`]);
+//! - [`write_str`] для `to_json` и строк лога ([`crate::logfmt`]).
+
+use crate::error::QuoteParseError;
+
+/// Глубина вложенности пропускаемых значений (защита стека)
+const MAX_DEPTH: usize = 32;
+
+/// Значение поля верхнего уровня
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value<'a> {
+    Str(String),
+    /// Число как записано: тип поля решает, как его разбирать
+    Num(&'a str),
+    Bool(bool),
+    Null,
+    /// Вложенный объект или массив
+    Nested,
+}
+
+impl Value<'_> {
+    /// Значение для сообщения об ошибке
+    pub(crate) fn describe(&self) -> String {
+        match self {
+            Value::Str(s) => format!("{s:?}"),
+            Value::Num(n) => n.to_string(),
+            Value::Bool(b) => b.to_string(),
+            Value::Null => "null".to_string(),
+            Value::Nested => "object or array".to_string(),
+        }
+    }
+}
+
+/// Разбирает `{"key": value, ...}` целиком (после объекта - только пробелы)
+pub(crate) fn parse_object(input: &str) -> Result<Vec<(String, Value<'_>)>, QuoteParseError> {
+    let mut p = Parser { input, pos: 0 };
+    p.ws();
+    p.expect(b'{', "'{'")?;
+
+    let mut fields = Vec::new();
+    p.ws();
+    if !p.eat(b'}') {
+        loop {
+            p.ws();
+            let key = p.string()?;
+            p.ws();
+            p.expect(b':', "':'")?;
+            p.ws();
+            let value = p.value(0)?;
+            fields.push((key, value));
+            p.ws();
+            if p.eat(b'}') {
+                break;
+            }
+            p.expect(b',', "',' or '}'")?;
+        }
+    }
+
+    p.ws();
+    if p.pos != input.len() {
+        return Err(p.error("end of input"));
+    }
+    Ok(fields)
+}
+
+/// Разбирает `["a", "b", ...]` целиком: массив строк (после него - только пробелы)
+pub(crate) fn parse_string_array(input: &str) -> Result<Vec<String>, QuoteParseError> {
+    let mut p = Parser { input, pos: 0 };
+    p.ws();
+    p.expect(b'[', "'['")?;
+
+    let mut items = Vec::new();
+    p.ws();
+    if !p.eat(b']') {
+        loop {
+            p.ws();
+            items.push(p.string()?);
+            p.ws();
+            if p.eat(b']') {
+                break;
+            }
+            p.expect(b',', "',' or ']'")?;
+        }
+    }
+
+    p.ws();
+    if p.pos != input.len() {
+        return Err(p.error("end of input"));
+    }
+    Ok(items)
+}
+
+/// Строка JSON в кавычках
+pub(crate) fn write_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if u32::from(c) < 0x20 => out.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, expected: &'static str) -> QuoteParseError {
+        QuoteParseError::Syntax {
+            offset: self.pos,
+            expected,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).copied()
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        if self.peek() == Some(b) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, b: u8, what: &'static str) -> Result<(), QuoteParseError> {
+        if self.eat(b) {
+            Ok(())
+        } else {
+            Err(self.error(what))
+        }
+    }
+
+    fn ws(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value<'a>, QuoteParseError> {
+        match self.peek() {
+            Some(b'"') => self.string().map(Value::Str),
+            Some(b'-' | b'0'..=b'9') => self.number().map(Value::Num),
+            Some(b'{' | b'[') => self.nested(depth + 1).map(|()| Value::Nested),
+            _ if self.keyword("true") => Ok(Value::Bool(true)),
+            _ if self.keyword("false") => Ok(Value::Bool(false)),
+            _ if self.keyword("null") => Ok(Value::Null),
+            _ => Err(self.error("a value")),
+        }
+    }
+
+    fn keyword(&mut self, word: &str) -> bool {
+        if self.input[self.pos..].starts_with(word) {
+            self.pos += word.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// `-?(0|[1-9]\d*)(\.\d+)?([eE][+-]?\d+)?`
+    fn number(&mut self) -> Result<&'a str, QuoteParseError> {
+        let start = self.pos;
+        self.eat(b'-');
+        if !self.eat(b'0') {
+            if !matches!(self.peek(), Some(b'1'..=b'9')) {
+                return Err(self.error("a digit"));
+            }
+            self.digits();
+        }
+        if self.eat(b'.') && self.digits() == 0 {
+            return Err(self.error("a digit"));
+        }
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            self.pos += 1;
+            if !self.eat(b'+') {
+                self.eat(b'-');
+            }
+            if self.digits() == 0 {
+                return Err(self.error("a digit"));
+            }
+        }
+        Ok(&self.input[start..self.pos])
+    }
+
+    fn digits(&mut self) -> usize {
+        let start = self.pos;
+        while matches!(self.peek(), Some(b'0'..=b'9')) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    fn string(&mut self) -> Result<String, QuoteParseError> {
+        self.expect(b'"', "'\"'")?;
+        let mut out = String::new();
+        loop {
+            let rest = &self.input[self.pos..];
+            let Some(c) = rest.chars().next() else {
+                return Err(self.error("closing '\"'"));
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(out),
+                '\\' => out.push(self.escape()?),
+                c if u32::from(c) < 0x20 => {
+                    self.pos -= 1;
+                    return Err(self.error("escaped control character"));
+                }
+                c => out.push(c),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, QuoteParseError> {
+        let c = match self.peek() {
+            Some(b'"') => '"',
+            Some(b'\\') => '\\',
+            Some(b'/') => '/',
+            Some(b'b') => '\u{8}',
+            Some(b'f') => '\u{c}',
+            Some(b'n') => '\n',
+            Some(b'r') => '\r',
+            Some(b't') => '\t',
+            Some(b'u') => {
+                self.pos += 1;
+                return self.unicode_escape();
+            }
+            _ => return Err(self.error("escape sequence")),
+        };
+        self.pos += 1;
+        Ok(c)
+    }
+
+    /// `\uXXXX`, суррогатная пара - двумя escape подряд
+    fn unicode_escape(&mut self) -> Result<char, QuoteParseError> {
+        let hi = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&hi) {
+            if !self.keyword("\\u") {
+                return Err(self.error("low surrogate"));
+            }
+            let lo = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&lo) {
+                return Err(self.error("low surrogate"));
+            }
+            0x10000 + ((hi - 0xD800) << 10) + (lo - 0xDC00)
+        } else {
+            hi
+        };
+        char::from_u32(code).ok_or_else(|| self.error("unicode scalar value"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, QuoteParseError> {
+        let hex = self
+            .input
+            .get(self.pos..self.pos + 4)
+            .filter(|h| h.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("4 hex digits"))?;
+        self.pos += 4;
+        Ok(u32::from_str_radix(hex, 16).expect("checked hex digits"))
+    }
+
+    /// Пропускает объект или массив, проверяя синтаксис
+    fn nested(&mut self, depth: usize) -> Result<(), QuoteParseError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("shallower nesting"));
+        }
+        let (close, is_object) = match self.peek() {
+            Some(b'{') => (b'}', true),
+            _ => (b']', false),
+        };
+        self.pos += 1;
+
+        self.ws();
+        if self.eat(close) {
+            return Ok(());
+        }
+        loop {
+            self.ws();
+            if is_object {
+                self.string()?;
+                self.ws();
+                self.expect(b':', "':'")?;
+                self.ws();
+            }
+            self.value(depth)?;
+            self.ws();
+            if self.eat(close) {
+                return Ok(());
+            }
+            self.expect(b',', "',' or closing bracket")?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_flat_object_and_skips_nested_values() {
+        let fields =
+            parse_object(r#" { "a" : "x\"é😀", "b": -1.5e3, "c": [1, {"d": null}], "e": true } "#)
+                .unwrap();
+        assert_eq!(
+            fields,
+            vec![
+                ("a".to_string(), Value::Str("x\"é😀".to_string())),
+                ("b".to_string(), Value::Num("-1.5e3")),
+                ("c".to_string(), Value::Nested),
+                ("e".to_string(), Value::Bool(true)),
+            ]
+        );
+        assert_eq!(parse_object("{}").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        for (bad, offset) in [
+            ("", 0),
+            ("[]", 0),
+            (r#"{"a":1,}"#, 7),
+            (r#"{"a":01}"#, 6),
+            (r#"{"a":1} x"#, 8),
+            (r#"{"a":"\x"}"#, 7),
+            (r#"{"a":"open}"#, 11),
+            (r#"{"a":[1 2]}"#, 8),
+        ] {
+            match parse_object(bad) {
+                Err(QuoteParseError::Syntax { offset: at, .. }) => assert_eq!(at, offset, "{bad}"),
+                other => panic!("{bad}: {other:?}"),
+            }
+        }
+
+        let deep = format!(r#"{{"a":{}{}}}"#, "[".repeat(40), "]".repeat(40));
+        assert!(parse_object(&deep).is_err());
+    }
+
+    #[test]
+    fn parses_string_array_and_rejects_other_values() {
+        assert_eq!(
+            parse_string_array(r#" ["a", "b\u0041" ,"" ] "#).unwrap(),
+            vec!["a", "bA", ""]
+        );
+        assert!(parse_string_array("[]").unwrap().is_empty());
+
+        for (bad, offset) in [
+            (r#"["a""#, 4),
+            (r#"["a" "b"]"#, 5),
+            ("[1]", 1),
+            (r#"["a",]"#, 5),
+            (r#"["a"] ["b"]"#, 6),
+        ] {
+            match parse_string_array(bad) {
+                Err(QuoteParseError::Syntax { offset: at, .. }) => assert_eq!(at, offset, "{bad}"),
+                other => panic!("{bad}: {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn write_str_escapes_quotes_and_control_chars() {
+        let mut out = String::new();
+        write_str(&mut out, "a\"b\\c\n\u{1}");
+        assert_eq!(out, r#""a\"b\\c\n\u0001""#);
+        let object = format!("{{{out}:null}}");
+        let parsed = parse_object(&object).unwrap();
+        assert_eq!(parsed[0].0, "a\"b\\c\n\u{1}");
+    }
+}
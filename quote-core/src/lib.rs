@@ -17,10 +17,11 @@
 //!
 //! let cmd = parse_command("STREAM udp://127.0.0.1:34254 AAPL,TSLA").unwrap();
 //! match cmd {
-//!     Command::Stream { udp_target, tickers } => {
+//!     Command::Stream { udp_target, tickers, .. } => {
 //!         assert_eq!(udp_target, "127.0.0.1:34254".parse().unwrap());
 //!         assert_eq!(tickers, vec!["AAPL".to_string(), "TSLA".to_string()]);
 //!     }
+//!     Command::Status | Command::Debug(_) | Command::WhoAmI { .. } => unreachable!(),
 //! }
 //! ```
 //!
@@ -45,6 +46,8 @@
 //!     price: 123_4500,
 //!     volume: 1500,
 //!     timestamp_ms: 1_700_000_000_000,
+//!     side: None,
+//!     sent_ms: None,
 //! });
 //!
 //! let bytes = encode_v1(&pkt).unwrap();
@@ -77,12 +80,18 @@ pub mod wire;
 /// Ошибки `quote-core`.
 pub mod error;
 
+/// Формат строк лога для `--log-json`.
+pub mod logfmt;
+
 /// Общие константы
 mod constants;
+
+/// Минимальный JSON без внешних зависимостей (котировки, файлы тикеров, логи)
+mod json;
 pub use constants::{PING_INTERVAL, PING_TIMEOUT};
 
 // --- Re-exports (публичный фасад API) ---
 
-pub use crate::error::{ProtocolError, QuoteCoreError, WireError};
-pub use crate::protocol::Command;
-pub use crate::types::StockQuote;
+pub use crate::error::{InvalidTicker, ProtocolError, QuoteCoreError, QuoteParseError, WireError};
+pub use crate::protocol::{Command, DebugTopic, Encoding, RawTickers};
+pub use crate::types::{Side, StockQuote};
@@ -0,0 +1,96 @@
+//! Строки лога в JSON (`--log-json` у сервера и клиента): одна запись - один
+//! объект в строке, для сборщиков логов. Логгер (env_logger) настраивают
+//! бинарники, формат записи общий - [`write_json_record`].
+
+use crate::json::write_str;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Формат записи для `env_logger::Builder::format`: [`json_log_line`]
+/// с текущим временем и переводом строки
+pub fn write_json_record(out: &mut impl io::Write, record: &log::Record<'_>) -> io::Result<()> {
+    let ts_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let line = json_log_line(
+        record.level().as_str(),
+        ts_ms,
+        &record.args().to_string(),
+        record.target(),
+    );
+    writeln!(out, "{line}")
+}
+
+/// Запись лога как JSON-объект без перевода строки:
+/// `{"level":"INFO","ts":<unix ms>,"msg":"...","target":"..."}`
+pub fn json_log_line(level: &str, ts_ms: u64, msg: &str, target: &str) -> String {
+    let mut out = String::with_capacity(msg.len() + target.len() + 48);
+    out.push_str("{\"level\":");
+    write_str(&mut out, level);
+    out.push_str(",\"ts\":");
+    out.push_str(&ts_ms.to_string());
+    out.push_str(",\"msg\":");
+    write_str(&mut out, msg);
+    out.push_str(",\"target\":");
+    write_str(&mut out, target);
+    out.push('}');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::{Value, parse_object};
+
+    #[test]
+    fn log_line_is_json_object_with_expected_fields() {
+        let line = json_log_line(
+            "INFO",
+            1_700_000_000_123,
+            "said \"hi\"\nbye",
+            "quote_server::tcp",
+        );
+        assert!(!line.contains('\n'), "one record per line: {line}");
+
+        let fields = parse_object(&line).unwrap();
+        assert_eq!(
+            fields,
+            vec![
+                ("level".to_string(), Value::Str("INFO".to_string())),
+                ("ts".to_string(), Value::Num("1700000000123")),
+                (
+                    "msg".to_string(),
+                    Value::Str("said \"hi\"\nbye".to_string())
+                ),
+                (
+                    "target".to_string(),
+                    Value::Str("quote_server::tcp".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn record_is_written_as_one_json_line() {
+        let mut out = Vec::new();
+        write_json_record(
+            &mut out,
+            &log::Record::builder()
+                .level(log::Level::Warn)
+                .target("quote_server::tcp")
+                .args(format_args!("client {} gone", "\"a\""))
+                .build(),
+        )
+        .unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        let line = out.strip_suffix('\n').expect("record ends with newline");
+        let fields = parse_object(line).unwrap();
+        let keys: Vec<&str> = fields.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, ["level", "ts", "msg", "target"]);
+        assert_eq!(fields[0].1, Value::Str("WARN".to_string()));
+        assert!(matches!(fields[1].1, Value::Num(ts) if ts.parse::<u64>().unwrap() > 0));
+        assert_eq!(fields[2].1, Value::Str("client \"a\" gone".to_string()));
+    }
+}
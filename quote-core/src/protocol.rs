@@ -1,9 +1,296 @@
 use crate::error::ProtocolError;
-use crate::tickers::parse_tickers_csv;
+use crate::tickers::{TickerCase, parse_tickers_csv_with_case};
+use crate::wire::WireFormat;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
 use std::net::SocketAddr;
+use std::time::Duration;
 
-/// Команды, принимаемые сервером
+/// Команда начала стриминга
+pub const VERB_STREAM: &str = "STREAM";
+/// Команда проверки доступности сервера
+pub const VERB_STATUS: &str = "STATUS";
+/// Диагностическая команда (сервер с `--debug-commands`)
+pub const VERB_DEBUG: &str = "DEBUG";
+/// Подписка сессий на UDP-адрес глазами сервера (сервер с `--debug-commands`)
+pub const VERB_WHOAMI: &str = "WHOAMI";
+/// Схема UDP-адреса клиента в `STREAM`
+pub const UDP_SCHEME: &str = "udp://";
+/// Успешный ответ сервера (`OK`, `OK <token>`, `OK sessions=<n>`; см. [`OkReply`])
+pub const REPLY_OK: &str = "OK";
+/// Префикс ответа с ошибкой (`ERR <причина>`)
+pub const REPLY_ERR_PREFIX: &str = "ERR";
+
+/// Подписка на все тикеры сервера: `STREAM udp://... *`.
+/// Единственная форма; `ALL` отклоняется с подсказкой
+pub const ALL_TICKERS: &str = "*";
+
+/// Ключ подсказки "повторите позже" в `ERR`
+pub const RETRY_AFTER_KEY: &str = "retry_after_ms";
+
+/// Ключ id сессии в `OK` (по запросу `SID=1`)
+pub const SESSION_ID_KEY: &str = "id";
+
+/// Ключ числа активных сессий в ответе на `STATUS`
+pub const SESSIONS_KEY: &str = "sessions";
+
+/// Ключ выбранной кодировки в ответе на `STREAM ... ENC=<enc>`
+pub const ENCODING_KEY: &str = "enc";
+
+/// Кодировка котировок сессии, запрошенная опцией `ENC=` в STREAM.
+///
+/// Протокол знает все варианты; какие из них поддерживает сервер, решает он сам
+/// и отвечает `OK enc=<выбранная>` или `ERR E_UNSUPPORTED_ENCODING`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Encoding {
+    /// `ENC=v1`: [`crate::wire::UdpPacketV1`] через postcard (как `WIRE=postcard`)
+    V1,
+    /// `ENC=fixed`: фиксированная раскладка (как `WIRE=fixed`)
+    Fixed,
+    /// `ENC=batch`: несколько котировок в одном пакете
+    Batch,
+    /// `ENC=text`: котировка текстовой строкой
+    Text,
+}
+
+impl Encoding {
+    /// Все кодировки протокола
+    pub const ALL: [Encoding; 4] = [
+        Encoding::V1,
+        Encoding::Fixed,
+        Encoding::Batch,
+        Encoding::Text,
+    ];
+
+    /// Значение опции `ENC=` (нижний регистр)
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::V1 => "v1",
+            Encoding::Fixed => "fixed",
+            Encoding::Batch => "batch",
+            Encoding::Text => "text",
+        }
+    }
+
+    /// Формат UDP-котировок для кодировки; `None` - в этой версии кодировка
+    /// не реализована (сервер её не предложит)
+    pub fn wire_format(self) -> Option<WireFormat> {
+        match self {
+            Encoding::V1 => Some(WireFormat::Postcard),
+            Encoding::Fixed => Some(WireFormat::Fixed),
+            Encoding::Batch | Encoding::Text => None,
+        }
+    }
+
+    /// Согласование: запрошенная кодировка, если она есть среди `supported`
+    pub fn negotiate(self, supported: &[Encoding]) -> Option<Encoding> {
+        supported.contains(&self).then_some(self)
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Encoding {
+    type Err = ProtocolError;
+
+    /// Без учёта регистра: `ENC=Fixed` == `ENC=fixed`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Encoding::ALL
+            .into_iter()
+            .find(|e| e.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| ProtocolError::InvalidEncoding(s.to_string()))
+    }
+}
+
+/// Успешный ответ сервера: `OK [<token>] [id=<n>] [key=value ...]`.
+///
+/// Собирается через `with_*` и печатается `Display`/[`OkReply::to_line`];
+/// [`parse_ok_reply`] разбирает его обратно. Грамматика ответа живёт только
+/// здесь, поэтому сервер и клиент не могут разойтись.
+///
+/// ```
+/// use quote_core::protocol::{OkReply, parse_ok_reply};
+///
+/// let reply = OkReply::new().with_token(Some(7)).with_field("sessions", 3);
+/// assert_eq!(reply.to_line(), "OK 7 sessions=3\n");
+/// assert_eq!(parse_ok_reply(&reply.to_line()), Some(reply));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OkReply {
+    /// Токен сессии для `PingToken` (сервер с `--ping-token`)
+    pub token: Option<u64>,
+    /// Id сессии на сервере (клиент прислал `SID=1`)
+    pub session_id: Option<u64>,
+    /// Остальные `key=value` в порядке следования, включая неизвестные
+    /// этой версии ключи
+    pub fields: Vec<(String, String)>,
+}
+
+impl OkReply {
+    /// Пустой `OK`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Токен сессии (`None` - без токена)
+    pub fn with_token(mut self, token: Option<u64>) -> Self {
+        self.token = token;
+        self
+    }
+
+    /// Id сессии, `id=<n>` (`None` - не выводится)
+    pub fn with_session_id(mut self, session_id: Option<u64>) -> Self {
+        self.session_id = session_id;
+        self
+    }
+
+    /// Добавляет `key=value`. Ключ и значение - без пробелов, в ключе нет `=`.
+    pub fn with_field(mut self, key: &str, value: impl fmt::Display) -> Self {
+        let value = value.to_string();
+        debug_assert!(
+            !key.is_empty() && !key.contains(['=', ' ']) && !value.contains(char::is_whitespace),
+            "bad OK field {key:?}={value:?}"
+        );
+        self.fields.push((key.to_string(), value));
+        self
+    }
+
+    /// Значение ключа из `fields` (первое, если ключ повторяется)
+    pub fn field(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Строка ответа с `\n`
+    pub fn to_line(&self) -> String {
+        format!("{self}\n")
+    }
+}
+
+impl fmt::Display for OkReply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REPLY_OK)?;
+        if let Some(token) = self.token {
+            write!(f, " {token}")?;
+        }
+        if let Some(id) = self.session_id {
+            write!(f, " {SESSION_ID_KEY}={id}")?;
+        }
+        for (key, value) in &self.fields {
+            write!(f, " {key}={value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Формирует `OK [<token>] [id=<n>] [key=value ...]` без конца строки
+pub fn format_ok_reply(reply: &OkReply) -> String {
+    reply.to_string()
+}
+
+/// Разбирает ответ `OK ...` (None - это не `OK` или он некорректен).
+/// Ключи, кроме `id`, попадают в `fields`: их может добавить более новый сервер.
+pub fn parse_ok_reply(line: &str) -> Option<OkReply> {
+    let rest = line.trim().strip_prefix(REPLY_OK)?;
+    if !rest.is_empty() && !rest.starts_with(' ') {
+        return None;
+    }
+
+    let mut reply = OkReply::default();
+    for part in rest.split_whitespace() {
+        match part.split_once('=') {
+            Some((key, value)) if key == SESSION_ID_KEY => {
+                reply.session_id = Some(value.parse().ok()?);
+            }
+            Some((key, value)) => reply.fields.push((key.to_string(), value.to_string())),
+            None if reply.token.is_none()
+                && reply.session_id.is_none()
+                && reply.fields.is_empty() =>
+            {
+                reply.token = Some(part.parse().ok()?)
+            }
+            None => return None,
+        }
+    }
+    Some(reply)
+}
+
+/// Ответ сервера с ошибкой: `ERR <code> [retry_after_ms=<n>] [<message>]`
 #[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrReply {
+    /// Машиночитаемый код (`E_SERVER_FULL`, `E_UNKNOWN_GROUP`, ...); сервер ставит его
+    /// в каждый `ERR`, у ответа без кода сюда попадает первое слово текста
+    pub code: String,
+    /// Через сколько имеет смысл повторить запрос
+    pub retry_after: Option<Duration>,
+    /// Пояснение для человека
+    pub message: String,
+}
+
+/// Формирует `ERR <code> [retry_after_ms=<n>] [<message>]` без конца строки
+pub fn format_err_reply(code: &str, retry_after: Option<Duration>, message: &str) -> String {
+    let mut out = format!("{REPLY_ERR_PREFIX} {code}");
+    if let Some(d) = retry_after {
+        out.push_str(&format!(" {RETRY_AFTER_KEY}={}", d.as_millis()));
+    }
+    if !message.is_empty() {
+        out.push(' ');
+        out.push_str(message);
+    }
+    out
+}
+
+/// Разбирает ответ `ERR ...` (None - это не `ERR`).
+/// Некорректное значение `retry_after_ms` считается частью сообщения.
+pub fn parse_err_reply(line: &str) -> Option<ErrReply> {
+    let rest = line.trim().strip_prefix(REPLY_ERR_PREFIX)?;
+    if !rest.is_empty() && !rest.starts_with(' ') {
+        return None;
+    }
+
+    let rest = rest.trim_start();
+    let (code, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+    let rest = rest.trim_start();
+
+    let (opt, msg) = rest.split_once(' ').unwrap_or((rest, ""));
+    let retry_ms = opt
+        .strip_prefix(RETRY_AFTER_KEY)
+        .and_then(|v| v.strip_prefix('='))
+        .and_then(|v| v.parse::<u64>().ok());
+    let (retry_after, message) = match retry_ms {
+        Some(ms) => (Some(Duration::from_millis(ms)), msg.trim_start()),
+        None => (None, rest),
+    };
+
+    Some(ErrReply {
+        code: code.to_string(),
+        retry_after,
+        message: message.to_string(),
+    })
+}
+
+/// Тикеры `STREAM` в том виде, как их прислал клиент: порядок и повторы
+/// сохраняются, элементы только обрезаны от пробелов. Для диагностики
+/// ("почему не пришёл GOOG?").
+///
+/// Не участвует в сравнении команд: `==` у [`Command`] сравнивает
+/// нормализованные `tickers`, а `raw_tickers` пропускает.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RawTickers(pub Vec<String>);
+
+/// Команды, принимаемые сервером.
+///
+/// Равенство - по смыслу команды: `raw_tickers` у `Stream` не сравниваются,
+/// `STREAM ... aapl` и `STREAM ... AAPL` - одна и та же команда.
+#[derive(Debug, Clone, Eq, Serialize, Deserialize)]
 pub enum Command {
     /// Начать стриминг тикеров
     Stream {
@@ -11,12 +298,175 @@ pub enum Command {
         udp_target: SocketAddr,
         /// Запрошенный список тикеров
         tickers: Vec<String>,
+        /// `RATE=<ms>`: не чаще одной котировки на тикер за указанное время
+        max_rate_ms: Option<u32>,
+        /// `BURST=<ms>`: первые миллисекунды сессии без ограничения `RATE=`
+        burst_ms: Option<u32>,
+        /// `WIRE=postcard|fixed|fixed-secs`: формат UDP-котировок для сессии
+        wire: WireFormat,
+        /// `ENC=v1|fixed|batch|text`: согласуемая кодировка (взамен `WIRE=`);
+        /// сервер подтверждает выбор в `OK enc=<enc>`
+        encoding: Option<Encoding>,
+        /// `ID=<id>`: стабильный id клиента; повторный STREAM с тем же id
+        /// заменяет прежнюю сессию этого клиента
+        client_id: Option<String>,
+        /// `SID=1`: клиент просит вернуть id сессии в `OK ... id=<n>`
+        want_session_id: bool,
+        /// `ACK=1`: клиент подтверждает `Checkpoint`-пакеты в ping
+        /// (оценка потерь UDP на сервере)
+        want_ack: bool,
+        /// Тикеры до нормализации (см. [`RawTickers`])
+        raw_tickers: RawTickers,
+    },
+    /// Проверка доступности сервера (сервер отвечает `OK ...` и закрывает соединение)
+    Status,
+    /// `DEBUG <topic>`: диагностика для оператора
+    Debug(DebugTopic),
+    /// `WHOAMI udp://IP:PORT`: текущий набор тикеров сессий, которые шлют
+    /// котировки на этот адрес (диагностика, как `DEBUG`)
+    WhoAmI {
+        /// UDP-адрес клиента из его `STREAM`
+        udp_target: SocketAddr,
     },
 }
 
+impl PartialEq for Command {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                Command::Stream {
+                    udp_target,
+                    tickers,
+                    max_rate_ms,
+                    burst_ms,
+                    wire,
+                    encoding,
+                    client_id,
+                    want_session_id,
+                    want_ack,
+                    raw_tickers: _,
+                },
+                Command::Stream {
+                    udp_target: other_target,
+                    tickers: other_tickers,
+                    max_rate_ms: other_rate,
+                    burst_ms: other_burst,
+                    wire: other_wire,
+                    encoding: other_encoding,
+                    client_id: other_client_id,
+                    want_session_id: other_want_session_id,
+                    want_ack: other_want_ack,
+                    raw_tickers: _,
+                },
+            ) => {
+                udp_target == other_target
+                    && tickers == other_tickers
+                    && max_rate_ms == other_rate
+                    && burst_ms == other_burst
+                    && wire == other_wire
+                    && encoding == other_encoding
+                    && client_id == other_client_id
+                    && want_session_id == other_want_session_id
+                    && want_ack == other_want_ack
+            }
+            (Command::Status, Command::Status) => true,
+            (Command::Debug(a), Command::Debug(b)) => a == b,
+            (Command::WhoAmI { udp_target: a }, Command::WhoAmI { udp_target: b }) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// Тема команды `DEBUG`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DebugTopic {
+    /// `DEBUG pings`: возраст последнего ping по каждому адресу
+    Pings,
+}
+
+impl Command {
+    /// Бинарная форма команды (postcard) - для инструментов, которым удобнее
+    /// передавать команды байтами. Основной интерфейс - текстовый [`parse_command`].
+    pub fn encode(&self) -> Vec<u8> {
+        postcard::to_allocvec(self).expect("serializing Command into a Vec cannot fail")
+    }
+
+    /// Обратное к [`Command::encode`]. Проверки те же, что у текстового
+    /// парсера: непустой список тикеров, корректный `ID=`. Лишние байты - ошибка.
+    pub fn decode(buf: &[u8]) -> Result<Command, ProtocolError> {
+        let (cmd, rest) = postcard::take_from_bytes::<Command>(buf)
+            .map_err(|e| ProtocolError::MalformedBinary(e.to_string()))?;
+        if !rest.is_empty() {
+            return Err(ProtocolError::MalformedBinary(format!(
+                "{} trailing bytes",
+                rest.len()
+            )));
+        }
+
+        if let Command::Stream {
+            tickers, client_id, ..
+        } = &cmd
+        {
+            if tickers.is_empty() {
+                return Err(ProtocolError::EmptyTickers);
+            }
+            if let Some(id) = client_id
+                && !is_valid_client_id(id)
+            {
+                return Err(ProtocolError::InvalidClientId(id.clone()));
+            }
+        }
+
+        Ok(cmd)
+    }
+
+    /// Подписка на все тикеры сервера ([`ALL_TICKERS`])
+    pub fn is_all_tickers(&self) -> bool {
+        matches!(self, Command::Stream { tickers, .. } if tickers.len() == 1 && tickers[0] == ALL_TICKERS)
+    }
+
+    /// Та же подписка: совпадает UDP-адрес и множество тикеров
+    /// (без учёта порядка и дубликатов). Тикеры сравниваются как есть: регистр
+    /// уже нормализован при разборе по [`TickerCase`], и при
+    /// [`TickerCase::Preserve`] `aapl` и `AAPL` - разные тикеры.
+    pub fn same_subscription(&self, other: &Command) -> bool {
+        match (self, other) {
+            (
+                Command::Stream {
+                    udp_target: a_target,
+                    tickers: a_tickers,
+                    ..
+                },
+                Command::Stream {
+                    udp_target: b_target,
+                    tickers: b_tickers,
+                    ..
+                },
+            ) => a_target == b_target && ticker_set(a_tickers) == ticker_set(b_tickers),
+            _ => false,
+        }
+    }
+}
+
+fn ticker_set(tickers: &[String]) -> BTreeSet<&str> {
+    tickers.iter().map(String::as_str).collect()
+}
+
 /// Парсит строку вида:
 /// "STREAM udp://127.0.0.1:34254 AAPL,TSLA"
+/// "STREAM udp://127.0.0.1:34254 AAPL,TSLA RATE=1000"
+/// "STREAM udp://127.0.0.1:34254 AAPL,TSLA RATE=1000 BURST=500"
+/// "STREAM udp://127.0.0.1:34254 AAPL,TSLA WIRE=FIXED"
+/// "STREAM udp://127.0.0.1:34254 AAPL,TSLA ID=3f2a9c01"
+///
+/// Имя команды регистронезависимо (`stream`, `Stream`, `STREAM`).
 pub fn parse_command(line: &str) -> Result<Command, ProtocolError> {
+    parse_command_with_case(line, TickerCase::Upper)
+}
+
+/// Как [`parse_command`], но с заданной нормализацией регистра тикеров
+/// (сервер с `--preserve-case` не должен поднимать регистр запроса)
+pub fn parse_command_with_case(line: &str, case: TickerCase) -> Result<Command, ProtocolError> {
     let line = line.trim();
     if line.is_empty() {
         return Err(ProtocolError::EmptyCommand);
@@ -25,48 +475,223 @@ pub fn parse_command(line: &str) -> Result<Command, ProtocolError> {
     let mut parts = line.split_whitespace();
     let cmd = parts.next().ok_or(ProtocolError::MissingCommand)?;
 
-    match cmd {
-        "STREAM" => {
+    match cmd.to_ascii_uppercase().as_str() {
+        VERB_STREAM => {
             let udp_uri = parts.next().ok_or(ProtocolError::MissingUdpTarget)?;
 
-            // забираем ВСЁ остальное как строку тикеров (включая пробелы)
-            let tickers_raw = parts.collect::<Vec<_>>().join(" ");
+            // опции вида KEY=VALUE отделяем, ВСЁ остальное - строка тикеров (включая пробелы);
+            // неизвестный KEY - ошибка, а не тикер `FOO=bar`
+            let mut max_rate_ms = None;
+            let mut burst_ms = None;
+            let mut wire = None;
+            let mut encoding = None;
+            let mut client_id = None;
+            let mut want_session_id = false;
+            let mut want_ack = false;
+            let mut ticker_parts = Vec::new();
+            for part in parts {
+                match split_option(part) {
+                    Some((key, value)) if key == "RATE" => {
+                        let rate = value
+                            .parse::<u32>()
+                            .map_err(|_| ProtocolError::InvalidRate(value.to_string()))?;
+                        max_rate_ms = Some(rate);
+                    }
+                    Some((key, value)) if key == "BURST" => {
+                        let burst = value
+                            .parse::<u32>()
+                            .map_err(|_| ProtocolError::InvalidBurst(value.to_string()))?;
+                        burst_ms = Some(burst);
+                    }
+                    Some((key, value)) if key == "WIRE" => {
+                        wire = Some(match value.to_ascii_uppercase().as_str() {
+                            "POSTCARD" => WireFormat::Postcard,
+                            "FIXED" => WireFormat::Fixed,
+                            "FIXED-SECS" => WireFormat::FixedSecs,
+                            _ => return Err(ProtocolError::InvalidWireFormat(value.to_string())),
+                        });
+                    }
+                    Some((key, value)) if key == "ENC" => {
+                        encoding = Some(value.parse::<Encoding>()?);
+                    }
+                    Some((key, value)) if key == "ID" => {
+                        if !is_valid_client_id(value) {
+                            return Err(ProtocolError::InvalidClientId(value.to_string()));
+                        }
+                        client_id = Some(value.to_string());
+                    }
+                    Some((key, value)) if key == "SID" => {
+                        want_session_id = match value {
+                            "1" => true,
+                            "0" => false,
+                            _ => return Err(ProtocolError::InvalidSid(value.to_string())),
+                        };
+                    }
+                    Some((key, value)) if key == "ACK" => {
+                        want_ack = match value {
+                            "1" => true,
+                            "0" => false,
+                            _ => return Err(ProtocolError::InvalidAck(value.to_string())),
+                        };
+                    }
+                    Some((key, _)) => return Err(ProtocolError::UnknownOption(key)),
+                    None => ticker_parts.push(part),
+                }
+            }
+
+            if wire.is_some() && encoding.is_some() {
+                return Err(ProtocolError::EncodingWithWire);
+            }
+            let wire = wire.unwrap_or_default();
+
+            let tickers_raw = ticker_parts.join(" ");
             if tickers_raw.trim().is_empty() {
                 return Err(ProtocolError::MissingTickers);
             }
 
-            let addr_str = udp_uri
-                .strip_prefix("udp://")
-                .ok_or(ProtocolError::BadUdpScheme)?;
+            let udp_target = parse_udp_target(udp_uri)?;
 
-            let udp_target: SocketAddr = addr_str
-                .parse()
-                .map_err(|_| ProtocolError::InvalidUdpAddress(addr_str.to_string()))?;
-
-            let tickers = parse_tickers_csv(&tickers_raw);
+            let mut tickers = parse_tickers_csv_with_case(&tickers_raw, case);
             if tickers.is_empty() {
                 return Err(ProtocolError::EmptyTickers);
             }
+            // `*` поглощает остальные тикеры; `ALL` вместо `*` - частая ошибка
+            if tickers.iter().any(|t| t == ALL_TICKERS) {
+                tickers = vec![ALL_TICKERS.to_string()];
+            } else if let [only] = tickers.as_slice()
+                && only.eq_ignore_ascii_case("ALL")
+            {
+                return Err(ProtocolError::NonCanonicalAllTickers(only.clone()));
+            }
+            let raw_tickers = RawTickers(
+                tickers_raw
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|t| !t.is_empty())
+                    .map(String::from)
+                    .collect(),
+            );
 
             Ok(Command::Stream {
                 udp_target,
                 tickers,
+                max_rate_ms,
+                burst_ms,
+                wire,
+                encoding,
+                client_id,
+                want_session_id,
+                want_ack,
+                raw_tickers,
             })
         }
-        other => Err(ProtocolError::UnknownCommand(other.to_string())),
+        VERB_STATUS => match parts.next() {
+            Some(_) => Err(ProtocolError::ExtraArgs),
+            None => Ok(Command::Status),
+        },
+        VERB_DEBUG => {
+            let topic = match parts.next() {
+                Some(t) if t.eq_ignore_ascii_case("pings") => DebugTopic::Pings,
+                other => {
+                    return Err(ProtocolError::UnknownDebugTopic(
+                        other.unwrap_or_default().to_string(),
+                    ));
+                }
+            };
+            match parts.next() {
+                Some(_) => Err(ProtocolError::ExtraArgs),
+                None => Ok(Command::Debug(topic)),
+            }
+        }
+        VERB_WHOAMI => {
+            let udp_uri = parts.next().ok_or(ProtocolError::MissingUdpTarget)?;
+            let udp_target = parse_udp_target(udp_uri)?;
+            match parts.next() {
+                Some(_) => Err(ProtocolError::ExtraArgs),
+                None => Ok(Command::WhoAmI { udp_target }),
+            }
+        }
+        _ => Err(ProtocolError::UnknownCommand(cmd.to_string())),
     }
 }
 
+fn is_valid_client_id(id: &str) -> bool {
+    (1..=64).contains(&id.len())
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// "rate=1000" -> Some(("RATE", "1000")); ключ нормализуется в uppercase
+fn split_option(part: &str) -> Option<(String, &str)> {
+    let (key, value) = part.split_once('=')?;
+    Some((key.to_ascii_uppercase(), value))
+}
+
 /// Формирует команду для стриминга котировок.
-pub fn format_stream_command(udp_target: SocketAddr, tickers: &[String]) -> String {
+///
+/// Строка всегда разбирается [`parse_command`] обратно в те же тикеры: тикер,
+/// который испортил бы её (пустой, с пробелом, `,`, `#` или `=`), - ошибка
+/// [`ProtocolError::UnformattableTicker`]; пустой список - [`ProtocolError::EmptyTickers`];
+/// список из одного `ALL` (его сервер отклоняет как опечатку вместо `*`) -
+/// [`ProtocolError::NonCanonicalAllTickers`].
+///
+/// ```rust
+/// use quote_core::protocol::format_stream_command;
+///
+/// let target = "127.0.0.1:1".parse().unwrap();
+/// assert!(format_stream_command(target, &["AAPL".into()]).is_ok());
+/// assert!(format_stream_command(target, &["BRK A".into()]).is_err());
+/// ```
+pub fn format_stream_command(
+    udp_target: SocketAddr,
+    tickers: &[String],
+) -> Result<String, ProtocolError> {
+    if tickers.is_empty() {
+        return Err(ProtocolError::EmptyTickers);
+    }
+    if let Some(bad) = tickers.iter().find(|t| !is_formattable_ticker(t)) {
+        return Err(ProtocolError::UnformattableTicker(bad.clone()));
+    }
+    // `ALL,all` после нормализации регистра - тоже один `ALL`
+    if tickers.iter().all(|t| t.eq_ignore_ascii_case("ALL")) {
+        return Err(ProtocolError::NonCanonicalAllTickers(tickers[0].clone()));
+    }
+
     let list = tickers.join(",");
-    format!("STREAM udp://{} {}", udp_target, list)
+    Ok(format!("{VERB_STREAM} {UDP_SCHEME}{udp_target} {list}"))
 }
 
 /// Формирует команду + конец строки для стриминга котировок.
 /// Используется клиентом.
-pub fn format_stream_command_line(udp_target: SocketAddr, tickers: &[String]) -> String {
-    format!("{}\n", format_stream_command(udp_target, tickers))
+pub fn format_stream_command_line(
+    udp_target: SocketAddr,
+    tickers: &[String],
+) -> Result<String, ProtocolError> {
+    Ok(format!("{}\n", format_stream_command(udp_target, tickers)?))
+}
+
+/// `udp://IP:PORT` -> адрес
+fn parse_udp_target(uri: &str) -> Result<SocketAddr, ProtocolError> {
+    let addr_str = uri
+        .strip_prefix(UDP_SCHEME)
+        .ok_or(ProtocolError::BadUdpScheme)?;
+    addr_str
+        .parse()
+        .map_err(|_| ProtocolError::InvalidUdpAddress(addr_str.to_string()))
+}
+
+/// Тикер переживает format -> parse без изменений (кроме регистра)
+fn is_formattable_ticker(t: &str) -> bool {
+    !t.is_empty()
+        && !t
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, ',' | '#' | '='))
+}
+
+/// Команда проверки доступности сервера + конец строки
+pub fn format_status_command_line() -> String {
+    format!("{VERB_STATUS}\n")
 }
 
 #[cfg(test)]
@@ -82,10 +707,206 @@ mod tests {
             Command::Stream {
                 udp_target: "127.0.0.1:34254".parse().unwrap(),
                 tickers: vec!["AAPL".to_string(), "TSLA".to_string()],
+                max_rate_ms: None,
+                burst_ms: None,
+                wire: WireFormat::Postcard,
+                encoding: None,
+                client_id: None,
+                want_session_id: false,
+                want_ack: false,
+                raw_tickers: RawTickers::default(),
             }
         );
     }
 
+    #[test]
+    fn parse_stream_star_is_the_all_tickers_sentinel() {
+        let cmd = parse_command("STREAM udp://127.0.0.1:1 *").unwrap();
+        assert!(cmd.is_all_tickers());
+
+        // `*` поглощает перечисленные рядом тикеры
+        let cmd = parse_command("STREAM udp://127.0.0.1:1 AAPL,*").unwrap();
+        assert!(cmd.is_all_tickers());
+
+        assert!(
+            !parse_command("STREAM udp://127.0.0.1:1 AAPL")
+                .unwrap()
+                .is_all_tickers()
+        );
+        assert!(!Command::Status.is_all_tickers());
+    }
+
+    #[test]
+    fn parse_stream_rejects_all_with_hint() {
+        let err = parse_command("STREAM udp://127.0.0.1:1 all").unwrap_err();
+        assert!(matches!(err, ProtocolError::NonCanonicalAllTickers(ref s) if s == "ALL"));
+        assert!(err.to_string().contains("use `*`"), "{err}");
+
+        // тикер ALL в списке с другими - обычный тикер
+        let cmd = parse_command("STREAM udp://127.0.0.1:1 ALL,AAPL").unwrap();
+        assert!(matches!(cmd, Command::Stream { ref tickers, .. } if tickers == &["AAPL", "ALL"]));
+    }
+
+    #[test]
+    fn parse_stream_sid_option() {
+        let cmd = parse_command("STREAM udp://127.0.0.1:1 AAPL sid=1").unwrap();
+        assert!(matches!(
+            cmd,
+            Command::Stream {
+                want_session_id: true,
+                want_ack: false,
+                ..
+            }
+        ));
+
+        let err = parse_command("STREAM udp://127.0.0.1:1 AAPL SID=yes").unwrap_err();
+        assert!(matches!(err, ProtocolError::InvalidSid(s) if s == "yes"));
+    }
+
+    #[test]
+    fn parse_stream_ack_option() {
+        let cmd = parse_command("STREAM udp://127.0.0.1:1 AAPL ack=1").unwrap();
+        assert!(matches!(cmd, Command::Stream { want_ack: true, .. }));
+
+        let cmd = parse_command("STREAM udp://127.0.0.1:1 AAPL ACK=0").unwrap();
+        assert!(matches!(
+            cmd,
+            Command::Stream {
+                want_ack: false,
+                ..
+            }
+        ));
+
+        let err = parse_command("STREAM udp://127.0.0.1:1 AAPL ACK=on").unwrap_err();
+        assert!(matches!(err, ProtocolError::InvalidAck(s) if s == "on"));
+    }
+
+    #[test]
+    fn parse_stream_enc_option_for_each_encoding() {
+        for (value, want) in [
+            ("v1", Encoding::V1),
+            ("fixed", Encoding::Fixed),
+            ("batch", Encoding::Batch),
+            ("TEXT", Encoding::Text),
+        ] {
+            let cmd = parse_command(&format!("STREAM udp://127.0.0.1:1 AAPL ENC={value}")).unwrap();
+            assert!(
+                matches!(cmd, Command::Stream { encoding: Some(e), wire: WireFormat::Postcard, .. } if e == want),
+                "ENC={value}: {cmd:?}"
+            );
+        }
+
+        let cmd = parse_command("STREAM udp://127.0.0.1:1 AAPL").unwrap();
+        assert!(matches!(cmd, Command::Stream { encoding: None, .. }));
+    }
+
+    #[test]
+    fn parse_stream_enc_rejects_unknown_value_and_wire_mix() {
+        let err = parse_command("STREAM udp://127.0.0.1:1 AAPL ENC=zstd").unwrap_err();
+        assert!(matches!(err, ProtocolError::InvalidEncoding(ref s) if s == "zstd"));
+        assert_eq!(
+            err.to_string(),
+            "invalid ENC value: zstd (expected v1, fixed, batch or text)"
+        );
+
+        let err = parse_command("STREAM udp://127.0.0.1:1 AAPL ENC=fixed WIRE=fixed").unwrap_err();
+        assert!(matches!(err, ProtocolError::EncodingWithWire));
+    }
+
+    #[test]
+    fn encoding_negotiation_picks_requested_only_if_supported() {
+        let supported = [Encoding::V1, Encoding::Fixed];
+
+        assert_eq!(Encoding::Fixed.negotiate(&supported), Some(Encoding::Fixed));
+        assert_eq!(Encoding::Batch.negotiate(&supported), None);
+        assert_eq!(Encoding::Fixed.wire_format(), Some(WireFormat::Fixed));
+        assert_eq!(Encoding::Text.wire_format(), None);
+    }
+
+    #[test]
+    fn ok_reply_roundtrips_token_and_session_id() {
+        for reply in [
+            OkReply::default(),
+            OkReply::new().with_token(Some(7)),
+            OkReply::new().with_session_id(Some(42)),
+            OkReply::new().with_token(Some(7)).with_session_id(Some(42)),
+        ] {
+            assert_eq!(parse_ok_reply(&format_ok_reply(&reply)), Some(reply));
+        }
+
+        assert_eq!(
+            format_ok_reply(&OkReply::new().with_token(Some(7)).with_session_id(Some(42))),
+            "OK 7 id=42"
+        );
+        // неизвестный клиенту ключ не мешает разбору
+        let reply = parse_ok_reply("OK id=3 future=x\r\n").unwrap();
+        assert_eq!(reply.session_id, Some(3));
+        assert_eq!(reply.field("future"), Some("x"));
+        assert_eq!(parse_ok_reply("OK abc"), None);
+        assert_eq!(parse_ok_reply("OK id=x"), None);
+        assert_eq!(parse_ok_reply("OKAY"), None);
+    }
+
+    #[test]
+    fn ok_reply_builder_roundtrips_several_fields() {
+        let reply = OkReply::new()
+            .with_token(Some(u64::MAX))
+            .with_session_id(Some(5))
+            .with_field(SESSIONS_KEY, 12)
+            .with_field("unknown", "GOOG,ZZZZ")
+            .with_field("retry_after_ms", 250);
+
+        let line = reply.to_line();
+        assert_eq!(
+            line,
+            format!(
+                "OK {} id=5 sessions=12 unknown=GOOG,ZZZZ retry_after_ms=250\n",
+                u64::MAX
+            )
+        );
+
+        let parsed = parse_ok_reply(&line).unwrap();
+        assert_eq!(parsed, reply);
+        assert_eq!(parsed.field(SESSIONS_KEY), Some("12"));
+        assert_eq!(parsed.field("missing"), None);
+
+        // токен - только первым словом
+        assert_eq!(parse_ok_reply("OK sessions=1 7"), None);
+    }
+
+    #[test]
+    fn parse_stream_keeps_raw_tickers_in_request_order() {
+        let cmd = parse_command("STREAM udp://127.0.0.1:1 tsla, goog ,AAPL,tsla").unwrap();
+
+        let Command::Stream {
+            tickers,
+            raw_tickers,
+            ..
+        } = &cmd
+        else {
+            panic!("expected STREAM, got {cmd:?}");
+        };
+        assert_eq!(tickers, &["AAPL", "GOOG", "TSLA"]);
+        assert_eq!(raw_tickers.0, ["tsla", "goog", "AAPL", "tsla"]);
+
+        // сырые тикеры не влияют на равенство команд, но сами по себе сравниваются
+        let canonical = parse_command("STREAM udp://127.0.0.1:1 AAPL,GOOG,TSLA").unwrap();
+        assert_eq!(cmd, canonical);
+        let Command::Stream {
+            raw_tickers: canonical_raw,
+            ..
+        } = &canonical
+        else {
+            unreachable!()
+        };
+        assert_ne!(raw_tickers, canonical_raw);
+        // остальные поля - сравниваются
+        assert_ne!(
+            cmd,
+            parse_command("STREAM udp://127.0.0.1:1 AAPL,GOOG,TSLA SID=1").unwrap()
+        );
+    }
+
     #[test]
     fn parse_stream_trims_and_uppercases_and_filters_empty() {
         let cmd = parse_command("  STREAM   udp://127.0.0.1:1   aapl,  tsla , ,goog  ").unwrap();
@@ -95,6 +916,14 @@ mod tests {
             Command::Stream {
                 udp_target: "127.0.0.1:1".parse().unwrap(),
                 tickers: vec!["AAPL".to_string(), "GOOG".to_string(), "TSLA".to_string()],
+                max_rate_ms: None,
+                burst_ms: None,
+                wire: WireFormat::Postcard,
+                encoding: None,
+                client_id: None,
+                want_session_id: false,
+                want_ack: false,
+                raw_tickers: RawTickers::default(),
             }
         );
     }
@@ -145,22 +974,490 @@ mod tests {
         assert!(matches!(err, ProtocolError::UnknownCommand(s) if s == "PING"));
     }
 
+    #[test]
+    fn parse_verb_is_case_insensitive() {
+        let expected = parse_command("STREAM udp://127.0.0.1:1 aapl").unwrap();
+
+        for line in [
+            "stream udp://127.0.0.1:1 aapl",
+            "Stream udp://127.0.0.1:1 aapl",
+        ] {
+            assert_eq!(parse_command(line).unwrap(), expected, "line: {line}");
+        }
+    }
+
+    #[test]
+    fn parse_unknown_lowercase_verb_is_error() {
+        let err = parse_command("subscribe udp://127.0.0.1:1 AAPL").unwrap_err();
+        assert!(matches!(err, ProtocolError::UnknownCommand(s) if s == "subscribe"));
+    }
+
+    #[test]
+    fn same_subscription_ignores_ticker_order_and_duplicates() {
+        let addr: SocketAddr = "127.0.0.1:1".parse().unwrap();
+        let a = Command::Stream {
+            udp_target: addr,
+            tickers: vec!["TSLA".to_string(), "AAPL".to_string()],
+            max_rate_ms: None,
+            burst_ms: None,
+            wire: WireFormat::Postcard,
+            encoding: None,
+            client_id: None,
+            want_session_id: false,
+            want_ack: false,
+            raw_tickers: RawTickers::default(),
+        };
+        let b = Command::Stream {
+            udp_target: addr,
+            tickers: vec!["AAPL".to_string(), "TSLA".to_string(), "AAPL".to_string()],
+            max_rate_ms: None,
+            burst_ms: None,
+            wire: WireFormat::Postcard,
+            encoding: None,
+            client_id: None,
+            want_session_id: false,
+            want_ack: false,
+            raw_tickers: RawTickers::default(),
+        };
+
+        assert_ne!(a, b);
+        assert!(a.same_subscription(&b));
+        assert!(b.same_subscription(&a));
+    }
+
+    #[test]
+    fn same_subscription_differs_on_target_or_tickers() {
+        let a = parse_command("STREAM udp://127.0.0.1:1 AAPL,TSLA").unwrap();
+        let other_target = parse_command("STREAM udp://127.0.0.1:2 TSLA,AAPL").unwrap();
+        let other_tickers = parse_command("STREAM udp://127.0.0.1:1 AAPL").unwrap();
+
+        assert!(!a.same_subscription(&other_target));
+        assert!(!a.same_subscription(&other_tickers));
+    }
+
+    #[test]
+    fn same_subscription_respects_preserved_case() {
+        let parse = |line| parse_command_with_case(line, TickerCase::Preserve).unwrap();
+        let lower = parse("STREAM udp://127.0.0.1:1 aapl");
+        let upper = parse("STREAM udp://127.0.0.1:1 AAPL");
+
+        assert!(!lower.same_subscription(&upper));
+        assert!(lower.same_subscription(&parse("STREAM udp://127.0.0.1:1 aapl,aapl")));
+
+        // по умолчанию регистр нормализован при разборе
+        let lower = parse_command("STREAM udp://127.0.0.1:1 aapl").unwrap();
+        assert!(lower.same_subscription(&parse_command("STREAM udp://127.0.0.1:1 AAPL").unwrap()));
+    }
+
+    #[test]
+    fn parse_stream_with_rate_option() {
+        let cmd = parse_command("STREAM udp://127.0.0.1:1 AAPL,TSLA RATE=1000").unwrap();
+        assert_eq!(
+            cmd,
+            Command::Stream {
+                udp_target: "127.0.0.1:1".parse().unwrap(),
+                tickers: vec!["AAPL".to_string(), "TSLA".to_string()],
+                max_rate_ms: Some(1000),
+                burst_ms: None,
+                wire: WireFormat::Postcard,
+                encoding: None,
+                client_id: None,
+                want_session_id: false,
+                want_ack: false,
+                raw_tickers: RawTickers::default(),
+            }
+        );
+
+        // ключ регистронезависим, тикеры с пробелами по-прежнему работают
+        let cmd = parse_command("STREAM udp://127.0.0.1:1 AAPL, TSLA rate=250").unwrap();
+        assert!(matches!(
+            cmd,
+            Command::Stream { max_rate_ms: Some(250), ref tickers, .. } if tickers.len() == 2
+        ));
+    }
+
+    #[test]
+    fn parse_stream_invalid_rate_is_error() {
+        let err = parse_command("STREAM udp://127.0.0.1:1 AAPL RATE=fast").unwrap_err();
+        assert!(matches!(err, ProtocolError::InvalidRate(s) if s == "fast"));
+    }
+
+    #[test]
+    fn binary_roundtrip_stream_and_status() {
+        let cmd = parse_command(
+            "STREAM udp://[::1]:6001 AAPL,BRK.B RATE=250 BURST=1000 WIRE=fixed-secs ID=bot-1",
+        )
+        .unwrap();
+        assert_eq!(Command::decode(&cmd.encode()).unwrap(), cmd);
+
+        assert_eq!(
+            Command::decode(&Command::Status.encode()).unwrap(),
+            Command::Status
+        );
+    }
+
+    #[test]
+    fn binary_decode_rejects_garbage() {
+        let err = Command::decode(&[0xFF, 0x13, 0x37]).unwrap_err();
+        assert!(matches!(err, ProtocolError::MalformedBinary(_)));
+
+        let mut buf = Command::Status.encode();
+        buf.push(0);
+        assert!(matches!(
+            Command::decode(&buf),
+            Err(ProtocolError::MalformedBinary(_))
+        ));
+
+        // валидная postcard-структура, но без тикеров
+        let empty = Command::Stream {
+            udp_target: "127.0.0.1:1".parse().unwrap(),
+            tickers: Vec::new(),
+            max_rate_ms: None,
+            burst_ms: None,
+            wire: WireFormat::Postcard,
+            encoding: None,
+            client_id: None,
+            want_session_id: false,
+            want_ack: false,
+            raw_tickers: RawTickers::default(),
+        };
+        assert!(matches!(
+            Command::decode(&empty.encode()),
+            Err(ProtocolError::EmptyTickers)
+        ));
+    }
+
+    #[test]
+    fn parse_stream_rejects_unknown_option() {
+        let err = parse_command("STREAM udp://127.0.0.1:1 AAPL foo=bar").unwrap_err();
+        assert!(matches!(err, ProtocolError::UnknownOption(ref k) if k == "FOO"));
+
+        // известная опция и тикер без `=` разбираются как раньше
+        let cmd = parse_command("STREAM udp://127.0.0.1:1 AAPL,BRK.B WIRE=fixed").unwrap();
+        assert!(matches!(
+            cmd,
+            Command::Stream { wire: WireFormat::Fixed, ref tickers, .. }
+                if tickers == &["AAPL".to_string(), "BRK.B".to_string()]
+        ));
+    }
+
+    #[test]
+    fn parse_command_with_preserved_case_keeps_tickers_as_is() {
+        let cmd =
+            parse_command_with_case("STREAM udp://127.0.0.1:1 btcUSD,AAPL", TickerCase::Preserve)
+                .unwrap();
+        assert!(matches!(
+            cmd,
+            Command::Stream { ref tickers, .. } if *tickers == ["AAPL", "btcUSD"]
+        ));
+    }
+
+    #[test]
+    fn parse_status_command() {
+        assert_eq!(parse_command("status").unwrap(), Command::Status);
+        assert!(matches!(
+            parse_command("STATUS now"),
+            Err(ProtocolError::ExtraArgs)
+        ));
+        assert_eq!(
+            parse_command(format_status_command_line().as_str()).unwrap(),
+            Command::Status
+        );
+    }
+
+    #[test]
+    fn parse_debug_command() {
+        assert_eq!(
+            parse_command("DEBUG pings").unwrap(),
+            Command::Debug(DebugTopic::Pings)
+        );
+        assert_eq!(
+            parse_command("debug PINGS\r\n").unwrap(),
+            Command::Debug(DebugTopic::Pings)
+        );
+        assert!(matches!(
+            parse_command("DEBUG"),
+            Err(ProtocolError::UnknownDebugTopic(t)) if t.is_empty()
+        ));
+        assert!(matches!(
+            parse_command("DEBUG sessions"),
+            Err(ProtocolError::UnknownDebugTopic(t)) if t == "sessions"
+        ));
+        assert!(matches!(
+            parse_command("DEBUG pings now"),
+            Err(ProtocolError::ExtraArgs)
+        ));
+
+        let cmd = Command::Debug(DebugTopic::Pings);
+        assert_eq!(Command::decode(&cmd.encode()).unwrap(), cmd);
+    }
+
+    #[test]
+    fn parse_whoami_command() {
+        assert_eq!(
+            parse_command("whoami udp://127.0.0.1:34254").unwrap(),
+            Command::WhoAmI {
+                udp_target: "127.0.0.1:34254".parse().unwrap()
+            }
+        );
+        assert!(matches!(
+            parse_command("WHOAMI"),
+            Err(ProtocolError::MissingUdpTarget)
+        ));
+        assert!(matches!(
+            parse_command("WHOAMI 127.0.0.1:1"),
+            Err(ProtocolError::BadUdpScheme)
+        ));
+        assert!(matches!(
+            parse_command("WHOAMI udp://nowhere"),
+            Err(ProtocolError::InvalidUdpAddress(a)) if a == "nowhere"
+        ));
+        assert!(matches!(
+            parse_command("WHOAMI udp://127.0.0.1:1 AAPL"),
+            Err(ProtocolError::ExtraArgs)
+        ));
+
+        let cmd = parse_command("WHOAMI udp://[::1]:5").unwrap();
+        assert_eq!(Command::decode(&cmd.encode()).unwrap(), cmd);
+    }
+
+    #[test]
+    fn parse_stream_with_client_id() {
+        let cmd = parse_command("STREAM udp://127.0.0.1:1 AAPL ID=client-1_a").unwrap();
+        assert!(matches!(
+            cmd,
+            Command::Stream { client_id: Some(ref id), .. } if id == "client-1_a"
+        ));
+
+        for bad in ["ID=", "ID=a.b", "id=ä"] {
+            let err = parse_command(&format!("STREAM udp://127.0.0.1:1 AAPL {bad}")).unwrap_err();
+            assert!(matches!(err, ProtocolError::InvalidClientId(_)), "{bad}");
+        }
+    }
+
+    #[test]
+    fn parse_stream_with_burst_option() {
+        let cmd = parse_command("STREAM udp://127.0.0.1:1 AAPL RATE=1000 burst=500").unwrap();
+        assert!(matches!(
+            cmd,
+            Command::Stream {
+                max_rate_ms: Some(1000),
+                burst_ms: Some(500),
+                ..
+            }
+        ));
+
+        let err = parse_command("STREAM udp://127.0.0.1:1 AAPL BURST=-1").unwrap_err();
+        assert!(matches!(err, ProtocolError::InvalidBurst(s) if s == "-1"));
+    }
+
+    #[test]
+    fn parse_stream_with_wire_option() {
+        let cmd = parse_command("STREAM udp://127.0.0.1:1 AAPL wire=fixed RATE=5").unwrap();
+        assert!(matches!(
+            cmd,
+            Command::Stream {
+                wire: WireFormat::Fixed,
+                encoding: None,
+                max_rate_ms: Some(5),
+                ..
+            }
+        ));
+
+        let cmd = parse_command("STREAM udp://127.0.0.1:1 AAPL WIRE=Fixed-Secs").unwrap();
+        assert!(matches!(
+            cmd,
+            Command::Stream {
+                wire: WireFormat::FixedSecs,
+                encoding: None,
+                ..
+            }
+        ));
+
+        let err = parse_command("STREAM udp://127.0.0.1:1 AAPL WIRE=json").unwrap_err();
+        assert!(matches!(err, ProtocolError::InvalidWireFormat(s) if s == "json"));
+    }
+
+    #[test]
+    fn parse_stream_with_only_comment_tokens_is_error() {
+        let err = parse_command("STREAM udp://127.0.0.1:1 #onlycomment").unwrap_err();
+        assert!(matches!(err, ProtocolError::EmptyTickers));
+
+        let cmd = parse_command("STREAM udp://127.0.0.1:1 AAPL,#bar,TSLA").unwrap();
+        assert!(matches!(cmd, Command::Stream { tickers, .. } if tickers == ["AAPL", "TSLA"]));
+    }
+
+    #[test]
+    fn parse_stream_rate_without_tickers_is_error() {
+        let err = parse_command("STREAM udp://127.0.0.1:1 RATE=100").unwrap_err();
+        assert!(matches!(err, ProtocolError::MissingTickers));
+    }
+
     #[test]
     fn format_stream_command_formats_as_expected() {
         let addr: SocketAddr = "127.0.0.1:34254".parse().unwrap();
         let tickers = vec!["AAPL".to_string(), "TSLA".to_string()];
 
-        let s = format_stream_command(addr, &tickers);
+        let s = format_stream_command(addr, &tickers).unwrap();
         assert_eq!(s, "STREAM udp://127.0.0.1:34254 AAPL,TSLA");
     }
 
+    #[test]
+    fn format_stream_command_rejects_tickers_that_break_the_line() {
+        let addr: SocketAddr = "127.0.0.1:1".parse().unwrap();
+
+        for bad in ["", "BRK A", "AAPL,TSLA", "AAPL#1", "RATE=5", "X\nSTATUS"] {
+            let err =
+                format_stream_command(addr, &["MSFT".to_string(), bad.to_string()]).unwrap_err();
+            assert!(
+                matches!(err, ProtocolError::UnformattableTicker(ref t) if t == bad),
+                "{bad:?}: {err:?}"
+            );
+        }
+        assert!(matches!(
+            format_stream_command(addr, &[]).unwrap_err(),
+            ProtocolError::EmptyTickers
+        ));
+        for only_all in [&["ALL"][..], &["all"], &["ALL", "all"]] {
+            let tickers: Vec<String> = only_all.iter().map(|t| t.to_string()).collect();
+            assert!(
+                matches!(
+                    format_stream_command(addr, &tickers).unwrap_err(),
+                    ProtocolError::NonCanonicalAllTickers(_)
+                ),
+                "{only_all:?}"
+            );
+        }
+        // ALL рядом с другими тикерами - обычный тикер
+        assert!(format_stream_command(addr, &["ALL".to_string(), "AAPL".to_string()]).is_ok());
+    }
+
+    #[test]
+    fn format_then_parse_keeps_ticker_set() {
+        // детерминированный перебор вместо proptest: xorshift по алфавиту
+        // символов, допустимых в нормализованных тикерах
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789.-_/:*@^";
+        let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let addr: SocketAddr = "127.0.0.1:9".parse().unwrap();
+
+        for _ in 0..500 {
+            let count = 1 + (next() % 8) as usize;
+            let tickers: Vec<String> = (0..count)
+                .map(|_| {
+                    // иногда - `ALL`: отдельно от других parse его отклоняет
+                    if next() % 8 == 0 {
+                        return "ALL".to_string();
+                    }
+                    let len = 1 + (next() % 10) as usize;
+                    (0..len)
+                        .map(|_| ALPHABET[(next() % ALPHABET.len() as u64) as usize] as char)
+                        .collect()
+                })
+                .collect();
+
+            let line = match format_stream_command(addr, &tickers) {
+                Ok(line) => line,
+                Err(e) => {
+                    assert!(tickers.iter().all(|t| t == "ALL"), "{tickers:?}: {e}");
+                    assert!(matches!(e, ProtocolError::NonCanonicalAllTickers(_)));
+                    continue;
+                }
+            };
+            let Command::Stream {
+                udp_target,
+                tickers: parsed,
+                ..
+            } = parse_command(&line).unwrap()
+            else {
+                panic!("expected STREAM from {line:?}");
+            };
+
+            let want: BTreeSet<String> = tickers.iter().cloned().collect();
+            let got: BTreeSet<String> = parsed.into_iter().collect();
+            assert_eq!(udp_target, addr);
+            // `*` поглощает остальные тикеры
+            if want.contains(ALL_TICKERS) {
+                assert_eq!(got, BTreeSet::from([ALL_TICKERS.to_string()]), "{line}");
+            } else {
+                assert_eq!(got, want, "{line}");
+            }
+        }
+    }
+
+    #[test]
+    fn err_reply_with_retry_after_roundtrips() {
+        let line = format_err_reply(
+            "E_SERVER_FULL",
+            Some(Duration::from_millis(5000)),
+            "server is at capacity",
+        );
+        assert_eq!(
+            line,
+            "ERR E_SERVER_FULL retry_after_ms=5000 server is at capacity"
+        );
+
+        assert_eq!(
+            parse_err_reply(&line).unwrap(),
+            ErrReply {
+                code: "E_SERVER_FULL".to_string(),
+                retry_after: Some(Duration::from_millis(5000)),
+                message: "server is at capacity".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn err_reply_without_retry_after_keeps_message() {
+        let reply = parse_err_reply("ERR E_UNKNOWN_GROUP unknown group: CRYPTO\n").unwrap();
+        assert_eq!(reply.code, "E_UNKNOWN_GROUP");
+        assert_eq!(reply.retry_after, None);
+        assert_eq!(reply.message, "unknown group: CRYPTO");
+
+        let reply = parse_err_reply("ERR E_NO_TICKERS no known tickers match request").unwrap();
+        assert_eq!(reply.code, "E_NO_TICKERS");
+        assert_eq!(reply.message, "no known tickers match request");
+
+        let reply = parse_err_reply("ERR E_BAD_TARGET").unwrap();
+        assert_eq!(reply.code, "E_BAD_TARGET");
+        assert!(reply.message.is_empty());
+
+        let reply = parse_err_reply("ERR E_X retry_after_ms=soon later").unwrap();
+        assert_eq!(reply.retry_after, None);
+        assert_eq!(reply.message, "retry_after_ms=soon later");
+
+        assert_eq!(parse_err_reply("OK 1"), None);
+        assert_eq!(parse_err_reply("ERROR x"), None);
+    }
+
+    #[test]
+    fn constants_match_parser_expectations() {
+        assert_eq!(VERB_STREAM, "STREAM");
+        assert_eq!(UDP_SCHEME, "udp://");
+        assert_eq!(REPLY_OK, "OK");
+        assert_eq!(REPLY_ERR_PREFIX, "ERR");
+
+        let target: SocketAddr = "127.0.0.1:1".parse().unwrap();
+        let line = format_stream_command(target, &["AAPL".to_string()]).unwrap();
+        assert!(line.starts_with(&format!("{VERB_STREAM} {UDP_SCHEME}")));
+        assert!(matches!(parse_command(&line), Ok(Command::Stream { .. })));
+
+        assert_eq!(format_status_command_line(), format!("{VERB_STATUS}\n"));
+        assert_eq!(parse_command(VERB_STATUS).unwrap(), Command::Status);
+    }
+
     #[test]
     fn roundtrip_parse_format_parse() {
         let addr: SocketAddr = "127.0.0.1:9".parse().unwrap();
         let tickers = vec!["aapl".to_string(), "TsLa".to_string()];
 
         // format не нормализует тикеры, но parse нормализует при разборе
-        let s = format_stream_command(addr, &tickers);
+        let s = format_stream_command(addr, &tickers).unwrap();
         let cmd = parse_command(&s).unwrap();
 
         assert_eq!(
@@ -168,6 +1465,14 @@ mod tests {
             Command::Stream {
                 udp_target: addr,
                 tickers: vec!["AAPL".to_string(), "TSLA".to_string()],
+                max_rate_ms: None,
+                burst_ms: None,
+                wire: WireFormat::Postcard,
+                encoding: None,
+                client_id: None,
+                want_session_id: false,
+                want_ack: false,
+                raw_tickers: RawTickers::default(),
             }
         );
     }
@@ -1,16 +1,85 @@
-use std::collections::BTreeSet;
+use crate::error::{InvalidTicker, InvalidTickerReason};
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader};
 use std::path::Path;
 
+/// Статистика разбора файла тикеров
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseReport {
+    /// всего строк
+    pub total_lines: usize,
+    /// строк, целиком состоящих из комментария
+    pub comments: usize,
+    /// повторов уже встреченного тикера
+    pub duplicates: usize,
+    /// тикеры с пробелами или не-ASCII символами (отброшены), в порядке файла
+    pub invalid: Vec<String>,
+}
+
+impl fmt::Display for ParseReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "lines={} comments={} duplicates={} invalid={}",
+            self.total_lines,
+            self.comments,
+            self.duplicates,
+            self.invalid.len()
+        )
+    }
+}
+
+/// Максимальная длина тикера в [`canonicalize`] (байт)
+pub const MAX_TICKER_LEN: usize = 16;
+
+/// Максимальная длина строки файла тикеров по умолчанию (байт, без `\n`)
+pub const DEFAULT_MAX_LINE_LEN: usize = 4096;
+
+/// Нормализация регистра тикеров
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TickerCase {
+    /// ASCII uppercase: `aapl` и `AAPL` - один тикер (по умолчанию)
+    #[default]
+    Upper,
+    /// Регистр сохраняется (площадки с регистрозависимыми символами)
+    Preserve,
+}
+
+impl TickerCase {
+    fn apply(self, s: &str) -> String {
+        match self {
+            TickerCase::Upper => s.to_ascii_uppercase(),
+            TickerCase::Preserve => s.to_string(),
+        }
+    }
+}
+
 /// Чтение тикеров
 pub fn read_tickers<R: io::Read>(reader: R) -> io::Result<Vec<String>> {
+    read_tickers_with_case(reader, TickerCase::Upper)
+}
+
+/// Чтение тикеров с заданной нормализацией регистра
+pub fn read_tickers_with_case<R: io::Read>(reader: R, case: TickerCase) -> io::Result<Vec<String>> {
+    read_tickers_with_max_line(reader, case, DEFAULT_MAX_LINE_LEN)
+}
+
+/// Как [`read_tickers_with_case`], но с явным лимитом длины строки.
+///
+/// Строка длиннее `max_line_len` байт (например, файл без переводов строк)
+/// не буферизуется целиком: чтение прерывается с `io::ErrorKind::InvalidData`.
+pub fn read_tickers_with_max_line<R: io::Read>(
+    reader: R,
+    case: TickerCase,
+    max_line_len: usize,
+) -> io::Result<Vec<String>> {
     let mut set = BTreeSet::new();
-    let buf = BufReader::new(reader);
 
-    for line in buf.lines() {
+    for line in LimitedLines::new(reader, max_line_len) {
         let line = line?;
-        if let Some(t) = normalize_line(&line) {
+        if let Some(t) = normalize_line_with(&line, case) {
             set.insert(t);
         }
     }
@@ -18,13 +87,163 @@ pub fn read_tickers<R: io::Read>(reader: R) -> io::Result<Vec<String>> {
     Ok(set.into_iter().collect())
 }
 
+/// Потоковое чтение тикеров: `on_ticker` вызывается один раз на каждый
+/// уникальный нормализованный тикер в порядке первого появления.
+///
+/// В отличие от [`read_tickers`] не собирает результат в `Vec` и не сортирует;
+/// в памяти держится только множество уже встреченных тикеров для дедупликации.
+/// Возвращает число уникальных тикеров.
+pub fn read_tickers_streaming<R, F>(reader: R, mut on_ticker: F) -> io::Result<usize>
+where
+    R: io::Read,
+    F: FnMut(String),
+{
+    let mut seen = HashSet::new();
+
+    for line in LimitedLines::new(reader, DEFAULT_MAX_LINE_LEN) {
+        let line = line?;
+        if let Some(t) = normalize_line(&line)
+            && seen.insert(t.clone())
+        {
+            on_ticker(t);
+        }
+    }
+
+    Ok(seen.len())
+}
+
+/// Чтение тикеров, как [`read_tickers`], но с отчётом о разборе.
+///
+/// В отличие от [`read_tickers`] отбрасывает невалидные тикеры
+/// (содержащие пробелы или не-ASCII символы) и перечисляет их в `invalid`,
+/// чтобы вызывающий мог сообщить о каждом.
+pub fn read_tickers_with_report<R: io::Read>(
+    reader: R,
+    case: TickerCase,
+) -> io::Result<(Vec<String>, ParseReport)> {
+    let mut set = BTreeSet::new();
+    let mut report = ParseReport::default();
+
+    for line in LimitedLines::new(reader, DEFAULT_MAX_LINE_LEN) {
+        let line = line?;
+        report.total_lines += 1;
+
+        if line.trim_start().starts_with('#') {
+            report.comments += 1;
+            continue;
+        }
+        let Some(t) = normalize_line_with(&line, case) else {
+            continue;
+        };
+
+        if !t.chars().all(|c| c.is_ascii_graphic()) {
+            report.invalid.push(t);
+        } else if !set.insert(t) {
+            report.duplicates += 1;
+        }
+    }
+
+    Ok((set.into_iter().collect(), report))
+}
+
 /// Чтение тикеров из файла
 pub fn read_tickers_from_path(path: impl AsRef<Path>) -> io::Result<Vec<String>> {
     let f = File::open(path)?;
     read_tickers(f)
 }
 
+/// Чтение тикеров с автоопределением формата:
+/// - JSON-массив строк: `["AAPL", "tsla"]`
+/// - иначе построчный формат, как в [`read_tickers`]
+///
+/// Нормализация одинакова для обоих форматов (trim, uppercase, сортировка + уникальность).
+pub fn read_tickers_auto<R: io::Read>(reader: R) -> io::Result<Vec<String>> {
+    read_tickers_auto_with_case(reader, TickerCase::Upper)
+}
+
+/// Как [`read_tickers_auto`], но с заданной нормализацией регистра (для обоих форматов)
+pub fn read_tickers_auto_with_case<R: io::Read>(
+    mut reader: R,
+    case: TickerCase,
+) -> io::Result<Vec<String>> {
+    let mut raw = String::new();
+    reader.read_to_string(&mut raw)?;
+
+    if !raw.trim_start().starts_with('[') {
+        return read_tickers_with_case(raw.as_bytes(), case);
+    }
+
+    let mut set = BTreeSet::new();
+    let items = crate::json::parse_string_array(&raw)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("json tickers: {e}")))?;
+    for item in items {
+        let t = item.trim();
+        if !t.is_empty() {
+            set.insert(case.apply(t));
+        }
+    }
+
+    Ok(set.into_iter().collect())
+}
+
+/// Строки без `\n`/`\r\n`, как `BufRead::lines`, но не длиннее `max_len` байт:
+/// из источника читается не больше `max_len + 2` байт на строку
+struct LimitedLines<R> {
+    reader: BufReader<R>,
+    max_len: usize,
+    buf: Vec<u8>,
+}
+
+impl<R: io::Read> LimitedLines<R> {
+    fn new(reader: R, max_len: usize) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            max_len,
+            buf: Vec::new(),
+        }
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        self.buf.clear();
+        // +2 - место под "\r\n"
+        let limit = self.max_len as u64 + 2;
+        let mut line = io::Read::take(&mut self.reader, limit);
+        if line.read_until(b'\n', &mut self.buf)? == 0 {
+            return Ok(None);
+        }
+
+        if self.buf.last() == Some(&b'\n') {
+            self.buf.pop();
+            if self.buf.last() == Some(&b'\r') {
+                self.buf.pop();
+            }
+        }
+        if self.buf.len() > self.max_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("tickers line exceeds {} bytes", self.max_len),
+            ));
+        }
+
+        String::from_utf8(std::mem::take(&mut self.buf))
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+impl<R: io::Read> Iterator for LimitedLines<R> {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_line().transpose()
+    }
+}
+
 fn normalize_line(line: &str) -> Option<String> {
+    normalize_line_with(line, TickerCase::Upper)
+}
+
+fn normalize_line_with(line: &str, case: TickerCase) -> Option<String> {
     let s = line.trim();
     if s.is_empty() || s.starts_with('#') {
         return None;
@@ -36,7 +255,7 @@ fn normalize_line(line: &str) -> Option<String> {
         return None;
     }
 
-    Some(s.to_ascii_uppercase())
+    Some(case.apply(s))
 }
 
 /// Парсит список тикеров из строки вида "AAPL, TSLA, ,GOOG".
@@ -44,22 +263,149 @@ fn normalize_line(line: &str) -> Option<String> {
 /// - разделитель: запятая
 /// - trim пробелов
 /// - пустые элементы игнорируются
+/// - `#` начинает комментарий до конца элемента, как в файле тикеров:
+///   `#foo` отбрасывается, `AAPL #x` -> `AAPL`
 /// - нормализация: ASCII uppercase
 /// - результат: отсортирован + уникален (BTreeSet)
 pub fn parse_tickers_csv(raw: &str) -> Vec<String> {
+    parse_tickers_csv_with_case(raw, TickerCase::Upper)
+}
+
+/// Как [`parse_tickers_csv`], но с заданной нормализацией регистра
+pub fn parse_tickers_csv_with_case(raw: &str, case: TickerCase) -> Vec<String> {
     let mut set = BTreeSet::new();
 
     for part in raw.split(',') {
-        let t = part.trim();
+        let t = part.split('#').next().unwrap_or("").trim();
         if t.is_empty() {
             continue;
         }
-        set.insert(t.to_ascii_uppercase());
+        set.insert(case.apply(t));
     }
 
     set.into_iter().collect()
 }
 
+/// Проверка и нормализация списка тикеров за один проход.
+///
+/// Ok - отсортированный уникальный список (trim + ASCII uppercase).
+/// Err - все отклонённые элементы с причинами, в порядке входа; в отличие от
+/// `read_tickers*`/`parse_tickers_csv` плохие элементы не отбрасываются молча.
+///
+/// ```rust
+/// use quote_core::tickers::canonicalize;
+///
+/// assert_eq!(canonicalize(&["tsla", " aapl ", "TSLA"]).unwrap(), vec!["AAPL", "TSLA"]);
+/// assert_eq!(canonicalize(&["AAPL", "", "BRK A"]).unwrap_err().len(), 2);
+/// ```
+pub fn canonicalize(raw: &[&str]) -> Result<Vec<String>, Vec<InvalidTicker>> {
+    let mut set = BTreeSet::new();
+    let mut rejects = Vec::new();
+
+    for &item in raw {
+        match check_ticker(item.trim()) {
+            Ok(t) => {
+                set.insert(t.to_ascii_uppercase());
+            }
+            Err(reason) => rejects.push(InvalidTicker {
+                raw: item.to_string(),
+                reason,
+            }),
+        }
+    }
+
+    if rejects.is_empty() {
+        Ok(set.into_iter().collect())
+    } else {
+        Err(rejects)
+    }
+}
+
+/// Строгая проверка CSV-списка (`--tickers` с `--strict-tickers`): элементы
+/// выделяются как в [`parse_tickers_csv`] (пустые и комментарии пропускаются),
+/// каждый проверяется как в [`canonicalize`]. Err - все отклонённые элементы.
+///
+/// ```rust
+/// use quote_core::tickers::check_tickers_csv;
+///
+/// assert!(check_tickers_csv("AAPL, TSLA #main").is_ok());
+/// let rejects = check_tickers_csv("AAPL,BRK A").unwrap_err();
+/// assert_eq!(rejects[0].raw, "BRK A");
+/// ```
+pub fn check_tickers_csv(raw: &str) -> Result<(), Vec<InvalidTicker>> {
+    let items: Vec<&str> = raw
+        .split(',')
+        .map(|part| part.split('#').next().unwrap_or("").trim())
+        .filter(|t| !t.is_empty())
+        .collect();
+    canonicalize(&items).map(|_| ())
+}
+
+/// Как [`check_tickers_csv`], но по одному тикеру на строку (как в файле тикеров)
+pub fn check_tickers_lines(raw: &str) -> Result<(), Vec<InvalidTicker>> {
+    let items: Vec<&str> = raw
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|t| !t.is_empty())
+        .collect();
+    canonicalize(&items).map(|_| ())
+}
+
+/// Отклонённые тикеры одной строкой для сообщения об ошибке:
+/// `invalid ticker "X": <причина>; invalid ticker "Y": ...`
+pub fn describe_invalid_tickers(rejects: &[InvalidTicker]) -> String {
+    rejects
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn check_ticker(t: &str) -> Result<&str, InvalidTickerReason> {
+    if t.is_empty() {
+        return Err(InvalidTickerReason::Empty);
+    }
+    if let Some(c) = t
+        .chars()
+        .find(|&c| !c.is_ascii_graphic() || c == ',' || c == '#')
+    {
+        return Err(InvalidTickerReason::BadChar(c));
+    }
+    if t.len() > MAX_TICKER_LEN {
+        return Err(InvalidTickerReason::TooLong {
+            max: MAX_TICKER_LEN,
+        });
+    }
+    Ok(t)
+}
+
+/// Объединяет несколько списков тикеров: trim, регистр по `case`,
+/// пустые отбрасываются; результат отсортирован и уникален.
+/// Нормализация до сравнения: `aapl` из одного источника и `AAPL` из другого
+/// дают один тикер (при `TickerCase::Upper`).
+pub fn merge_tickers<'a, I>(sources: I, case: TickerCase) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a [String]>,
+{
+    let set: BTreeSet<String> = sources
+        .into_iter()
+        .flatten()
+        .map(|t| t.trim())
+        .filter(|t| !t.is_empty())
+        .map(|t| case.apply(t))
+        .collect();
+    set.into_iter().collect()
+}
+
+/// Оставить первые `count` тикеров отсортированного списка (`None` - без ограничения)
+pub fn limit_tickers(mut tickers: Vec<String>, count: Option<usize>) -> Vec<String> {
+    if let Some(n) = count {
+        tickers.sort();
+        tickers.truncate(n);
+    }
+    tickers
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -67,6 +413,123 @@ mod tests {
     use std::time::{SystemTime, UNIX_EPOCH};
     use std::{fs, path::PathBuf};
 
+    #[test]
+    fn merge_tickers_normalizes_before_dedup() {
+        let file = vec!["aapl".to_string(), " tsla ".to_string()];
+        let defaults = vec!["AAPL".to_string(), "MSFT".to_string(), String::new()];
+
+        assert_eq!(
+            merge_tickers([file.as_slice(), defaults.as_slice()], TickerCase::Upper),
+            vec!["AAPL", "MSFT", "TSLA"]
+        );
+        // с Preserve регистр различает тикеры
+        assert_eq!(
+            merge_tickers([file.as_slice(), defaults.as_slice()], TickerCase::Preserve),
+            vec!["AAPL", "MSFT", "aapl", "tsla"]
+        );
+    }
+
+    #[test]
+    fn parse_tickers_csv_drops_comment_tokens() {
+        assert!(parse_tickers_csv("#foo").is_empty());
+        assert_eq!(parse_tickers_csv("AAPL,#bar,TSLA"), vec!["AAPL", "TSLA"]);
+        assert_eq!(parse_tickers_csv("aapl # main, tsla"), vec!["AAPL", "TSLA"]);
+    }
+
+    #[test]
+    fn canonicalize_all_valid_returns_sorted_unique() {
+        assert_eq!(
+            canonicalize(&["tsla", " aapl", "BRK.B ", "AAPL"]),
+            Ok(vec![
+                "AAPL".to_string(),
+                "BRK.B".to_string(),
+                "TSLA".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn canonicalize_reports_every_reject() {
+        let long = "X".repeat(MAX_TICKER_LEN + 1);
+        let rejects = canonicalize(&["AAPL", "  ", &long, "BRK A", "TSLA", "AAPL#1"]).unwrap_err();
+
+        assert_eq!(
+            rejects,
+            vec![
+                InvalidTicker {
+                    raw: "  ".to_string(),
+                    reason: InvalidTickerReason::Empty,
+                },
+                InvalidTicker {
+                    raw: long.clone(),
+                    reason: InvalidTickerReason::TooLong {
+                        max: MAX_TICKER_LEN
+                    },
+                },
+                InvalidTicker {
+                    raw: "BRK A".to_string(),
+                    reason: InvalidTickerReason::BadChar(' '),
+                },
+                InvalidTicker {
+                    raw: "AAPL#1".to_string(),
+                    reason: InvalidTickerReason::BadChar('#'),
+                },
+            ]
+        );
+        assert_eq!(
+            rejects[2].to_string(),
+            "invalid ticker \"BRK A\": bad character ' '"
+        );
+    }
+
+    #[test]
+    fn check_tickers_csv_names_every_rejected_token() {
+        let rejects =
+            check_tickers_csv("AAPL,TOO_LONG_SYMBOL_NAME, TSLA,BRK A,#comment").unwrap_err();
+
+        let raw: Vec<&str> = rejects.iter().map(|r| r.raw.as_str()).collect();
+        assert_eq!(raw, vec!["TOO_LONG_SYMBOL_NAME", "BRK A"]);
+        assert_eq!(
+            describe_invalid_tickers(&rejects),
+            "invalid ticker \"TOO_LONG_SYMBOL_NAME\": longer than 16 bytes; \
+             invalid ticker \"BRK A\": bad character ' '"
+        );
+        assert!(check_tickers_lines("AAPL # main\nBRK.B\n").is_ok());
+        assert_eq!(
+            check_tickers_lines("AAPL\nBRK A\n").unwrap_err()[0].raw,
+            "BRK A"
+        );
+    }
+
+    #[test]
+    fn canonicalize_empty_input_is_empty_list() {
+        assert_eq!(canonicalize(&[]), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn read_tickers_rejects_line_over_cap() {
+        let huge = "A".repeat(1024 * 1024);
+        let err = read_tickers_with_max_line(Cursor::new(huge), TickerCase::Upper, 64).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // строка ровно в лимит (и CRLF) проходит
+        let input = format!("{}\r\ntsla\n", "B".repeat(64));
+        let tickers =
+            read_tickers_with_max_line(Cursor::new(input), TickerCase::Upper, 64).unwrap();
+        assert_eq!(tickers, vec!["B".repeat(64), "TSLA".to_string()]);
+    }
+
+    #[test]
+    fn limit_tickers_keeps_first_sorted() {
+        let tickers = vec!["TSLA".to_string(), "AAPL".to_string(), "MSFT".to_string()];
+
+        assert_eq!(
+            limit_tickers(tickers.clone(), Some(2)),
+            vec!["AAPL", "MSFT"]
+        );
+        assert_eq!(limit_tickers(tickers.clone(), None), tickers);
+    }
+
     #[test]
     fn parse_tickers_csv_sorts_and_dedups_and_filters_empty() {
         let got = parse_tickers_csv(" aapl, TSLA, ,goog ,AAPL,, tsla ");
@@ -79,6 +542,25 @@ mod tests {
         assert!(parse_tickers_csv(" , ,  ,").is_empty());
     }
 
+    #[test]
+    fn preserve_case_keeps_distinct_tickers_upper_merges_them() {
+        assert_eq!(
+            parse_tickers_csv_with_case("aapl, AAPL, btc-usdT", TickerCase::Preserve),
+            vec!["AAPL", "aapl", "btc-usdT"]
+        );
+        assert_eq!(
+            parse_tickers_csv_with_case("aapl, AAPL", TickerCase::Upper),
+            vec!["AAPL"]
+        );
+
+        let input = "aapl\n AAPL # c\n";
+        assert_eq!(
+            read_tickers_with_case(Cursor::new(input), TickerCase::Preserve).unwrap(),
+            vec!["AAPL", "aapl"]
+        );
+        assert_eq!(read_tickers(Cursor::new(input)).unwrap(), vec!["AAPL"]);
+    }
+
     #[test]
     fn normalize_line_ignores_empty_and_full_line_comments() {
         assert_eq!(normalize_line(""), None);
@@ -141,6 +623,87 @@ AAPL
         assert_eq!(got, vec!["AAPL", "NVDA", "TSLA"]);
     }
 
+    #[test]
+    fn read_tickers_streaming_calls_back_once_per_unique_ticker() {
+        let input = "msft\naapl # inline\n# comment\nAAPL\n  msft \ntsla\n";
+
+        let mut got = Vec::new();
+        let n = read_tickers_streaming(Cursor::new(input), |t| got.push(t)).unwrap();
+
+        // порядок первого появления, без повторов
+        assert_eq!(got, vec!["MSFT", "AAPL", "TSLA"]);
+        assert_eq!(n, 3);
+    }
+
+    #[test]
+    fn read_tickers_streaming_matches_read_tickers_as_set() {
+        let input = "b\na\nB\n\n# x\nc # y\na\n";
+
+        let mut streamed = Vec::new();
+        read_tickers_streaming(Cursor::new(input), |t| streamed.push(t)).unwrap();
+        streamed.sort();
+
+        assert_eq!(streamed, read_tickers(Cursor::new(input)).unwrap());
+    }
+
+    #[test]
+    fn read_tickers_with_report_counts_each_category() {
+        let input = include_str!("../testdata/tickers_report.txt");
+
+        let (tickers, report) =
+            read_tickers_with_report(Cursor::new(input), TickerCase::Upper).unwrap();
+
+        assert_eq!(tickers, vec!["AAPL", "MSFT", "NVDA", "TSLA"]);
+        assert_eq!(
+            report,
+            ParseReport {
+                total_lines: 11,
+                comments: 3,
+                duplicates: 2,
+                invalid: vec!["BRK A".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn read_tickers_with_report_empty_input() {
+        let (tickers, report) =
+            read_tickers_with_report(Cursor::new(""), TickerCase::Upper).unwrap();
+        assert!(tickers.is_empty());
+        assert_eq!(report, ParseReport::default());
+    }
+
+    #[test]
+    fn read_tickers_auto_parses_json_array() {
+        let input = r#" ["aapl", " TSLA ", "", "AAPL"] "#;
+        let got = read_tickers_auto(Cursor::new(input)).unwrap();
+        assert_eq!(got, vec!["AAPL", "TSLA"]);
+    }
+
+    #[test]
+    fn read_tickers_auto_with_case_preserves_case_in_both_formats() {
+        for input in [r#"["btcUSD", "aapl"]"#, "btcUSD\naapl\n"] {
+            let got =
+                read_tickers_auto_with_case(Cursor::new(input), TickerCase::Preserve).unwrap();
+            assert_eq!(got, vec!["aapl", "btcUSD"], "input: {input}");
+        }
+    }
+
+    #[test]
+    fn read_tickers_auto_falls_back_to_lines() {
+        let input = "msft\n# comment\naapl # inline\n";
+        let got = read_tickers_auto(Cursor::new(input)).unwrap();
+        assert_eq!(got, vec!["AAPL", "MSFT"]);
+    }
+
+    #[test]
+    fn read_tickers_auto_rejects_malformed_json() {
+        for input in [r#"["AAPL""#, r#"["AAPL" "TSLA"]"#, "[1, 2]"] {
+            let err = read_tickers_auto(Cursor::new(input)).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "input: {input}");
+        }
+    }
+
     #[test]
     fn read_tickers_from_path_reads_file() {
         // делаем уникальный путь в temp без сторонних crate
@@ -1,3 +1,6 @@
+use crate::error::QuoteParseError;
+use crate::json;
+use crate::wire::UdpPacketV1;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
@@ -12,6 +15,139 @@ pub struct StockQuote {
     pub volume: u32,
     /// время формирования
     pub timestamp_ms: u128,
+    /// направление сделки (если известно)
+    #[serde(default)]
+    pub side: Option<Side>,
+    /// момент отправки пакета сервером, мс от эпохи (не путать с `timestamp_ms` -
+    /// временем генерации). `recv_ms - sent_ms` - время в сети
+    #[serde(default)]
+    pub sent_ms: Option<u64>,
+}
+
+/// Направление сделки
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Side {
+    /// покупка
+    Buy,
+    /// продажа
+    Sell,
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Side::Buy => f.write_str("buy"),
+            Side::Sell => f.write_str("sell"),
+        }
+    }
+}
+
+impl StockQuote {
+    /// Котировка в JSON со стабильными именами полей - отдельно от serde derive,
+    /// чтобы переименование полей структуры не ломало внешних потребителей:
+    ///
+    /// ```json
+    /// {"ticker":"AAPL","price":12345,"volume":10,"timestamp_ms":1700000000000,"side":"buy","sent_ms":1700000000005}
+    /// ```
+    ///
+    /// `price` - целое в минимальных единицах (как в структуре), `side`
+    /// (`"buy"`/`"sell"`) и `sent_ms` пишутся, только если известны.
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{\"ticker\":");
+        json::write_str(&mut out, &self.ticker);
+        out.push_str(&format!(
+            ",\"price\":{},\"volume\":{},\"timestamp_ms\":{}",
+            self.price, self.volume, self.timestamp_ms
+        ));
+        if let Some(side) = self.side {
+            out.push_str(&format!(",\"side\":\"{side}\""));
+        }
+        if let Some(sent_ms) = self.sent_ms {
+            out.push_str(&format!(",\"sent_ms\":{sent_ms}"));
+        }
+        out.push('}');
+        out
+    }
+
+    /// Обратное к [`StockQuote::to_json`]. `ticker`, `price`, `volume` и
+    /// `timestamp_ms` обязательны; `side` и `sent_ms` можно опустить или
+    /// передать `null`. Неизвестные поля игнорируются.
+    pub fn from_json(s: &str) -> Result<StockQuote, QuoteParseError> {
+        let (mut ticker, mut price, mut volume, mut timestamp_ms) = (None, None, None, None);
+        let (mut side, mut sent_ms) = (None, None);
+
+        for (key, value) in json::parse_object(s)? {
+            match key.as_str() {
+                "ticker" => set_once(&mut ticker, "ticker", json_str("ticker", value)?)?,
+                "price" => set_once(&mut price, "price", json_int("price", value)?)?,
+                "volume" => set_once(&mut volume, "volume", json_int("volume", value)?)?,
+                "timestamp_ms" => set_once(
+                    &mut timestamp_ms,
+                    "timestamp_ms",
+                    json_int("timestamp_ms", value)?,
+                )?,
+                "side" => {
+                    let parsed = match value {
+                        json::Value::Null => None,
+                        json::Value::Str(s) if s == "buy" => Some(Side::Buy),
+                        json::Value::Str(s) if s == "sell" => Some(Side::Sell),
+                        other => return Err(invalid("side", &other)),
+                    };
+                    set_once(&mut side, "side", parsed)?;
+                }
+                "sent_ms" => {
+                    let parsed = match value {
+                        json::Value::Null => None,
+                        other => Some(json_int("sent_ms", other)?),
+                    };
+                    set_once(&mut sent_ms, "sent_ms", parsed)?;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(StockQuote {
+            ticker: ticker.ok_or(QuoteParseError::MissingField("ticker"))?,
+            price: price.ok_or(QuoteParseError::MissingField("price"))?,
+            volume: volume.ok_or(QuoteParseError::MissingField("volume"))?,
+            timestamp_ms: timestamp_ms.ok_or(QuoteParseError::MissingField("timestamp_ms"))?,
+            side: side.flatten(),
+            sent_ms: sent_ms.flatten(),
+        })
+    }
+}
+
+fn set_once<T>(slot: &mut Option<T>, field: &'static str, value: T) -> Result<(), QuoteParseError> {
+    if slot.is_some() {
+        return Err(QuoteParseError::DuplicateField(field));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+fn invalid(field: &'static str, value: &json::Value<'_>) -> QuoteParseError {
+    QuoteParseError::InvalidField {
+        field,
+        value: value.describe(),
+    }
+}
+
+fn json_str(field: &'static str, value: json::Value<'_>) -> Result<String, QuoteParseError> {
+    match value {
+        json::Value::Str(s) => Ok(s),
+        other => Err(invalid(field, &other)),
+    }
+}
+
+/// Целое нужного типа: дробь, экспонента и выход за диапазон - ошибка
+fn json_int<T: std::str::FromStr>(
+    field: &'static str,
+    value: json::Value<'_>,
+) -> Result<T, QuoteParseError> {
+    match value {
+        json::Value::Num(n) => n.parse().map_err(|_| invalid(field, &value)),
+        other => Err(invalid(field, &other)),
+    }
 }
 
 impl fmt::Display for StockQuote {
@@ -26,6 +162,230 @@ impl fmt::Display for StockQuote {
             f,
             "{} price={}{}.{:02} volume={} ts_ms={}",
             self.ticker, sign, major, minor, self.volume, self.timestamp_ms
-        )
+        )?;
+
+        if let Some(side) = self.side {
+            write!(f, " side={side}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Текстовое представление котировки из UDP-пакета (формат `Display` у [`StockQuote`]).
+/// Для служебных пакетов (`Ping`, `Pong`, ...) - `None`.
+pub fn quote_from_udp(pkt: &UdpPacketV1) -> Option<String> {
+    match pkt {
+        UdpPacketV1::Quote(q) => Some(q.to_string()),
+        _ => None,
+    }
+}
+
+/// Обратное к [`quote_from_udp`]: строка
+/// `AAPL price=123.45 volume=10 ts_ms=1700000000000 side=buy` -> `Quote`.
+/// `None` - строка не в этом формате.
+pub fn udp_from_text(line: &str) -> Option<UdpPacketV1> {
+    let mut parts = line.split_whitespace();
+    let ticker = parts.next()?.to_string();
+
+    let (mut price, mut volume, mut timestamp_ms, mut side) = (None, None, None, None);
+    for part in parts {
+        let (key, value) = part.split_once('=')?;
+        match key {
+            "price" => price = Some(parse_price(value)?),
+            "volume" => volume = Some(value.parse().ok()?),
+            "ts_ms" => timestamp_ms = Some(value.parse().ok()?),
+            "side" => {
+                side = Some(match value {
+                    "buy" => Side::Buy,
+                    "sell" => Side::Sell,
+                    _ => return None,
+                })
+            }
+            _ => return None,
+        }
+    }
+
+    Some(UdpPacketV1::Quote(StockQuote {
+        ticker,
+        price: price?,
+        volume: volume?,
+        timestamp_ms: timestamp_ms?,
+        side,
+        sent_ms: None,
+    }))
+}
+
+/// "-123.45" -> -12345 (ровно два знака после точки, как в `Display`)
+fn parse_price(s: &str) -> Option<i64> {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let (major, minor) = s.split_once('.')?;
+    if minor.len() != 2
+        || !major
+            .bytes()
+            .chain(minor.bytes())
+            .all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+
+    let abs = major.parse::<i64>().ok()?.checked_mul(100)? + minor.parse::<i64>().ok()?;
+    Some(if negative { -abs } else { abs })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(price: i64, side: Option<Side>) -> UdpPacketV1 {
+        UdpPacketV1::Quote(StockQuote {
+            ticker: "AAPL".to_string(),
+            price,
+            volume: 1500,
+            timestamp_ms: 1_700_000_000_000,
+            side,
+            sent_ms: None,
+        })
+    }
+
+    #[test]
+    fn quote_text_roundtrips_both_directions() {
+        for pkt in [
+            quote(12_345, Some(Side::Buy)),
+            quote(-5, Some(Side::Sell)),
+            quote(0, None),
+        ] {
+            let text = quote_from_udp(&pkt).unwrap();
+            assert_eq!(udp_from_text(&text).unwrap(), pkt);
+        }
+
+        let line = "TSLA price=250.07 volume=3 ts_ms=42 side=sell";
+        let pkt = udp_from_text(line).unwrap();
+        assert_eq!(quote_from_udp(&pkt).unwrap(), line);
+    }
+
+    fn full_quote() -> StockQuote {
+        StockQuote {
+            ticker: "BRK.B".to_string(),
+            price: -12_345,
+            volume: u32::MAX,
+            timestamp_ms: 1_700_000_000_000,
+            side: Some(Side::Sell),
+            sent_ms: Some(1_700_000_000_005),
+        }
+    }
+
+    #[test]
+    fn json_has_stable_field_names() {
+        assert_eq!(
+            full_quote().to_json(),
+            r#"{"ticker":"BRK.B","price":-12345,"volume":4294967295,"timestamp_ms":1700000000000,"side":"sell","sent_ms":1700000000005}"#
+        );
+        let UdpPacketV1::Quote(bare) = quote(100, None) else {
+            unreachable!()
+        };
+        assert_eq!(
+            bare.to_json(),
+            r#"{"ticker":"AAPL","price":100,"volume":1500,"timestamp_ms":1700000000000}"#
+        );
+    }
+
+    #[test]
+    fn json_roundtrips() {
+        let odd_ticker = StockQuote {
+            ticker: "a\"b\\c\u{1}é".to_string(),
+            side: None,
+            sent_ms: None,
+            ..full_quote()
+        };
+        for q in [full_quote(), odd_ticker] {
+            assert_eq!(StockQuote::from_json(&q.to_json()).unwrap(), q);
+        }
+
+        let spaced =
+            r#" { "volume": 1, "timestamp_ms": 2, "price": 3, "ticker": "X", "side": null } "#;
+        let q = StockQuote::from_json(spaced).unwrap();
+        assert_eq!((q.price, q.volume, q.timestamp_ms, q.side), (3, 1, 2, None));
+    }
+
+    #[test]
+    fn json_ignores_unknown_fields() {
+        let json = r#"{"ticker":"AAPL","exchange":"NASDAQ","price":1,"meta":{"tags":["a",{"b":null}]},"volume":2,"timestamp_ms":3,"v":2.5}"#;
+        let q = StockQuote::from_json(json).unwrap();
+        assert_eq!(q.ticker, "AAPL");
+        assert_eq!((q.price, q.volume, q.timestamp_ms), (1, 2, 3));
+    }
+
+    #[test]
+    fn json_rejects_missing_duplicate_and_mistyped_fields() {
+        assert_eq!(
+            StockQuote::from_json(r#"{"ticker":"A","price":1,"volume":2}"#),
+            Err(QuoteParseError::MissingField("timestamp_ms"))
+        );
+        assert_eq!(
+            StockQuote::from_json(
+                r#"{"ticker":"A","ticker":"B","price":1,"volume":2,"timestamp_ms":3}"#
+            ),
+            Err(QuoteParseError::DuplicateField("ticker"))
+        );
+        for (json, field, value) in [
+            (
+                r#"{"ticker":1,"price":1,"volume":2,"timestamp_ms":3}"#,
+                "ticker",
+                "1",
+            ),
+            (
+                r#"{"ticker":"A","price":1.5,"volume":2,"timestamp_ms":3}"#,
+                "price",
+                "1.5",
+            ),
+            (
+                r#"{"ticker":"A","price":1,"volume":-2,"timestamp_ms":3}"#,
+                "volume",
+                "-2",
+            ),
+            (
+                r#"{"ticker":"A","price":1,"volume":2,"timestamp_ms":"3"}"#,
+                "timestamp_ms",
+                "\"3\"",
+            ),
+            (
+                r#"{"ticker":"A","price":1,"volume":2,"timestamp_ms":3,"side":"hold"}"#,
+                "side",
+                "\"hold\"",
+            ),
+        ] {
+            assert_eq!(
+                StockQuote::from_json(json),
+                Err(QuoteParseError::InvalidField {
+                    field,
+                    value: value.to_string()
+                }),
+                "{json}"
+            );
+        }
+        assert!(matches!(
+            StockQuote::from_json("AAPL price=1"),
+            Err(QuoteParseError::Syntax { offset: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn service_packets_and_junk_have_no_text_form() {
+        assert_eq!(quote_from_udp(&UdpPacketV1::Ping), None);
+
+        for bad in [
+            "",
+            "AAPL",
+            "AAPL price=1.5 volume=1 ts_ms=1",
+            "AAPL price=1.50 volume=x ts_ms=1",
+            "AAPL price=1.50 volume=1 ts_ms=1 side=hold",
+            "AAPL price=1.50 volume=1 ts_ms=1 extra=1",
+        ] {
+            assert_eq!(udp_from_text(bad), None, "{bad:?}");
+        }
     }
 }
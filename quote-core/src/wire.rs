@@ -1,36 +1,300 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 use crate::error::WireError;
-use crate::types::StockQuote;
+use crate::types::{Side, StockQuote};
+
+/// Фиксированная раскладка котировки (`WIRE=FIXED`, `WIRE=FIXED-SECS`)
+pub mod fixed;
 
 /// Версия протокола
 pub const WIRE_VERSION: u8 = 1;
 
-/// Возможный payload
+/// Формат, в котором сервер шлёт котировки сессии
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum WireFormat {
+    /// [`UdpPacketV1`] через postcard (по умолчанию)
+    #[default]
+    Postcard,
+    /// [`fixed`]: фиксированный размер, без varint
+    Fixed,
+    /// [`fixed`] с временем в секундах: пакет короче, миллисекунды теряются
+    FixedSecs,
+}
+
+/// Возможный payload.
+///
+/// `#[non_exhaustive]`: новые варианты могут появляться, поэтому
+/// обработчики вне `quote-core` обязаны иметь catch-all ветку.
+///
+/// Индекс варианта - байт типа в заголовке пакета (см. [`peek_kind`]):
+/// новые варианты добавляются только в конец.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[non_exhaustive]
 pub enum UdpPacketV1 {
     /// Котировка
     Quote(StockQuote),
     /// Пинг (keep-alive)
     Ping,
+    /// Пинг с токеном сессии: сервер находит сессию по токену,
+    /// даже если адрес источника отличается от объявленного `udp_target` (NAT)
+    PingToken(u64),
+    /// Ответ сервера на ping (если включён): клиент видит, что сервер жив,
+    /// даже когда котировки приходят редко
+    Pong,
+    /// Контрольная точка сессии `ACK=1`: сервер шлёт её среди котировок,
+    /// клиент считает полученные. Номер - порядковый в сессии, с 1
+    Checkpoint(u64),
+    /// Ping с подтверждением (сессия `ACK=1`): `ack_seq` - сколько
+    /// `Checkpoint` клиент получил за сессию; `token` - как у `PingToken`
+    PingAck {
+        /// Токен сессии (`--ping-token`), иначе сессия ищется по адресу
+        token: Option<u64>,
+        /// Число полученных `Checkpoint`
+        ack_seq: u64,
+    },
+    /// Тикер убран с сервера (перезагрузка списка тикеров): котировок по нему
+    /// больше не будет. Шлётся только сессиям, подписанным на этот тикер
+    TickerRemoved(String),
+}
+
+/// Текстовое представление для логов:
+/// `PING`, `PING token=<t>`, `PONG`, `CHECKPOINT seq=<n>`,
+/// `PING ack_seq=<n> [token=<t>]`, `TICKER_REMOVED <тикер>`,
+/// котировка - как [`StockQuote`]
+impl fmt::Display for UdpPacketV1 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UdpPacketV1::Quote(q) => write!(f, "{q}"),
+            UdpPacketV1::Ping => f.write_str("PING"),
+            UdpPacketV1::PingToken(token) => write!(f, "PING token={token}"),
+            UdpPacketV1::Pong => f.write_str("PONG"),
+            UdpPacketV1::Checkpoint(seq) => write!(f, "CHECKPOINT seq={seq}"),
+            UdpPacketV1::PingAck { token, ack_seq } => {
+                write!(f, "PING ack_seq={ack_seq}")?;
+                if let Some(token) = token {
+                    write!(f, " token={token}")?;
+                }
+                Ok(())
+            }
+            UdpPacketV1::TickerRemoved(ticker) => write!(f, "TICKER_REMOVED {ticker}"),
+        }
+    }
 }
 
 /// Закодировать payload
 pub fn encode_v1(pkt: &UdpPacketV1) -> Result<Vec<u8>, WireError> {
     let mut out = Vec::new();
-    out.push(WIRE_VERSION);
-    out.extend_from_slice(&postcard::to_allocvec(pkt)?);
+    encode_v1_into(pkt, &mut out)?;
     Ok(out)
 }
 
+/// Закодировать payload в переиспользуемый буфер (без аллокации на каждый пакет).
+/// Буфер очищается перед записью; при ошибке его содержимое не определено.
+pub fn encode_v1_into(pkt: &UdpPacketV1, out: &mut Vec<u8>) -> Result<(), WireError> {
+    out.clear();
+    out.push(WIRE_VERSION);
+    *out = postcard::to_extend(pkt, std::mem::take(out))?;
+    Ok(())
+}
+
+/// Точный размер [`encode_v1`] (байт версии + postcard) без записи байтов:
+/// для заранее выделенных буферов и расчётов под MTU.
+pub fn encoded_len(pkt: &UdpPacketV1) -> Result<usize, WireError> {
+    let payload = postcard::serialize_with_flavor(pkt, postcard::ser_flavors::Size::default())?;
+    Ok(1 + payload)
+}
+
 /// Распаковать payload
 pub fn decode(buf: &[u8]) -> Result<UdpPacketV1, WireError> {
     let (&ver, payload) = buf.split_first().ok_or(WireError::PacketTooShort)?;
     if ver != WIRE_VERSION {
         return Err(WireError::UnsupportedWireVersion(ver));
     }
-    let pkt = postcard::from_bytes(payload)?;
-    Ok(pkt)
+    if payload.is_empty() {
+        return Err(WireError::MissingPayload);
+    }
+    match postcard::from_bytes(payload) {
+        Ok(pkt) => Ok(pkt),
+        // postcard не self-describing: `serde(default)` не спасает от отсутствующего
+        // хвостового поля, поэтому пакеты старых отправителей разбираем отдельно
+        Err(postcard::Error::DeserializeUnexpectedEnd) => decode_legacy_quote(payload),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Тип пакета, определённый по заголовку ([`peek_kind`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PacketKind {
+    /// [`UdpPacketV1::Quote`] (в том числе в фиксированной раскладке)
+    Quote,
+    /// [`UdpPacketV1::Ping`]
+    Ping,
+    /// [`UdpPacketV1::PingToken`]
+    PingToken,
+    /// [`UdpPacketV1::Pong`]
+    Pong,
+    /// [`UdpPacketV1::Checkpoint`]
+    Checkpoint,
+    /// [`UdpPacketV1::PingAck`]
+    PingAck,
+    /// [`UdpPacketV1::TickerRemoved`]
+    TickerRemoved,
+}
+
+/// Тип пакета по первым двум байтам, без разбора payload и без аллокаций.
+///
+/// Отдельное поле не нужно: postcard пишет индекс варианта [`UdpPacketV1`]
+/// первым байтом payload, так что пакет уже начинается с `[версия][тип]`.
+/// Полный разбор - [`decode`] / [`decode_any`].
+pub fn peek_kind(buf: &[u8]) -> Result<PacketKind, WireError> {
+    let (&ver, payload) = buf.split_first().ok_or(WireError::PacketTooShort)?;
+    if fixed::is_fixed_version(ver) {
+        return Ok(PacketKind::Quote);
+    }
+    if ver != WIRE_VERSION {
+        return Err(WireError::UnsupportedWireVersion(ver));
+    }
+
+    // varint индекса варианта: для индексов < 128 - ровно один байт
+    match payload.first() {
+        None => Err(WireError::MissingPayload),
+        Some(0) => Ok(PacketKind::Quote),
+        Some(1) => Ok(PacketKind::Ping),
+        Some(2) => Ok(PacketKind::PingToken),
+        Some(3) => Ok(PacketKind::Pong),
+        Some(4) => Ok(PacketKind::Checkpoint),
+        Some(5) => Ok(PacketKind::PingAck),
+        Some(6) => Ok(PacketKind::TickerRemoved),
+        Some(&tag) => Err(WireError::UnknownPacketKind(tag)),
+    }
+}
+
+/// Распаковать пакет любого поддерживаемого формата по байту версии:
+/// [`fixed::FIXED_WIRE_VERSION`] - котировка в фиксированной раскладке,
+/// иначе - как [`decode`]
+pub fn decode_any(buf: &[u8]) -> Result<UdpPacketV1, WireError> {
+    decode_with(buf, DecodeMode::Strict)
+}
+
+/// Режим разбора датаграммы
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DecodeMode {
+    /// Датаграмма разбирается как есть (по умолчанию)
+    #[default]
+    Strict,
+    /// Если разбор как есть не удался, повторить без одного завершающего
+    /// `\n` / `\r\n` (его добавляют некоторые транспорты и тестовые стенды)
+    Lenient,
+}
+
+/// Как [`decode_any`], но с выбором режима.
+///
+/// В `Lenient` перевод строки снимается только после неудачной попытки:
+/// байт `0x0A` может быть законной частью payload (например, `PingToken(10)`).
+pub fn decode_with(buf: &[u8], mode: DecodeMode) -> Result<UdpPacketV1, WireError> {
+    let decode_once = |buf: &[u8]| match buf.first() {
+        Some(&ver) if fixed::is_fixed_version(ver) => {
+            fixed::decode_fixed(buf).map(UdpPacketV1::Quote)
+        }
+        _ => decode(buf),
+    };
+
+    match (decode_once(buf), mode) {
+        (Err(e), DecodeMode::Lenient) => {
+            let trimmed = buf
+                .strip_suffix(b"\r\n")
+                .or_else(|| buf.strip_suffix(b"\n"))
+                .ok_or(e)?;
+            decode_once(trimmed)
+        }
+        (res, _) => res,
+    }
+}
+
+/// Котировка в раскладке до появления поля `side`
+#[derive(Deserialize)]
+struct LegacyStockQuote {
+    ticker: String,
+    price: i64,
+    volume: u32,
+    timestamp_ms: u128,
+}
+
+/// Раскладка `UdpPacketV1` с котировкой без `side` (порядок вариантов совпадает)
+#[derive(Deserialize)]
+enum LegacyPacketV1 {
+    Quote(LegacyStockQuote),
+}
+
+/// Котировка в раскладке до появления поля `sent_ms`
+#[derive(Deserialize)]
+struct SideStockQuote {
+    ticker: String,
+    price: i64,
+    volume: u32,
+    timestamp_ms: u128,
+    side: Option<Side>,
+}
+
+/// Раскладка `UdpPacketV1` с котировкой без `sent_ms`
+#[derive(Deserialize)]
+enum SidePacketV1 {
+    Quote(SideStockQuote),
+}
+
+/// Хвостовые поля добавлялись по одному, поэтому пробуем раскладки
+/// от новой к старой: без `sent_ms`, затем без `side`.
+fn decode_legacy_quote(payload: &[u8]) -> Result<UdpPacketV1, WireError> {
+    if let Ok(SidePacketV1::Quote(q)) = postcard::from_bytes(payload) {
+        return Ok(UdpPacketV1::Quote(StockQuote {
+            ticker: q.ticker,
+            price: q.price,
+            volume: q.volume,
+            timestamp_ms: q.timestamp_ms,
+            side: q.side,
+            sent_ms: None,
+        }));
+    }
+
+    let LegacyPacketV1::Quote(q) = postcard::from_bytes(payload)
+        .map_err(|_| WireError::Postcard(postcard::Error::DeserializeUnexpectedEnd))?;
+
+    Ok(UdpPacketV1::Quote(StockQuote {
+        ticker: q.ticker,
+        price: q.price,
+        volume: q.volume,
+        timestamp_ms: q.timestamp_ms,
+        side: None,
+        sent_ms: None,
+    }))
+}
+
+/// Проверка при старте: encode/decode известных `Ping` и `Quote`
+/// должен вернуть исходные пакеты. Позволяет бинарю упасть сразу,
+/// если сериализация собрана/работает некорректно.
+pub fn self_test() -> Result<(), WireError> {
+    let samples = [
+        UdpPacketV1::Ping,
+        UdpPacketV1::Quote(StockQuote {
+            ticker: "SELFTEST".to_string(),
+            price: -12_345,
+            volume: u32::MAX,
+            timestamp_ms: u128::from(u64::MAX) + 1,
+            side: Some(Side::Sell),
+            sent_ms: None,
+        }),
+    ];
+
+    for pkt in &samples {
+        let bytes = encode_v1(pkt)?;
+        if decode(&bytes)? != *pkt {
+            return Err(WireError::SelfTestMismatch);
+        }
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -44,6 +308,8 @@ mod tests {
             price: 123_4500,
             volume: 1500,
             timestamp_ms: 1_700_000_000_000,
+            side: None,
+            sent_ms: None,
         };
 
         let pkt = UdpPacketV1::Quote(q.clone());
@@ -54,6 +320,185 @@ mod tests {
         assert_eq!(decoded, UdpPacketV1::Quote(q));
     }
 
+    #[test]
+    fn peek_kind_matches_every_variant() {
+        let quote = StockQuote {
+            ticker: "AAPL".to_string(),
+            price: 1,
+            volume: 1,
+            timestamp_ms: 1,
+            side: None,
+            sent_ms: None,
+        };
+        let cases = [
+            (UdpPacketV1::Quote(quote.clone()), PacketKind::Quote),
+            (UdpPacketV1::Ping, PacketKind::Ping),
+            (UdpPacketV1::PingToken(u64::MAX), PacketKind::PingToken),
+            (UdpPacketV1::Pong, PacketKind::Pong),
+            (UdpPacketV1::Checkpoint(7), PacketKind::Checkpoint),
+            (
+                UdpPacketV1::PingAck {
+                    token: Some(1),
+                    ack_seq: 3,
+                },
+                PacketKind::PingAck,
+            ),
+            (
+                UdpPacketV1::TickerRemoved("AAPL".to_string()),
+                PacketKind::TickerRemoved,
+            ),
+        ];
+        for (pkt, kind) in cases {
+            let bytes = encode_v1(&pkt).unwrap();
+            assert_eq!(peek_kind(&bytes).unwrap(), kind, "{pkt}");
+        }
+
+        let fixed = fixed::encode_fixed(&quote).unwrap();
+        assert_eq!(peek_kind(&fixed).unwrap(), PacketKind::Quote);
+    }
+
+    #[test]
+    fn peek_kind_rejects_bad_headers() {
+        assert!(matches!(peek_kind(&[]), Err(WireError::PacketTooShort)));
+        assert!(matches!(
+            peek_kind(&[WIRE_VERSION]),
+            Err(WireError::MissingPayload)
+        ));
+        assert!(matches!(
+            peek_kind(&[42, 1]),
+            Err(WireError::UnsupportedWireVersion(42))
+        ));
+        assert!(matches!(
+            peek_kind(&[WIRE_VERSION, 9]),
+            Err(WireError::UnknownPacketKind(9))
+        ));
+    }
+
+    #[test]
+    fn roundtrip_quote_with_side() {
+        for side in [Some(Side::Buy), Some(Side::Sell), None] {
+            let pkt = UdpPacketV1::Quote(StockQuote {
+                ticker: "AAPL".to_string(),
+                price: 100,
+                volume: 1,
+                timestamp_ms: 1,
+                side,
+                sent_ms: None,
+            });
+
+            let bytes = encode_v1(&pkt).expect("encode");
+            assert_eq!(decode(&bytes).expect("decode"), pkt);
+        }
+    }
+
+    #[test]
+    fn roundtrip_quote_with_sent_ms() {
+        let pkt = UdpPacketV1::Quote(StockQuote {
+            ticker: "AAPL".to_string(),
+            price: 100,
+            volume: 1,
+            timestamp_ms: 1_700_000_000_000,
+            side: Some(Side::Buy),
+            sent_ms: Some(1_700_000_000_007),
+        });
+
+        let bytes = encode_v1(&pkt).unwrap();
+        assert_eq!(decode(&bytes).unwrap(), pkt);
+    }
+
+    #[test]
+    fn decode_accepts_quote_from_sender_without_sent_ms() {
+        // раскладка StockQuote до sent_ms (side уже есть)
+        #[derive(Serialize)]
+        struct OldQuote {
+            ticker: String,
+            price: i64,
+            volume: u32,
+            timestamp_ms: u128,
+            side: Option<Side>,
+        }
+        #[derive(Serialize)]
+        enum OldPacket {
+            Quote(OldQuote),
+        }
+
+        let mut bytes = vec![WIRE_VERSION];
+        bytes.extend(
+            postcard::to_allocvec(&OldPacket::Quote(OldQuote {
+                ticker: "AAPL".to_string(),
+                price: 100,
+                volume: 1,
+                timestamp_ms: 5,
+                side: Some(Side::Sell),
+            }))
+            .unwrap(),
+        );
+
+        let UdpPacketV1::Quote(q) = decode(&bytes).unwrap() else {
+            panic!("expected quote");
+        };
+        assert_eq!(q.side, Some(Side::Sell));
+        assert_eq!(q.sent_ms, None);
+    }
+
+    #[test]
+    fn old_receiver_ignores_sent_ms_tail() {
+        // получатель с раскладкой до sent_ms читает новый пакет
+        let pkt = UdpPacketV1::Quote(StockQuote {
+            ticker: "AAPL".to_string(),
+            price: 100,
+            volume: 1,
+            timestamp_ms: 5,
+            side: Some(Side::Buy),
+            sent_ms: Some(6),
+        });
+        let bytes = encode_v1(&pkt).unwrap();
+
+        let SidePacketV1::Quote(q) = postcard::from_bytes(&bytes[1..]).unwrap();
+        assert_eq!(q.side, Some(Side::Buy));
+        assert_eq!(q.timestamp_ms, 5);
+    }
+
+    #[test]
+    fn decode_accepts_quote_from_sender_without_side() {
+        // старая раскладка StockQuote (без side)
+        #[derive(Serialize)]
+        struct OldQuote {
+            ticker: String,
+            price: i64,
+            volume: u32,
+            timestamp_ms: u128,
+        }
+        #[derive(Serialize)]
+        enum OldPacket {
+            Quote(OldQuote),
+        }
+
+        let mut bytes = vec![WIRE_VERSION];
+        bytes.extend(
+            postcard::to_allocvec(&OldPacket::Quote(OldQuote {
+                ticker: "AAPL".to_string(),
+                price: 123_4500,
+                volume: 1500,
+                timestamp_ms: 1_700_000_000_000,
+            }))
+            .unwrap(),
+        );
+
+        let decoded = decode(&bytes).expect("decode legacy quote");
+        assert_eq!(
+            decoded,
+            UdpPacketV1::Quote(StockQuote {
+                ticker: "AAPL".to_string(),
+                price: 123_4500,
+                volume: 1500,
+                timestamp_ms: 1_700_000_000_000,
+                side: None,
+                sent_ms: None,
+            })
+        );
+    }
+
     #[test]
     fn roundtrip_ping() {
         let pkt = UdpPacketV1::Ping;
@@ -64,6 +509,183 @@ mod tests {
         assert_eq!(decoded, UdpPacketV1::Ping);
     }
 
+    #[test]
+    fn encode_into_reuses_buffer_and_matches_encode_v1() {
+        let pkt = UdpPacketV1::Quote(StockQuote {
+            ticker: "TSLA".to_string(),
+            price: 25_000,
+            volume: 42,
+            timestamp_ms: 1_700_000_000_123,
+            side: None,
+            sent_ms: None,
+        });
+
+        // буфер с "мусором" от предыдущего пакета
+        let mut buf = vec![0xAA; 64];
+        encode_v1_into(&pkt, &mut buf).expect("encode_into");
+
+        assert_eq!(buf, encode_v1(&pkt).expect("encode"));
+        assert_eq!(decode(&buf).expect("decode"), pkt);
+    }
+
+    #[test]
+    fn encoded_len_matches_encode_v1() {
+        let quote = |ticker: &str, price: i64, side: Option<Side>, sent_ms: Option<u64>| {
+            UdpPacketV1::Quote(StockQuote {
+                ticker: ticker.to_string(),
+                price,
+                volume: 1_000,
+                timestamp_ms: 1_700_000_000_123,
+                side,
+                sent_ms,
+            })
+        };
+        let pkts = [
+            UdpPacketV1::Ping,
+            UdpPacketV1::PingToken(u64::MAX),
+            UdpPacketV1::Checkpoint(3),
+            UdpPacketV1::TickerRemoved("AAPL".to_string()),
+            quote("A", 1, None, None),
+            quote("TSLA", -25_000, Some(Side::Buy), None),
+            quote(
+                "VERYLONGTICKER16",
+                i64::MAX,
+                Some(Side::Sell),
+                Some(u64::MAX),
+            ),
+        ];
+
+        for pkt in &pkts {
+            assert_eq!(
+                encoded_len(pkt).expect("encoded_len"),
+                encode_v1(pkt).expect("encode").len(),
+                "{pkt:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn roundtrip_ticker_removed() {
+        let pkt = UdpPacketV1::TickerRemoved("BRK.B".to_string());
+
+        let bytes = encode_v1(&pkt).expect("encode");
+        assert_eq!(bytes[1], 6, "new variants go to the end of the enum");
+        assert_eq!(decode_any(&bytes).expect("decode"), pkt);
+        assert_eq!(pkt.to_string(), "TICKER_REMOVED BRK.B");
+    }
+
+    #[test]
+    fn roundtrip_ping_token() {
+        let pkt = UdpPacketV1::PingToken(0xDEAD_BEEF);
+
+        let bytes = encode_v1(&pkt).expect("encode");
+        let decoded = decode(&bytes).expect("decode");
+
+        assert_eq!(decoded, pkt);
+    }
+
+    #[test]
+    fn decode_any_dispatches_on_version_byte() {
+        let q = StockQuote {
+            ticker: "AAPL".to_string(),
+            price: 100,
+            volume: 1,
+            timestamp_ms: 1,
+            side: None,
+            sent_ms: None,
+        };
+
+        let fixed_bytes = fixed::encode_fixed(&q).unwrap();
+        assert_eq!(
+            decode_any(&fixed_bytes).unwrap(),
+            UdpPacketV1::Quote(q.clone())
+        );
+
+        let secs_bytes = fixed::encode_fixed_secs(&StockQuote {
+            timestamp_ms: 2_000,
+            ..q.clone()
+        })
+        .unwrap();
+        assert!(matches!(
+            decode_any(&secs_bytes).unwrap(),
+            UdpPacketV1::Quote(StockQuote {
+                timestamp_ms: 2_000,
+                ..
+            })
+        ));
+
+        let v1_bytes = encode_v1(&UdpPacketV1::Ping).unwrap();
+        assert_eq!(decode_any(&v1_bytes).unwrap(), UdpPacketV1::Ping);
+    }
+
+    #[test]
+    fn lenient_decode_accepts_trailing_newline() {
+        let q = StockQuote {
+            ticker: "AAPL".to_string(),
+            price: 100,
+            volume: 1,
+            timestamp_ms: 1,
+            side: None,
+            sent_ms: None,
+        };
+        let plain = fixed::encode_fixed(&q).unwrap();
+
+        for suffix in [&b""[..], b"\n", b"\r\n"] {
+            let mut bytes = plain.clone();
+            bytes.extend_from_slice(suffix);
+            assert_eq!(
+                decode_with(&bytes, DecodeMode::Lenient).unwrap(),
+                UdpPacketV1::Quote(q.clone())
+            );
+        }
+
+        // strict по-прежнему требует точную датаграмму
+        let mut with_nl = plain;
+        with_nl.push(b'\n');
+        assert!(matches!(
+            decode_with(&with_nl, DecodeMode::Strict),
+            Err(WireError::FixedLength(_))
+        ));
+    }
+
+    #[test]
+    fn lenient_decode_keeps_payload_ending_with_newline_byte() {
+        // varint 10 == b'\n'
+        let bytes = encode_v1(&UdpPacketV1::PingToken(10)).unwrap();
+        assert_eq!(bytes.last(), Some(&b'\n'));
+
+        assert_eq!(
+            decode_with(&bytes, DecodeMode::Lenient).unwrap(),
+            UdpPacketV1::PingToken(10)
+        );
+    }
+
+    #[test]
+    fn display_packets() {
+        let q = StockQuote {
+            ticker: "AAPL".to_string(),
+            price: 12_345,
+            volume: 10,
+            timestamp_ms: 1,
+            side: None,
+            sent_ms: None,
+        };
+
+        assert_eq!(UdpPacketV1::Ping.to_string(), "PING");
+        assert_eq!(UdpPacketV1::PingToken(7).to_string(), "PING token=7");
+        assert_eq!(UdpPacketV1::Pong.to_string(), "PONG");
+        assert_eq!(
+            UdpPacketV1::Quote(q).to_string(),
+            "AAPL price=123.45 volume=10 ts_ms=1"
+        );
+    }
+
+    #[test]
+    fn roundtrip_pong() {
+        let bytes = encode_v1(&UdpPacketV1::Pong).expect("encode");
+        assert_eq!(decode(&bytes).expect("decode"), UdpPacketV1::Pong);
+    }
+
     #[test]
     fn decode_rejects_unknown_version() {
         let pkt = UdpPacketV1::Ping;
@@ -76,9 +698,24 @@ mod tests {
         assert!(matches!(err, WireError::UnsupportedWireVersion(_)));
     }
 
+    #[test]
+    fn self_test_passes() {
+        self_test().expect("wire self-test");
+    }
+
     #[test]
     fn decode_rejects_too_short_packet() {
         let err = decode(&[]).unwrap_err();
         assert!(matches!(err, WireError::PacketTooShort));
     }
+
+    #[test]
+    fn decode_rejects_version_without_payload() {
+        let err = decode(&[WIRE_VERSION]).unwrap_err();
+        assert!(matches!(err, WireError::MissingPayload));
+
+        // валидный пакет по-прежнему разбирается
+        let bytes = encode_v1(&UdpPacketV1::Ping).expect("encode");
+        assert_eq!(decode(&bytes).expect("decode"), UdpPacketV1::Ping);
+    }
 }
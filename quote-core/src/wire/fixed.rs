@@ -0,0 +1,283 @@
+//! Фиксированная бинарная раскладка котировки (без postcard и varint).
+//!
+//! Пакет всегда занимает [`FIXED_QUOTE_LEN`] байт (little-endian):
+//!
+//! | смещение | размер | поле                                        |
+//! |----------|--------|---------------------------------------------|
+//! | 0        | 1      | версия [`FIXED_WIRE_VERSION`]               |
+//! | 1        | 8      | тикер, ASCII, дополнен нулями               |
+//! | 9        | 8      | `price: i64`                                |
+//! | 17       | 4      | `volume: u32`                               |
+//! | 21       | 8      | `timestamp_ms: u64`                         |
+//! | 29       | 1      | `side`: 0 - нет, 1 - buy, 2 - sell          |
+//!
+//! Вариант с секундами (`WIRE=FIXED-SECS`): в байте версии выставлен
+//! [`TS_SECONDS_FLAG`], время передаётся как `u32` секунд от эпохи
+//! (хватает до 2106 года), пакет занимает [`FIXED_SECS_QUOTE_LEN`] байт.
+//! Получатель восстанавливает `timestamp_ms` как `секунды * 1000`:
+//! миллисекунды теряются.
+//!
+//! Компромиссы по сравнению с [`encode_v1`](super::encode_v1):
+//! - размер не зависит от значений, разбор - набор чтений по смещениям;
+//! - тикер длиннее [`FIXED_TICKER_LEN`] байт обрезается (обратно не восстановить);
+//! - `timestamp_ms` ограничен `u64`, большие значения - ошибка кодирования;
+//! - переносит только котировки; `Ping`/`Pong` остаются в формате v1.
+
+use crate::error::WireError;
+use crate::types::{Side, StockQuote};
+
+/// Версия фиксированного формата (отличается от [`WIRE_VERSION`](super::WIRE_VERSION))
+pub const FIXED_WIRE_VERSION: u8 = 2;
+
+/// Максимальная длина тикера в байтах
+pub const FIXED_TICKER_LEN: usize = 8;
+
+/// Полный размер пакета в байтах
+pub const FIXED_QUOTE_LEN: usize = 1 + FIXED_TICKER_LEN + 8 + 4 + 8 + 1;
+
+/// Бит в байте версии: время в секундах (`u32`) вместо миллисекунд (`u64`)
+pub const TS_SECONDS_FLAG: u8 = 0x80;
+
+/// Размер пакета с временем в секундах
+pub const FIXED_SECS_QUOTE_LEN: usize = FIXED_QUOTE_LEN - 4;
+
+/// Байт версии относится к фиксированной раскладке (в любом варианте времени)
+pub fn is_fixed_version(ver: u8) -> bool {
+    ver & !TS_SECONDS_FLAG == FIXED_WIRE_VERSION
+}
+
+/// Закодировать котировку в фиксированную раскладку
+pub fn encode_fixed(q: &StockQuote) -> Result<Vec<u8>, WireError> {
+    let mut out = Vec::with_capacity(FIXED_QUOTE_LEN);
+    encode_fixed_into(q, &mut out)?;
+    Ok(out)
+}
+
+/// Закодировать котировку в переиспользуемый буфер (буфер очищается перед записью)
+pub fn encode_fixed_into(q: &StockQuote, out: &mut Vec<u8>) -> Result<(), WireError> {
+    let ts = u64::try_from(q.timestamp_ms)
+        .map_err(|_| WireError::FixedTimestampOverflow(q.timestamp_ms))?;
+
+    encode_with_ts(q, FIXED_WIRE_VERSION, &ts.to_le_bytes(), out);
+    Ok(())
+}
+
+/// Закодировать котировку с временем в секундах (миллисекунды отбрасываются)
+pub fn encode_fixed_secs(q: &StockQuote) -> Result<Vec<u8>, WireError> {
+    let mut out = Vec::with_capacity(FIXED_SECS_QUOTE_LEN);
+    encode_fixed_secs_into(q, &mut out)?;
+    Ok(out)
+}
+
+/// Как [`encode_fixed_secs`], но в переиспользуемый буфер
+pub fn encode_fixed_secs_into(q: &StockQuote, out: &mut Vec<u8>) -> Result<(), WireError> {
+    let secs = u32::try_from(q.timestamp_ms / 1000)
+        .map_err(|_| WireError::FixedTimestampOverflow(q.timestamp_ms))?;
+
+    encode_with_ts(
+        q,
+        FIXED_WIRE_VERSION | TS_SECONDS_FLAG,
+        &secs.to_le_bytes(),
+        out,
+    );
+    Ok(())
+}
+
+fn encode_with_ts(q: &StockQuote, ver: u8, ts: &[u8], out: &mut Vec<u8>) {
+    let mut ticker = [0u8; FIXED_TICKER_LEN];
+    let bytes = q.ticker.as_bytes();
+    let n = bytes.len().min(FIXED_TICKER_LEN);
+    ticker[..n].copy_from_slice(&bytes[..n]);
+
+    out.clear();
+    out.push(ver);
+    out.extend_from_slice(&ticker);
+    out.extend_from_slice(&q.price.to_le_bytes());
+    out.extend_from_slice(&q.volume.to_le_bytes());
+    out.extend_from_slice(ts);
+    out.push(match q.side {
+        None => 0,
+        Some(Side::Buy) => 1,
+        Some(Side::Sell) => 2,
+    });
+}
+
+/// Распаковать котировку из фиксированной раскладки (любой вариант времени)
+pub fn decode_fixed(buf: &[u8]) -> Result<StockQuote, WireError> {
+    let (&ver, _) = buf.split_first().ok_or(WireError::PacketTooShort)?;
+    if !is_fixed_version(ver) {
+        return Err(WireError::UnsupportedWireVersion(ver));
+    }
+    let secs = ver & TS_SECONDS_FLAG != 0;
+    let expected_len = if secs {
+        FIXED_SECS_QUOTE_LEN
+    } else {
+        FIXED_QUOTE_LEN
+    };
+    if buf.len() != expected_len {
+        return Err(WireError::FixedLength(buf.len()));
+    }
+
+    let ticker_raw = &buf[1..1 + FIXED_TICKER_LEN];
+    let ticker_len = ticker_raw
+        .iter()
+        .position(|&b| b == 0)
+        .unwrap_or(FIXED_TICKER_LEN);
+    let ticker = std::str::from_utf8(&ticker_raw[..ticker_len])
+        .map_err(|_| WireError::FixedInvalidTicker)?
+        .to_string();
+
+    let price = i64::from_le_bytes(field(buf, 9));
+    let volume = u32::from_le_bytes(field(buf, 17));
+    let timestamp_ms = if secs {
+        u128::from(u32::from_le_bytes(field(buf, 21))) * 1000
+    } else {
+        u64::from_le_bytes(field(buf, 21)).into()
+    };
+    let side = match buf[expected_len - 1] {
+        0 => None,
+        1 => Some(Side::Buy),
+        2 => Some(Side::Sell),
+        other => return Err(WireError::FixedInvalidSide(other)),
+    };
+
+    Ok(StockQuote {
+        ticker,
+        price,
+        volume,
+        timestamp_ms,
+        side,
+        sent_ms: None,
+    })
+}
+
+/// Поле фиксированного размера по смещению (длина буфера уже проверена)
+fn field<const N: usize>(buf: &[u8], at: usize) -> [u8; N] {
+    let mut out = [0u8; N];
+    out.copy_from_slice(&buf[at..at + N]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(ticker: &str) -> StockQuote {
+        StockQuote {
+            ticker: ticker.to_string(),
+            price: -12_345,
+            volume: u32::MAX,
+            timestamp_ms: 1_700_000_000_000,
+            side: Some(Side::Buy),
+            sent_ms: None,
+        }
+    }
+
+    #[test]
+    fn roundtrip_fixed_quote() {
+        for side in [None, Some(Side::Buy), Some(Side::Sell)] {
+            let q = StockQuote {
+                side,
+                ..quote("AAPL")
+            };
+
+            let bytes = encode_fixed(&q).expect("encode");
+            assert_eq!(bytes.len(), FIXED_QUOTE_LEN);
+            assert_eq!(bytes[0], FIXED_WIRE_VERSION);
+            assert_eq!(decode_fixed(&bytes).expect("decode"), q);
+        }
+    }
+
+    #[test]
+    fn seconds_timestamp_roundtrips_at_second_resolution() {
+        let q = StockQuote {
+            timestamp_ms: 1_700_000_000_999,
+            ..quote("AAPL")
+        };
+
+        let bytes = encode_fixed_secs(&q).expect("encode");
+        assert_eq!(bytes.len(), FIXED_SECS_QUOTE_LEN);
+        assert_eq!(bytes[0], FIXED_WIRE_VERSION | TS_SECONDS_FLAG);
+
+        let got = decode_fixed(&bytes).expect("decode");
+        assert_eq!(got.timestamp_ms, 1_700_000_000_000);
+        assert_eq!(
+            got,
+            StockQuote {
+                timestamp_ms: 1_700_000_000_000,
+                ..q
+            }
+        );
+    }
+
+    #[test]
+    fn millisecond_timestamp_keeps_full_precision() {
+        let q = StockQuote {
+            timestamp_ms: 1_700_000_000_999,
+            ..quote("AAPL")
+        };
+        assert_eq!(decode_fixed(&encode_fixed(&q).unwrap()).unwrap(), q);
+    }
+
+    #[test]
+    fn seconds_timestamp_after_2106_is_rejected() {
+        let q = StockQuote {
+            timestamp_ms: (u128::from(u32::MAX) + 1) * 1000,
+            ..quote("AAPL")
+        };
+        assert!(matches!(
+            encode_fixed_secs(&q),
+            Err(WireError::FixedTimestampOverflow(_))
+        ));
+    }
+
+    #[test]
+    fn ticker_of_exactly_max_len_roundtrips() {
+        let q = quote("ABCDEFGH");
+        assert_eq!(decode_fixed(&encode_fixed(&q).unwrap()).unwrap(), q);
+    }
+
+    #[test]
+    fn long_ticker_is_truncated() {
+        let bytes = encode_fixed(&quote("VERYLONGTICKER")).unwrap();
+        assert_eq!(bytes.len(), FIXED_QUOTE_LEN);
+        assert_eq!(decode_fixed(&bytes).unwrap().ticker, "VERYLONG");
+    }
+
+    #[test]
+    fn timestamp_above_u64_is_rejected() {
+        let q = StockQuote {
+            timestamp_ms: u128::from(u64::MAX) + 1,
+            ..quote("AAPL")
+        };
+        assert!(matches!(
+            encode_fixed(&q),
+            Err(WireError::FixedTimestampOverflow(_))
+        ));
+    }
+
+    #[test]
+    fn decode_fixed_rejects_bad_input() {
+        let bytes = encode_fixed(&quote("AAPL")).unwrap();
+
+        assert!(matches!(
+            decode_fixed(&bytes[..10]),
+            Err(WireError::FixedLength(10))
+        ));
+        assert!(matches!(decode_fixed(&[]), Err(WireError::PacketTooShort)));
+
+        let mut v1 = bytes.clone();
+        v1[0] = super::super::WIRE_VERSION;
+        assert!(matches!(
+            decode_fixed(&v1),
+            Err(WireError::UnsupportedWireVersion(_))
+        ));
+
+        let mut bad_side = bytes;
+        bad_side[FIXED_QUOTE_LEN - 1] = 9;
+        assert!(matches!(
+            decode_fixed(&bad_side),
+            Err(WireError::FixedInvalidSide(9))
+        ));
+    }
+}
@@ -0,0 +1,119 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+/// Общий для всех сессий лимит исходящего UDP-трафика (`--max-udp-bps`).
+///
+/// Token bucket: бюджет пополняется со скоростью `rate` байт/с и копится
+/// не больше чем на секунду вперёд. Пакет, на который бюджета не хватает,
+/// не отправляется (частичного списания нет). Сессии обращаются к бакету
+/// независимо, поэтому при нехватке бюджета теряют котировки примерно
+/// поровну, а не "первая забирает всё".
+#[derive(Debug)]
+pub(crate) struct TokenBucket {
+    rate: u64,
+    state: Mutex<BucketState>,
+    /// Пакеты, не отправленные из-за исчерпанного бюджета
+    dropped: AtomicU64,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// `rate` - байт в секунду (не меньше 1); бакет стартует полным
+    pub(crate) fn new(rate: u64) -> Self {
+        Self::new_at(rate, Instant::now())
+    }
+
+    fn new_at(rate: u64, now: Instant) -> Self {
+        let rate = rate.max(1);
+        Self {
+            rate,
+            state: Mutex::new(BucketState {
+                tokens: rate as f64,
+                last: now,
+            }),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// true - `bytes` списаны из бюджета и пакет можно отправлять;
+    /// false - бюджета нет, пакет учитывается как отброшенный
+    pub(crate) fn try_take(&self, bytes: usize) -> bool {
+        self.try_take_at(bytes, Instant::now())
+    }
+
+    fn try_take_at(&self, bytes: usize, now: Instant) -> bool {
+        let mut st = match self.state.lock() {
+            Ok(g) => g,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let elapsed = now.saturating_duration_since(st.last).as_secs_f64();
+        st.tokens = (st.tokens + elapsed * self.rate as f64).min(self.rate as f64);
+        st.last = st.last.max(now);
+
+        if st.tokens >= bytes as f64 {
+            st.tokens -= bytes as f64;
+            true
+        } else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            false
+        }
+    }
+
+    /// Сколько пакетов отброшено с момента запуска
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn allows_bytes_up_to_rate_and_denies_beyond_it() {
+        let t0 = Instant::now();
+        let bucket = TokenBucket::new_at(1000, t0);
+
+        // полный бакет - секунда трафика
+        assert!(bucket.try_take_at(600, t0));
+        assert!(bucket.try_take_at(400, t0));
+        assert!(!bucket.try_take_at(1, t0));
+
+        // за полсекунды набежало 500 байт
+        let t1 = t0 + Duration::from_millis(500);
+        assert!(!bucket.try_take_at(501, t1), "no partial take");
+        assert!(bucket.try_take_at(500, t1));
+        assert!(!bucket.try_take_at(1, t1));
+        assert_eq!(bucket.dropped(), 3);
+
+        // долгий простой не копит больше секунды бюджета
+        let t2 = t1 + Duration::from_secs(10);
+        assert!(bucket.try_take_at(1000, t2));
+        assert!(!bucket.try_take_at(1, t2));
+    }
+
+    #[test]
+    fn throughput_over_window_stays_within_budget() {
+        let t0 = Instant::now();
+        let bucket = TokenBucket::new_at(10_000, t0);
+
+        // 100-байтные пакеты каждую мс в течение 2 с: спрос 200 КБ/с
+        let sent: usize = (0..2000)
+            .filter(|ms| bucket.try_take_at(100, t0 + Duration::from_millis(*ms)))
+            .count()
+            * 100;
+
+        // стартовый бюджет + 2 с пополнения
+        assert!(sent <= 10_000 + 2 * 10_000, "sent {sent} bytes");
+        assert!(sent >= 2 * 10_000, "sent {sent} bytes");
+        assert!(bucket.dropped() > 0);
+    }
+}
@@ -0,0 +1,144 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub(crate) enum CidrError {
+    #[error("invalid CIDR {0:?}: expected ADDR/PREFIX, e.g. 10.0.0.0/8")]
+    Malformed(String),
+
+    #[error("invalid CIDR {cidr:?}: prefix must be at most {max}")]
+    PrefixTooLong { cidr: String, max: u8 },
+}
+
+/// Подсеть `ADDR/PREFIX` (IPv4 или IPv6); адрес без `/` - одиночный хост
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Cidr {
+    net: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Входит ли адрес в подсеть. IPv4-mapped IPv6 (`::ffff:a.b.c.d`)
+    /// сравнивается как IPv4.
+    pub(crate) fn contains(&self, ip: IpAddr) -> bool {
+        match (self.net, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_eq(u32::from(net).into(), u32::from(ip).into(), self.prefix, 32)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_eq(u128::from(net), u128::from(ip), self.prefix, 128)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Совпадают ли старшие `prefix` бит адресов длины `bits`
+fn prefix_eq(a: u128, b: u128, prefix: u8, bits: u8) -> bool {
+    if prefix == 0 {
+        return true;
+    }
+    let shift = u32::from(bits - prefix);
+    (a >> shift) == (b >> shift)
+}
+
+impl FromStr for Cidr {
+    type Err = CidrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let malformed = || CidrError::Malformed(s.to_string());
+
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let net: IpAddr = addr.parse().map_err(|_| malformed())?;
+        let max = if net.is_ipv4() { 32 } else { 128 };
+
+        let prefix = match prefix {
+            Some(p) => p.parse::<u8>().map_err(|_| malformed())?,
+            None => max,
+        };
+        if prefix > max {
+            return Err(CidrError::PrefixTooLong {
+                cidr: s.to_string(),
+                max,
+            });
+        }
+
+        Ok(Self { net, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.net, self.prefix)
+    }
+}
+
+/// Пустой список - разрешены все адреса
+pub(crate) fn is_allowed(allow: &[Cidr], ip: IpAddr) -> bool {
+    allow.is_empty() || allow.iter().any(|c| c.contains(ip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn contains_matches_prefix_bits() {
+        let net: Cidr = "10.1.0.0/16".parse().unwrap();
+        assert!(net.contains(ip("10.1.255.7")));
+        assert!(!net.contains(ip("10.2.0.1")));
+        assert!(!net.contains(ip("fe80::1")));
+
+        // IPv4-mapped IPv6 от dual-stack сокета
+        assert!(net.contains(ip("::ffff:10.1.2.3")));
+
+        let v6: Cidr = "fd00::/8".parse().unwrap();
+        assert!(v6.contains(ip("fd12:3456::1")));
+        assert!(!v6.contains(ip("fe80::1")));
+
+        let any: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains(ip("203.0.113.9")));
+    }
+
+    #[test]
+    fn bare_address_is_single_host() {
+        let host: Cidr = "127.0.0.1".parse().unwrap();
+        assert_eq!(host.to_string(), "127.0.0.1/32");
+        assert!(host.contains(ip("127.0.0.1")));
+        assert!(!host.contains(ip("127.0.0.2")));
+    }
+
+    #[test]
+    fn parse_rejects_bad_input() {
+        assert!(matches!(
+            "10.0.0.0/33".parse::<Cidr>(),
+            Err(CidrError::PrefixTooLong { max: 32, .. })
+        ));
+        assert!(matches!(
+            "10.0.0/8".parse::<Cidr>(),
+            Err(CidrError::Malformed(_))
+        ));
+        assert!(matches!(
+            "10.0.0.0/x".parse::<Cidr>(),
+            Err(CidrError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn empty_allow_list_allows_everyone() {
+        assert!(is_allowed(&[], ip("198.51.100.1")));
+
+        let allow = ["10.0.0.0/8".parse().unwrap()];
+        assert!(!is_allowed(&allow, ip("198.51.100.1")));
+    }
+}
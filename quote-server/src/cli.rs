@@ -1,8 +1,13 @@
 use clap::{ArgGroup, Parser};
+use quote_core::tickers::TickerCase;
 use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::time::Duration;
 
+use crate::cidr::Cidr;
 use crate::config;
+use crate::tcp::AcceptConfig;
+use crate::udp_tuning::UdpTuning;
 
 /// Quote Server - раздаёт котировки по UDP, управляется по TCP командой STREAM.
 #[derive(Parser, Debug, Clone)]
@@ -20,6 +25,22 @@ pub(crate) struct Args {
     #[arg(long, default_value = config::TCP_BIND_ADDR)]
     pub(crate) tcp_bind: SocketAddr,
 
+    /// Принимать команды через Unix-сокет по этому пути вместо TCP
+    /// (доступ - по правам на файл); котировки по-прежнему идут по UDP
+    #[cfg(unix)]
+    #[arg(long, conflicts_with = "tcp_bind")]
+    pub(crate) control_uds: Option<PathBuf>,
+
+    /// Пауза (мс) перед повтором accept после ошибки (например, кончились
+    /// дескрипторы). TCP и Unix-сокет ждут соединений в блокирующем accept
+    #[arg(long, default_value_t = config::ACCEPT_ERROR_BACKOFF.as_millis() as u64, value_parser = clap::value_parser!(u64).range(1..))]
+    pub(crate) accept_error_backoff_ms: u64,
+
+    /// Длина очереди ещё не принятых соединений (listen backlog); по умолчанию
+    /// 128. Ядро обрезает значение до net.core.somaxconn
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub(crate) tcp_backlog: Option<u32>,
+
     /// UDP bind address, например 0.0.0.0:5556
     #[arg(long, default_value = config::UDP_BIND_ADDR)]
     pub(crate) udp_bind: SocketAddr,
@@ -28,9 +49,239 @@ pub(crate) struct Args {
     #[arg(long, conflicts_with = "tickers")]
     pub(crate) tickers_file: Option<PathBuf>,
 
+    /// Добавить к тикерам из `--tickers-file`/`--tickers` встроенный список
+    /// по умолчанию (совпадения с учётом регистра схлопываются)
+    #[arg(long)]
+    pub(crate) with_default_tickers: bool,
+
+    /// Не подставлять встроенный список тикеров: без `--tickers`/`--tickers-file`
+    /// сервер не запустится (защита от случайного старта с тикерами по умолчанию)
+    #[arg(long, conflicts_with = "with_default_tickers")]
+    pub(crate) no_default_tickers: bool,
+
+    /// Следить за `--tickers-file` и перезагружать тикеры при изменении файла
+    #[arg(long, requires = "tickers_file")]
+    pub(crate) watch_tickers: bool,
+
     /// Источник тикеров: текст. Поддерживает:
     /// - CSV: "AAPL, TSLA, GOOG"
     /// - многострочный текст: "AAPL\nTSLA\n#comment\nGOOG"
     #[arg(long, conflicts_with = "tickers_file")]
     pub(crate) tickers: Option<String>,
+
+    /// Строгая проверка `--tickers`: неверный элемент (пробел внутри, длиннее 16 байт)
+    /// - ошибка запуска с его именем, а не тикер генератора
+    #[arg(long, requires = "tickers")]
+    pub(crate) strict_tickers: bool,
+
+    /// Источник тикеров: HTTP URL (http://host:port/path), построчный текст или JSON-массив
+    #[cfg(feature = "tickers-url")]
+    #[arg(long, conflicts_with_all = ["tickers_file", "tickers"])]
+    pub(crate) tickers_url: Option<String>,
+
+    /// Загрузить только первые N тикеров (после нормализации и сортировки);
+    /// для быстрых тестов на большом файле
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub(crate) tickers_count: Option<u32>,
+
+    /// Файл групп тикеров (`tech = AAPL,MSFT` на строку); клиент подписывается
+    /// на группу как `@tech` в списке тикеров STREAM
+    #[arg(long)]
+    pub(crate) groups_file: Option<PathBuf>,
+
+    /// CSV начальных цен `TICKER,PRICE` (например `AAPL,189.50`); тикеры без цены
+    /// стартуют со случайной, неизвестные серверу - игнорируются с warning
+    #[arg(long)]
+    pub(crate) price_seeds: Option<PathBuf>,
+
+    /// `ID=` приоритетного клиента (флаг можно повторять или перечислить через запятую).
+    /// Под нагрузкой такие клиенты теряют котировки последними
+    #[arg(long, value_delimiter = ',')]
+    pub(crate) priority_client: Vec<String>,
+
+    /// Максимум одновременных сессий; сверх лимита клиент получает
+    /// `ERR E_SERVER_FULL retry_after_ms=<n> ...`
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub(crate) max_sessions: Option<u32>,
+
+    /// Разрешить TCP-подключения только из подсети (`10.0.0.0/8`, `::1`);
+    /// флаг повторяется или список через запятую. По умолчанию - всем
+    #[arg(long, value_delimiter = ',')]
+    pub(crate) allow_cidr: Vec<Cidr>,
+
+    /// Файл счётчиков в текстовом формате Prometheus; перезаписывается раз в 5с
+    /// (например, для node_exporter textfile collector)
+    #[arg(long)]
+    pub(crate) metrics_file: Option<PathBuf>,
+
+    /// Дописывать все сгенерированные котировки в файл, по строке на котировку.
+    /// Запись не тормозит рассылку: если диск не успевает, котировки пропускаются
+    #[arg(long)]
+    pub(crate) record: Option<PathBuf>,
+
+    /// С `--record`: нумеровать строки записи (`seq=<n>`) и хранить следующий
+    /// номер в этом файле, чтобы нумерация продолжалась после перезапуска
+    #[arg(long, requires = "record")]
+    pub(crate) seq_state: Option<PathBuf>,
+
+    /// При остановке сервера дописать в этот файл котировки, оставшиеся
+    /// в очередях сессий (клиентам они уже не уйдут), по строке на котировку
+    #[arg(long)]
+    pub(crate) drain_record: Option<PathBuf>,
+
+    /// Сколько котировок сессия отправляет за одну итерацию, прежде чем
+    /// снова проверить shutdown и ping timeout (не меньше 1)
+    #[arg(long, default_value_t = config::SESSION_DRAIN_BUDGET, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub(crate) session_drain_budget: usize,
+
+    /// Доля заполнения очереди клиента (0..=1), при которой сессия пишет warning
+    #[arg(long, default_value_t = config::SESSION_BACKLOG_WARN_RATIO, value_parser = parse_ratio)]
+    pub(crate) backlog_warn_ratio: f64,
+
+    /// Не приводить тикеры к верхнему регистру (`aapl` и `AAPL` - разные тикеры).
+    /// Действует на список тикеров сервера и на тикеры в STREAM
+    #[arg(long)]
+    pub(crate) preserve_case: bool,
+
+    /// Схлопывать накопившиеся в очереди сессии котировки одного тикера до последней
+    #[arg(long)]
+    pub(crate) coalesce: bool,
+
+    /// Не рассылать котировку тикера, если цена и объём совпадают с
+    /// предыдущей разосланной (время котировки не учитывается)
+    #[arg(long)]
+    pub(crate) dedup: bool,
+
+    /// Сглаживание цен генератора (EMA), доля истории в диапазоне [0, 1).
+    /// Чем ближе к 1, тем плавнее ряд; без флага - чистое случайное блуждание
+    #[arg(long, value_parser = parse_ema_alpha)]
+    pub(crate) ema_alpha: Option<f64>,
+
+    /// Часы торгов по UTC, например `9-17` (или `22-6` через полночь).
+    /// Вне окна генератор молчит; без флага - торги круглосуточно
+    #[arg(long, value_parser = parse_market_hours)]
+    pub(crate) market_hours: Option<(u32, u32)>,
+
+    /// Шаг цены генератора по нормальному закону с этим относительным
+    /// стандартным отклонением (например `0.0005`); без флага - равномерный шаг
+    #[arg(long, value_parser = parse_std_dev)]
+    pub(crate) normal_std_dev: Option<f64>,
+
+    /// Прогнать модель цен N тиков до старта рассылки, чтобы первые
+    /// котировки не начинались со случайной стартовой цены
+    #[arg(long, default_value_t = 0)]
+    pub(crate) warmup_ticks: u32,
+
+    /// Генерировать котировки только по тикерам, на которые подписана хотя бы
+    /// одна сессия (экономия CPU при большом списке тикеров и малом числе клиентов)
+    #[arg(long)]
+    pub(crate) on_demand: bool,
+
+    /// Отвечать на ping клиента пакетом Pong (клиент сможет заметить, что сервер умер)
+    #[arg(long)]
+    pub(crate) enable_pong: bool,
+
+    /// IP TTL (для IPv6 - hop limit) UDP-пакетов с котировками
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=255))]
+    pub(crate) udp_ttl: Option<u32>,
+
+    /// Байт ToS / Traffic Class UDP-пакетов с котировками: DSCP в старших
+    /// 6 битах (например `184` = DSCP EF)
+    #[arg(long)]
+    pub(crate) udp_tos: Option<u8>,
+
+    /// Общий лимит исходящего UDP-трафика с котировками, байт/с, на все сессии
+    /// сразу; котировки сверх лимита отбрасываются. Не меньше 1500
+    #[arg(long, value_parser = clap::value_parser!(u64).range(config::MIN_MAX_UDP_BPS..))]
+    pub(crate) max_udp_bps: Option<u64>,
+
+    /// Проверить wire-формат (encode/decode) при старте и упасть при ошибке
+    #[arg(long)]
+    pub(crate) self_test: bool,
+
+    /// Напечатать итоговую конфигурацию (умолчания + флаги) в TOML и выйти
+    #[arg(long)]
+    pub(crate) dump_config: bool,
+
+    /// Выдавать клиенту токен сессии в ответе `OK <token>` и принимать
+    /// `PingToken` с любого адреса (для клиентов за NAT)
+    #[arg(long)]
+    pub(crate) ping_token: bool,
+
+    /// Разрешить диагностические команды (`DEBUG pings`) на управляющем канале
+    #[arg(long)]
+    pub(crate) debug_commands: bool,
+
+    /// Писать лог JSON-строками (`{"level":..,"ts":..,"msg":..}`) вместо текста
+    #[arg(long)]
+    pub(crate) log_json: bool,
+}
+
+impl Args {
+    /// `--accept-error-backoff-ms`/`--tcp-backlog`
+    pub(crate) fn accept_config(&self) -> AcceptConfig {
+        AcceptConfig {
+            error_backoff: Duration::from_millis(self.accept_error_backoff_ms),
+            backlog: self.tcp_backlog,
+        }
+    }
+
+    /// `--udp-ttl`/`--udp-tos`
+    pub(crate) fn udp_tuning(&self) -> UdpTuning {
+        UdpTuning {
+            ttl: self.udp_ttl,
+            tos: self.udp_tos,
+        }
+    }
+
+    /// Нормализация регистра тикеров по флагу `--preserve-case`
+    pub(crate) fn ticker_case(&self) -> TickerCase {
+        if self.preserve_case {
+            TickerCase::Preserve
+        } else {
+            TickerCase::Upper
+        }
+    }
+}
+
+fn parse_ema_alpha(s: &str) -> Result<f64, String> {
+    let v: f64 = s.parse().map_err(|e| format!("{e}"))?;
+    if !(0.0..1.0).contains(&v) {
+        return Err(format!("must be in [0, 1), got {v}"));
+    }
+    Ok(v)
+}
+
+fn parse_std_dev(s: &str) -> Result<f64, String> {
+    let v: f64 = s.parse().map_err(|e| format!("{e}"))?;
+    if !(v.is_finite() && v > 0.0) {
+        return Err(format!("must be a positive number, got {v}"));
+    }
+    Ok(v)
+}
+
+fn parse_market_hours(s: &str) -> Result<(u32, u32), String> {
+    let (start, end) = s
+        .split_once('-')
+        .ok_or_else(|| format!("expected START-END, got {s:?}"))?;
+    let hour = |v: &str| -> Result<u32, String> {
+        let h: u32 = v.trim().parse().map_err(|e| format!("{e}"))?;
+        if h > 23 {
+            return Err(format!("hour must be in [0, 23], got {h}"));
+        }
+        Ok(h)
+    };
+    let (start, end) = (hour(start)?, hour(end)?);
+    if start == end {
+        return Err(format!("empty market window: {s}"));
+    }
+    Ok((start, end))
+}
+
+fn parse_ratio(s: &str) -> Result<f64, String> {
+    let v: f64 = s.parse().map_err(|e| format!("{e}"))?;
+    if !(0.0..=1.0).contains(&v) {
+        return Err(format!("must be in [0, 1], got {v}"));
+    }
+    Ok(v)
 }
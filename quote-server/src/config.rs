@@ -1,3 +1,5 @@
+use quote_core::Encoding;
+use quote_core::tickers::TickerCase;
 use std::io;
 use std::io::Cursor;
 use std::path::PathBuf;
@@ -8,16 +10,180 @@ const DEFAULT_TICKERS: &str = include_str!("../assets/tickers.txt");
 pub(crate) const UDP_SOCKET_TICK: Duration = Duration::from_millis(10);
 pub(crate) use quote_core::PING_TIMEOUT;
 
+/// Сколько котировок сессия разбирает из очереди за одну итерацию
+pub(crate) const SESSION_DRAIN_BUDGET: usize = 64;
+
+/// Сессия `ACK=1`: один `Checkpoint` на столько отправленных котировок
+pub(crate) const CHECKPOINT_EVERY_QUOTES: u64 = 100;
+
+/// Кодировки `ENC=`, которые сервер согласует (остальные - `E_UNSUPPORTED_ENCODING`)
+pub(crate) const SUPPORTED_ENCODINGS: &[Encoding] = &[Encoding::V1, Encoding::Fixed];
+
+/// Ёмкость очереди котировок клиента в хабе (на единицу веса)
+pub(crate) const CLIENT_QUEUE_CAPACITY: usize = 256;
+
+/// Порог заполнения очереди клиента для warning (доля от ёмкости)
+pub(crate) const SESSION_BACKLOG_WARN_RATIO: f64 = 0.75;
+
 pub(crate) const QUOTE_INTERVAL: Duration = Duration::from_millis(500);
 
+/// Сколько сессии дорассылают накопленные котировки после Ctrl+C
+pub(crate) const SHUTDOWN_DRAIN_GRACE: Duration = Duration::from_millis(500);
+
+/// Наименьший `--max-udp-bps`: бакет (запас - секунда трафика) должен вмещать
+/// любой UDP-пакет с котировкой, иначе отбрасывались бы все котировки
+pub(crate) const MIN_MAX_UDP_BPS: u64 = 1500;
+
+/// Пауза перед повтором accept после ошибки (`--accept-error-backoff-ms`)
+pub(crate) const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Период проверки mtime файла тикеров (`--watch-tickers`)
+pub(crate) const TICKERS_WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Ёмкость очереди записи `--record` (котировок)
+pub(crate) const RECORD_QUEUE_CAPACITY: usize = 4096;
+
+/// Как часто сохраняется номер `--seq-state` (и всегда - при остановке записи)
+pub(crate) const SEQ_STATE_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Период перезаписи `--metrics-file`
+pub(crate) const METRICS_FILE_INTERVAL: Duration = Duration::from_secs(5);
+
+#[cfg(feature = "tickers-url")]
+pub(crate) const TICKERS_URL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Подсказка `retry_after_ms` в `ERR E_SERVER_FULL`: место освобождается,
+/// когда чья-то сессия истекает по keep-alive
+pub(crate) const SERVER_FULL_RETRY_AFTER: Duration = PING_TIMEOUT;
+
+/// Вес в хабе клиентов из `--priority-client`
+pub(crate) const PRIORITY_CLIENT_WEIGHT: u32 = 4;
+
+/// Максимальная длина команды по TCP (без `\n`)
+pub(crate) const MAX_COMMAND_LINE_LEN: usize = 4096;
+
 pub(crate) const TCP_BIND_ADDR: &str = "0.0.0.0:5555";
 pub(crate) const UDP_BIND_ADDR: &str = "0.0.0.0:5556";
 
-pub(crate) fn load_server_tickers(path: Option<PathBuf>) -> io::Result<Vec<String>> {
+pub(crate) fn load_server_tickers(
+    path: Option<PathBuf>,
+    case: TickerCase,
+) -> io::Result<Vec<String>> {
     match path {
-        Some(p) => quote_core::tickers::read_tickers_from_path(p),
-        None => quote_core::tickers::read_tickers(Cursor::new(DEFAULT_TICKERS)),
+        Some(p) => {
+            let (tickers, report) =
+                quote_core::tickers::read_tickers_with_report(std::fs::File::open(&p)?, case)?;
+            log::info!("tickers file {p:?}: {report}");
+            for t in &report.invalid {
+                log::warn!("tickers file {p:?}: invalid ticker {t:?} dropped");
+            }
+            Ok(tickers)
+        }
+        None => quote_core::tickers::read_tickers_with_case(Cursor::new(DEFAULT_TICKERS), case),
     }
 }
 
+/// Тикеры из явного источника плюс встроенные по умолчанию (`--with-default-tickers`).
+/// Совпадения после нормализации регистра дают один тикер.
+pub(crate) fn merge_with_default_tickers(
+    tickers: &[String],
+    case: TickerCase,
+) -> io::Result<Vec<String>> {
+    let defaults = load_server_tickers(None, case)?;
+    Ok(quote_core::tickers::merge_tickers(
+        [tickers, defaults.as_slice()],
+        case,
+    ))
+}
+
+/// Тикеры из `--tickers`: CSV или многострочный текст (с переводом строки
+/// или `#` - по одному на строку). `strict` (`--strict-tickers`) - неверный
+/// элемент даёт ошибку с его именем вместо тикера генератора.
+pub(crate) fn parse_server_tickers_text(
+    raw: &str,
+    case: TickerCase,
+    strict: bool,
+) -> anyhow::Result<Vec<String>> {
+    let raw_trimmed = raw.trim();
+    if raw_trimmed.is_empty() {
+        anyhow::bail!("tickers text is empty");
+    }
+
+    let by_lines = raw_trimmed.contains('\n') || raw_trimmed.contains('#');
+    if strict {
+        let checked = if by_lines {
+            quote_core::tickers::check_tickers_lines(raw_trimmed)
+        } else {
+            quote_core::tickers::check_tickers_csv(raw_trimmed)
+        };
+        if let Err(rejects) = checked {
+            anyhow::bail!(
+                "invalid --tickers value: {}",
+                quote_core::tickers::describe_invalid_tickers(&rejects)
+            );
+        }
+    }
+
+    let v = if by_lines {
+        quote_core::tickers::read_tickers_with_case(Cursor::new(raw_trimmed), case)?
+    } else {
+        quote_core::tickers::parse_tickers_csv_with_case(raw_trimmed, case)
+    };
+
+    if v.is_empty() {
+        anyhow::bail!("tickers list is empty (--tickers)");
+    }
+    Ok(v)
+}
+
 pub(crate) type ClientId = u64;
+
+/// Токен сессии, который клиент возвращает в `PingToken`
+pub(crate) type SessionToken = u64;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::{GeneratorConfig, QuoteGenerator};
+
+    #[test]
+    fn merged_file_and_default_tickers_give_single_generator_state() {
+        let file = quote_core::tickers::read_tickers_with_case(
+            Cursor::new("aapl\nzzzz\n"),
+            TickerCase::Upper,
+        )
+        .unwrap();
+        let defaults = load_server_tickers(None, TickerCase::Upper).unwrap();
+        assert!(defaults.contains(&"AAPL".to_string()));
+
+        let merged = merge_with_default_tickers(&file, TickerCase::Upper).unwrap();
+        assert_eq!(merged.len(), defaults.len() + 1, "only ZZZZ is new");
+
+        let g = QuoteGenerator::with_seed(merged, GeneratorConfig::default(), 1);
+        let tickers = g.tickers();
+        assert_eq!(tickers.iter().filter(|t| *t == "AAPL").count(), 1);
+        assert!(tickers.contains(&"ZZZZ".to_string()));
+    }
+
+    #[test]
+    fn strict_tickers_text_error_names_rejected_token() {
+        let err =
+            parse_server_tickers_text("AAPL,TOO_LONG_SYMBOL_NAME,TSLA", TickerCase::Upper, true)
+                .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid --tickers value: invalid ticker \"TOO_LONG_SYMBOL_NAME\": longer than 16 bytes"
+        );
+
+        let err =
+            parse_server_tickers_text("AAPL\nBRK A # note\n", TickerCase::Upper, true).unwrap_err();
+        assert!(err.to_string().contains("\"BRK A\""), "{err}");
+
+        // без --strict-tickers прежнее поведение
+        assert_eq!(
+            parse_server_tickers_text("aapl,TOO_LONG_SYMBOL_NAME", TickerCase::Upper, false)
+                .unwrap(),
+            vec!["AAPL", "TOO_LONG_SYMBOL_NAME"]
+        );
+    }
+}
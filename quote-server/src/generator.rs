@@ -1,7 +1,10 @@
-use quote_core::StockQuote;
-use rand::Rng;
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+use quote_core::{Side, StockQuote};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::collections::{HashMap, HashSet};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[derive(Debug, Clone)]
 pub(crate) struct GeneratorConfig {
@@ -9,6 +12,36 @@ pub(crate) struct GeneratorConfig {
     pub(crate) max_rel_step: f64,
     /// Минимальная допустимая цена
     pub(crate) min_price: i64,
+    /// Сглаживание (EMA) цены: доля истории в `ema = alpha * ema + (1 - alpha) * raw`.
+    /// Чем ближе к 1, тем плавнее ряд. `None` - чистое случайное блуждание.
+    pub(crate) ema_alpha: Option<f64>,
+    /// Часы работы "биржи" по UTC: `(начало, конец)`, конец не включается.
+    /// Вне окна `next_batch` ничего не генерирует. Окно может переходить
+    /// через полночь: `(22, 6)`. `None` - торги круглосуточно.
+    pub(crate) market_hours: Option<(u32, u32)>,
+    /// Распределение относительного шага цены
+    pub(crate) distribution: StepDistribution,
+    /// Начальные цены (`--price-seeds`); остальные тикеры стартуют со случайной цены
+    pub(crate) initial_prices: HashMap<String, i64>,
+    /// Коррелированные группы (сектора): `(тикеры, rho)`, rho в `[0, 1]`.
+    /// Шаг тикера группы: `rho * общий + sqrt(1 - rho^2) * собственный`, общий
+    /// шаг разыгрывается раз за тик на группу. Тикер из нескольких групп
+    /// относится к первой.
+    pub(crate) correlation_groups: Vec<(Vec<String>, f64)>,
+    /// Сколько тиков модель цен прогоняется при создании генератора, до первой
+    /// рассылки: цены стартуют уже "прожитыми", а не со случайного начального значения
+    pub(crate) warmup_ticks: u32,
+}
+
+/// Распределение относительного шага цены за тик
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) enum StepDistribution {
+    /// Равномерно в `(-max_rel_step, max_rel_step)`
+    #[default]
+    Uniform,
+    /// Нормальное с нулевым средним: мелкие движения частые, крупные - редкие.
+    /// Шаг обрезается до `±max_rel_step`.
+    Normal { std_dev: f64 },
 }
 
 impl Default for GeneratorConfig {
@@ -16,19 +49,60 @@ impl Default for GeneratorConfig {
         Self {
             max_rel_step: 0.002,
             min_price: 1,
+            ema_alpha: None,
+            market_hours: None,
+            distribution: StepDistribution::Uniform,
+            initial_prices: HashMap::new(),
+            correlation_groups: Vec::new(),
+            warmup_ticks: 0,
         }
     }
 }
 
+/// Источник текущего времени (подменяется в тестах)
+pub(crate) type Clock = fn() -> SystemTime;
+
+/// "Есть ли подписчики у тикера?" (`--on-demand`): тикеры без спроса пропускаются
+pub(crate) type Demand = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Открыт ли рынок в момент `now` при окне `(start, end)` часов UTC
+fn market_open(hours: (u32, u32), now: SystemTime) -> bool {
+    let secs = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let hour = ((secs / 3600) % 24) as u32;
+
+    let (start, end) = hours;
+    if start <= end {
+        (start..end).contains(&hour)
+    } else {
+        hour >= start || hour < end
+    }
+}
+
+/// N(0, 1) по Бокса-Мюллеру (rand_distr не тянем ради одного сэмплера)
+fn standard_normal(rng: &mut StdRng) -> f64 {
+    // u1 в (0, 1]: ln(0) не определён
+    let u1 = 1.0 - rng.random::<f64>();
+    let u2 = rng.random::<f64>();
+    (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+}
+
 /// Внутреннее состояние тикера.
 #[derive(Debug, Clone)]
 struct TickerState {
     price: i64,
+    /// "сырое" случайное блуждание (используется только с ema_alpha)
+    raw: f64,
+    /// EMA сырого блуждания (используется только с ema_alpha)
+    ema: f64,
 }
 
 pub(crate) struct QuoteGenerator {
     cfg: GeneratorConfig,
     states: HashMap<String, TickerState>,
+    rng: StdRng,
+    clock: Clock,
+    /// None - котировки по всем тикерам
+    demand: Option<Demand>,
 
     /// Набор "высоколиквидных" тикеров для более крупного volume.
     high_volume: HashSet<String>,
@@ -36,14 +110,21 @@ pub(crate) struct QuoteGenerator {
 
 impl QuoteGenerator {
     pub(crate) fn new(tickers: Vec<String>, cfg: GeneratorConfig) -> Self {
-        let mut rng = rand::rng();
+        Self::with_rng(tickers, cfg, StdRng::from_os_rng())
+    }
 
+    /// Детерминированный генератор (для тестов и воспроизводимых демо)
+    #[cfg(test)]
+    pub(crate) fn with_seed(tickers: Vec<String>, cfg: GeneratorConfig, seed: u64) -> Self {
+        Self::with_rng(tickers, cfg, StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(tickers: Vec<String>, cfg: GeneratorConfig, mut rng: StdRng) -> Self {
         let states = tickers
             .into_iter()
             .map(|t| {
-                let start_price = rng.random_range(5000..50000);
-
-                (t, TickerState { price: start_price })
+                let st = initial_state(&cfg, &mut rng, &t);
+                (t, st)
             })
             .collect::<HashMap<_, _>>();
 
@@ -52,22 +133,127 @@ impl QuoteGenerator {
             .map(|s| s.to_string())
             .collect::<HashSet<_>>();
 
-        Self {
+        let mut g = Self {
             cfg,
             states,
+            rng,
+            clock: SystemTime::now,
+            demand: None,
             high_volume,
+        };
+        g.warm_up(g.cfg.warmup_ticks);
+        g
+    }
+
+    /// Генерировать котировки только по тикерам, для которых `demand` - true.
+    /// Цены тикеров без спроса стоят, пока на них никто не подписан
+    pub(crate) fn with_demand(mut self, demand: Demand) -> Self {
+        self.demand = Some(demand);
+        self
+    }
+
+    /// Прогнать модель цен `ticks` раз без выдачи котировок.
+    /// `market_hours` не проверяется: прогрев идёт до старта рассылки
+    pub(crate) fn warm_up(&mut self, ticks: u32) {
+        for _ in 0..ticks {
+            self.step_all(0);
+        }
+    }
+
+    /// Начать генерировать котировки по тикеру; false - тикер уже есть
+    pub(crate) fn add_ticker(&mut self, ticker: String) -> bool {
+        if self.states.contains_key(&ticker) {
+            return false;
         }
+        let st = initial_state(&self.cfg, &mut self.rng, &ticker);
+        self.states.insert(ticker, st);
+        true
     }
 
-    /// сгенерировать котировку для тикера
+    /// Перестать генерировать котировки по тикеру; false - тикера не было
+    pub(crate) fn remove_ticker(&mut self, ticker: &str) -> bool {
+        self.states.remove(ticker).is_some()
+    }
+
+    /// Приводит набор тикеров к `tickers` через `add_ticker`/`remove_ticker`.
+    /// Цены оставшихся тикеров не сбрасываются. Возвращает (добавлено, удалено).
+    pub(crate) fn set_tickers(&mut self, tickers: &[String]) -> (usize, usize) {
+        let wanted: HashSet<&str> = tickers.iter().map(String::as_str).collect();
+
+        let stale: Vec<String> = self
+            .states
+            .keys()
+            .filter(|t| !wanted.contains(t.as_str()))
+            .cloned()
+            .collect();
+        let removed = stale.iter().filter(|t| self.remove_ticker(t)).count();
+
+        let added = tickers
+            .iter()
+            .filter(|t| self.add_ticker(t.to_string()))
+            .count();
+
+        (added, removed)
+    }
+
+    /// Текущий набор тикеров (отсортирован)
+    #[cfg(test)]
+    pub(crate) fn tickers(&self) -> Vec<String> {
+        let mut v: Vec<String> = self.states.keys().cloned().collect();
+        v.sort();
+        v
+    }
+
+    /// Подменить источник времени (для тестов)
+    #[cfg(test)]
+    pub(crate) fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// сгенерировать котировку для тикера (в работе сервера - через `next_batch`)
+    #[cfg(test)]
     pub(crate) fn next_quote(&mut self, ticker: &str) -> Option<StockQuote> {
+        let ts_ms = self.now_ms();
+        self.next_quote_at(ticker, ts_ms, None)
+    }
+
+    /// текущее время по `clock` в мс от эпохи
+    fn now_ms(&self) -> u64 {
+        (self.clock)()
+            .duration_since(UNIX_EPOCH)
+            .expect("time went backwards")
+            .as_millis() as u64
+    }
+
+    /// `common` - общий шаг группы тикера и её rho (см. `correlation_groups`)
+    fn next_quote_at(
+        &mut self,
+        ticker: &str,
+        ts_ms: u64,
+        common: Option<(f64, f64)>,
+    ) -> Option<StockQuote> {
         let st = self.states.get_mut(ticker)?;
 
-        let mut rng = rand::rng();
+        let step = self.cfg.max_rel_step;
+        let own = draw_step(&self.cfg, &mut self.rng);
+        let delta = match common {
+            None => own,
+            Some((shock, rho)) => (rho * shock + (1.0 - rho * rho).sqrt() * own).clamp(-step, step),
+        };
 
-        let delta = rng.random_range(-self.cfg.max_rel_step..self.cfg.max_rel_step);
+        let rng = &mut self.rng;
 
-        st.price = (((1.0 + delta) * (st.price as f64)).round() as i64).max(self.cfg.min_price);
+        st.price = match self.cfg.ema_alpha {
+            None => ((1.0 + delta) * (st.price as f64)).round() as i64,
+            Some(alpha) => {
+                // блуждание идёт "под капотом", наружу отдаём его EMA
+                st.raw = ((1.0 + delta) * st.raw).max(self.cfg.min_price as f64);
+                st.ema = alpha * st.ema + (1.0 - alpha) * st.raw;
+                st.ema.round() as i64
+            }
+        }
+        .max(self.cfg.min_price);
 
         // volume: популярные -> больше
         let volume = if self.high_volume.contains(ticker) {
@@ -76,27 +262,65 @@ impl QuoteGenerator {
             100 + rng.random_range(0..1000)
         };
 
-        let timestamp_ms = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("time went backwards")
-            .as_millis();
+        let side = if rng.random_bool(0.5) {
+            Side::Buy
+        } else {
+            Side::Sell
+        };
 
         Some(StockQuote {
             ticker: ticker.to_string(),
             price: st.price,
             volume,
-            timestamp_ms,
+            timestamp_ms: ts_ms.into(),
+            side: Some(side),
+            sent_ms: None,
         })
     }
 
     /// сгенерировать котировки для всех тикеров
     pub(crate) fn next_batch(&mut self) -> Vec<StockQuote> {
-        let keys: Vec<String> = self.states.keys().cloned().collect();
+        let ts_ms = self.now_ms();
+        self.next_batch_at(ts_ms)
+    }
+
+    /// Как [`Self::next_batch`], но все котировки получают время `ts_ms`
+    /// (воспроизводимые фикстуры, дозаполнение истории).
+    /// Окно `market_hours` проверяется по этому же времени.
+    pub(crate) fn next_batch_at(&mut self, ts_ms: u64) -> Vec<StockQuote> {
+        if let Some(hours) = self.cfg.market_hours
+            && !market_open(hours, UNIX_EPOCH + Duration::from_millis(ts_ms))
+        {
+            return Vec::new();
+        }
+
+        self.step_all(ts_ms)
+    }
+
+    /// Один тик модели по всем тикерам со спросом (общие шаги групп - один раз на тик)
+    fn step_all(&mut self, ts_ms: u64) -> Vec<StockQuote> {
+        let keys: Vec<String> = self
+            .states
+            .keys()
+            .filter(|t| self.demand.as_ref().is_none_or(|wanted| wanted(t)))
+            .cloned()
+            .collect();
+
+        // общий шаг каждой группы - один на тик
+        let shocks: Vec<f64> = (0..self.cfg.correlation_groups.len())
+            .map(|_| draw_step(&self.cfg, &mut self.rng))
+            .collect();
 
         let mut out = Vec::with_capacity(keys.len());
 
         for t in keys {
-            if let Some(q) = self.next_quote(&t) {
+            let common = self
+                .cfg
+                .correlation_groups
+                .iter()
+                .position(|(members, _)| members.contains(&t))
+                .map(|i| (shocks[i], self.cfg.correlation_groups[i].1.clamp(0.0, 1.0)));
+            if let Some(q) = self.next_quote_at(&t, ts_ms, common) {
                 out.push(q);
             }
         }
@@ -104,3 +328,341 @@ impl QuoteGenerator {
         out
     }
 }
+
+/// Относительный шаг цены по `cfg.distribution`, в пределах `±max_rel_step`
+fn draw_step(cfg: &GeneratorConfig, rng: &mut StdRng) -> f64 {
+    let step = cfg.max_rel_step;
+    match cfg.distribution {
+        StepDistribution::Uniform => rng.random_range(-step..step),
+        StepDistribution::Normal { std_dev } => (standard_normal(rng) * std_dev).clamp(-step, step),
+    }
+}
+
+/// Начальное состояние тикера: цена из `initial_prices` или случайная
+fn initial_state(cfg: &GeneratorConfig, rng: &mut StdRng, ticker: &str) -> TickerState {
+    let start_price = match cfg.initial_prices.get(ticker) {
+        Some(&price) => price,
+        None => rng.random_range(5000..50000),
+    };
+
+    TickerState {
+        price: start_price,
+        raw: start_price as f64,
+        ema: start_price as f64,
+    }
+}
+
+/// Команды потоку генератора
+#[derive(Debug)]
+pub(crate) enum GeneratorCmd {
+    /// Завершить цикл
+    Stop,
+    /// Заменить набор тикеров (перезагрузка `--tickers-file`)
+    SetTickers(Vec<String>),
+}
+
+/// Цикл генератора: батч котировок раз в `interval`, каждая - в `on_quote`.
+///
+/// Пауза между батчами - ожидание на `cmds`: `Stop` или закрытие канала
+/// завершает цикл сразу, не досыпая `interval`.
+pub(crate) fn run_generator(
+    mut q_gen: QuoteGenerator,
+    interval: Duration,
+    cmds: &Receiver<GeneratorCmd>,
+    mut on_quote: impl FnMut(StockQuote),
+) {
+    loop {
+        for q in q_gen.next_batch() {
+            on_quote(q);
+        }
+
+        match cmds.recv_timeout(interval) {
+            Err(RecvTimeoutError::Timeout) => {}
+            Ok(GeneratorCmd::SetTickers(tickers)) => {
+                let (added, removed) = q_gen.set_tickers(&tickers);
+                log::info!("generator tickers reloaded: +{added} -{removed}");
+            }
+            Ok(GeneratorCmd::Stop) | Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Дисперсия приращений цены за `ticks` тиков
+    fn step_variance(cfg: GeneratorConfig, seed: u64, ticks: usize) -> f64 {
+        let mut g = QuoteGenerator::with_seed(vec!["AAPL".to_string()], cfg, seed);
+
+        let prices: Vec<f64> = (0..ticks)
+            .map(|_| g.next_quote("AAPL").unwrap().price as f64)
+            .collect();
+        let steps: Vec<f64> = prices.windows(2).map(|w| w[1] - w[0]).collect();
+
+        let mean = steps.iter().sum::<f64>() / steps.len() as f64;
+        steps.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / steps.len() as f64
+    }
+
+    /// Дисперсия относительных шагов цены (не зависит от уровня цены)
+    fn rel_step_variance(cfg: GeneratorConfig, seed: u64, ticks: usize) -> f64 {
+        let mut g = QuoteGenerator::with_seed(vec!["AAPL".to_string()], cfg, seed);
+
+        let prices: Vec<f64> = (0..ticks)
+            .map(|_| g.next_quote("AAPL").unwrap().price as f64)
+            .collect();
+        let steps: Vec<f64> = prices.windows(2).map(|w| w[1] / w[0] - 1.0).collect();
+
+        let mean = steps.iter().sum::<f64>() / steps.len() as f64;
+        steps.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / steps.len() as f64
+    }
+
+    #[test]
+    fn normal_steps_have_expected_spread_relative_to_uniform() {
+        // U(-s, s): s^2 / 3; N(0, s/4) после обрезки на 4 сигмах: ~s^2 / 16
+        let s = 0.01;
+        let uniform = rel_step_variance(
+            GeneratorConfig {
+                max_rel_step: s,
+                ..GeneratorConfig::default()
+            },
+            42,
+            20_000,
+        );
+        let normal = rel_step_variance(
+            GeneratorConfig {
+                max_rel_step: s,
+                distribution: StepDistribution::Normal { std_dev: s / 4.0 },
+                ..GeneratorConfig::default()
+            },
+            42,
+            20_000,
+        );
+
+        let ratio = normal / uniform;
+        assert!(
+            (ratio - 3.0 / 16.0).abs() < 0.03,
+            "normal var={normal}, uniform var={uniform}, ratio={ratio}"
+        );
+        assert!((uniform - s * s / 3.0).abs() < s * s / 3.0 * 0.1);
+    }
+
+    #[test]
+    fn ema_with_high_alpha_is_smoother_than_random_walk() {
+        let raw = step_variance(GeneratorConfig::default(), 42, 2000);
+        let smooth = step_variance(
+            GeneratorConfig {
+                ema_alpha: Some(0.9),
+                ..GeneratorConfig::default()
+            },
+            42,
+            2000,
+        );
+
+        assert!(
+            smooth < raw / 2.0,
+            "expected smoother series: ema var={smooth}, raw var={raw}"
+        );
+    }
+
+    /// 2023-11-14 10:00 UTC
+    fn at_10_utc() -> SystemTime {
+        UNIX_EPOCH + std::time::Duration::from_secs(1_699_956_000)
+    }
+
+    /// 2023-11-14 23:00 UTC
+    fn at_23_utc() -> SystemTime {
+        UNIX_EPOCH + std::time::Duration::from_secs(1_699_956_000 + 13 * 3600)
+    }
+
+    fn market_gen(hours: (u32, u32), clock: Clock) -> QuoteGenerator {
+        let cfg = GeneratorConfig {
+            market_hours: Some(hours),
+            ..GeneratorConfig::default()
+        };
+        QuoteGenerator::with_seed(vec!["AAPL".to_string()], cfg, 1).with_clock(clock)
+    }
+
+    #[test]
+    fn market_hours_emit_quotes_only_inside_window() {
+        let mut open = market_gen((9, 17), at_10_utc);
+        let batch = open.next_batch();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].timestamp_ms, 1_699_956_000_000);
+
+        assert!(market_gen((9, 17), at_23_utc).next_batch().is_empty());
+    }
+
+    #[test]
+    fn market_hours_window_can_wrap_midnight() {
+        assert!(!market_gen((22, 6), at_23_utc).next_batch().is_empty());
+        assert!(market_gen((22, 6), at_10_utc).next_batch().is_empty());
+    }
+
+    #[test]
+    fn next_batch_at_stamps_all_quotes_with_given_time() {
+        let tickers = ["AAPL", "MSFT", "TSLA"].map(String::from).to_vec();
+        let mut g = QuoteGenerator::with_seed(tickers, GeneratorConfig::default(), 3);
+
+        let batch = g.next_batch_at(1_600_000_000_123);
+
+        assert_eq!(batch.len(), 3);
+        assert!(batch.iter().all(|q| q.timestamp_ms == 1_600_000_000_123));
+    }
+
+    #[test]
+    fn run_generator_exits_promptly_when_woken_mid_sleep() {
+        let (cmd_tx, cmd_rx) = crossbeam_channel::unbounded();
+        let (quote_tx, quote_rx) = crossbeam_channel::unbounded();
+
+        let g = QuoteGenerator::with_seed(vec!["AAPL".to_string()], Default::default(), 5);
+        let h = std::thread::spawn(move || {
+            run_generator(g, Duration::from_secs(30), &cmd_rx, |q| {
+                quote_tx.send(q).unwrap();
+            });
+        });
+
+        // первый батч отдан, генератор уснул на 30с
+        quote_rx.recv_timeout(Duration::from_secs(2)).unwrap();
+
+        let signaled = std::time::Instant::now();
+        cmd_tx.send(GeneratorCmd::Stop).unwrap();
+        h.join().unwrap();
+
+        assert!(
+            signaled.elapsed() < Duration::from_secs(1),
+            "generator exit took {:?}",
+            signaled.elapsed()
+        );
+    }
+
+    #[test]
+    fn warmup_moves_prices_within_plausible_bounds() {
+        let seed_price = 10_000;
+        let ticks = 1_000;
+        let cfg = GeneratorConfig {
+            initial_prices: [("AAPL".to_string(), seed_price)].into(),
+            warmup_ticks: ticks,
+            ..GeneratorConfig::default()
+        };
+        let max_rel_step = cfg.max_rel_step;
+
+        let g = QuoteGenerator::with_seed(vec!["AAPL".to_string()], cfg, 11);
+        let price = g.states["AAPL"].price;
+
+        // модель отработала
+        assert_ne!(price, seed_price);
+        // но не дальше, чем позволяет максимальный шаг за все тики
+        let bound = (1.0 + max_rel_step).powi(ticks as i32);
+        assert!(
+            (price as f64) < seed_price as f64 * bound
+                && (price as f64) > seed_price as f64 / bound,
+            "price {price} after {ticks} ticks from {seed_price}"
+        );
+        assert!(price >= GeneratorConfig::default().min_price);
+    }
+
+    #[test]
+    fn initial_prices_override_random_start() {
+        let seeds = crate::price_seeds::parse_price_seeds(
+            std::io::Cursor::new(
+                "AAPL,189.50
+MSFT,410
+",
+            ),
+            quote_core::tickers::TickerCase::Upper,
+        )
+        .unwrap();
+        let cfg = GeneratorConfig {
+            initial_prices: seeds,
+            ..GeneratorConfig::default()
+        };
+
+        let tickers = ["AAPL", "MSFT", "TSLA"].map(String::from).to_vec();
+        let g = QuoteGenerator::with_seed(tickers, cfg, 9);
+
+        assert_eq!(g.states["AAPL"].price, 18_950);
+        assert_eq!(g.states["MSFT"].price, 41_000);
+        // нет в файле - случайная цена
+        assert!((5000..50000).contains(&g.states["TSLA"].price));
+    }
+
+    #[test]
+    fn fully_correlated_tickers_move_together() {
+        let tickers = ["AAPL", "MSFT", "TSLA"].map(String::from).to_vec();
+        let cfg = GeneratorConfig {
+            // крупная цена: округление до целого не съедает шаг
+            initial_prices: tickers.iter().map(|t| (t.clone(), 1_000_000)).collect(),
+            correlation_groups: vec![(vec!["AAPL".to_string(), "MSFT".to_string()], 1.0)],
+            ..Default::default()
+        };
+        let mut g = QuoteGenerator::with_seed(tickers, cfg, 11);
+
+        let mut prev: HashMap<String, i64> = HashMap::new();
+        let mut tsla_diverged = false;
+        for tick in 0..50 {
+            let batch = g.next_batch_at(tick);
+            let moves: HashMap<&str, i64> = batch
+                .iter()
+                .map(|q| {
+                    let before = prev.get(&q.ticker).copied().unwrap_or(1_000_000);
+                    (q.ticker.as_str(), (q.price - before).signum())
+                })
+                .collect();
+
+            assert_eq!(moves["AAPL"], moves["MSFT"], "tick {tick}");
+            tsla_diverged |= moves["TSLA"] != moves["AAPL"];
+
+            prev = batch.iter().map(|q| (q.ticker.clone(), q.price)).collect();
+        }
+        assert!(
+            tsla_diverged,
+            "uncorrelated ticker must not mirror the group"
+        );
+    }
+
+    #[test]
+    fn same_seed_gives_same_prices() {
+        let mut a = QuoteGenerator::with_seed(vec!["TSLA".to_string()], Default::default(), 7);
+        let mut b = QuoteGenerator::with_seed(vec!["TSLA".to_string()], Default::default(), 7);
+
+        for _ in 0..10 {
+            assert_eq!(
+                a.next_quote("TSLA").unwrap().price,
+                b.next_quote("TSLA").unwrap().price
+            );
+        }
+    }
+
+    #[test]
+    fn on_demand_batch_covers_only_subscribed_tickers() {
+        let hub = Arc::new(crate::hub::Hub::new());
+        let tickers = vec!["AAPL".to_string(), "TSLA".to_string()];
+        let demand: Demand = {
+            let hub = hub.clone();
+            Arc::new(move |t| hub.has_subscribers(t))
+        };
+        let mut g = QuoteGenerator::with_seed(tickers, Default::default(), 1).with_demand(demand);
+
+        assert!(
+            g.next_batch().is_empty(),
+            "no subscribers - nothing to generate"
+        );
+
+        let _rx = hub.add_client(1).unwrap();
+        hub.set_client_tickers(1, vec!["TSLA".to_string()]);
+        let batch = g.next_batch();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].ticker, "TSLA");
+
+        hub.remove_client(1);
+        assert!(g.next_batch().is_empty());
+    }
+
+    #[test]
+    fn without_demand_all_tickers_are_generated() {
+        let tickers = vec!["AAPL".to_string(), "TSLA".to_string()];
+        let mut g = QuoteGenerator::with_seed(tickers, Default::default(), 1);
+        assert_eq!(g.next_batch().len(), 2);
+    }
+}
@@ -0,0 +1,158 @@
+use quote_core::tickers::TickerCase;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{self, BufRead, BufReader};
+use thiserror::Error;
+
+/// Префикс группы в списке тикеров STREAM: `@tech`
+pub(crate) const GROUP_PREFIX: char = '@';
+
+#[derive(Debug, Error)]
+pub(crate) enum GroupsError {
+    #[error("groups file line {line}: expected `name = TICKER1,TICKER2`")]
+    Malformed { line: usize },
+
+    #[error("groups file line {line}: group {name:?} has no tickers")]
+    EmptyGroup { line: usize, name: String },
+
+    #[error("groups file io error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Именованные группы тикеров (`tech` -> `AAPL,MSFT`).
+/// Имена групп регистронезависимы.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct TickerGroups {
+    groups: HashMap<String, Vec<String>>,
+}
+
+impl TickerGroups {
+    /// Формат - по группе на строку, `#` - комментарий:
+    /// ```text
+    /// tech   = AAPL, MSFT, NVDA
+    /// energy = XOM, CVX
+    /// ```
+    /// Тикеры нормализуются так же, как тикеры STREAM (`case`).
+    pub(crate) fn parse<R: io::Read>(reader: R, case: TickerCase) -> Result<Self, GroupsError> {
+        let mut groups = HashMap::new();
+
+        for (idx, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (name, tickers) = line
+                .split_once('=')
+                .ok_or(GroupsError::Malformed { line: idx + 1 })?;
+            let name = name.trim().to_ascii_uppercase();
+            if name.is_empty() {
+                return Err(GroupsError::Malformed { line: idx + 1 });
+            }
+
+            let tickers = quote_core::tickers::parse_tickers_csv_with_case(tickers, case);
+            if tickers.is_empty() {
+                return Err(GroupsError::EmptyGroup {
+                    line: idx + 1,
+                    name,
+                });
+            }
+
+            groups.insert(name, tickers);
+        }
+
+        Ok(Self { groups })
+    }
+
+    /// Раскрывает `@group` в тикеры группы, остальные элементы оставляет как есть.
+    /// Err - имя первой неизвестной группы.
+    pub(crate) fn expand(&self, requested: Vec<String>) -> Result<Vec<String>, String> {
+        let mut out = Vec::with_capacity(requested.len());
+
+        for t in requested {
+            match t.strip_prefix(GROUP_PREFIX) {
+                Some(name) => {
+                    let members = self
+                        .groups
+                        .get(&name.to_ascii_uppercase())
+                        .ok_or_else(|| name.to_string())?;
+                    out.extend(members.iter().cloned());
+                }
+                None => out.push(t),
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Тикеры групп, которых нет среди `known` (отсортированы)
+    pub(crate) fn unknown_members(&self, known: &HashSet<String>) -> Vec<String> {
+        let unknown: BTreeSet<&String> = self
+            .groups
+            .values()
+            .flatten()
+            .filter(|t| !known.contains(*t))
+            .collect();
+        unknown.into_iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn strings(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn groups() -> TickerGroups {
+        TickerGroups::parse(
+            Cursor::new("# sectors\ntech = aapl, MSFT\n\nEnergy=XOM,CVX # oil\n"),
+            TickerCase::Upper,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn expand_replaces_groups_and_keeps_plain_tickers() {
+        let got = groups()
+            .expand(strings(&["@TECH", "TSLA", "@energy"]))
+            .unwrap();
+        assert_eq!(got, strings(&["AAPL", "MSFT", "TSLA", "CVX", "XOM"]));
+    }
+
+    #[test]
+    fn preserve_case_keeps_member_case_and_reports_unknown_members() {
+        let groups = TickerGroups::parse(
+            Cursor::new("crypto = btcUSD, ethUSD\n"),
+            TickerCase::Preserve,
+        )
+        .unwrap();
+        assert_eq!(
+            groups.expand(strings(&["@CRYPTO"])).unwrap(),
+            strings(&["btcUSD", "ethUSD"])
+        );
+
+        let known: HashSet<String> = ["btcUSD".to_string(), "ETHUSD".to_string()].into();
+        assert_eq!(groups.unknown_members(&known), strings(&["ethUSD"]));
+    }
+
+    #[test]
+    fn expand_reports_unknown_group() {
+        let err = groups().expand(strings(&["AAPL", "@CRYPTO"])).unwrap_err();
+        assert_eq!(err, "CRYPTO");
+    }
+
+    #[test]
+    fn parse_rejects_malformed_and_empty_groups() {
+        assert!(matches!(
+            TickerGroups::parse(Cursor::new("tech AAPL\n"), TickerCase::Upper),
+            Err(GroupsError::Malformed { line: 1 })
+        ));
+        assert!(matches!(
+            TickerGroups::parse(Cursor::new("tech = AAPL\nempty = , ,\n"), TickerCase::Upper),
+            Err(GroupsError::EmptyGroup { line: 2, .. })
+        ));
+    }
+}
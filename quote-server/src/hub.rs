@@ -1,9 +1,10 @@
-use crate::config::ClientId;
+use crate::config::{CLIENT_QUEUE_CAPACITY, ClientId};
 use crossbeam_channel::{Receiver, Sender, TrySendError};
 use quote_core::StockQuote;
 use std::collections::HashMap;
 use std::collections::hash_map::Entry;
 use std::fmt;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::Mutex;
 use thiserror::Error;
@@ -12,13 +13,16 @@ use thiserror::Error;
 pub(crate) enum HubError {
     #[error("client already exists: {0}")]
     ClientAlreadyExists(ClientId),
+
+    #[error("hub is at capacity: {0} clients")]
+    AtCapacity(usize),
 }
 
 #[derive(Debug)]
 pub(crate) struct BroadcastStats {
-    sent: usize,
-    dropped_full: usize,
-    dropped_dead: usize,
+    pub(crate) sent: usize,
+    pub(crate) dropped_full: usize,
+    pub(crate) dropped_dead: usize,
 }
 
 impl fmt::Display for BroadcastStats {
@@ -40,60 +44,365 @@ impl BroadcastStats {
     }
 }
 
+/// Подавление повторов перед broadcast (`--dedup`): котировка тикера не
+/// рассылается, если `(price, volume)` совпадает с последней разосланной.
+/// Время котировки не учитывается.
+#[derive(Debug, Default)]
+pub(crate) struct QuoteDedup {
+    last: HashMap<String, (i64, u32)>,
+}
+
+impl QuoteDedup {
+    /// true - котировку нужно разослать (и она запоминается как последняя)
+    pub(crate) fn should_send(&mut self, q: &StockQuote) -> bool {
+        let key = (q.price, q.volume);
+        match self.last.get_mut(&q.ticker) {
+            Some(last) if *last == key => false,
+            Some(last) => {
+                *last = key;
+                true
+            }
+            None => {
+                self.last.insert(q.ticker.clone(), key);
+                true
+            }
+        }
+    }
+}
+
+/// Стабильный id клиента (`ID=` в STREAM) -> его текущая сессия.
+/// Позволяет отличать клиентов на одном UDP-порту и узнавать вернувшегося клиента.
+#[derive(Debug, Default)]
+pub(crate) struct ClientRegistry {
+    by_id: Mutex<HashMap<String, ClientId>>,
+}
+
+impl ClientRegistry {
+    /// Закрепляет сессию `cid` за `id`; возвращает прежнюю сессию этого клиента
+    pub(crate) fn register(&self, id: &str, cid: ClientId) -> Option<ClientId> {
+        let mut by_id = match self.by_id.lock() {
+            Ok(g) => g,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        by_id.insert(id.to_string(), cid)
+    }
+
+    /// Забывает `id`, только если он всё ещё указывает на `cid`
+    /// (сессия, вытесненная переподключением, не удаляет новую)
+    pub(crate) fn unregister(&self, id: &str, cid: ClientId) {
+        let mut by_id = match self.by_id.lock() {
+            Ok(g) => g,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if by_id.get(id) == Some(&cid) {
+            by_id.remove(id);
+        }
+    }
+}
+
+/// Вес клиента по умолчанию
+pub(crate) const DEFAULT_CLIENT_WEIGHT: u32 = 1;
+
+/// Очередь клиента в хабе
+struct ClientSlot {
+    tx: Sender<Arc<StockQuote>>,
+    /// Приоритет: broadcast обходит клиентов по убыванию веса,
+    /// а очередь клиента вмещает `capacity_per_client * weight` котировок
+    weight: u32,
+    /// Копия receiver для повторной выдачи (только у клиентов из
+    /// [`Hub::get_or_add_client`]); пока она здесь, канал не отключается
+    shared_rx: Option<Receiver<Arc<StockQuote>>>,
+    /// Тикеры подписки (см. [`Hub::set_client_tickers`])
+    tickers: Vec<String>,
+    /// UDP-адрес, куда сессия шлёт котировки (для `WHOAMI`)
+    target: Option<SocketAddr>,
+    /// Уведомления об удалённых тикерах (см. [`Hub::ticker_removals`])
+    removals: Option<Sender<String>>,
+}
+
 pub(crate) struct Hub {
-    clients: Mutex<HashMap<ClientId, Sender<Arc<StockQuote>>>>,
+    clients: Mutex<HashMap<ClientId, ClientSlot>>,
     capacity_per_client: usize,
+    /// Максимум одновременных клиентов (None = без ограничения)
+    max_clients: Option<usize>,
+    /// Тикер -> число клиентов, подписанных на него
+    demand: Mutex<HashMap<String, usize>>,
 }
 
 impl Hub {
     pub(crate) fn new() -> Self {
         Self {
             clients: Mutex::new(HashMap::new()),
-            capacity_per_client: 256,
+            capacity_per_client: CLIENT_QUEUE_CAPACITY,
+            max_clients: None,
+            demand: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn with_max_clients(max_clients: usize) -> Self {
+        Self {
+            max_clients: Some(max_clients),
+            ..Self::new()
         }
     }
 
     pub(crate) fn add_client(&self, cid: ClientId) -> Result<Receiver<Arc<StockQuote>>, HubError> {
+        self.add_client_weighted(cid, DEFAULT_CLIENT_WEIGHT)
+    }
+
+    /// Как [`Hub::add_client`], но с весом: под нагрузкой (клиенты не успевают
+    /// разбирать очереди) котировки первыми теряют клиенты с меньшим весом.
+    /// Вес 0 считается за 1.
+    pub(crate) fn add_client_weighted(
+        &self,
+        cid: ClientId,
+        weight: u32,
+    ) -> Result<Receiver<Arc<StockQuote>>, HubError> {
+        let weight = weight.max(1);
         let mut clients = match self.clients.lock() {
             Ok(g) => g,
             Err(poisoned) => poisoned.into_inner(), // продолжаем, несмотря на poison
         };
 
+        if let Some(max) = self.max_clients
+            && clients.len() >= max
+            && !clients.contains_key(&cid)
+        {
+            return Err(HubError::AtCapacity(max));
+        }
+
         match clients.entry(cid) {
             Entry::Vacant(e) => {
-                let (tx, rx) = crossbeam_channel::bounded(self.capacity_per_client);
-                e.insert(tx);
+                let capacity = self.capacity_per_client.saturating_mul(weight as usize);
+                let (tx, rx) = crossbeam_channel::bounded(capacity);
+                e.insert(ClientSlot {
+                    tx,
+                    weight,
+                    shared_rx: None,
+                    tickers: Vec::new(),
+                    target: None,
+                    removals: None,
+                });
                 Ok(rx)
             }
             Entry::Occupied(_) => Err(HubError::ClientAlreadyExists(cid)),
         }
     }
 
+    /// Идемпотентная подписка: для уже известного `cid` возвращает receiver той же
+    /// очереди, а не [`HubError::ClientAlreadyExists`] - тот же логический клиент
+    /// может переподключиться и продолжить читать свою очередь.
+    ///
+    /// Хаб хранит копию receiver, поэтому такой клиент не удаляется broadcast'ом
+    /// как отключившийся: его убирает только [`Hub::remove_client`].
+    /// Клиента, добавленного через строгий [`Hub::add_client`], поделить нельзя
+    /// (его receiver у хаба не сохранён) - для него по-прежнему `ClientAlreadyExists`.
+    #[allow(dead_code)] // для сценариев переподключения; сессии tcp пока используют add_client
+    pub(crate) fn get_or_add_client(
+        &self,
+        cid: ClientId,
+    ) -> Result<Receiver<Arc<StockQuote>>, HubError> {
+        let mut clients = match self.clients.lock() {
+            Ok(g) => g,
+            Err(poisoned) => poisoned.into_inner(), // продолжаем, несмотря на poison
+        };
+
+        if let Some(slot) = clients.get(&cid) {
+            return slot
+                .shared_rx
+                .clone()
+                .ok_or(HubError::ClientAlreadyExists(cid));
+        }
+
+        if let Some(max) = self.max_clients
+            && clients.len() >= max
+        {
+            return Err(HubError::AtCapacity(max));
+        }
+
+        let (tx, rx) = crossbeam_channel::bounded(self.capacity_per_client);
+        clients.insert(
+            cid,
+            ClientSlot {
+                tx,
+                weight: DEFAULT_CLIENT_WEIGHT,
+                shared_rx: Some(rx.clone()),
+                tickers: Vec::new(),
+                target: None,
+                removals: None,
+            },
+        );
+        Ok(rx)
+    }
+
+    /// Число активных клиентов
+    pub(crate) fn client_count(&self) -> usize {
+        let clients = match self.clients.lock() {
+            Ok(g) => g,
+            Err(poisoned) => poisoned.into_inner(), // продолжаем, несмотря на poison
+        };
+
+        clients.len()
+    }
+
     pub(crate) fn remove_client(&self, cid: ClientId) -> bool {
         let mut clients = match self.clients.lock() {
             Ok(g) => g,
             Err(poisoned) => poisoned.into_inner(), // продолжаем, несмотря на poison
         };
 
-        clients.remove(&cid).is_some()
+        match clients.remove(&cid) {
+            Some(slot) => {
+                self.update_demand(&slot.tickers, &[]);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Запоминает тикеры подписки клиента: по ним [`Hub::has_subscribers`]
+    /// отвечает генератору, нужны ли кому-то котировки тикера.
+    /// false - клиента нет
+    pub(crate) fn set_client_tickers(&self, cid: ClientId, tickers: Vec<String>) -> bool {
+        let mut clients = match self.clients.lock() {
+            Ok(g) => g,
+            Err(poisoned) => poisoned.into_inner(), // продолжаем, несмотря на poison
+        };
+
+        let Some(slot) = clients.get_mut(&cid) else {
+            return false;
+        };
+        self.update_demand(&slot.tickers, &tickers);
+        slot.tickers = tickers;
+        true
+    }
+
+    /// Запоминает UDP-адрес сессии клиента; false - клиента нет
+    pub(crate) fn set_client_target(&self, cid: ClientId, target: SocketAddr) -> bool {
+        let mut clients = match self.clients.lock() {
+            Ok(g) => g,
+            Err(poisoned) => poisoned.into_inner(), // продолжаем, несмотря на poison
+        };
+
+        match clients.get_mut(&cid) {
+            Some(slot) => {
+                slot.target = Some(target);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Сессии, шлющие котировки на `target`, и их текущие тикеры
+    /// (с учётом удалённых): по возрастанию id, тикеры отсортированы
+    pub(crate) fn subscriptions_for(&self, target: SocketAddr) -> Vec<(ClientId, Vec<String>)> {
+        let clients = match self.clients.lock() {
+            Ok(g) => g,
+            Err(poisoned) => poisoned.into_inner(), // продолжаем, несмотря на poison
+        };
+
+        let mut out: Vec<(ClientId, Vec<String>)> = clients
+            .iter()
+            .filter(|(_, slot)| slot.target == Some(target))
+            .map(|(&cid, slot)| {
+                let mut tickers = slot.tickers.clone();
+                tickers.sort_unstable();
+                (cid, tickers)
+            })
+            .collect();
+        out.sort_unstable_by_key(|&(cid, _)| cid);
+        out
+    }
+
+    /// Канал уведомлений клиента об удалении его тикеров ([`Hub::remove_ticker`]).
+    /// Повторный вызов заменяет канал. None - клиента нет
+    pub(crate) fn ticker_removals(&self, cid: ClientId) -> Option<Receiver<String>> {
+        let mut clients = match self.clients.lock() {
+            Ok(g) => g,
+            Err(poisoned) => poisoned.into_inner(), // продолжаем, несмотря на poison
+        };
+
+        let slot = clients.get_mut(&cid)?;
+        let (tx, rx) = crossbeam_channel::unbounded();
+        slot.removals = Some(tx);
+        Some(rx)
+    }
+
+    /// Тикер убран с сервера: каждый подписанный на него клиент получает
+    /// уведомление, тикер исключается из его подписки и из спроса.
+    /// Возвращает число затронутых клиентов
+    pub(crate) fn remove_ticker(&self, ticker: &str) -> usize {
+        let mut clients = match self.clients.lock() {
+            Ok(g) => g,
+            Err(poisoned) => poisoned.into_inner(), // продолжаем, несмотря на poison
+        };
+
+        let mut affected = 0;
+        for slot in clients.values_mut() {
+            let Some(pos) = slot.tickers.iter().position(|t| t == ticker) else {
+                continue;
+            };
+            let removed = slot.tickers.swap_remove(pos);
+            self.update_demand(std::slice::from_ref(&removed), &[]);
+            if let Some(tx) = &slot.removals {
+                // сессия уже завершается - уведомлять некого
+                let _ = tx.send(removed);
+            }
+            affected += 1;
+        }
+        affected
+    }
+
+    /// Подписан ли хоть один клиент на `ticker`
+    pub(crate) fn has_subscribers(&self, ticker: &str) -> bool {
+        let demand = match self.demand.lock() {
+            Ok(g) => g,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        demand.contains_key(ticker)
+    }
+
+    /// Пересчёт `demand` при замене тикеров клиента `old` -> `new`.
+    /// Вызывается под локом `clients`, поэтому счётчики не расходятся со слотами
+    fn update_demand(&self, old: &[String], new: &[String]) {
+        let mut demand = match self.demand.lock() {
+            Ok(g) => g,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        for t in new {
+            *demand.entry(t.clone()).or_default() += 1;
+        }
+        for t in old {
+            if let Entry::Occupied(mut e) = demand.entry(t.clone()) {
+                *e.get_mut() -= 1;
+                if *e.get() == 0 {
+                    e.remove();
+                }
+            }
+        }
     }
 
     pub(crate) fn broadcast(&self, q: StockQuote) -> BroadcastStats {
         let q = Arc::new(q);
 
-        let clients_snapshot: Vec<(ClientId, Sender<Arc<StockQuote>>)> = {
+        let mut clients_snapshot: Vec<(ClientId, Sender<Arc<StockQuote>>, u32)> = {
             let clients = match self.clients.lock() {
                 Ok(g) => g,
                 Err(poisoned) => poisoned.into_inner(),
             };
-            clients.iter().map(|(&cid, tx)| (cid, tx.clone())).collect()
+            clients
+                .iter()
+                .map(|(&cid, slot)| (cid, slot.tx.clone(), slot.weight))
+                .collect()
         };
+        // приоритетные клиенты получают котировку первыми
+        clients_snapshot.sort_by_key(|&(_, _, weight)| std::cmp::Reverse(weight));
 
         let mut sent: usize = 0;
         let mut dropped_full: usize = 0;
         let mut dropped_disconnected: Vec<ClientId> = Vec::new();
 
-        for (cid, tx) in clients_snapshot.iter() {
+        for (cid, tx, _) in clients_snapshot.iter() {
             match tx.try_send(q.clone()) {
                 Ok(()) => sent += 1,
                 Err(TrySendError::Disconnected(_)) => dropped_disconnected.push(*cid),
@@ -124,9 +433,72 @@ mod tests {
             price,
             volume: 1,
             timestamp_ms: 1,
+            side: None,
+            sent_ms: None,
         }
     }
 
+    #[test]
+    fn dedup_suppresses_identical_consecutive_quotes() {
+        let mut dedup = QuoteDedup::default();
+
+        let mut q = mk_quote("AAPL", 100);
+        assert!(dedup.should_send(&q));
+
+        // только время изменилось - повтор
+        q.timestamp_ms += 500;
+        assert!(!dedup.should_send(&q));
+
+        // другой тикер с той же ценой - не повтор
+        assert!(dedup.should_send(&mk_quote("TSLA", 100)));
+
+        q.volume += 1;
+        assert!(dedup.should_send(&q));
+        q.price += 1;
+        assert!(dedup.should_send(&q));
+        assert!(!dedup.should_send(&q));
+    }
+
+    #[test]
+    fn demand_follows_client_tickers() {
+        let hub = Hub::new();
+        let _a = hub.add_client(1).unwrap();
+        let _b = hub.add_client(2).unwrap();
+        assert!(!hub.has_subscribers("AAPL"));
+
+        assert!(hub.set_client_tickers(1, vec!["AAPL".into(), "TSLA".into()]));
+        assert!(hub.set_client_tickers(2, vec!["AAPL".into()]));
+        assert!(!hub.set_client_tickers(3, vec!["MSFT".into()]));
+        assert!(!hub.has_subscribers("MSFT"));
+
+        hub.remove_client(1);
+        assert!(hub.has_subscribers("AAPL"), "client 2 is still subscribed");
+        assert!(!hub.has_subscribers("TSLA"));
+
+        assert!(hub.set_client_tickers(2, vec!["MSFT".into()]));
+        assert!(!hub.has_subscribers("AAPL"));
+        assert!(hub.has_subscribers("MSFT"));
+    }
+
+    #[test]
+    fn remove_ticker_notifies_only_subscribed_clients() {
+        let hub = Hub::new();
+        let _a = hub.add_client(1).unwrap();
+        let _b = hub.add_client(2).unwrap();
+        hub.set_client_tickers(1, vec!["AAPL".into(), "TSLA".into()]);
+        hub.set_client_tickers(2, vec!["TSLA".into()]);
+        let a_removed = hub.ticker_removals(1).unwrap();
+        let b_removed = hub.ticker_removals(2).unwrap();
+
+        assert_eq!(hub.remove_ticker("AAPL"), 1);
+
+        assert_eq!(a_removed.try_recv().unwrap(), "AAPL");
+        assert!(b_removed.try_recv().is_err());
+        assert!(!hub.has_subscribers("AAPL"));
+        assert!(hub.has_subscribers("TSLA"));
+        assert_eq!(hub.remove_ticker("AAPL"), 0, "already removed");
+    }
+
     #[test]
     fn add_client_ok_and_duplicate_fails() {
         let hub = Hub::new();
@@ -137,6 +509,49 @@ mod tests {
         assert!(matches!(err, HubError::ClientAlreadyExists(1)));
     }
 
+    #[test]
+    fn get_or_add_client_returns_receiver_of_same_queue() {
+        let hub = Hub::new();
+
+        let rx1 = hub.get_or_add_client(1).unwrap();
+        let rx2 = hub.get_or_add_client(1).unwrap();
+        assert!(rx1.same_channel(&rx2));
+        assert_eq!(hub.client_count(), 1);
+
+        // обе копии читают очередь одного sender'а
+        hub.broadcast(mk_quote("AAPL", 100));
+        hub.broadcast(mk_quote("TSLA", 200));
+        assert_eq!(rx1.try_recv().unwrap().ticker, "AAPL");
+        assert_eq!(rx2.try_recv().unwrap().ticker, "TSLA");
+        assert!(rx1.try_recv().is_err(), "one queue, one copy of each quote");
+        assert!(rx2.try_recv().is_err());
+
+        // строгий вариант не меняется
+        assert!(matches!(
+            hub.add_client(1).unwrap_err(),
+            HubError::ClientAlreadyExists(1)
+        ));
+        let _strict = hub.add_client(2).unwrap();
+        assert!(matches!(
+            hub.get_or_add_client(2).unwrap_err(),
+            HubError::ClientAlreadyExists(2)
+        ));
+    }
+
+    #[test]
+    fn add_client_rejects_over_capacity_until_slot_freed() {
+        let hub = Hub::with_max_clients(2);
+
+        let _rx1 = hub.add_client(1).unwrap();
+        let _rx2 = hub.add_client(2).unwrap();
+
+        let err = hub.add_client(3).unwrap_err();
+        assert!(matches!(err, HubError::AtCapacity(2)));
+
+        assert!(hub.remove_client(1));
+        let _rx3 = hub.add_client(3).expect("slot must be free again");
+    }
+
     #[test]
     fn remove_client_returns_bool() {
         let hub = Hub::new();
@@ -175,6 +590,8 @@ mod tests {
         let hub = Hub {
             clients: Mutex::new(HashMap::new()),
             capacity_per_client: 1,
+            max_clients: None,
+            demand: Mutex::new(HashMap::new()),
         };
 
         let _rx = hub.add_client(1).unwrap();
@@ -191,6 +608,31 @@ mod tests {
         assert_eq!(st2.dropped_dead, 0);
     }
 
+    #[test]
+    fn broadcast_drops_low_weight_client_first_under_pressure() {
+        let hub = Hub {
+            clients: Mutex::new(HashMap::new()),
+            capacity_per_client: 2,
+            max_clients: None,
+            demand: Mutex::new(HashMap::new()),
+        };
+
+        // оба клиента не читают очередь
+        let high = hub.add_client_weighted(1, 3).unwrap();
+        let low = hub.add_client(2).unwrap();
+
+        let mut low_drops = 0;
+        for price in 0..6 {
+            let st = hub.broadcast(mk_quote("AAPL", price));
+            low_drops += st.dropped_full;
+        }
+
+        // low (ёмкость 2) потерял 4 котировки, high (ёмкость 6) - ни одной
+        assert_eq!(low_drops, 4);
+        assert_eq!(high.len(), 6);
+        assert_eq!(low.len(), 2);
+    }
+
     #[test]
     fn broadcast_removes_disconnected_client() {
         // capacity не важен
@@ -208,4 +650,21 @@ mod tests {
         // После broadcast хаб должен почистить реестр
         assert!(!hub.remove_client(1));
     }
+
+    #[test]
+    fn client_registry_replaces_same_id_and_keeps_distinct_ids() {
+        let reg = ClientRegistry::default();
+
+        assert_eq!(reg.register("a", 1), None);
+        assert_eq!(reg.register("b", 2), None);
+        // тот же клиент вернулся
+        assert_eq!(reg.register("a", 3), Some(1));
+
+        // вытесненная сессия 1 не должна снять регистрацию сессии 3
+        reg.unregister("a", 1);
+        assert_eq!(reg.register("a", 4), Some(3));
+
+        reg.unregister("b", 2);
+        assert_eq!(reg.register("b", 5), None);
+    }
 }
@@ -0,0 +1,136 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub(crate) enum LineError {
+    #[error("client closed connection")]
+    Closed,
+
+    #[error("connection closed before end of line")]
+    Unterminated,
+
+    #[error("command line too long (max {0} bytes)")]
+    TooLong(usize),
+
+    #[error("timed out waiting for command")]
+    Timeout,
+
+    #[error("command line is not valid utf-8")]
+    InvalidUtf8,
+
+    #[error(transparent)]
+    Io(io::Error),
+}
+
+/// Соединение управляющего канала: TCP или Unix-сокет (`--control-uds`)
+pub(crate) trait ControlStream: Read + Write {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    /// Закрыть соединение в обе стороны
+    fn shutdown_both(&self) -> io::Result<()>;
+}
+
+impl ControlStream for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn shutdown_both(&self) -> io::Result<()> {
+        self.shutdown(Shutdown::Both)
+    }
+}
+
+#[cfg(unix)]
+impl ControlStream for UnixStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+
+    fn shutdown_both(&self) -> io::Result<()> {
+        self.shutdown(Shutdown::Both)
+    }
+}
+
+/// Читает одну команду (строку до `\n`) из потока управляющего канала
+/// с ограничением длины и таймаутом чтения.
+pub(crate) fn read_command_line<S: ControlStream>(
+    stream: &mut S,
+    max_len: usize,
+    timeout: Duration,
+) -> Result<String, LineError> {
+    stream
+        .set_read_timeout(Some(timeout))
+        .map_err(LineError::Io)?;
+    read_line_capped(BufReader::new(stream), max_len)
+}
+
+/// Строка без завершающего `\n` (и `\r`), не длиннее `max_len` байт
+fn read_line_capped<R: BufRead>(reader: R, max_len: usize) -> Result<String, LineError> {
+    let mut buf = Vec::new();
+
+    // +1 на сам `\n`
+    let limit = u64::try_from(max_len).unwrap_or(u64::MAX).saturating_add(1);
+    let n = reader
+        .take(limit)
+        .read_until(b'\n', &mut buf)
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => LineError::Timeout,
+            _ => LineError::Io(e),
+        })?;
+
+    if buf.last() != Some(&b'\n') {
+        return Err(if n == 0 {
+            LineError::Closed
+        } else if buf.len() > max_len {
+            LineError::TooLong(max_len)
+        } else {
+            LineError::Unterminated
+        });
+    }
+
+    buf.pop();
+    if buf.last() == Some(&b'\r') {
+        buf.pop();
+    }
+
+    String::from_utf8(buf).map_err(|_| LineError::InvalidUtf8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn reads_normal_line() {
+        let got =
+            read_line_capped(Cursor::new("STREAM udp://127.0.0.1:1 AAPL\r\nrest"), 64).unwrap();
+        assert_eq!(got, "STREAM udp://127.0.0.1:1 AAPL");
+    }
+
+    #[test]
+    fn eof_before_any_data_is_closed() {
+        let err = read_line_capped(Cursor::new(""), 64).unwrap_err();
+        assert!(matches!(err, LineError::Closed));
+    }
+
+    #[test]
+    fn eof_before_newline_is_unterminated() {
+        let err = read_line_capped(Cursor::new("STREAM udp://"), 64).unwrap_err();
+        assert!(matches!(err, LineError::Unterminated));
+    }
+
+    #[test]
+    fn over_length_line_is_rejected() {
+        let err = read_line_capped(Cursor::new("A".repeat(100) + "\n"), 16).unwrap_err();
+        assert!(matches!(err, LineError::TooLong(16)));
+
+        // ровно max_len + `\n` - ещё можно
+        let got = read_line_capped(Cursor::new("A".repeat(16) + "\n"), 16).unwrap();
+        assert_eq!(got.len(), 16);
+    }
+}
@@ -0,0 +1,122 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Оценка потерь UDP в сессии `ACK=1`.
+///
+/// Сессия шлёт `Checkpoint` раз в `every` отправленных котировок, клиент
+/// возвращает в `PingAck` число полученных. Это выборка, а не надёжная
+/// доставка: потерю самих ping и checkpoint "в полёте" на момент конца
+/// сессии оценка не различает.
+#[derive(Debug)]
+pub(crate) struct LossMonitor {
+    every: u64,
+    quotes: AtomicU64,
+    sent: AtomicU64,
+    acked: AtomicU64,
+}
+
+impl LossMonitor {
+    /// `every` - котировок на один checkpoint (не меньше 1)
+    pub(crate) fn new(every: u64) -> Self {
+        Self {
+            every: every.max(1),
+            quotes: AtomicU64::new(0),
+            sent: AtomicU64::new(0),
+            acked: AtomicU64::new(0),
+        }
+    }
+
+    /// Учитывает отправленную котировку; Some(seq) - пора отправить
+    /// `Checkpoint(seq)` (он уже учтён как отправленный)
+    pub(crate) fn on_quote_sent(&self) -> Option<u64> {
+        let n = self.quotes.fetch_add(1, Ordering::Relaxed) + 1;
+        if !n.is_multiple_of(self.every) {
+            return None;
+        }
+        Some(self.sent.fetch_add(1, Ordering::Relaxed) + 1)
+    }
+
+    /// `ack_seq` из `PingAck`: счётчик клиента только растёт, поэтому
+    /// запоздавший ping не уменьшает уже учтённое
+    pub(crate) fn on_ack(&self, ack_seq: u64) {
+        self.acked.fetch_max(ack_seq, Ordering::Relaxed);
+    }
+
+    pub(crate) fn estimate(&self) -> LossEstimate {
+        LossEstimate {
+            sent: self.sent.load(Ordering::Relaxed),
+            acked: self.acked.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Снимок счётчиков [`LossMonitor`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LossEstimate {
+    /// Отправленные checkpoint
+    pub(crate) sent: u64,
+    /// Подтверждённые клиентом (не больше `sent`)
+    pub(crate) acked: u64,
+}
+
+impl LossEstimate {
+    /// Доля доставленных checkpoint в `[0, 1]`; None - ещё ни одного не отправлено
+    pub(crate) fn delivered_ratio(&self) -> Option<f64> {
+        (self.sent > 0).then(|| self.acked.min(self.sent) as f64 / self.sent as f64)
+    }
+
+    /// `1 - delivered_ratio`
+    pub(crate) fn loss_ratio(&self) -> Option<f64> {
+        self.delivered_ratio().map(|d| 1.0 - d)
+    }
+}
+
+impl fmt::Display for LossEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "checkpoints={}/{}", self.acked.min(self.sent), self.sent)?;
+        if let Some(loss) = self.loss_ratio() {
+            write!(f, " loss={:.1}%", loss * 100.0)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checkpoint_is_due_every_n_quotes() {
+        let m = LossMonitor::new(3);
+        let due: Vec<Option<u64>> = (0..7).map(|_| m.on_quote_sent()).collect();
+        assert_eq!(due, vec![None, None, Some(1), None, None, Some(2), None]);
+        assert_eq!(m.estimate().sent, 2);
+    }
+
+    #[test]
+    fn loss_ratio_from_sent_and_acked_counts() {
+        let est = |sent, acked| LossEstimate { sent, acked };
+
+        assert_eq!(est(0, 0).loss_ratio(), None);
+        assert_eq!(est(10, 10).loss_ratio(), Some(0.0));
+        assert_eq!(est(10, 0).loss_ratio(), Some(1.0));
+        assert_eq!(est(8, 6).delivered_ratio(), Some(0.75));
+        assert_eq!(est(8, 6).loss_ratio(), Some(0.25));
+        // клиент не может подтвердить больше, чем отправлено
+        assert_eq!(est(4, 9).loss_ratio(), Some(0.0));
+
+        assert_eq!(est(8, 6).to_string(), "checkpoints=6/8 loss=25.0%");
+        assert_eq!(est(0, 0).to_string(), "checkpoints=0/0");
+    }
+
+    #[test]
+    fn late_ack_does_not_decrease_acked() {
+        let m = LossMonitor::new(1);
+        for _ in 0..5 {
+            m.on_quote_sent();
+        }
+        m.on_ack(4);
+        m.on_ack(2);
+        assert_eq!(m.estimate(), LossEstimate { sent: 5, acked: 4 });
+    }
+}
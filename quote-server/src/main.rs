@@ -5,65 +5,126 @@
 //! - запуск общего UDP-сокета и потока приёма ping
 //! - запуск потока генерации котировок и рассылки в сессии
 //! - запуск TCP-listener: `STREAM` и создание сессии на клиента
-//! - при shutdown: корректное завершение и `join` фоновых потоков
+//! - при shutdown: фаза `Draining` (сессии дорассылают очереди, не дольше
+//!   `SHUTDOWN_DRAIN_GRACE`), затем `Stopped` и `join` фоновых потоков
 
+use anyhow::Context;
 use clap::Parser;
 use log::{info, warn};
+use quote_core::logfmt;
 use std::collections::HashMap;
-use std::io::Cursor;
 use std::net::UdpSocket;
-use std::sync::{
-    Arc, RwLock,
-    atomic::{AtomicBool, AtomicU64, Ordering},
-};
+use std::sync::{Arc, RwLock, atomic::AtomicU64};
 use std::thread;
 
+mod bandwidth;
+mod cidr;
 mod cli;
 mod config;
 mod generator;
+mod groups;
 mod hub;
+mod line_codec;
+mod loss;
+mod metrics;
+mod price_seeds;
+mod recorder;
+mod server_config;
 mod session;
+mod shutdown;
+mod subscription;
 mod tcp;
+#[cfg(feature = "tickers-url")]
+mod tickers_url;
+mod tickers_watch;
 mod udp_ping;
+mod udp_tuning;
 
 use crate::cli::Args;
 use crate::hub::Hub;
-use crate::udp_ping::{LastPingMap, run_udp_ping_listener};
+use crate::tcp::ConnContext;
+use crate::tickers_watch::KnownTickers;
+use crate::udp_ping::{LastPingMap, SessionTokenMap, run_udp_ping_listener};
+use crate::udp_tuning::UdpTuning;
 
 fn main() -> anyhow::Result<()> {
-    env_logger::init();
-
     let args = Args::parse();
+    let mut logger = env_logger::Builder::from_default_env();
+    if args.log_json {
+        logger.format(logfmt::write_json_record);
+    }
+    logger.init();
+
+    if args.self_test {
+        quote_core::wire::self_test()?;
+        info!("wire self-test passed");
+    }
 
-    let shutdown = Arc::new(AtomicBool::new(false));
+    if args.dump_config {
+        let tickers = load_server_tickers_from_args(&args)?;
+        print!(
+            "{}",
+            server_config::ServerConfig::resolve(&args, &tickers).to_toml()
+        );
+        return Ok(());
+    }
+
+    let shutdown = Arc::new(shutdown::ShutdownFlag::default());
+    // команды генератору: Stop будит его, спящего между батчами
+    let (gen_cmd_tx, gen_cmd_rx) = crossbeam_channel::unbounded();
 
-    // Ctrl+C => ставим shutdown=true
+    // Ctrl+C => Draining (генератор стоп, сессии дорассылают очереди),
+    // через SHUTDOWN_DRAIN_GRACE - Stopped
     {
         let shutdown = shutdown.clone();
+        let gen_cmd_tx = gen_cmd_tx.clone();
         ctrlc::set_handler(move || {
-            shutdown.store(true, Ordering::Relaxed);
-            info!("shutting down...");
+            shutdown.begin_drain();
+            let _ = gen_cmd_tx.send(generator::GeneratorCmd::Stop);
+            info!("shutting down, draining sessions...");
+
+            thread::sleep(config::SHUTDOWN_DRAIN_GRACE);
+            shutdown.stop();
         })?;
     }
 
     // shared state
-    let hub = Arc::new(Hub::new());
+    let hub = Arc::new(match args.max_sessions {
+        Some(max) => Hub::with_max_clients(max as usize),
+        None => Hub::new(),
+    });
     let curr_client_id = Arc::new(AtomicU64::new(1));
     let last_ping: LastPingMap = Arc::new(RwLock::new(HashMap::new()));
+    let tokens: SessionTokenMap = Arc::new(RwLock::new(HashMap::new()));
+    let bandwidth = args
+        .max_udp_bps
+        .map(|bps| Arc::new(bandwidth::TokenBucket::new(bps)));
+    let metrics = Arc::new(metrics::ServerMetrics::default().with_bandwidth(bandwidth.clone()));
 
-    // общий UDP-сокет
+    // общий UDP-сокет: только приём ping, котировки шлёт сокет каждой сессии
     let udp = Arc::new(UdpSocket::bind(args.udp_bind)?);
     info!("UDP bound on {}", args.udp_bind);
+    // котировки уходят с сокетов сессий; здесь - ранняя проверка поддержки
+    let udp_tuning = args.udp_tuning();
+    if udp_tuning != UdpTuning::default()
+        && let Err(e) = udp_tuning.apply(&udp)
+    {
+        warn!("--udp-ttl/--udp-tos not supported here, ignored: {e}");
+    }
 
     let mut handles = Vec::new();
 
     // слушаем PING по UDP и обновляем last_ping
     {
-        let udp = udp.clone();
         let last_ping = last_ping.clone();
+        let tokens = tokens.clone();
+        let enable_pong = args.enable_pong;
+        let metrics = metrics.clone();
         let shutdown = shutdown.clone();
         handles.push(thread::spawn(move || {
-            if let Err(e) = run_udp_ping_listener(udp, last_ping, shutdown) {
+            if let Err(e) =
+                run_udp_ping_listener(udp, last_ping, tokens, enable_pong, None, metrics, shutdown)
+            {
                 warn!("udp ping listener stopped: {e}");
             }
         }));
@@ -71,59 +132,251 @@ fn main() -> anyhow::Result<()> {
 
     // тикеры генератора: default / файл / текст
     let tickers = load_server_tickers_from_args(&args)?;
+    let known_tickers: KnownTickers = Arc::new(RwLock::new(tickers.iter().cloned().collect()));
+    let groups = match &args.groups_file {
+        Some(p) => groups::TickerGroups::parse(
+            std::fs::File::open(p).with_context(|| format!("open groups file {p:?}"))?,
+            args.ticker_case(),
+        )?,
+        None => groups::TickerGroups::default(),
+    };
+    {
+        let known = match known_tickers.read() {
+            Ok(g) => g,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        for t in groups.unknown_members(&known) {
+            warn!("groups file: ticker {t} is not served by the generator");
+        }
+    }
+    let initial_prices = match &args.price_seeds {
+        Some(p) => {
+            let mut seeds = price_seeds::parse_price_seeds(
+                std::fs::File::open(p).with_context(|| format!("open price seeds {p:?}"))?,
+                args.ticker_case(),
+            )?;
+            seeds.retain(|ticker, _| {
+                let known = known_tickers
+                    .read()
+                    .is_ok_and(|known| known.contains(ticker));
+                if !known {
+                    warn!("price seeds: unknown ticker {ticker}, ignored");
+                }
+                known
+            });
+            seeds
+        }
+        None => HashMap::new(),
+    };
+
+    let recorder = match &args.record {
+        Some(p) => Some(
+            recorder::FileSink::create(
+                p,
+                config::RECORD_QUEUE_CAPACITY,
+                args.seq_state
+                    .clone()
+                    .map(|state| recorder::SeqState::load(state, config::SEQ_STATE_FLUSH_INTERVAL)),
+            )
+            .with_context(|| format!("open record file {p:?}"))?,
+        ),
+        None => None,
+    };
 
     // генерация котировок + broadcast в hub
     {
         let hub = hub.clone();
-        let shutdown = shutdown.clone();
+        let metrics = metrics.clone();
+        let mut dedup = args.dedup.then(hub::QuoteDedup::default);
+        let ema_alpha = args.ema_alpha;
+        let market_hours = args.market_hours;
+        let warmup_ticks = args.warmup_ticks;
+        let on_demand = args.on_demand;
+        let distribution = match args.normal_std_dev {
+            Some(std_dev) => generator::StepDistribution::Normal { std_dev },
+            None => generator::StepDistribution::Uniform,
+        };
 
         handles.push(thread::spawn(move || {
-            let gen_cfg = generator::GeneratorConfig::default();
+            let gen_cfg = generator::GeneratorConfig {
+                ema_alpha,
+                market_hours,
+                distribution,
+                initial_prices,
+                warmup_ticks,
+                ..Default::default()
+            };
             let mut q_gen = generator::QuoteGenerator::new(tickers, gen_cfg);
+            if on_demand {
+                let hub = hub.clone();
+                q_gen = q_gen.with_demand(Arc::new(move |t| hub.has_subscribers(t)));
+            }
 
-            while !shutdown.load(Ordering::Relaxed) {
-                let quote_batch = q_gen.next_batch();
-                for q in quote_batch.into_iter() {
-                    let stats = hub.broadcast(q);
-                    if stats.not_empty() {
-                        info!("{}", stats);
-                    }
+            generator::run_generator(q_gen, config::QUOTE_INTERVAL, &gen_cmd_rx, |q| {
+                if let Some(dedup) = &mut dedup
+                    && !dedup.should_send(&q)
+                {
+                    return;
                 }
+                if let Some(recorder) = &recorder {
+                    recorder.record(&q);
+                }
+                let stats = hub.broadcast(q);
+                metrics.record_broadcast(&stats);
+                if stats.not_empty() {
+                    info!("{}", stats);
+                }
+            });
+
+            info!("generator stopped");
 
-                thread::sleep(config::QUOTE_INTERVAL);
+            if let Some(recorder) = recorder {
+                match recorder.finish() {
+                    Ok(stats) => info!(
+                        "recorder stopped: written={} dropped={}",
+                        stats.written, stats.dropped
+                    ),
+                    Err(e) => warn!("recorder failed: {e}"),
+                }
             }
+        }));
+    }
 
-            info!("generator stopped");
+    // перезагрузка --tickers-file
+    if args.watch_tickers
+        && let Some(path) = &args.tickers_file
+    {
+        let watch = tickers_watch::TickersWatch::new(
+            path.clone(),
+            args.ticker_case(),
+            args.tickers_count.map(|n| n as usize),
+        )
+        .with_default_tickers(args.with_default_tickers);
+        let known_tickers = known_tickers.clone();
+        let hub = hub.clone();
+        let shutdown = shutdown.clone();
+        handles.push(thread::spawn(move || {
+            tickers_watch::run_tickers_watcher(
+                watch,
+                config::TICKERS_WATCH_INTERVAL,
+                known_tickers,
+                hub,
+                gen_cmd_tx,
+                shutdown,
+            );
+            info!("tickers watcher stopped");
+        }));
+    }
+
+    if let Some(path) = &args.metrics_file {
+        let metrics = metrics.clone();
+        let path = path.clone();
+        let shutdown = shutdown.clone();
+        handles.push(thread::spawn(move || {
+            metrics::run_metrics_writer(metrics, path, config::METRICS_FILE_INTERVAL, shutdown);
         }));
     }
 
-    // TCP listener
-    info!("TCP listening on {}", args.tcp_bind);
-    crate::tcp::run_tcp_listener(
-        args.tcp_bind,
+    // --drain-record: остаток очередей сессий при shutdown. Поток писателя
+    // завершается, когда сессии и listener отпустят свои копии отправителя
+    let shutdown_sink = match &args.drain_record {
+        Some(p) => {
+            let drain = recorder::DrainRecord::create(p)
+                .with_context(|| format!("open drain record file {p:?}"))?;
+            let tx = drain.sender();
+            handles.push(thread::spawn(move || match drain.finish() {
+                Ok(written) => info!("drain record stopped: written={written}"),
+                Err(e) => warn!("drain record failed: {e}"),
+            }));
+            Some(tx)
+        }
+        None => None,
+    };
+
+    // управляющий канал: TCP или Unix-сокет
+    let ctx = ConnContext {
         hub,
-        udp,
+        udp_bind_ip: args.udp_bind.ip(),
+        udp_tuning,
         curr_client_id,
+        clients: Arc::new(hub::ClientRegistry::default()),
         last_ping,
-        shutdown.clone(),
-    )?;
+        tokens: args.ping_token.then_some(tokens),
+        enable_pong: args.enable_pong,
+        debug_commands: args.debug_commands,
+        known_tickers,
+        groups: Arc::new(groups),
+        allow_cidrs: Arc::new(args.allow_cidr.clone()),
+        priority_clients: Arc::new(args.priority_client.iter().cloned().collect()),
+        ticker_case: args.ticker_case(),
+        session_cfg: session::SessionConfig {
+            drain_budget: args.session_drain_budget,
+            coalesce: args.coalesce,
+            backlog_warn_ratio: args.backlog_warn_ratio,
+            min_send_interval: None,
+            initial_burst: None,
+            wire: Default::default(),
+            shutdown_sink,
+            loss: None,
+            removals: None,
+            bandwidth: bandwidth.clone(),
+            unresolved: Vec::new(),
+        },
+        metrics,
+        accept: args.accept_config(),
+        shutdown: shutdown.clone(),
+    };
+
+    #[cfg(unix)]
+    if let Some(path) = &args.control_uds {
+        info!("control socket listening on {path:?}");
+        crate::tcp::run_uds_listener(path, ctx)?;
+    } else {
+        info!("TCP listening on {}", args.tcp_bind);
+        crate::tcp::run_tcp_listener(args.tcp_bind, ctx)?;
+    }
+    #[cfg(not(unix))]
+    {
+        info!("TCP listening on {}", args.tcp_bind);
+        crate::tcp::run_tcp_listener(args.tcp_bind, ctx)?;
+    }
 
     // shutdown
-    shutdown.store(true, Ordering::Relaxed); // гарантия
+    // все сессии завершены (или listener упал): grace больше не нужен
+    shutdown.stop();
     for h in handles {
         if let Err(panic) = h.join() {
             warn!("background thread panicked: {:?}", panic);
         }
     }
 
+    if let Some(bucket) = &bandwidth {
+        info!("--max-udp-bps: {} quotes dropped", bucket.dropped());
+    }
     info!("server stopped");
     Ok(())
 }
 
 fn load_server_tickers_from_args(args: &Args) -> anyhow::Result<Vec<String>> {
+    let mut v = load_all_server_tickers(args)?;
+    if args.with_default_tickers {
+        let own = v.len();
+        v = config::merge_with_default_tickers(&v, args.ticker_case())?;
+        info!(
+            "--with-default-tickers: {own} + defaults = {} tickers",
+            v.len()
+        );
+    }
+    Ok(quote_core::tickers::limit_tickers(
+        v,
+        args.tickers_count.map(|n| n as usize),
+    ))
+}
+
+fn load_all_server_tickers(args: &Args) -> anyhow::Result<Vec<String>> {
     // 1) файл
     if let Some(p) = &args.tickers_file {
-        let v = config::load_server_tickers(Some(p.clone()))?;
+        let v = config::load_server_tickers(Some(p.clone()), args.ticker_case())?;
         if v.is_empty() {
             anyhow::bail!("tickers list is empty (file: {:?})", p);
         }
@@ -132,28 +385,79 @@ fn load_server_tickers_from_args(args: &Args) -> anyhow::Result<Vec<String>> {
 
     // 2) текст (CSV или многострочный)
     if let Some(raw) = &args.tickers {
-        let raw_trimmed = raw.trim();
-        if raw_trimmed.is_empty() {
-            anyhow::bail!("tickers text is empty");
-        }
-
-        // Если есть перевод строки или комментарии - трактуем как "по одному на строку"
-        let v = if raw_trimmed.contains('\n') || raw_trimmed.contains('#') {
-            quote_core::tickers::read_tickers(Cursor::new(raw_trimmed))?
-        } else {
-            quote_core::tickers::parse_tickers_csv(raw_trimmed)
-        };
+        return config::parse_server_tickers_text(raw, args.ticker_case(), args.strict_tickers);
+    }
 
+    // 3) HTTP URL (feature "tickers-url")
+    #[cfg(feature = "tickers-url")]
+    if let Some(url) = &args.tickers_url {
+        let v = tickers_url::fetch_tickers(url, config::TICKERS_URL_TIMEOUT, args.ticker_case())?;
         if v.is_empty() {
-            anyhow::bail!("tickers list is empty (--tickers)");
+            anyhow::bail!("tickers list is empty (url: {url})");
         }
         return Ok(v);
     }
 
-    // 3) default (встроенный DEFAULT_TICKERS)
-    let v = config::load_server_tickers(None)?;
+    // 4) default (встроенный DEFAULT_TICKERS)
+    if args.no_default_tickers {
+        #[cfg(feature = "tickers-url")]
+        let sources = "--tickers, --tickers-file or --tickers-url";
+        #[cfg(not(feature = "tickers-url"))]
+        let sources = "--tickers or --tickers-file";
+        anyhow::bail!("no tickers source given and --no-default-tickers is set: provide {sources}");
+    }
+    let v = config::load_server_tickers(None, args.ticker_case())?;
     if v.is_empty() {
         anyhow::bail!("default tickers list is empty (DEFAULT_TICKERS)");
     }
     Ok(v)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn no_default_tickers_requires_explicit_source() {
+        let args = Args::try_parse_from(["quote-server", "--no-default-tickers"]).unwrap();
+        let err = load_server_tickers_from_args(&args).unwrap_err();
+        assert!(err.to_string().contains("--no-default-tickers"), "{err}");
+        assert!(err.to_string().contains("--tickers-file"), "{err}");
+        #[cfg(feature = "tickers-url")]
+        assert!(err.to_string().contains("--tickers-url"), "{err}");
+
+        let args = Args::try_parse_from([
+            "quote-server",
+            "--no-default-tickers",
+            "--tickers",
+            "aapl,tsla",
+        ])
+        .unwrap();
+        assert_eq!(
+            load_server_tickers_from_args(&args).unwrap(),
+            vec!["AAPL", "TSLA"]
+        );
+
+        // без флага - встроенный список, как раньше
+        let args = Args::try_parse_from(["quote-server"]).unwrap();
+        assert!(!load_server_tickers_from_args(&args).unwrap().is_empty());
+    }
+
+    #[test]
+    fn max_udp_bps_must_fit_a_quote_packet() {
+        let parse = |v: &str| Args::try_parse_from(["quote-server", "--max-udp-bps", v]);
+        assert!(parse("100").is_err());
+        assert_eq!(
+            parse(&config::MIN_MAX_UDP_BPS.to_string())
+                .unwrap()
+                .max_udp_bps,
+            Some(config::MIN_MAX_UDP_BPS)
+        );
+    }
+
+    #[test]
+    fn session_drain_budget_rejects_zero() {
+        let parse = |v: &str| Args::try_parse_from(["quote-server", "--session-drain-budget", v]);
+        assert!(parse("0").is_err());
+        assert_eq!(parse("1").unwrap().session_drain_budget, 1);
+    }
+}
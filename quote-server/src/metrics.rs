@@ -0,0 +1,281 @@
+use crate::bandwidth::TokenBucket;
+use crate::hub::BroadcastStats;
+use crate::session::StopReason;
+use crate::shutdown::ShutdownFlag;
+use std::fmt::Write as _;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Шаг сна между проверками shutdown
+const SHUTDOWN_POLL_STEP: Duration = Duration::from_millis(100);
+
+/// Счётчики сервера (монотонные, с момента запуска)
+#[derive(Debug, Default)]
+pub(crate) struct ServerMetrics {
+    /// Сгенерированные котировки
+    quotes: AtomicU64,
+    /// Котировки, поставленные в очереди сессий
+    sent: AtomicU64,
+    /// Котировки, не поставленные из-за переполненной очереди сессии
+    dropped_full: AtomicU64,
+    /// Котировки, не поставленные отключившимся сессиям
+    dropped_dead: AtomicU64,
+    /// Открытые сессии STREAM
+    sessions: AtomicU64,
+    /// STREAM, отклонённые из-за `--max-sessions`
+    sessions_rejected: AtomicU64,
+    /// Завершённые сессии по причине (`StopReason`)
+    sessions_ended_shutdown: AtomicU64,
+    sessions_ended_ping_timeout: AtomicU64,
+    sessions_ended_send_error_limit: AtomicU64,
+    sessions_ended_disconnected: AtomicU64,
+    /// Запрошенные тикеры, ни разу не доставленные за сессию (включая неизвестные)
+    tickers_undelivered: AtomicU64,
+    /// UDP-пакеты с чужой версией wire-протокола
+    wire_version_mismatch: AtomicU64,
+    /// Лимит `--max-udp-bps`: отброшенные им котировки считает сам бакет
+    bandwidth: Option<Arc<TokenBucket>>,
+}
+
+impl ServerMetrics {
+    /// Учитывать котировки, отброшенные лимитом `--max-udp-bps`
+    pub(crate) fn with_bandwidth(mut self, bandwidth: Option<Arc<TokenBucket>>) -> Self {
+        self.bandwidth = bandwidth;
+        self
+    }
+
+    /// Учитывает одну сгенерированную котировку и результат её рассылки
+    pub(crate) fn record_broadcast(&self, stats: &BroadcastStats) {
+        self.quotes.fetch_add(1, Ordering::Relaxed);
+        self.sent.fetch_add(stats.sent as u64, Ordering::Relaxed);
+        self.dropped_full
+            .fetch_add(stats.dropped_full as u64, Ordering::Relaxed);
+        self.dropped_dead
+            .fetch_add(stats.dropped_dead as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_session(&self) {
+        self.sessions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_session_rejected(&self) {
+        self.sessions_rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// `undelivered` - запрошенные, но ни разу не доставленные тикеры сессии
+    pub(crate) fn record_session_end(&self, reason: StopReason, undelivered: usize) {
+        let counter = match reason {
+            StopReason::Shutdown => &self.sessions_ended_shutdown,
+            StopReason::PingTimeout => &self.sessions_ended_ping_timeout,
+            StopReason::SendErrorLimit => &self.sessions_ended_send_error_limit,
+            StopReason::Disconnected => &self.sessions_ended_disconnected,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.tickers_undelivered
+            .fetch_add(undelivered as u64, Ordering::Relaxed);
+    }
+
+    /// Возвращает число несовпадений с учётом этого (1 - первое)
+    pub(crate) fn record_wire_version_mismatch(&self) -> u64 {
+        self.wire_version_mismatch.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Счётчики в текстовом формате Prometheus (exposition format 0.0.4)
+    pub(crate) fn render_prometheus(&self) -> String {
+        let load = |c: &AtomicU64| c.load(Ordering::Relaxed);
+        let mut out = String::new();
+
+        counter(
+            &mut out,
+            "quote_server_quotes_total",
+            "Generated quotes",
+            &[("", load(&self.quotes))],
+        );
+        counter(
+            &mut out,
+            "quote_server_quotes_sent_total",
+            "Quotes queued to sessions",
+            &[("", load(&self.sent))],
+        );
+        counter(
+            &mut out,
+            "quote_server_quotes_dropped_total",
+            "Quotes not queued to sessions",
+            &[
+                ("reason=\"full\"", load(&self.dropped_full)),
+                ("reason=\"dead\"", load(&self.dropped_dead)),
+            ],
+        );
+        counter(
+            &mut out,
+            "quote_server_quotes_bandwidth_dropped_total",
+            "Quotes not sent to clients because of --max-udp-bps",
+            &[(
+                "",
+                self.bandwidth.as_ref().map_or(0, |bucket| bucket.dropped()),
+            )],
+        );
+        counter(
+            &mut out,
+            "quote_server_sessions_total",
+            "Accepted STREAM sessions",
+            &[("", load(&self.sessions))],
+        );
+        counter(
+            &mut out,
+            "quote_server_sessions_rejected_total",
+            "STREAM requests rejected by --max-sessions",
+            &[("", load(&self.sessions_rejected))],
+        );
+        counter(
+            &mut out,
+            "quote_server_sessions_ended_total",
+            "Finished STREAM sessions by stop reason",
+            &[
+                ("reason=\"shutdown\"", load(&self.sessions_ended_shutdown)),
+                (
+                    "reason=\"ping_timeout\"",
+                    load(&self.sessions_ended_ping_timeout),
+                ),
+                (
+                    "reason=\"send_error_limit\"",
+                    load(&self.sessions_ended_send_error_limit),
+                ),
+                (
+                    "reason=\"disconnected\"",
+                    load(&self.sessions_ended_disconnected),
+                ),
+            ],
+        );
+        counter(
+            &mut out,
+            "quote_server_tickers_undelivered_total",
+            "Requested tickers never delivered during a session",
+            &[("", load(&self.tickers_undelivered))],
+        );
+        counter(
+            &mut out,
+            "quote_server_wire_version_mismatch_total",
+            "UDP packets with an unsupported wire version",
+            &[("", load(&self.wire_version_mismatch))],
+        );
+
+        out
+    }
+
+    /// Перезаписывает `path` текущими счётчиками (через временный файл и rename,
+    /// чтобы читатель не увидел файл наполовину)
+    pub(crate) fn write_file(&self, path: &Path) -> io::Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+
+        std::fs::write(&tmp, self.render_prometheus())?;
+        std::fs::rename(&tmp, path)
+    }
+}
+
+/// Поток `--metrics-file`: перезапись файла раз в `interval` и финальная - при shutdown
+pub(crate) fn run_metrics_writer(
+    metrics: Arc<ServerMetrics>,
+    path: PathBuf,
+    interval: Duration,
+    shutdown: Arc<ShutdownFlag>,
+) {
+    loop {
+        if let Err(e) = metrics.write_file(&path) {
+            log::warn!("metrics file {path:?} write failed: {e}");
+        }
+        if shutdown.is_stopped() {
+            break;
+        }
+
+        let mut slept = Duration::ZERO;
+        while slept < interval && !shutdown.is_stopped() {
+            std::thread::sleep(SHUTDOWN_POLL_STEP);
+            slept += SHUTDOWN_POLL_STEP;
+        }
+    }
+}
+
+/// `# HELP`, `# TYPE` и по строке на набор меток ("" - без меток)
+fn counter(out: &mut String, name: &str, help: &str, samples: &[(&str, u64)]) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} counter");
+    for (labels, value) in samples {
+        if labels.is_empty() {
+            let _ = writeln!(out, "{name} {value}");
+        } else {
+            let _ = writeln!(out, "{name}{{{labels}}} {value}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_valid_metric_name(name: &str) -> bool {
+        let mut chars = name.chars();
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+    }
+
+    #[test]
+    fn render_prometheus_lists_every_counter_with_value() {
+        let bucket = Arc::new(TokenBucket::new(1500));
+        assert!(!bucket.try_take(1501));
+        let m = ServerMetrics::default().with_bandwidth(Some(bucket));
+        for _ in 0..3 {
+            m.record_broadcast(&BroadcastStats {
+                sent: 2,
+                dropped_full: 1,
+                dropped_dead: 0,
+            });
+        }
+        m.record_broadcast(&BroadcastStats {
+            sent: 0,
+            dropped_full: 0,
+            dropped_dead: 1,
+        });
+        m.record_session();
+        m.record_session();
+        m.record_session_rejected();
+        m.record_session_end(StopReason::PingTimeout, 0);
+        m.record_session_end(StopReason::Disconnected, 2);
+        m.record_session_end(StopReason::PingTimeout, 1);
+        m.record_wire_version_mismatch();
+
+        let text = m.render_prometheus();
+        let samples: Vec<&str> = text.lines().filter(|l| !l.starts_with('#')).collect();
+
+        assert_eq!(
+            samples,
+            vec![
+                "quote_server_quotes_total 4",
+                "quote_server_quotes_sent_total 6",
+                "quote_server_quotes_dropped_total{reason=\"full\"} 3",
+                "quote_server_quotes_dropped_total{reason=\"dead\"} 1",
+                "quote_server_quotes_bandwidth_dropped_total 1",
+                "quote_server_sessions_total 2",
+                "quote_server_sessions_rejected_total 1",
+                "quote_server_sessions_ended_total{reason=\"shutdown\"} 0",
+                "quote_server_sessions_ended_total{reason=\"ping_timeout\"} 2",
+                "quote_server_sessions_ended_total{reason=\"send_error_limit\"} 0",
+                "quote_server_sessions_ended_total{reason=\"disconnected\"} 1",
+                "quote_server_tickers_undelivered_total 3",
+                "quote_server_wire_version_mismatch_total 1",
+            ]
+        );
+
+        for line in samples {
+            let name = line.split(['{', ' ']).next().unwrap();
+            assert!(is_valid_metric_name(name), "bad metric name {name:?}");
+            assert!(text.contains(&format!("# TYPE {name} counter\n")));
+        }
+    }
+}
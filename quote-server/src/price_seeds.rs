@@ -0,0 +1,97 @@
+use quote_core::tickers::TickerCase;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub(crate) enum PriceSeedsError {
+    #[error("price seeds line {line}: expected `TICKER,PRICE`")]
+    Malformed { line: usize },
+
+    #[error("price seeds line {line}: invalid price {value:?}")]
+    InvalidPrice { line: usize, value: String },
+
+    #[error("price seeds io error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Начальные цены тикеров из CSV (`--price-seeds`), по строке на тикер:
+/// ```text
+/// # ticker,price
+/// AAPL,189.50
+/// TSLA,250
+/// ```
+/// Цена - в рублях/долларах с точностью до копеек/центов, результат - в копейках,
+/// как `StockQuote::price`.
+pub(crate) fn parse_price_seeds<R: io::Read>(
+    reader: R,
+    case: TickerCase,
+) -> Result<HashMap<String, i64>, PriceSeedsError> {
+    let mut seeds = HashMap::new();
+
+    for (idx, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line?;
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (ticker, price) = line
+            .split_once(',')
+            .ok_or(PriceSeedsError::Malformed { line: idx + 1 })?;
+        let ticker = ticker.trim();
+        if ticker.is_empty() {
+            return Err(PriceSeedsError::Malformed { line: idx + 1 });
+        }
+
+        let price = price.trim();
+        let cents = price
+            .parse::<f64>()
+            .ok()
+            .filter(|p| p.is_finite() && *p > 0.0)
+            .map(|p| (p * 100.0).round() as i64)
+            .ok_or_else(|| PriceSeedsError::InvalidPrice {
+                line: idx + 1,
+                value: price.to_string(),
+            })?;
+
+        let ticker = match case {
+            TickerCase::Upper => ticker.to_ascii_uppercase(),
+            TickerCase::Preserve => ticker.to_string(),
+        };
+        seeds.insert(ticker, cents);
+    }
+
+    Ok(seeds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn parse_reads_prices_in_cents() {
+        let seeds = parse_price_seeds(
+            Cursor::new("# ticker,price\naapl, 189.50\n\nTSLA,250 # round\n"),
+            TickerCase::Upper,
+        )
+        .unwrap();
+
+        assert_eq!(seeds.len(), 2);
+        assert_eq!(seeds["AAPL"], 18_950);
+        assert_eq!(seeds["TSLA"], 25_000);
+    }
+
+    #[test]
+    fn parse_rejects_malformed_lines_and_bad_prices() {
+        assert!(matches!(
+            parse_price_seeds(Cursor::new("AAPL 189\n"), TickerCase::Upper),
+            Err(PriceSeedsError::Malformed { line: 1 })
+        ));
+        assert!(matches!(
+            parse_price_seeds(Cursor::new("AAPL,1\nTSLA,-3\n"), TickerCase::Upper),
+            Err(PriceSeedsError::InvalidPrice { line: 2, .. })
+        ));
+    }
+}
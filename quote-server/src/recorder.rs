@@ -0,0 +1,367 @@
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use log::warn;
+use quote_core::StockQuote;
+use std::fs::OpenOptions;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Итог записи (`FileSink::finish`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RecordStats {
+    pub(crate) written: u64,
+    /// Не записаны: очередь писателя была полна
+    pub(crate) dropped: u64,
+}
+
+/// Сквозная нумерация записи (`--seq-state`): строки получают префикс `seq=<n>`,
+/// следующий номер хранится в файле состояния и переживает перезапуск сервера -
+/// склеенные записи разных запусков не путаются.
+pub(crate) struct SeqState {
+    path: PathBuf,
+    next: AtomicU64,
+    /// Как часто писатель сохраняет номер (и обязательно - в `FileSink::finish`)
+    flush_every: Duration,
+}
+
+impl SeqState {
+    /// Номер из файла `path`; нет файла или он испорчен - с 0 (с warning)
+    pub(crate) fn load(path: PathBuf, flush_every: Duration) -> Self {
+        let next = match std::fs::read_to_string(&path) {
+            Ok(text) => text.trim().parse::<u64>().unwrap_or_else(|_| {
+                warn!(
+                    "seq state {path:?} is corrupt ({:?}); starting from 0",
+                    text.trim()
+                );
+                0
+            }),
+            Err(e) => {
+                warn!("seq state {path:?} unavailable ({e}); starting from 0");
+                0
+            }
+        };
+        Self {
+            path,
+            next: AtomicU64::new(next),
+            flush_every,
+        }
+    }
+
+    /// Следующий номер, который получит котировка
+    pub(crate) fn next(&self) -> u64 {
+        self.next.load(Ordering::Relaxed)
+    }
+
+    /// Сохраняет `next` через временный файл: оборванная запись не портит состояние
+    fn save(&self, next: u64) -> io::Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, format!("{next}\n"))?;
+        std::fs::rename(&tmp, &self.path)
+    }
+}
+
+/// Запись котировок в файл (`--record`), по строке на котировку.
+///
+/// Горячий путь (рассылка) только кладёт котировку в ограниченную очередь,
+/// в файл пишет отдельный поток. Если диск не успевает и очередь полна,
+/// котировка отбрасывается и учитывается в `dropped` - рассылка не ждёт диск.
+pub(crate) struct FileSink {
+    tx: Sender<(Option<u64>, Arc<StockQuote>)>,
+    dropped: AtomicU64,
+    seq: Option<Arc<SeqState>>,
+    writer: JoinHandle<io::Result<u64>>,
+}
+
+impl FileSink {
+    /// Дописывает в конец `path` (файл создаётся при необходимости);
+    /// `seq` - нумерация строк (`--seq-state`)
+    pub(crate) fn create(path: &Path, capacity: usize, seq: Option<SeqState>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::with_writer_seq(file, capacity, seq))
+    }
+
+    #[cfg(test)]
+    pub(crate) fn with_writer<W: Write + Send + 'static>(out: W, capacity: usize) -> Self {
+        Self::with_writer_seq(out, capacity, None)
+    }
+
+    pub(crate) fn with_writer_seq<W: Write + Send + 'static>(
+        out: W,
+        capacity: usize,
+        seq: Option<SeqState>,
+    ) -> Self {
+        let (tx, rx) = crossbeam_channel::bounded(capacity);
+        let seq = seq.map(Arc::new);
+        let writer_seq = seq.clone();
+        let writer =
+            thread::spawn(move || write_quotes(&rx, BufWriter::new(out), writer_seq.as_deref()));
+        Self {
+            tx,
+            dropped: AtomicU64::new(0),
+            seq,
+            writer,
+        }
+    }
+
+    /// Не блокирует: false - очередь полна (или писатель упал), котировка отброшена.
+    /// Номер `--seq-state` расходуется и на отброшенную котировку: пропуск виден в файле
+    pub(crate) fn record(&self, q: &StockQuote) -> bool {
+        let n = self
+            .seq
+            .as_ref()
+            .map(|s| s.next.fetch_add(1, Ordering::Relaxed));
+        match self.tx.try_send((n, Arc::new(q.clone()))) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => {
+                if self.dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                    warn!("recorder is falling behind: dropping quotes");
+                }
+                false
+            }
+        }
+    }
+
+    /// Закрывает очередь и ждёт, пока писатель допишет остаток и сделает flush
+    pub(crate) fn finish(self) -> io::Result<RecordStats> {
+        let Self {
+            tx,
+            dropped,
+            seq,
+            writer,
+        } = self;
+        drop(tx);
+
+        let written = writer
+            .join()
+            .map_err(|_| io::Error::other("recorder writer thread panicked"))??;
+        if let Some(seq) = seq {
+            seq.save(seq.next())?;
+        }
+        Ok(RecordStats {
+            written,
+            dropped: dropped.into_inner(),
+        })
+    }
+}
+
+/// Запись остатка очередей сессий при shutdown (`--drain-record`).
+///
+/// В отличие от [`FileSink`] не теряет котировки: сервер уже останавливается,
+/// и сессии могут подождать диск. Писатель завершается, когда закрыты все
+/// копии отправителя из [`DrainRecord::sender`]
+pub(crate) struct DrainRecord {
+    tx: Sender<Arc<StockQuote>>,
+    writer: JoinHandle<io::Result<u64>>,
+}
+
+impl DrainRecord {
+    /// Дописывает в конец `path` (файл создаётся при необходимости)
+    pub(crate) fn create(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let (tx, rx) = crossbeam_channel::unbounded::<Arc<StockQuote>>();
+        let writer = thread::spawn(move || {
+            let mut out = BufWriter::new(file);
+            let mut written = 0;
+            for q in rx {
+                writeln!(out, "{q}")?;
+                written += 1;
+            }
+            out.flush()?;
+            Ok(written)
+        });
+        Ok(Self { tx, writer })
+    }
+
+    /// Отправитель для сессий (`SessionConfig::shutdown_sink`)
+    pub(crate) fn sender(&self) -> Sender<Arc<StockQuote>> {
+        self.tx.clone()
+    }
+
+    /// Ждёт, пока все отправители закроются и писатель сделает flush;
+    /// возвращает число записанных котировок
+    pub(crate) fn finish(self) -> io::Result<u64> {
+        let Self { tx, writer } = self;
+        drop(tx);
+        writer
+            .join()
+            .map_err(|_| io::Error::other("drain record writer thread panicked"))?
+    }
+}
+
+/// Поток писателя: flush, как только очередь опустела, чтобы файл
+/// отставал от рассылки не больше, чем на содержимое очереди.
+/// Номер `--seq-state` сохраняется после flush, не чаще `flush_every`
+fn write_quotes<W: Write>(
+    rx: &Receiver<(Option<u64>, Arc<StockQuote>)>,
+    mut out: BufWriter<W>,
+    seq: Option<&SeqState>,
+) -> io::Result<u64> {
+    let mut written = 0;
+    let mut last_save = Instant::now();
+    for (n, q) in rx {
+        match n {
+            Some(n) => writeln!(out, "seq={n} {q}")?,
+            None => writeln!(out, "{q}")?,
+        }
+        written += 1;
+        if rx.is_empty() {
+            out.flush()?;
+            if let (Some(seq), Some(n)) = (seq, n)
+                && last_save.elapsed() >= seq.flush_every
+            {
+                if let Err(e) = seq.save(n + 1) {
+                    warn!("failed to save seq state {:?}: {e}", seq.path);
+                }
+                last_save = Instant::now();
+            }
+        }
+    }
+    out.flush()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn mk_quote(ticker: &str) -> StockQuote {
+        StockQuote {
+            ticker: ticker.to_string(),
+            price: 100_0000,
+            volume: 1,
+            timestamp_ms: 1,
+            side: None,
+            sent_ms: None,
+        }
+    }
+
+    /// Каждая запись ждёт сигнала (или закрытия `gate`) - "медленный диск"
+    struct GatedWriter {
+        gate: Receiver<()>,
+        out: Vec<u8>,
+    }
+
+    impl Write for GatedWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let _ = self.gate.recv();
+            self.out.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn blocked_writer_drops_instead_of_stalling_producer() {
+        let (gate_tx, gate) = crossbeam_channel::unbounded();
+        let sink = FileSink::with_writer(
+            GatedWriter {
+                gate,
+                out: Vec::new(),
+            },
+            4,
+        );
+
+        let started = Instant::now();
+        let recorded = (0..100).filter(|_| sink.record(&mk_quote("AAPL"))).count();
+        assert!(
+            started.elapsed() < Duration::from_millis(500),
+            "producer must not wait for the writer"
+        );
+        assert!(recorded < 100, "queue of 4 cannot take 100 quotes");
+
+        drop(gate_tx);
+        let stats = sink.finish().unwrap();
+        assert_eq!(stats.written, recorded as u64);
+        assert_eq!(stats.written + stats.dropped, 100);
+    }
+
+    #[test]
+    fn finish_flushes_every_queued_quote() {
+        let path = std::env::temp_dir().join(format!("quote-record-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let sink = FileSink::create(&path, 16, None).unwrap();
+        for t in ["AAPL", "TSLA"] {
+            assert!(sink.record(&mk_quote(t)));
+        }
+        let stats = sink.finish().unwrap();
+        assert_eq!(
+            stats,
+            RecordStats {
+                written: 2,
+                dropped: 0
+            }
+        );
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let tickers: Vec<&str> = text.lines().map(|l| l.split(' ').next().unwrap()).collect();
+        assert_eq!(tickers, ["AAPL", "TSLA"]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn drain_writer_writes_every_quote_once_senders_close() {
+        let path = std::env::temp_dir().join(format!("quote-drain-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let drain = DrainRecord::create(&path).unwrap();
+        let session_tx = drain.sender();
+        for t in ["AAPL", "TSLA"] {
+            session_tx.send(Arc::new(mk_quote(t))).unwrap();
+        }
+        drop(session_tx);
+        assert_eq!(drain.finish().unwrap(), 2);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let tickers: Vec<&str> = text.lines().map(|l| l.split(' ').next().unwrap()).collect();
+        assert_eq!(tickers, ["AAPL", "TSLA"]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn seq_state_continues_numbering_after_restart() {
+        let dir = std::env::temp_dir();
+        let record = dir.join(format!("quote-record-seq-{}.log", std::process::id()));
+        let state = dir.join(format!("quote-record-seq-{}.state", std::process::id()));
+        let _ = std::fs::remove_file(&record);
+        let _ = std::fs::remove_file(&state);
+
+        // первый запуск: файла состояния нет - с 0
+        for tickers in [&["AAPL", "TSLA", "MSFT"][..], &["AAPL", "TSLA"][..]] {
+            let seq = SeqState::load(state.clone(), Duration::from_secs(60));
+            let sink = FileSink::create(&record, 16, Some(seq)).unwrap();
+            for t in tickers {
+                assert!(sink.record(&mk_quote(t)));
+            }
+            sink.finish().unwrap();
+        }
+
+        let text = std::fs::read_to_string(&record).unwrap();
+        let seqs: Vec<&str> = text.lines().map(|l| l.split(' ').next().unwrap()).collect();
+        assert_eq!(seqs, ["seq=0", "seq=1", "seq=2", "seq=3", "seq=4"]);
+        assert_eq!(std::fs::read_to_string(&state).unwrap(), "5\n");
+
+        std::fs::remove_file(&record).unwrap();
+        std::fs::remove_file(&state).unwrap();
+    }
+
+    #[test]
+    fn corrupt_seq_state_starts_from_zero() {
+        let state =
+            std::env::temp_dir().join(format!("quote-seq-corrupt-{}.state", std::process::id()));
+        std::fs::write(&state, "not a number").unwrap();
+
+        assert_eq!(SeqState::load(state.clone(), Duration::ZERO).next(), 0);
+
+        std::fs::remove_file(&state).unwrap();
+    }
+}
@@ -0,0 +1,314 @@
+use crate::cli::Args;
+use crate::config;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+
+/// Итоговая конфигурация сервера после применения умолчаний и флагов
+/// (`--dump-config`)
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ServerConfig {
+    pub(crate) tcp_bind: SocketAddr,
+    pub(crate) udp_bind: SocketAddr,
+    /// Unix-сокет управляющего канала (вместо `tcp_bind`)
+    pub(crate) control_uds: Option<String>,
+    pub(crate) accept_error_backoff_ms: u64,
+    pub(crate) tcp_backlog: Option<u32>,
+    /// Источник тикеров: `default`, `file:<path>`, `text`, `url:<url>`
+    pub(crate) tickers_source: String,
+    /// Сколько тикеров генерирует сервер (после `--tickers-count`)
+    pub(crate) ticker_count: usize,
+    pub(crate) with_default_tickers: bool,
+    pub(crate) no_default_tickers: bool,
+    pub(crate) strict_tickers: bool,
+    pub(crate) watch_tickers: bool,
+    pub(crate) preserve_case: bool,
+    pub(crate) quote_interval_ms: u64,
+    pub(crate) ping_timeout_ms: u64,
+    pub(crate) client_queue_capacity: usize,
+    pub(crate) max_sessions: Option<u32>,
+    pub(crate) session_drain_budget: usize,
+    pub(crate) backlog_warn_ratio: f64,
+    pub(crate) coalesce: bool,
+    pub(crate) dedup: bool,
+    pub(crate) ema_alpha: Option<f64>,
+    pub(crate) normal_std_dev: Option<f64>,
+    pub(crate) warmup_ticks: u32,
+    pub(crate) on_demand: bool,
+    /// `START-END` по UTC
+    pub(crate) market_hours: Option<String>,
+    pub(crate) enable_pong: bool,
+    pub(crate) udp_ttl: Option<u32>,
+    pub(crate) udp_tos: Option<u8>,
+    pub(crate) max_udp_bps: Option<u64>,
+    pub(crate) ping_token: bool,
+    pub(crate) debug_commands: bool,
+    pub(crate) log_json: bool,
+    pub(crate) allow_cidr: Vec<String>,
+    pub(crate) priority_client: Vec<String>,
+    pub(crate) metrics_file: Option<String>,
+    pub(crate) record: Option<String>,
+    pub(crate) drain_record: Option<String>,
+    pub(crate) seq_state: Option<String>,
+}
+
+impl ServerConfig {
+    /// `tickers` - уже загруженный список тикеров генератора
+    pub(crate) fn resolve(args: &Args, tickers: &[String]) -> Self {
+        Self {
+            tcp_bind: args.tcp_bind,
+            udp_bind: args.udp_bind,
+            #[cfg(unix)]
+            control_uds: args.control_uds.as_ref().map(|p| p.display().to_string()),
+            #[cfg(not(unix))]
+            control_uds: None,
+            accept_error_backoff_ms: args.accept_error_backoff_ms,
+            tcp_backlog: args.tcp_backlog,
+            tickers_source: tickers_source(args),
+            ticker_count: tickers.len(),
+            with_default_tickers: args.with_default_tickers,
+            no_default_tickers: args.no_default_tickers,
+            strict_tickers: args.strict_tickers,
+            watch_tickers: args.watch_tickers,
+            preserve_case: args.preserve_case,
+            quote_interval_ms: config::QUOTE_INTERVAL.as_millis() as u64,
+            ping_timeout_ms: config::PING_TIMEOUT.as_millis() as u64,
+            client_queue_capacity: config::CLIENT_QUEUE_CAPACITY,
+            max_sessions: args.max_sessions,
+            session_drain_budget: args.session_drain_budget,
+            backlog_warn_ratio: args.backlog_warn_ratio,
+            coalesce: args.coalesce,
+            dedup: args.dedup,
+            ema_alpha: args.ema_alpha,
+            normal_std_dev: args.normal_std_dev,
+            warmup_ticks: args.warmup_ticks,
+            on_demand: args.on_demand,
+            market_hours: args
+                .market_hours
+                .map(|(start, end)| format!("{start}-{end}")),
+            enable_pong: args.enable_pong,
+            udp_ttl: args.udp_ttl,
+            udp_tos: args.udp_tos,
+            max_udp_bps: args.max_udp_bps,
+            ping_token: args.ping_token,
+            debug_commands: args.debug_commands,
+            log_json: args.log_json,
+            allow_cidr: args.allow_cidr.iter().map(|c| c.to_string()).collect(),
+            priority_client: args.priority_client.clone(),
+            metrics_file: args.metrics_file.as_ref().map(|p| p.display().to_string()),
+            record: args.record.as_ref().map(|p| p.display().to_string()),
+            drain_record: args.drain_record.as_ref().map(|p| p.display().to_string()),
+            seq_state: args.seq_state.as_ref().map(|p| p.display().to_string()),
+        }
+    }
+
+    /// TOML (`key = value` на строку); незаданные опции пропускаются
+    pub(crate) fn to_toml(&self) -> String {
+        let mut out = String::new();
+        let mut kv = |key: &str, value: String| {
+            let _ = writeln!(out, "{key} = {value}");
+        };
+
+        kv("tcp_bind", toml_str(&self.tcp_bind.to_string()));
+        kv("udp_bind", toml_str(&self.udp_bind.to_string()));
+        if let Some(v) = &self.control_uds {
+            kv("control_uds", toml_str(v));
+        }
+        kv(
+            "accept_error_backoff_ms",
+            self.accept_error_backoff_ms.to_string(),
+        );
+        if let Some(v) = self.tcp_backlog {
+            kv("tcp_backlog", v.to_string());
+        }
+        kv("tickers_source", toml_str(&self.tickers_source));
+        kv("ticker_count", self.ticker_count.to_string());
+        kv(
+            "with_default_tickers",
+            self.with_default_tickers.to_string(),
+        );
+        kv("no_default_tickers", self.no_default_tickers.to_string());
+        kv("strict_tickers", self.strict_tickers.to_string());
+        kv("watch_tickers", self.watch_tickers.to_string());
+        kv("preserve_case", self.preserve_case.to_string());
+        kv("quote_interval_ms", self.quote_interval_ms.to_string());
+        kv("ping_timeout_ms", self.ping_timeout_ms.to_string());
+        kv(
+            "client_queue_capacity",
+            self.client_queue_capacity.to_string(),
+        );
+        if let Some(v) = self.max_sessions {
+            kv("max_sessions", v.to_string());
+        }
+        kv(
+            "session_drain_budget",
+            self.session_drain_budget.to_string(),
+        );
+        kv("backlog_warn_ratio", toml_float(self.backlog_warn_ratio));
+        kv("coalesce", self.coalesce.to_string());
+        kv("dedup", self.dedup.to_string());
+        if let Some(v) = self.ema_alpha {
+            kv("ema_alpha", toml_float(v));
+        }
+        if let Some(v) = self.normal_std_dev {
+            kv("normal_std_dev", toml_float(v));
+        }
+        kv("warmup_ticks", self.warmup_ticks.to_string());
+        kv("on_demand", self.on_demand.to_string());
+        if let Some(v) = &self.market_hours {
+            kv("market_hours", toml_str(v));
+        }
+        kv("enable_pong", self.enable_pong.to_string());
+        if let Some(v) = self.udp_ttl {
+            kv("udp_ttl", v.to_string());
+        }
+        if let Some(v) = self.udp_tos {
+            kv("udp_tos", v.to_string());
+        }
+        if let Some(v) = self.max_udp_bps {
+            kv("max_udp_bps", v.to_string());
+        }
+        kv("ping_token", self.ping_token.to_string());
+        kv("debug_commands", self.debug_commands.to_string());
+        kv("log_json", self.log_json.to_string());
+        kv("allow_cidr", toml_str_array(&self.allow_cidr));
+        kv("priority_client", toml_str_array(&self.priority_client));
+        if let Some(v) = &self.metrics_file {
+            kv("metrics_file", toml_str(v));
+        }
+        if let Some(v) = &self.record {
+            kv("record", toml_str(v));
+        }
+        if let Some(v) = &self.drain_record {
+            kv("drain_record", toml_str(v));
+        }
+        if let Some(v) = &self.seq_state {
+            kv("seq_state", toml_str(v));
+        }
+
+        out
+    }
+}
+
+fn tickers_source(args: &Args) -> String {
+    if let Some(p) = &args.tickers_file {
+        return format!("file:{}", p.display());
+    }
+    if args.tickers.is_some() {
+        return "text".to_string();
+    }
+    #[cfg(feature = "tickers-url")]
+    if let Some(url) = &args.tickers_url {
+        return format!("url:{url}");
+    }
+    "default".to_string()
+}
+
+/// Строка TOML в двойных кавычках
+fn toml_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04X}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn toml_str_array(items: &[String]) -> String {
+    let items: Vec<String> = items.iter().map(|s| toml_str(s)).collect();
+    format!("[{}]", items.join(", "))
+}
+
+/// TOML требует точку у float: `1.0`, а не `1`
+fn toml_float(v: f64) -> String {
+    format!("{v:?}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::collections::HashMap;
+
+    /// Минимальный разбор того подмножества TOML, которое пишет `to_toml`
+    fn parse_toml(s: &str) -> HashMap<String, String> {
+        s.lines()
+            .map(|l| {
+                let (k, v) = l.split_once(" = ").expect("key = value");
+                (k.to_string(), v.to_string())
+            })
+            .collect()
+    }
+
+    fn unquote(v: &str) -> String {
+        v.strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .expect("quoted string")
+            .replace("\\\"", "\"")
+            .replace("\\\\", "\\")
+    }
+
+    #[test]
+    fn dump_matches_cli_inputs() {
+        let args = Args::try_parse_from([
+            "quote-server",
+            "--tcp-bind",
+            "127.0.0.1:7000",
+            "--tickers",
+            "AAPL,MSFT,TSLA",
+            "--max-sessions",
+            "8",
+            "--ema-alpha",
+            "0.5",
+            "--market-hours",
+            "9-17",
+            "--allow-cidr",
+            "10.0.0.0/8,127.0.0.1",
+            "--coalesce",
+        ])
+        .unwrap();
+        let tickers = vec!["AAPL".to_string(), "MSFT".to_string(), "TSLA".to_string()];
+
+        let cfg = ServerConfig::resolve(&args, &tickers);
+        let dumped = parse_toml(&cfg.to_toml());
+
+        assert_eq!(unquote(&dumped["tcp_bind"]), "127.0.0.1:7000");
+        assert_eq!(
+            unquote(&dumped["udp_bind"]).parse::<SocketAddr>().unwrap(),
+            args.udp_bind
+        );
+        assert_eq!(unquote(&dumped["tickers_source"]), "text");
+        assert_eq!(dumped["ticker_count"], "3");
+        assert_eq!(dumped["max_sessions"], "8");
+        assert_eq!(dumped["ema_alpha"].parse::<f64>().unwrap(), 0.5);
+        assert_eq!(unquote(&dumped["market_hours"]), "9-17");
+        assert_eq!(dumped["coalesce"], "true");
+        assert_eq!(dumped["preserve_case"], "false");
+        assert_eq!(
+            dumped["quote_interval_ms"],
+            config::QUOTE_INTERVAL.as_millis().to_string()
+        );
+        assert_eq!(dumped["allow_cidr"], r#"["10.0.0.0/8", "127.0.0.1/32"]"#);
+        assert_eq!(dumped["priority_client"], "[]");
+
+        // незаданные опции не попадают в вывод
+        assert!(!dumped.contains_key("normal_std_dev"));
+        assert!(!dumped.contains_key("metrics_file"));
+    }
+
+    #[test]
+    fn toml_str_escapes_quotes_and_backslashes() {
+        let s = r#"C:\tmp\"x".txt"#;
+        assert_eq!(unquote(&toml_str(s)), s);
+        assert_eq!(toml_float(1.0), "1.0");
+    }
+}
@@ -1,70 +1,389 @@
+use crate::bandwidth::TokenBucket;
 use crate::config::ClientId;
-use crate::config::{PING_TIMEOUT, UDP_SOCKET_TICK};
-use crate::udp_ping::LastPingMap;
-use crossbeam_channel::Receiver;
-use log::{info, warn};
-use quote_core::StockQuote;
-use quote_core::wire::{UdpPacketV1, encode_v1};
-use std::collections::HashSet;
-use std::time::Instant;
-use std::{
-    net::UdpSocket,
-    sync::{Arc, atomic::AtomicBool, atomic::Ordering},
+use crate::config::{
+    PING_TIMEOUT, SESSION_BACKLOG_WARN_RATIO, SESSION_DRAIN_BUDGET, UDP_SOCKET_TICK,
 };
+use crate::loss::LossMonitor;
+use crate::shutdown::{ShutdownFlag, ShutdownPhase};
+use crate::udp_ping::LastPingMap;
+use crossbeam_channel::{Receiver, Sender};
+use log::{debug, info, warn};
+use quote_core::wire::{UdpPacketV1, WireFormat, encode_v1, encode_v1_into, fixed};
+use quote_core::{StockQuote, WireError};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{net::UdpSocket, sync::Arc};
 
 const BACK_TO_BACK_SEND_ERR_LIMIT: usize = 20;
 
+/// Настройки сессии
+#[derive(Debug, Clone)]
+pub(crate) struct SessionConfig {
+    /// Максимум котировок, разбираемых из очереди за одну итерацию цикла
+    /// (чтобы большой backlog не задерживал проверку shutdown/ping)
+    pub(crate) drain_budget: usize,
+    /// Схлопывать накопившиеся котировки одного тикера до последней
+    pub(crate) coalesce: bool,
+    /// Доля заполнения очереди клиента, при которой пишем warning
+    pub(crate) backlog_warn_ratio: f64,
+    /// Не чаще одной котировки на тикер за интервал (клиентский `RATE=<ms>`)
+    pub(crate) min_send_interval: Option<Duration>,
+    /// Окно от старта сессии, в котором `min_send_interval` не применяется
+    /// (клиентский `BURST=<ms>`: сразу получить текущее состояние, потом троттлинг)
+    pub(crate) initial_burst: Option<Duration>,
+    /// Формат UDP-котировок (клиентский `WIRE=`)
+    pub(crate) wire: WireFormat,
+    /// Получатель для записи котировок: при shutdown остаток очереди сессии
+    /// (по её тикерам) уходит сюда, а не теряется. None - остаток отбрасывается
+    pub(crate) shutdown_sink: Option<Sender<Arc<StockQuote>>>,
+    /// Оценка потерь (клиентский `ACK=1`): checkpoint среди котировок
+    pub(crate) loss: Option<Arc<LossMonitor>>,
+    /// Тикеры, убранные с сервера ([`crate::hub::Hub::remove_ticker`]): клиент
+    /// получает `TickerRemoved`, тикер выходит из фильтра сессии
+    pub(crate) removals: Option<Receiver<String>>,
+    /// Общий для всех сессий лимит исходящего трафика (`--max-udp-bps`):
+    /// котировка, на которую не хватило бюджета, отбрасывается
+    pub(crate) bandwidth: Option<Arc<TokenBucket>>,
+    /// Запрошенные клиентом тикеры и шаблоны, которых нет на сервере
+    /// (`unknown` из [`crate::subscription::resolve_subscription`]): в фильтр
+    /// сессии не входят, но в итоге сессии считаются недоставленными
+    pub(crate) unresolved: Vec<String>,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            drain_budget: SESSION_DRAIN_BUDGET,
+            coalesce: false,
+            backlog_warn_ratio: SESSION_BACKLOG_WARN_RATIO,
+            min_send_interval: None,
+            initial_burst: None,
+            wire: WireFormat::Postcard,
+            shutdown_sink: None,
+            loss: None,
+            removals: None,
+            bandwidth: None,
+            unresolved: Vec::new(),
+        }
+    }
+}
+
+/// Кодирует котировки в выбранном формате;
+/// scratch-буфер переиспользуется между пакетами
+#[derive(Debug)]
+struct QuoteEncoder {
+    format: WireFormat,
+    buf: Vec<u8>,
+}
+
+impl QuoteEncoder {
+    fn new(format: WireFormat) -> Self {
+        Self {
+            format,
+            buf: Vec::new(),
+        }
+    }
+
+    /// В postcard-формате котировка получает `sent_ms` - момент кодирования
+    fn encode(&mut self, q: &StockQuote) -> Result<&[u8], WireError> {
+        match self.format {
+            WireFormat::Postcard => {
+                let stamped = StockQuote {
+                    sent_ms: Some(now_ms()),
+                    ..q.clone()
+                };
+                encode_v1_into(&UdpPacketV1::Quote(stamped), &mut self.buf)?
+            }
+            WireFormat::Fixed => fixed::encode_fixed_into(q, &mut self.buf)?,
+            WireFormat::FixedSecs => fixed::encode_fixed_secs_into(q, &mut self.buf)?,
+        }
+        Ok(&self.buf)
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Per-ticker ограничение частоты отправки
+#[derive(Debug)]
+struct RateLimiter {
+    min_interval: Option<Duration>,
+    last_sent: HashMap<String, Instant>,
+    /// до этого момента ограничение не действует (начальный burst)
+    burst_until: Option<Instant>,
+}
+
+impl RateLimiter {
+    fn new(min_interval: Option<Duration>) -> Self {
+        Self {
+            min_interval,
+            last_sent: HashMap::new(),
+            burst_until: None,
+        }
+    }
+
+    fn with_burst_until(mut self, until: Option<Instant>) -> Self {
+        self.burst_until = until;
+        self
+    }
+
+    /// true - котировку тикера можно отправить сейчас (и это запоминается)
+    fn allow(&mut self, ticker: &str, now: Instant) -> bool {
+        let Some(interval) = self.min_interval else {
+            return true;
+        };
+
+        // в burst пропускаем всё, но запоминаем время, чтобы после окна
+        // троттлинг продолжился от последней отправленной котировки
+        if self.burst_until.is_some_and(|until| now < until) {
+            self.last_sent.insert(ticker.to_string(), now);
+            return true;
+        }
+
+        match self.last_sent.get_mut(ticker) {
+            Some(last) if now.duration_since(*last) < interval => false,
+            Some(last) => {
+                *last = now;
+                true
+            }
+            None => {
+                self.last_sent.insert(ticker.to_string(), now);
+                true
+            }
+        }
+    }
+}
+
+/// Предупреждение о заполнении очереди: срабатывает при пересечении порога
+/// снизу вверх и снова "взводится", когда очередь опускается ниже порога.
+#[derive(Debug, Default)]
+struct BacklogMonitor {
+    above: bool,
+}
+
+impl BacklogMonitor {
+    /// true - очередь только что пересекла порог (нужно предупредить)
+    fn check(&mut self, len: usize, capacity: usize, ratio: f64) -> bool {
+        let above = capacity > 0 && len as f64 >= capacity as f64 * ratio;
+        let crossed = above && !self.above;
+        self.above = above;
+        crossed
+    }
+}
+
+/// Тикеры, котировки которых сессия хоть раз отправила клиенту
+#[derive(Debug, Default)]
+struct DeliveredTickers {
+    sent: HashSet<String>,
+}
+
+impl DeliveredTickers {
+    fn record(&mut self, ticker: &str) {
+        if !self.sent.contains(ticker) {
+            self.sent.insert(ticker.to_string());
+        }
+    }
+
+    /// Запрошенные, но ни разу не отправленные тикеры (отсортированы)
+    fn undelivered(&self, requested: &HashSet<String>) -> Vec<String> {
+        let mut out: Vec<String> = requested.difference(&self.sent).cloned().collect();
+        out.sort_unstable();
+        out
+    }
+}
+
+/// Причина завершения сессии
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StopReason {
+    /// Сервер останавливается
+    Shutdown,
+    /// Клиент перестал присылать ping
+    PingTimeout,
+    /// Слишком много ошибок отправки подряд
+    SendErrorLimit,
+    /// Hub закрыл канал котировок
+    Disconnected,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            StopReason::Shutdown => "shutdown",
+            StopReason::PingTimeout => "ping_timeout",
+            StopReason::SendErrorLimit => "send_error_limit",
+            StopReason::Disconnected => "disconnected",
+        };
+        f.write_str(s)
+    }
+}
+
+/// События жизненного цикла сессии, логируются единообразно
+#[derive(Debug)]
+pub(crate) enum SessionEvent<'a> {
+    Started {
+        cid: ClientId,
+        target: SocketAddr,
+        tickers: &'a HashSet<String>,
+    },
+    Stopped {
+        cid: ClientId,
+        reason: StopReason,
+        /// сколько запрошенных тикеров было доставлено хотя бы раз
+        delivered: usize,
+        requested: usize,
+        /// запрошенные, но ни разу не доставленные тикеры
+        undelivered: &'a [String],
+    },
+}
+
+impl fmt::Display for SessionEvent<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionEvent::Started {
+                cid,
+                target,
+                tickers,
+            } => {
+                let mut list: Vec<&str> = tickers.iter().map(String::as_str).collect();
+                list.sort_unstable();
+                write!(
+                    f,
+                    "session_started cid={cid} target={target} tickers={}",
+                    list.join(",")
+                )
+            }
+            SessionEvent::Stopped {
+                cid,
+                reason,
+                delivered,
+                requested,
+                undelivered,
+            } => {
+                write!(
+                    f,
+                    "session_stopped cid={cid} reason={reason} delivered={delivered}/{requested}"
+                )?;
+                if !undelivered.is_empty() {
+                    write!(f, " undelivered={}", undelivered.join(","))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn log_event(ev: &SessionEvent<'_>) {
+    info!("{ev}");
+}
+
+/// Итог сессии
+#[derive(Debug)]
+pub(crate) struct SessionSummary {
+    pub(crate) reason: StopReason,
+    /// Запрошенные, но ни разу не доставленные тикеры, включая неизвестные серверу
+    pub(crate) undelivered: Vec<String>,
+}
+
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn run_session(
     cid: ClientId,
     rx: Receiver<Arc<StockQuote>>,
-    udp_target: std::net::SocketAddr,
+    udp_target: SocketAddr,
     udp: Arc<UdpSocket>,
-    tickers: HashSet<String>,
+    mut tickers: HashSet<String>,
     last_ping: LastPingMap,
-    shutdown: Arc<AtomicBool>,
-) -> anyhow::Result<()> {
+    shutdown: Arc<ShutdownFlag>,
+    cfg: &SessionConfig,
+) -> SessionSummary {
     let session_start = Instant::now();
-    let mut back_to_back_err_count = 0;
+    let mut sender =
+        QuoteSender::new(&udp, udp_target, cid, cfg.wire).with_bandwidth(cfg.bandwidth.as_deref());
+    let mut backlog = BacklogMonitor::default();
+    let mut delivered = DeliveredTickers::default();
+    let mut rate = RateLimiter::new(cfg.min_send_interval)
+        .with_burst_until(cfg.initial_burst.map(|burst| session_start + burst));
 
-    loop {
-        if shutdown.load(Ordering::Relaxed) {
-            info!("shutting down {cid} {udp_target}");
-            break;
+    log_event(&SessionEvent::Started {
+        cid,
+        target: udp_target,
+        tickers: &tickers,
+    });
+
+    let reason = 'session: loop {
+        match shutdown.phase() {
+            ShutdownPhase::Running => {}
+            // генератор уже остановлен: дорассылаем то, что успело накопиться
+            ShutdownPhase::Draining if !rx.is_empty() => {}
+            ShutdownPhase::Draining | ShutdownPhase::Stopped => break StopReason::Shutdown,
         }
 
         if ping_expired(&last_ping, udp_target, session_start) {
-            info!("ping timeout for {cid} {udp_target}; stopping session");
-            break;
+            break StopReason::PingTimeout;
         }
 
-        // разгребаем очередь
-        for q in rx.try_iter() {
-            handle_quote(
-                &udp,
-                udp_target,
-                q,
-                &tickers,
-                &mut back_to_back_err_count,
-                cid,
-            )?;
-        }
-        // ждём ещё одно сообщение + роль sleep
-        match rx.recv_timeout(UDP_SOCKET_TICK) {
-            Ok(q) => {
-                handle_quote(
-                    &udp,
-                    udp_target,
-                    q,
-                    &tickers,
-                    &mut back_to_back_err_count,
-                    cid,
-                )?;
+        if let Some(capacity) = rx.capacity()
+            && backlog.check(rx.len(), capacity, cfg.backlog_warn_ratio)
+        {
+            warn!(
+                "session {cid} {udp_target}: backlog {}/{capacity}, client is falling behind",
+                rx.len()
+            );
+        }
+
+        if let Some(removals) = &cfg.removals {
+            for ticker in removals.try_iter() {
+                notify_ticker_removed(&udp, udp_target, &ticker, cid);
+                tickers.remove(&ticker);
+            }
+        }
+
+        // ждём первое сообщение (роль sleep), затем разгребаем очередь,
+        // но не больше drain_budget за итерацию
+        let first = match rx.recv_timeout(UDP_SOCKET_TICK) {
+            Ok(q) => Some(q),
+            Err(crossbeam_channel::RecvTimeoutError::Timeout) => None,
+            Err(crossbeam_channel::RecvTimeoutError::Disconnected) => {
+                break StopReason::Disconnected;
+            }
+        };
+        let batch: Vec<_> = first
+            .into_iter()
+            .chain(rx.try_iter().take(cfg.drain_budget.saturating_sub(1)))
+            .collect();
+        let batch = if cfg.coalesce {
+            coalesce_latest(batch)
+        } else {
+            batch
+        };
+        for q in batch {
+            if tickers.contains(&q.ticker) && !rate.allow(&q.ticker, Instant::now()) {
+                continue;
             }
-            Err(crossbeam_channel::RecvTimeoutError::Timeout) => {
-                // ничего, просто тик
+            match sender.handle_quote(&q, &tickers) {
+                Ok(true) => {
+                    delivered.record(&q.ticker);
+                    send_checkpoint_if_due(cfg.loss.as_deref(), &udp, udp_target);
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    warn!("session {cid} {udp_target}: last send error: {e}");
+                    break 'session StopReason::SendErrorLimit;
+                }
             }
-            Err(crossbeam_channel::RecvTimeoutError::Disconnected) => break,
         }
+    };
+
+    if reason == StopReason::Shutdown
+        && let Some(sink) = &cfg.shutdown_sink
+    {
+        let drained = drain_to_sink(&rx, &tickers, sink);
+        info!("session {cid} {udp_target}: {drained} buffered quotes drained to sink");
     }
 
     let mut map = match last_ping.write() {
@@ -72,42 +391,157 @@ pub(crate) fn run_session(
         Err(poisoned) => poisoned.into_inner(),
     };
     map.remove(&udp_target);
+    drop(map);
 
-    Ok(())
+    if let Some(loss) = &cfg.loss {
+        info!("session {cid} {udp_target}: {}", loss.estimate());
+    }
+
+    let mut undelivered = delivered.undelivered(&tickers);
+    let delivered = tickers.len() - undelivered.len();
+    undelivered.extend(cfg.unresolved.iter().cloned());
+    undelivered.sort_unstable();
+    log_event(&SessionEvent::Stopped {
+        cid,
+        reason,
+        delivered,
+        requested: tickers.len() + cfg.unresolved.len(),
+        undelivered: &undelivered,
+    });
+
+    SessionSummary {
+        reason,
+        undelivered,
+    }
 }
 
-fn send_quote(
-    sock: &std::net::UdpSocket,
-    target: std::net::SocketAddr,
-    q: &StockQuote,
-) -> anyhow::Result<()> {
-    let pkt = UdpPacketV1::Quote(q.clone());
-    let bytes = encode_v1(&pkt)?;
-    sock.send_to(&bytes, target)?;
-    Ok(())
+/// Перекладывает накопленные котировки тикеров сессии в `sink` (не в UDP).
+/// Возвращает число переданных котировок.
+fn drain_to_sink(
+    rx: &Receiver<Arc<StockQuote>>,
+    tickers: &HashSet<String>,
+    sink: &Sender<Arc<StockQuote>>,
+) -> usize {
+    rx.try_iter()
+        .filter(|q| tickers.contains(&q.ticker))
+        .take_while(|q| sink.send(q.clone()).is_ok())
+        .count()
 }
 
-fn handle_quote(
-    sock: &std::net::UdpSocket,
-    target: std::net::SocketAddr,
-    q: Arc<StockQuote>,
-    tickers_fltr: &HashSet<String>,
-    err_count: &mut usize,
+/// Оставляет только последнюю котировку каждого тикера,
+/// сохраняя относительный порядок оставшихся.
+fn coalesce_latest(quotes: Vec<Arc<StockQuote>>) -> Vec<Arc<StockQuote>> {
+    let mut seen = HashSet::new();
+    let mut out: Vec<_> = quotes
+        .into_iter()
+        .rev()
+        .filter(|q| seen.insert(q.ticker.clone()))
+        .collect();
+    out.reverse();
+    out
+}
+
+/// `TickerRemoved` клиенту. Уведомление не повторяется: при потере пакета
+/// клиент просто перестаёт получать котировки тикера, как раньше
+fn notify_ticker_removed(sock: &UdpSocket, target: SocketAddr, ticker: &str, cid: ClientId) {
+    let sent = encode_v1(&UdpPacketV1::TickerRemoved(ticker.to_string()))
+        .map_err(anyhow::Error::from)
+        .and_then(|bytes| Ok(sock.send_to(&bytes, target)?));
+    match sent {
+        Ok(_) => debug!("session {cid} {target}: ticker {ticker} removed"),
+        Err(e) => debug!("session {cid} {target}: ticker {ticker} removal not sent: {e}"),
+    }
+}
+
+/// `Checkpoint` после каждой `every`-й котировки сессии `ACK=1`.
+/// Неотправленный checkpoint остаётся учтённым: для клиента он потерян
+fn send_checkpoint_if_due(loss: Option<&LossMonitor>, sock: &UdpSocket, target: SocketAddr) {
+    let Some(seq) = loss.and_then(LossMonitor::on_quote_sent) else {
+        return;
+    };
+    let sent = encode_v1(&UdpPacketV1::Checkpoint(seq))
+        .map_err(anyhow::Error::from)
+        .and_then(|bytes| Ok(sock.send_to(&bytes, target)?));
+    if let Err(e) = sent {
+        debug!("checkpoint {seq} to {target} not sent: {e}");
+    }
+}
+
+/// Отправка котировок одной сессии: сокет, адрес клиента, кодировщик,
+/// общий лимит `--max-udp-bps` и счётчик ошибок отправки подряд
+struct QuoteSender<'a> {
+    sock: &'a UdpSocket,
+    target: SocketAddr,
     cid: ClientId,
-) -> anyhow::Result<()> {
-    if tickers_fltr.contains(&q.ticker) {
-        match send_quote(sock, target, &q) {
-            Ok(()) => *err_count = 0,
+    encoder: QuoteEncoder,
+    bandwidth: Option<&'a TokenBucket>,
+    err_count: usize,
+}
+
+impl<'a> QuoteSender<'a> {
+    fn new(sock: &'a UdpSocket, target: SocketAddr, cid: ClientId, wire: WireFormat) -> Self {
+        Self {
+            sock,
+            target,
+            cid,
+            encoder: QuoteEncoder::new(wire),
+            bandwidth: None,
+            err_count: 0,
+        }
+    }
+
+    fn with_bandwidth(mut self, bandwidth: Option<&'a TokenBucket>) -> Self {
+        self.bandwidth = bandwidth;
+        self
+    }
+
+    /// `Ok(false)` - пакет не отправлен: не хватило бюджета `bandwidth`
+    fn send(&mut self, q: &StockQuote) -> anyhow::Result<bool> {
+        let bytes = self.encoder.encode(q)?;
+        if self
+            .bandwidth
+            .is_some_and(|bucket| !bucket.try_take(bytes.len()))
+        {
+            return Ok(false);
+        }
+        self.sock.send_to(bytes, self.target)?;
+        Ok(true)
+    }
+
+    /// Отправляет котировку, если тикер в подписке и хватает бюджета `bandwidth`.
+    /// `Ok(true)` - пакет ушёл клиенту
+    fn handle_quote(
+        &mut self,
+        q: &StockQuote,
+        tickers_fltr: &HashSet<String>,
+    ) -> anyhow::Result<bool> {
+        if !tickers_fltr.contains(&q.ticker) {
+            return Ok(false);
+        }
+
+        let (cid, target) = (self.cid, self.target);
+        match self.send(q) {
+            Ok(true) => {
+                self.err_count = 0;
+                Ok(true)
+            }
+            Ok(false) => {
+                debug!(
+                    "session {cid} {target}: {} dropped by --max-udp-bps",
+                    q.ticker
+                );
+                Ok(false)
+            }
             Err(e) => {
                 warn!("Failed to send quote to {cid} {target} due to {e}");
-                *err_count += 1;
-                if *err_count >= BACK_TO_BACK_SEND_ERR_LIMIT {
+                self.err_count += 1;
+                if self.err_count >= BACK_TO_BACK_SEND_ERR_LIMIT {
                     return Err(e);
                 }
+                Ok(false)
             }
         }
     }
-    Ok(())
 }
 
 fn ping_expired(
@@ -145,6 +579,8 @@ mod tests {
             price: 123_4500,
             volume: 10,
             timestamp_ms: 1,
+            side: None,
+            sent_ms: None,
         }
     }
 
@@ -161,19 +597,11 @@ mod tests {
         let mut tickers = HashSet::new();
         tickers.insert("AAPL".to_string());
 
-        let mut err_count = 999;
-        let cid: ClientId = 1;
+        let mut sender = QuoteSender::new(&send_sock, target, 1, WireFormat::Postcard);
+        sender.err_count = 999;
 
-        handle_quote(
-            &send_sock,
-            target,
-            Arc::new(mk_quote("AAPL")),
-            &tickers,
-            &mut err_count,
-            cid,
-        )
-        .unwrap();
-        assert_eq!(err_count, 0);
+        sender.handle_quote(&mk_quote("AAPL"), &tickers).unwrap();
+        assert_eq!(sender.err_count, 0);
 
         let mut buf = [0u8; 2048];
         let (n, _src) = recv_sock.recv_from(&mut buf).unwrap();
@@ -185,6 +613,137 @@ mod tests {
         }
     }
 
+    #[test]
+    fn postcard_quote_is_stamped_with_send_time() {
+        let send_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        recv_sock
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+
+        let tickers: HashSet<String> = ["AAPL".to_string()].into();
+        let generated = StockQuote {
+            timestamp_ms: u128::from(now_ms()),
+            ..mk_quote("AAPL")
+        };
+
+        QuoteSender::new(
+            &send_sock,
+            recv_sock.local_addr().unwrap(),
+            1,
+            WireFormat::Postcard,
+        )
+        .handle_quote(&generated, &tickers)
+        .unwrap();
+
+        let mut buf = [0u8; 2048];
+        let (n, _) = recv_sock.recv_from(&mut buf).unwrap();
+        let UdpPacketV1::Quote(q) = decode(&buf[..n]).unwrap() else {
+            panic!("expected Quote packet");
+        };
+
+        let sent_ms = q.sent_ms.expect("sent_ms must be stamped");
+        assert!(u128::from(sent_ms) >= generated.timestamp_ms);
+        assert_eq!(StockQuote { sent_ms: None, ..q }, generated);
+    }
+
+    #[test]
+    fn handle_quote_sends_fixed_layout_when_selected() {
+        let send_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        recv_sock
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+
+        let tickers: HashSet<String> = ["AAPL".to_string()].into();
+        QuoteSender::new(
+            &send_sock,
+            recv_sock.local_addr().unwrap(),
+            1,
+            WireFormat::Fixed,
+        )
+        .handle_quote(&mk_quote("AAPL"), &tickers)
+        .unwrap();
+
+        let mut buf = [0u8; 2048];
+        let (n, _src) = recv_sock.recv_from(&mut buf).unwrap();
+        assert_eq!(n, fixed::FIXED_QUOTE_LEN);
+        assert_eq!(fixed::decode_fixed(&buf[..n]).unwrap(), mk_quote("AAPL"));
+    }
+
+    #[test]
+    fn session_summary_lists_unresolved_and_never_delivered_tickers() {
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let udp = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+        let last_ping: LastPingMap = Arc::new(RwLock::new(HashMap::new()));
+
+        // генератор прислал только AAPL и отключился
+        let (tx, rx) = crossbeam_channel::unbounded::<Arc<StockQuote>>();
+        tx.send(Arc::new(mk_quote("AAPL"))).unwrap();
+        drop(tx);
+
+        let cfg = SessionConfig {
+            unresolved: vec!["MSFT".to_string()],
+            ..SessionConfig::default()
+        };
+        let tickers: HashSet<String> = ["AAPL".to_string(), "TSLA".to_string()].into();
+        let summary = run_session(
+            1,
+            rx,
+            recv_sock.local_addr().unwrap(),
+            udp,
+            tickers,
+            last_ping,
+            Arc::new(ShutdownFlag::default()),
+            &cfg,
+        );
+
+        assert_eq!(summary.reason, StopReason::Disconnected);
+        assert_eq!(summary.undelivered, vec!["MSFT", "TSLA"]);
+
+        let ev = SessionEvent::Stopped {
+            cid: 1,
+            reason: summary.reason,
+            delivered: 1,
+            requested: 3,
+            undelivered: &summary.undelivered,
+        };
+        assert_eq!(
+            ev.to_string(),
+            "session_stopped cid=1 reason=disconnected delivered=1/3 undelivered=MSFT,TSLA"
+        );
+    }
+
+    #[test]
+    fn handle_quote_drops_quote_when_bandwidth_budget_is_exhausted() {
+        let send_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        recv_sock
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        let target = recv_sock.local_addr().unwrap();
+
+        let tickers: HashSet<String> = ["AAPL".to_string()].into();
+        // бюджета хватает ровно на один пакет фиксированного формата
+        let bucket = TokenBucket::new(fixed::FIXED_QUOTE_LEN as u64);
+        let mut sender = QuoteSender::new(&send_sock, target, 1, WireFormat::Fixed)
+            .with_bandwidth(Some(&bucket));
+
+        let sent: Vec<bool> = (0..2)
+            .map(|_| sender.handle_quote(&mk_quote("AAPL"), &tickers).unwrap())
+            .collect();
+
+        assert_eq!(sent, vec![true, false]);
+        assert_eq!(bucket.dropped(), 1);
+
+        let mut buf = [0u8; 2048];
+        assert!(recv_sock.recv_from(&mut buf).is_ok());
+        assert!(
+            recv_sock.recv_from(&mut buf).is_err(),
+            "second quote must be dropped"
+        );
+    }
+
     #[test]
     fn handle_quote_does_not_send_when_ticker_not_in_filter() {
         let send_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
@@ -198,18 +757,9 @@ mod tests {
         let mut tickers = HashSet::new();
         tickers.insert("TSLA".to_string()); // AAPL не входит
 
-        let mut err_count = 0;
-        let cid: ClientId = 1;
-
-        handle_quote(
-            &send_sock,
-            target,
-            Arc::new(mk_quote("AAPL")),
-            &tickers,
-            &mut err_count,
-            cid,
-        )
-        .unwrap();
+        QuoteSender::new(&send_sock, target, 1, WireFormat::Postcard)
+            .handle_quote(&mk_quote("AAPL"), &tickers)
+            .unwrap();
 
         let mut buf = [0u8; 2048];
         let res = recv_sock.recv_from(&mut buf);
@@ -225,31 +775,216 @@ mod tests {
         let mut tickers = HashSet::new();
         tickers.insert("AAPL".to_string());
 
-        let mut err_count = 0usize;
-        let cid: ClientId = 1;
+        let mut sender = QuoteSender::new(&send_sock, target, 1, WireFormat::Postcard);
 
         // первые (LIMIT-1) раз Ok, на LIMIT-й — Err
         for _ in 0..(BACK_TO_BACK_SEND_ERR_LIMIT - 1) {
-            let r = handle_quote(
-                &send_sock,
-                target,
-                Arc::new(mk_quote("AAPL")),
-                &tickers,
-                &mut err_count,
-                cid,
-            );
+            let r = sender.handle_quote(&mk_quote("AAPL"), &tickers);
             assert!(r.is_ok());
         }
 
-        let r = handle_quote(
-            &send_sock,
-            target,
-            Arc::new(mk_quote("AAPL")),
-            &tickers,
-            &mut err_count,
-            cid,
-        );
+        let r = sender.handle_quote(&mk_quote("AAPL"), &tickers);
         assert!(r.is_err());
+        assert_eq!(sender.err_count, BACK_TO_BACK_SEND_ERR_LIMIT);
+    }
+
+    #[test]
+    fn run_session_drains_buffered_quotes_to_sink_on_shutdown() {
+        let udp_target: SocketAddr = "127.0.0.1:34568".parse().unwrap();
+        let udp = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+        let last_ping: LastPingMap = Arc::new(RwLock::new(std::collections::HashMap::new()));
+
+        let (tx, rx) = crossbeam_channel::unbounded::<Arc<StockQuote>>();
+        for t in ["AAPL", "MSFT", "TSLA"] {
+            tx.send(Arc::new(mk_quote(t))).unwrap();
+        }
+        let tickers: HashSet<String> = ["AAPL", "TSLA"].map(String::from).into();
+
+        let (sink_tx, sink_rx) = crossbeam_channel::unbounded();
+        let cfg = SessionConfig {
+            shutdown_sink: Some(sink_tx),
+            ..SessionConfig::default()
+        };
+
+        // сервер уже остановлен: цикл сессии не успевает ничего отправить по UDP
+        let shutdown = Arc::new(ShutdownFlag::default());
+        shutdown.stop();
+        let reason = run_session(1, rx, udp_target, udp, tickers, last_ping, shutdown, &cfg).reason;
+        assert_eq!(reason, StopReason::Shutdown);
+
+        let drained: Vec<String> = sink_rx.try_iter().map(|q| q.ticker.clone()).collect();
+        assert_eq!(drained, vec!["AAPL", "TSLA"]);
+    }
+
+    #[test]
+    fn run_session_delivers_queued_quotes_while_draining() {
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        recv_sock
+            .set_read_timeout(Some(Duration::from_millis(150)))
+            .unwrap();
+        let udp_target = recv_sock.local_addr().unwrap();
+        let udp = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+        let last_ping: LastPingMap = Arc::new(RwLock::new(HashMap::new()));
+
+        // tx жив (как у hub), иначе сессия завершится по Disconnected
+        let (tx, rx) = crossbeam_channel::unbounded::<Arc<StockQuote>>();
+        for t in ["AAPL", "TSLA", "AAPL", "TSLA", "AAPL"] {
+            tx.send(Arc::new(mk_quote(t))).unwrap();
+        }
+        let tickers: HashSet<String> = ["AAPL", "TSLA"].map(String::from).into();
+
+        // бюджет 2: очередь разбирается за несколько итераций фазы Draining
+        let cfg = SessionConfig {
+            drain_budget: 2,
+            ..SessionConfig::default()
+        };
+        let shutdown = Arc::new(ShutdownFlag::default());
+        shutdown.begin_drain();
+
+        let reason = run_session(1, rx, udp_target, udp, tickers, last_ping, shutdown, &cfg).reason;
+        assert_eq!(reason, StopReason::Shutdown);
+
+        let mut buf = [0u8; 2048];
+        let mut received = 0;
+        while recv_sock.recv_from(&mut buf).is_ok() {
+            received += 1;
+        }
+        assert_eq!(
+            received, 5,
+            "queued quotes must be sent before the final stop"
+        );
+        drop(tx);
+    }
+
+    #[test]
+    fn removed_ticker_is_announced_and_no_longer_delivered() {
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        recv_sock
+            .set_read_timeout(Some(Duration::from_millis(150)))
+            .unwrap();
+        let udp_target = recv_sock.local_addr().unwrap();
+        let udp = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+        let last_ping: LastPingMap = Arc::new(RwLock::new(HashMap::new()));
+
+        let hub = crate::hub::Hub::new();
+        let rx = hub.add_client(1).unwrap();
+        hub.set_client_tickers(1, vec!["AAPL".to_string(), "TSLA".to_string()]);
+        let cfg = SessionConfig {
+            removals: hub.ticker_removals(1),
+            ..SessionConfig::default()
+        };
+
+        assert_eq!(hub.remove_ticker("AAPL"), 1);
+        // котировки, уже стоящие в очереди после удаления
+        hub.broadcast(mk_quote("AAPL"));
+        hub.broadcast(mk_quote("TSLA"));
+
+        let shutdown = Arc::new(ShutdownFlag::default());
+        shutdown.begin_drain();
+        let tickers: HashSet<String> = ["AAPL".to_string(), "TSLA".to_string()].into();
+        run_session(1, rx, udp_target, udp, tickers, last_ping, shutdown, &cfg);
+
+        let mut buf = [0u8; 2048];
+        let mut got = Vec::new();
+        while let Ok(n) = recv_sock.recv(&mut buf) {
+            got.push(decode(&buf[..n]).unwrap().to_string());
+        }
+        assert_eq!(got.len(), 2, "{got:?}");
+        assert_eq!(got[0], "TICKER_REMOVED AAPL");
+        assert!(got[1].starts_with("TSLA "), "{got:?}");
+    }
+
+    #[test]
+    fn run_session_sends_checkpoint_every_n_quotes_with_ack() {
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        recv_sock
+            .set_read_timeout(Some(Duration::from_millis(150)))
+            .unwrap();
+        let udp_target = recv_sock.local_addr().unwrap();
+        let udp = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+        let last_ping: LastPingMap = Arc::new(RwLock::new(HashMap::new()));
+
+        let (tx, rx) = crossbeam_channel::unbounded::<Arc<StockQuote>>();
+        for _ in 0..5 {
+            tx.send(Arc::new(mk_quote("AAPL"))).unwrap();
+        }
+        let loss = Arc::new(LossMonitor::new(2));
+        let cfg = SessionConfig {
+            loss: Some(loss.clone()),
+            ..SessionConfig::default()
+        };
+        let shutdown = Arc::new(ShutdownFlag::default());
+        shutdown.begin_drain();
+
+        let tickers: HashSet<String> = ["AAPL".to_string()].into();
+        run_session(1, rx, udp_target, udp, tickers, last_ping, shutdown, &cfg);
+
+        let mut buf = [0u8; 2048];
+        let mut checkpoints = Vec::new();
+        let mut quotes = 0;
+        while let Ok(n) = recv_sock.recv(&mut buf) {
+            match decode(&buf[..n]).unwrap() {
+                UdpPacketV1::Quote(_) => quotes += 1,
+                UdpPacketV1::Checkpoint(seq) => checkpoints.push(seq),
+                other => panic!("unexpected {other}"),
+            }
+        }
+        assert_eq!(quotes, 5);
+        assert_eq!(checkpoints, vec![1, 2]);
+        assert_eq!(loss.estimate().sent, 2);
+        drop(tx);
+    }
+
+    #[test]
+    fn run_session_stops_with_disconnected_when_hub_drops_channel() {
+        let udp_target: SocketAddr = "127.0.0.1:34569".parse().unwrap();
+        let udp = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+        let last_ping: LastPingMap = Arc::new(RwLock::new(HashMap::new()));
+
+        // hub удалил клиента: отправляющая сторона закрыта
+        let (tx, rx) = crossbeam_channel::unbounded::<Arc<StockQuote>>();
+        drop(tx);
+
+        let reason = run_session(
+            1,
+            rx,
+            udp_target,
+            udp,
+            HashSet::new(),
+            last_ping,
+            Arc::new(ShutdownFlag::default()),
+            &SessionConfig::default(),
+        )
+        .reason;
+        assert_eq!(reason, StopReason::Disconnected);
+    }
+
+    #[test]
+    fn run_session_stops_with_send_error_limit_on_failing_socket() {
+        // IPv4 сокет + IPv6 адрес => каждая отправка - ошибка
+        let udp_target: SocketAddr = "[::1]:12345".parse().unwrap();
+        let udp = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+        let last_ping: LastPingMap = Arc::new(RwLock::new(HashMap::new()));
+
+        let (tx, rx) = crossbeam_channel::unbounded::<Arc<StockQuote>>();
+        for _ in 0..BACK_TO_BACK_SEND_ERR_LIMIT {
+            tx.send(Arc::new(mk_quote("AAPL"))).unwrap();
+        }
+        let tickers: HashSet<String> = ["AAPL".to_string()].into();
+
+        let reason = run_session(
+            1,
+            rx,
+            udp_target,
+            udp,
+            tickers,
+            last_ping,
+            Arc::new(ShutdownFlag::default()),
+            &SessionConfig::default(),
+        )
+        .reason;
+        assert_eq!(reason, StopReason::SendErrorLimit);
+        drop(tx);
     }
 
     #[test]
@@ -260,7 +995,7 @@ mod tests {
 
         let (_tx, rx) = crossbeam_channel::bounded::<Arc<StockQuote>>(1);
         let tickers = HashSet::new();
-        let shutdown = Arc::new(AtomicBool::new(false));
+        let shutdown = Arc::new(ShutdownFlag::default());
 
         let last_ping: LastPingMap = Arc::new(RwLock::new(std::collections::HashMap::new()));
         {
@@ -271,7 +1006,7 @@ mod tests {
             );
         }
 
-        run_session(
+        let reason = run_session(
             cid,
             rx,
             udp_target,
@@ -279,8 +1014,10 @@ mod tests {
             tickers,
             last_ping.clone(),
             shutdown,
+            &SessionConfig::default(),
         )
-        .unwrap();
+        .reason;
+        assert_eq!(reason, StopReason::PingTimeout);
 
         let map = last_ping.read().unwrap();
         assert!(
@@ -288,4 +1025,259 @@ mod tests {
             "last_ping entry must be removed after session stops on timeout"
         );
     }
+
+    #[test]
+    fn run_session_notices_ping_timeout_before_draining_backlog() {
+        let cid: ClientId = 1;
+        let udp_target: SocketAddr = "127.0.0.1:34568".parse().unwrap();
+        let udp = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+
+        let backlog = 1000;
+        let cfg = SessionConfig {
+            drain_budget: 8,
+            ..SessionConfig::default()
+        };
+
+        let (tx, rx) = crossbeam_channel::bounded::<Arc<StockQuote>>(backlog);
+        for _ in 0..backlog {
+            tx.send(Arc::new(mk_quote("AAPL"))).unwrap();
+        }
+
+        let mut tickers = HashSet::new();
+        tickers.insert("AAPL".to_string());
+        let shutdown = Arc::new(ShutdownFlag::default());
+
+        let last_ping: LastPingMap = Arc::new(RwLock::new(std::collections::HashMap::new()));
+        last_ping.write().unwrap().insert(
+            udp_target,
+            Instant::now() - PING_TIMEOUT - Duration::from_millis(1),
+        );
+
+        let reason = run_session(
+            cid,
+            rx.clone(),
+            udp_target,
+            udp,
+            tickers,
+            last_ping,
+            shutdown,
+            &cfg,
+        )
+        .reason;
+
+        assert_eq!(reason, StopReason::PingTimeout);
+        assert!(
+            rx.len() >= backlog - cfg.drain_budget - 1,
+            "session must not drain the whole backlog before checking ping (left {})",
+            rx.len()
+        );
+    }
+
+    #[test]
+    fn coalesce_latest_keeps_newest_per_ticker_in_order() {
+        let q = |ticker: &str, price: i64| {
+            Arc::new(StockQuote {
+                price,
+                ..mk_quote(ticker)
+            })
+        };
+
+        let got = coalesce_latest(vec![
+            q("AAPL", 1),
+            q("TSLA", 1),
+            q("AAPL", 2),
+            q("MSFT", 1),
+            q("TSLA", 2),
+        ]);
+
+        let got: Vec<(&str, i64)> = got.iter().map(|q| (q.ticker.as_str(), q.price)).collect();
+        assert_eq!(got, vec![("AAPL", 2), ("MSFT", 1), ("TSLA", 2)]);
+    }
+
+    #[test]
+    fn coalesce_latest_keeps_distinct_tickers_untouched() {
+        let input = vec![Arc::new(mk_quote("AAPL")), Arc::new(mk_quote("TSLA"))];
+        assert_eq!(coalesce_latest(input.clone()), input);
+        assert!(coalesce_latest(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn backlog_monitor_warns_once_when_crossing_threshold() {
+        let (tx, rx) = crossbeam_channel::bounded::<Arc<StockQuote>>(4);
+        let capacity = rx.capacity().unwrap();
+        let mut mon = BacklogMonitor::default();
+
+        tx.send(Arc::new(mk_quote("AAPL"))).unwrap();
+        tx.send(Arc::new(mk_quote("AAPL"))).unwrap();
+        assert!(!mon.check(rx.len(), capacity, 0.75), "2/4 is below 75%");
+
+        tx.send(Arc::new(mk_quote("AAPL"))).unwrap();
+        assert!(mon.check(rx.len(), capacity, 0.75), "3/4 crosses 75%");
+        assert!(
+            !mon.check(rx.len(), capacity, 0.75),
+            "no repeat while above"
+        );
+
+        // очередь разгрузилась -> монитор снова взведён
+        rx.try_iter().for_each(drop);
+        assert!(!mon.check(rx.len(), capacity, 0.75));
+        for _ in 0..4 {
+            tx.send(Arc::new(mk_quote("AAPL"))).unwrap();
+        }
+        assert!(mon.check(rx.len(), capacity, 0.75));
+    }
+
+    #[test]
+    fn rate_limiter_throttles_per_ticker() {
+        let mut rate = RateLimiter::new(Some(Duration::from_millis(1000)));
+        let t0 = Instant::now();
+
+        assert!(rate.allow("AAPL", t0));
+        assert!(!rate.allow("AAPL", t0 + Duration::from_millis(500)));
+        // другой тикер ограничивается независимо
+        assert!(rate.allow("TSLA", t0 + Duration::from_millis(500)));
+        assert!(rate.allow("AAPL", t0 + Duration::from_millis(1000)));
+        assert!(!rate.allow("AAPL", t0 + Duration::from_millis(1999)));
+    }
+
+    #[test]
+    fn rate_limiter_burst_allows_everything_then_throttles() {
+        let t0 = Instant::now();
+        let mut rate = RateLimiter::new(Some(Duration::from_millis(1000)))
+            .with_burst_until(Some(t0 + Duration::from_millis(100)));
+
+        assert!(rate.allow("AAPL", t0));
+        assert!(rate.allow("AAPL", t0 + Duration::from_millis(50)));
+        // окно закончилось: интервал отсчитывается от последней отправки в burst
+        assert!(!rate.allow("AAPL", t0 + Duration::from_millis(150)));
+        assert!(rate.allow("AAPL", t0 + Duration::from_millis(1050)));
+    }
+
+    #[test]
+    fn run_session_sends_initial_burst_then_rate_limits() {
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        recv_sock
+            .set_read_timeout(Some(Duration::from_millis(150)))
+            .unwrap();
+        let udp_target = recv_sock.local_addr().unwrap();
+
+        let (tx, rx) = crossbeam_channel::unbounded::<Arc<StockQuote>>();
+        let tickers: HashSet<String> = ["AAPL".to_string(), "TSLA".to_string()].into();
+        let last_ping: LastPingMap = Arc::new(RwLock::new(HashMap::new()));
+        last_ping
+            .write()
+            .unwrap()
+            .insert(udp_target, Instant::now());
+        let shutdown = Arc::new(ShutdownFlag::default());
+
+        let cfg = SessionConfig {
+            min_send_interval: Some(Duration::from_secs(60)),
+            initial_burst: Some(Duration::from_millis(300)),
+            ..SessionConfig::default()
+        };
+
+        // текущее состояние (по несколько котировок на тикер) уже в очереди
+        for t in ["AAPL", "TSLA", "AAPL", "TSLA"] {
+            tx.send(Arc::new(mk_quote(t))).unwrap();
+        }
+
+        let h = {
+            let udp = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+            let shutdown = shutdown.clone();
+            std::thread::spawn(move || {
+                run_session(1, rx, udp_target, udp, tickers, last_ping, shutdown, &cfg)
+            })
+        };
+
+        let count_received = || {
+            let mut buf = [0u8; 2048];
+            let mut n = 0;
+            while recv_sock.recv_from(&mut buf).is_ok() {
+                n += 1;
+            }
+            n
+        };
+
+        assert_eq!(count_received(), 4, "burst must deliver everything at once");
+
+        std::thread::sleep(Duration::from_millis(300));
+        for t in ["AAPL", "TSLA"] {
+            tx.send(Arc::new(mk_quote(t))).unwrap();
+        }
+        assert_eq!(count_received(), 0, "after burst quotes are rate-limited");
+
+        shutdown.stop();
+        assert_eq!(h.join().unwrap().reason, StopReason::Shutdown);
+    }
+
+    #[test]
+    fn run_session_with_rate_sends_one_quote_per_ticker_per_interval() {
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        recv_sock
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        let udp_target = recv_sock.local_addr().unwrap();
+
+        let (tx, rx) = crossbeam_channel::unbounded::<Arc<StockQuote>>();
+        let tickers: HashSet<String> = ["AAPL".to_string(), "TSLA".to_string()].into();
+        let last_ping: LastPingMap = Arc::new(RwLock::new(HashMap::new()));
+        last_ping
+            .write()
+            .unwrap()
+            .insert(udp_target, Instant::now());
+        let shutdown = Arc::new(ShutdownFlag::default());
+
+        // RATE=250: не чаще одной котировки тикера в 250мс
+        let cfg = SessionConfig {
+            min_send_interval: Some(Duration::from_millis(250)),
+            ..SessionConfig::default()
+        };
+
+        let h = {
+            let udp = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+            let shutdown = shutdown.clone();
+            std::thread::spawn(move || {
+                run_session(1, rx, udp_target, udp, tickers, last_ping, shutdown, &cfg)
+            })
+        };
+
+        let received = || {
+            let mut buf = [0u8; 2048];
+            let mut out = Vec::new();
+            while let Ok(n) = recv_sock.recv(&mut buf) {
+                if let UdpPacketV1::Quote(q) = decode(&buf[..n]).unwrap() {
+                    out.push(q.ticker);
+                }
+            }
+            out.sort();
+            out
+        };
+
+        for t in ["AAPL", "AAPL", "TSLA", "AAPL", "TSLA"] {
+            tx.send(Arc::new(mk_quote(t))).unwrap();
+        }
+        assert_eq!(
+            received(),
+            ["AAPL", "TSLA"],
+            "one quote per ticker per interval"
+        );
+
+        std::thread::sleep(Duration::from_millis(250));
+        for t in ["AAPL", "AAPL"] {
+            tx.send(Arc::new(mk_quote(t))).unwrap();
+        }
+        assert_eq!(received(), ["AAPL"], "next interval lets one more through");
+
+        shutdown.stop();
+        assert_eq!(h.join().unwrap().reason, StopReason::Shutdown);
+    }
+
+    #[test]
+    fn rate_limiter_without_interval_allows_everything() {
+        let mut rate = RateLimiter::new(None);
+        let t0 = Instant::now();
+
+        assert!(rate.allow("AAPL", t0));
+        assert!(rate.allow("AAPL", t0));
+    }
 }
@@ -0,0 +1,139 @@
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Фаза остановки сервера
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ShutdownPhase {
+    /// Обычная работа
+    Running,
+    /// Генератор остановлен, новые STREAM не принимаются; сессии дорассылают
+    /// накопленные котировки
+    Draining,
+    /// Окончательная остановка: все потоки завершаются
+    Stopped,
+}
+
+/// Колбэк начала остановки: будит поток, заблокированный в I/O
+type Waker = Box<dyn FnOnce() + Send>;
+
+/// Двухфазный флаг остановки: `Running` -> `Draining` -> `Stopped`.
+/// Фаза только растёт: `begin_drain` после `stop` ничего не меняет.
+#[derive(Default)]
+pub(crate) struct ShutdownFlag {
+    phase: AtomicU8,
+    /// Вызываются один раз, при выходе из `Running`
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl fmt::Debug for ShutdownFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ShutdownFlag").field(&self.phase()).finish()
+    }
+}
+
+impl ShutdownFlag {
+    const RUNNING: u8 = 0;
+    const DRAINING: u8 = 1;
+    const STOPPED: u8 = 2;
+
+    pub(crate) fn phase(&self) -> ShutdownPhase {
+        match self.phase.load(Ordering::Relaxed) {
+            Self::RUNNING => ShutdownPhase::Running,
+            Self::DRAINING => ShutdownPhase::Draining,
+            _ => ShutdownPhase::Stopped,
+        }
+    }
+
+    pub(crate) fn begin_drain(&self) {
+        self.phase.fetch_max(Self::DRAINING, Ordering::Relaxed);
+        self.wake_all();
+    }
+
+    pub(crate) fn stop(&self) {
+        self.phase.store(Self::STOPPED, Ordering::Relaxed);
+        self.wake_all();
+    }
+
+    /// Вызвать `waker` при начале остановки (`begin_drain` или `stop`);
+    /// если остановка уже началась - сразу
+    pub(crate) fn on_shutdown(&self, waker: impl FnOnce() + Send + 'static) {
+        let mut wakers = self.lock_wakers();
+        if self.is_shutting_down() {
+            drop(wakers);
+            waker();
+            return;
+        }
+        wakers.push(Box::new(waker));
+    }
+
+    fn wake_all(&self) {
+        let wakers = std::mem::take(&mut *self.lock_wakers());
+        for waker in wakers {
+            waker();
+        }
+    }
+
+    fn lock_wakers(&self) -> std::sync::MutexGuard<'_, Vec<Waker>> {
+        match self.wakers.lock() {
+            Ok(g) => g,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// `Draining` или `Stopped`
+    pub(crate) fn is_shutting_down(&self) -> bool {
+        self.phase() != ShutdownPhase::Running
+    }
+
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.phase() == ShutdownPhase::Stopped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phase_only_moves_forward() {
+        let flag = ShutdownFlag::default();
+        assert_eq!(flag.phase(), ShutdownPhase::Running);
+        assert!(!flag.is_shutting_down());
+
+        flag.begin_drain();
+        assert_eq!(flag.phase(), ShutdownPhase::Draining);
+        assert!(flag.is_shutting_down());
+        assert!(!flag.is_stopped());
+
+        flag.stop();
+        flag.begin_drain();
+        assert_eq!(flag.phase(), ShutdownPhase::Stopped);
+    }
+
+    #[test]
+    fn wakers_fire_once_on_first_shutdown_transition() {
+        use std::sync::Arc;
+        use std::sync::atomic::AtomicUsize;
+
+        let flag = ShutdownFlag::default();
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let c = calls.clone();
+        flag.on_shutdown(move || {
+            c.fetch_add(1, Ordering::Relaxed);
+        });
+        assert_eq!(calls.load(Ordering::Relaxed), 0);
+
+        flag.begin_drain();
+        flag.stop();
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        // остановка уже идёт - вызывается сразу
+        let c = calls.clone();
+        flag.on_shutdown(move || {
+            c.fetch_add(1, Ordering::Relaxed);
+        });
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::HashSet;
+
+/// Результат сопоставления запроса клиента с тикерами сервера
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct SubscriptionResult {
+    /// Тикеры, на которые оформляется подписка
+    pub(crate) accepted: HashSet<String>,
+    /// Известные серверу тикеры, отсеянные allow/deny-списками
+    pub(crate) rejected: HashSet<String>,
+    /// Неизвестные тикеры и шаблоны, не совпавшие ни с одним тикером
+    pub(crate) unknown: HashSet<String>,
+}
+
+/// Вычисляет итоговый набор тикеров сессии.
+///
+/// Порядок:
+/// 1. раскрытие шаблонов: `BRK*` - по префиксу, `*` - все известные тикеры
+/// 2. пересечение с тикерами сервера (остальное - в `unknown`)
+/// 3. allow-список (если задан): всё, что не в нём, - в `rejected`
+/// 4. deny-список (если задан): имеет приоритет над allow
+pub(crate) fn resolve_subscription(
+    requested: &[String],
+    known: &HashSet<String>,
+    allow: Option<&HashSet<String>>,
+    deny: Option<&HashSet<String>>,
+) -> SubscriptionResult {
+    let mut res = SubscriptionResult::default();
+
+    let mut candidates = HashSet::new();
+    for t in requested {
+        match t.strip_suffix('*') {
+            Some(prefix) => {
+                let matched: Vec<_> = known.iter().filter(|k| k.starts_with(prefix)).collect();
+                if matched.is_empty() {
+                    res.unknown.insert(t.clone());
+                }
+                candidates.extend(matched.into_iter().cloned());
+            }
+            None if known.contains(t) => {
+                candidates.insert(t.clone());
+            }
+            None => {
+                res.unknown.insert(t.clone());
+            }
+        }
+    }
+
+    for t in candidates {
+        let allowed = allow.is_none_or(|a| a.contains(&t));
+        let denied = deny.is_some_and(|d| d.contains(&t));
+
+        if allowed && !denied {
+            res.accepted.insert(t);
+        } else {
+            res.rejected.insert(t);
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(v: &[&str]) -> HashSet<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn req(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn known() -> HashSet<String> {
+        set(&["AAPL", "BRK.A", "BRK.B", "TSLA"])
+    }
+
+    #[test]
+    fn exact_tickers_are_intersected_with_known() {
+        let got = resolve_subscription(&req(&["AAPL", "MSFT"]), &known(), None, None);
+
+        assert_eq!(got.accepted, set(&["AAPL"]));
+        assert_eq!(got.unknown, set(&["MSFT"]));
+        assert!(got.rejected.is_empty());
+    }
+
+    #[test]
+    fn prefix_and_star_patterns_are_expanded() {
+        let got = resolve_subscription(&req(&["BRK.*", "TSLA"]), &known(), None, None);
+        assert_eq!(got.accepted, set(&["BRK.A", "BRK.B", "TSLA"]));
+
+        let got = resolve_subscription(&req(&["*"]), &known(), None, None);
+        assert_eq!(got.accepted, known());
+    }
+
+    #[test]
+    fn pattern_without_matches_is_unknown() {
+        let got = resolve_subscription(&req(&["ZZZ*"]), &known(), None, None);
+
+        assert!(got.accepted.is_empty());
+        assert_eq!(got.unknown, set(&["ZZZ*"]));
+    }
+
+    #[test]
+    fn overlapping_pattern_and_exact_ticker_are_not_unknown() {
+        let got = resolve_subscription(&req(&["BRK.A", "BRK*"]), &known(), None, None);
+
+        assert_eq!(got.accepted, set(&["BRK.A", "BRK.B"]));
+        assert!(got.unknown.is_empty());
+    }
+
+    #[test]
+    fn allow_list_rejects_everything_else() {
+        let allow = set(&["AAPL", "BRK.A"]);
+        let got = resolve_subscription(&req(&["*"]), &known(), Some(&allow), None);
+
+        assert_eq!(got.accepted, set(&["AAPL", "BRK.A"]));
+        assert_eq!(got.rejected, set(&["BRK.B", "TSLA"]));
+    }
+
+    #[test]
+    fn deny_list_wins_over_allow_list() {
+        let allow = set(&["AAPL", "TSLA"]);
+        let deny = set(&["TSLA"]);
+        let got =
+            resolve_subscription(&req(&["AAPL", "TSLA"]), &known(), Some(&allow), Some(&deny));
+
+        assert_eq!(got.accepted, set(&["AAPL"]));
+        assert_eq!(got.rejected, set(&["TSLA"]));
+    }
+
+    #[test]
+    fn deny_list_applies_to_expanded_patterns() {
+        let deny = set(&["BRK.B"]);
+        let got = resolve_subscription(&req(&["BRK*", "NOPE"]), &known(), None, Some(&deny));
+
+        assert_eq!(got.accepted, set(&["BRK.A"]));
+        assert_eq!(got.rejected, set(&["BRK.B"]));
+        assert_eq!(got.unknown, set(&["NOPE"]));
+    }
+
+    #[test]
+    fn allow_list_does_not_make_unknown_tickers_known() {
+        let allow = set(&["MSFT"]);
+        let got = resolve_subscription(&req(&["MSFT"]), &known(), Some(&allow), None);
+
+        assert!(got.accepted.is_empty());
+        assert!(got.rejected.is_empty());
+        assert_eq!(got.unknown, set(&["MSFT"]));
+    }
+}
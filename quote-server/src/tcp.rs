@@ -1,48 +1,116 @@
-use crate::hub::Hub;
-use crate::session::run_session;
-use crate::udp_ping::LastPingMap;
+use crate::cidr::{Cidr, is_allowed};
+use crate::config::{
+    ACCEPT_ERROR_BACKOFF, CHECKPOINT_EVERY_QUOTES, ClientId, MAX_COMMAND_LINE_LEN,
+    PRIORITY_CLIENT_WEIGHT, SERVER_FULL_RETRY_AFTER, SUPPORTED_ENCODINGS, SessionToken,
+};
+use crate::groups::TickerGroups;
+use crate::hub::{ClientRegistry, Hub, HubError};
+use crate::line_codec::{ControlStream, read_command_line};
+use crate::loss::LossMonitor;
+use crate::metrics::ServerMetrics;
+use crate::session::{SessionConfig, run_session};
+use crate::shutdown::ShutdownFlag;
+use crate::subscription::resolve_subscription;
+use crate::tickers_watch::KnownTickers;
+use crate::udp_ping::{LastPingMap, SessionTokenMap, ping_ages, run_udp_ping_listener};
+use crate::udp_tuning::UdpTuning;
 use anyhow::Context;
-use log::{info, warn};
-use quote_core::protocol::{Command, parse_command};
+use log::{debug, info, warn};
+use quote_core::protocol::{
+    Command, DebugTopic, ENCODING_KEY, Encoding, OkReply, SESSION_ID_KEY, SESSIONS_KEY,
+    format_err_reply, parse_command_with_case,
+};
+use quote_core::tickers::TickerCase;
+use quote_core::wire::WireFormat;
 use std::collections::HashSet;
-use std::io::{BufRead, BufReader, Write};
-use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
-use std::sync::{Arc, atomic::AtomicBool, atomic::AtomicU64, atomic::Ordering};
+use std::io::{self, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::{Arc, atomic::AtomicU64, atomic::Ordering};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 const TCP_READ_TIMEOUT_S: u64 = 5;
 const TCP_WRITE_TIMEOUT_S: u64 = 5;
 
+/// Параметры accept loop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AcceptConfig {
+    /// Пауза перед повтором accept после ошибки (`--accept-error-backoff-ms`)
+    pub(crate) error_backoff: Duration,
+    /// Очередь ещё не принятых соединений, `listen(backlog)` (`--tcp-backlog`).
+    /// None - значение std (128)
+    pub(crate) backlog: Option<u32>,
+}
+
+impl Default for AcceptConfig {
+    fn default() -> Self {
+        Self {
+            error_backoff: ACCEPT_ERROR_BACKOFF,
+            backlog: None,
+        }
+    }
+}
+
+/// Общее состояние сервера, которое нужно каждому TCP-соединению
+#[derive(Clone)]
+pub(crate) struct ConnContext {
+    pub(crate) hub: Arc<Hub>,
+    /// Адрес, на котором сессии открывают свои UDP-сокеты для рассылки
+    pub(crate) udp_bind_ip: IpAddr,
+    /// TTL/ToS сокетов сессий (`--udp-ttl`, `--udp-tos`)
+    pub(crate) udp_tuning: UdpTuning,
+    pub(crate) curr_client_id: Arc<AtomicU64>,
+    /// Сессии клиентов, приславших `ID=`
+    pub(crate) clients: Arc<ClientRegistry>,
+    pub(crate) last_ping: LastPingMap,
+    /// Some => режим --ping-token
+    pub(crate) tokens: Option<SessionTokenMap>,
+    /// Отвечать Pong на ping (`--enable-pong`)
+    pub(crate) enable_pong: bool,
+    /// Принимать `DEBUG ...` (`--debug-commands`)
+    pub(crate) debug_commands: bool,
+    /// Тикеры генератора (для раскрытия шаблонов `BRK*`); меняются при `--watch-tickers`
+    pub(crate) known_tickers: KnownTickers,
+    /// Группы тикеров для `@group` в STREAM (`--groups-file`)
+    pub(crate) groups: Arc<TickerGroups>,
+    /// Подсети, которым разрешено подключаться (`--allow-cidr`); пусто - всем
+    pub(crate) allow_cidrs: Arc<Vec<Cidr>>,
+    /// `ID=` клиентов с повышенным приоритетом в хабе (`--priority-client`)
+    pub(crate) priority_clients: Arc<HashSet<String>>,
+    /// Регистр тикеров в STREAM (`--preserve-case`)
+    pub(crate) ticker_case: TickerCase,
+    pub(crate) session_cfg: SessionConfig,
+    /// Счётчики для `--metrics-file`
+    pub(crate) metrics: Arc<ServerMetrics>,
+    pub(crate) accept: AcceptConfig,
+    pub(crate) shutdown: Arc<ShutdownFlag>,
+}
+
 // accept loop + чтение команд по TCP
-pub(crate) fn run_tcp_listener(
-    tcp_addr: SocketAddr,
-    hub: Arc<Hub>,
-    udp: Arc<UdpSocket>,
-    curr_client_id: Arc<AtomicU64>,
-    last_ping: LastPingMap,
-    shutdown: Arc<AtomicBool>,
-) -> anyhow::Result<()> {
-    let listener =
-        TcpListener::bind(tcp_addr).with_context(|| format!("bind TCP listener {}", tcp_addr))?;
-    listener
-        .set_nonblocking(true)
-        .context("listener.set_nonblocking(true)")?;
+//
+// accept блокирующий: при остановке его будит подключение к самому себе
+// (см. `wake_listener`), без опроса с паузами
+pub(crate) fn run_tcp_listener(tcp_addr: SocketAddr, ctx: ConnContext) -> anyhow::Result<()> {
+    let listener = bind_tcp_listener(tcp_addr, ctx.accept.backlog)?;
+    let local = listener.local_addr().context("listener.local_addr()")?;
+    ctx.shutdown.on_shutdown(move || wake_listener(local));
     let mut session_handles = Vec::new();
 
     loop {
         reap_finished_sessions(&mut session_handles);
 
-        if shutdown.load(Ordering::Relaxed) {
+        if ctx.shutdown.is_shutting_down() {
             info!("shutting down tcp listener");
             break;
         }
 
         match listener.accept() {
-            Ok((stream, _addr)) => {
-                stream
-                    .set_nonblocking(false)
-                    .context("stream.set_nonblocking(false)")?;
+            // соединение, разбудившее accept при остановке, не обслуживаем
+            Ok(_) if ctx.shutdown.is_shutting_down() => continue,
+            Ok((mut stream, peer)) => {
+                if !admit_peer(&mut stream, peer, &ctx.allow_cidrs) {
+                    continue;
+                }
 
                 stream.set_nodelay(true).ok();
                 stream
@@ -52,39 +120,164 @@ pub(crate) fn run_tcp_listener(
                     .set_write_timeout(Some(Duration::from_secs(TCP_WRITE_TIMEOUT_S)))
                     .ok();
 
-                let hub = hub.clone();
-                let udp = udp.clone();
-                let curr_client_id = curr_client_id.clone();
-                let last_ping = last_ping.clone();
-                let shutdown = shutdown.clone();
-
-                let h = thread::spawn(move || {
-                    if let Err(e) =
-                        handle_conn(stream, hub, curr_client_id, udp, last_ping, shutdown)
-                    {
-                        warn!("handle_conn error: {e}");
-                    }
-                });
-                session_handles.push(h);
+                session_handles.push(spawn_conn(stream, ctx.clone()));
             }
-            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                // нет новых соединений прямо сейчас
-                thread::sleep(Duration::from_millis(50));
+            Err(e) => backoff_after_accept_error(&e, ctx.accept.error_backoff),
+        }
+    }
+
+    join_sessions(session_handles);
+    Ok(())
+}
+
+/// TCP listener с очередью `backlog` (None - как у std). Не удалось задать
+/// backlog - warning и очередь по умолчанию
+fn bind_tcp_listener(tcp_addr: SocketAddr, backlog: Option<u32>) -> anyhow::Result<TcpListener> {
+    let listener =
+        TcpListener::bind(tcp_addr).with_context(|| format!("bind TCP listener {}", tcp_addr))?;
+    if let Some(backlog) = backlog
+        && let Err(e) = set_listen_backlog(&listener, backlog)
+    {
+        warn!("--tcp-backlog {backlog} not applied: {e}");
+    }
+    Ok(listener)
+}
+
+/// Повторный `listen()` на слушающем сокете меняет длину очереди
+/// (std всегда слушает со 128). Ядро обрезает значение до `somaxconn`
+#[cfg(unix)]
+fn set_listen_backlog(listener: &impl std::os::fd::AsRawFd, backlog: u32) -> std::io::Result<()> {
+    let backlog = libc::c_int::try_from(backlog).unwrap_or(libc::c_int::MAX);
+    // SAFETY: дескриптор жив, пока жив `listener`
+    let rc = unsafe { libc::listen(listener.as_raw_fd(), backlog) };
+    if rc == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(unix))]
+fn set_listen_backlog(_listener: &TcpListener, _backlog: u32) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "listen backlog is not supported on this platform",
+    ))
+}
+
+/// Ошибка accept (например, кончились дескрипторы): пауза `backoff`, чтобы
+/// повтор не крутился в цикле, пока причина не исчезнет
+fn backoff_after_accept_error(e: &io::Error, backoff: Duration) {
+    warn!("accept error: {e}, retrying in {backoff:?}");
+    thread::sleep(backoff);
+}
+
+/// Подключается к listener-у `local`, чтобы вернуть его из блокирующего accept.
+/// Адрес `0.0.0.0`/`::` заменяется на loopback той же версии
+fn wake_listener(local: SocketAddr) {
+    let mut addr = local;
+    if addr.ip().is_unspecified() {
+        addr.set_ip(match addr {
+            SocketAddr::V4(_) => IpAddr::from(std::net::Ipv4Addr::LOCALHOST),
+            SocketAddr::V6(_) => IpAddr::from(std::net::Ipv6Addr::LOCALHOST),
+        });
+    }
+    if let Err(e) = TcpStream::connect_timeout(&addr, Duration::from_secs(1)) {
+        warn!("failed to wake tcp listener at {addr}: {e}");
+    }
+}
+
+/// Как [`run_tcp_listener`], но управляющий канал - Unix-сокет `path`
+/// (`--control-uds`). Доступ ограничивается правами на файл сокета,
+/// `--allow-cidr` не применяется. Котировки по-прежнему идут по UDP.
+/// accept тоже блокирующий: при остановке его будит подключение к `path`
+#[cfg(unix)]
+pub(crate) fn run_uds_listener(path: &std::path::Path, ctx: ConnContext) -> anyhow::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixListener;
+
+    // сокет от прошлого запуска мешает bind; обычный файл не трогаем
+    if let Ok(meta) = std::fs::symlink_metadata(path)
+        && meta.file_type().is_socket()
+    {
+        std::fs::remove_file(path).with_context(|| format!("remove stale socket {path:?}"))?;
+    }
+
+    let listener =
+        UnixListener::bind(path).with_context(|| format!("bind control socket {path:?}"))?;
+    if let Some(backlog) = ctx.accept.backlog
+        && let Err(e) = set_listen_backlog(&listener, backlog)
+    {
+        warn!("--tcp-backlog {backlog} not applied to control socket: {e}");
+    }
+    {
+        let path = path.to_path_buf();
+        ctx.shutdown.on_shutdown(move || {
+            if let Err(e) = std::os::unix::net::UnixStream::connect(&path) {
+                warn!("failed to wake control socket listener at {path:?}: {e}");
             }
-            Err(e) => {
-                warn!("accept error: {e}");
-                thread::sleep(Duration::from_millis(50));
+        });
+    }
+    let mut session_handles = Vec::new();
+
+    loop {
+        reap_finished_sessions(&mut session_handles);
+
+        if ctx.shutdown.is_shutting_down() {
+            info!("shutting down control socket listener");
+            break;
+        }
+
+        match listener.accept() {
+            // соединение, разбудившее accept при остановке, не обслуживаем
+            Ok(_) if ctx.shutdown.is_shutting_down() => continue,
+            Ok((stream, _)) => {
+                stream
+                    .set_write_timeout(Some(Duration::from_secs(TCP_WRITE_TIMEOUT_S)))
+                    .ok();
+
+                session_handles.push(spawn_conn(stream, ctx.clone()));
             }
+            Err(e) => backoff_after_accept_error(&e, ctx.accept.error_backoff),
         }
     }
 
-    for h in session_handles {
+    join_sessions(session_handles);
+    let _ = std::fs::remove_file(path);
+    Ok(())
+}
+
+fn spawn_conn<S: ControlStream + Send + 'static>(
+    stream: S,
+    ctx: ConnContext,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        if let Err(e) = handle_conn(stream, ctx) {
+            warn!("handle_conn error: {e}");
+        }
+    })
+}
+
+fn join_sessions(handles: Vec<thread::JoinHandle<()>>) {
+    for h in handles {
         if let Err(panic) = h.join() {
             warn!("session thread panicked: {:?}", panic);
         }
     }
+}
 
-    Ok(())
+/// Проверка адреса клиента по `--allow-cidr`; чужим - `ERR E_FORBIDDEN` и закрытие
+fn admit_peer(stream: &mut TcpStream, peer: SocketAddr, allow: &[Cidr]) -> bool {
+    if is_allowed(allow, peer.ip()) {
+        return true;
+    }
+
+    info!("rejecting connection from {peer}: not in --allow-cidr");
+    // ответ - по возможности: ошибки записи не важны, соединение закрывается
+    let reply = format_err_reply("E_FORBIDDEN", None, "address not allowed");
+    let _ = stream.write_all(format!("{reply}\n").as_bytes());
+    let _ = stream.shutdown(std::net::Shutdown::Both);
+    false
 }
 
 fn reap_finished_sessions(handles: &mut Vec<thread::JoinHandle<()>>) {
@@ -101,34 +294,53 @@ fn reap_finished_sessions(handles: &mut Vec<thread::JoinHandle<()>>) {
     }
 }
 
-fn extract_command(stream: &mut TcpStream) -> anyhow::Result<Command> {
-    let mut line = String::new();
+fn extract_command<S: ControlStream>(stream: &mut S, case: TickerCase) -> anyhow::Result<Command> {
+    let line = read_command_line(
+        stream,
+        MAX_COMMAND_LINE_LEN,
+        Duration::from_secs(TCP_READ_TIMEOUT_S),
+    )?;
 
-    {
-        let mut reader = BufReader::new(stream);
-        let n = reader.read_line(&mut line)?;
-        if n == 0 {
-            anyhow::bail!("client closed connection");
-        }
-    }
+    parse_command_with_case(&line, case).map_err(|e| anyhow::anyhow!(e))
+}
 
-    parse_command(&line).map_err(|e| anyhow::anyhow!(e))
+/// Можно ли слать котировки на адрес: отсекаем broadcast, multicast и 0.0.0.0/::,
+/// чтобы клиент не мог заставить сервер "заливать" подсеть.
+/// (Направленный broadcast подсети, например 10.0.0.255, без маски не распознать.)
+fn is_sendable_unicast(addr: &SocketAddr) -> bool {
+    match addr.ip() {
+        IpAddr::V4(ip) => !(ip.is_broadcast() || ip.is_multicast() || ip.is_unspecified()),
+        IpAddr::V6(ip) => !(ip.is_multicast() || ip.is_unspecified()),
+    }
 }
 
-fn handle_conn(
-    mut stream: TcpStream,
-    hub: Arc<Hub>,
-    curr_client_id: Arc<AtomicU64>,
-    udp: Arc<UdpSocket>,
-    last_ping: LastPingMap,
-    shutdown: Arc<AtomicBool>,
-) -> anyhow::Result<()> {
+fn handle_conn<S: ControlStream>(mut stream: S, ctx: ConnContext) -> anyhow::Result<()> {
+    let ConnContext {
+        hub,
+        udp_bind_ip,
+        udp_tuning,
+        curr_client_id,
+        clients,
+        last_ping,
+        tokens,
+        enable_pong,
+        debug_commands,
+        known_tickers,
+        groups,
+        allow_cidrs: _,
+        priority_clients,
+        ticker_case,
+        session_cfg,
+        metrics,
+        accept: _,
+        shutdown,
+    } = ctx;
+
     // парсинг команды
-    let cmd = match extract_command(&mut stream) {
+    let cmd = match extract_command(&mut stream, ticker_case) {
         Ok(c) => c,
         Err(e) => {
-            let msg = format!("ERR {e}\n");
-            let _ = stream.write_all(msg.as_bytes());
+            reply_err(&mut stream, "E_BAD_COMMAND", e);
             return Ok(());
         }
     };
@@ -137,52 +349,323 @@ fn handle_conn(
         Command::Stream {
             udp_target,
             tickers,
+            max_rate_ms,
+            burst_ms,
+            wire,
+            encoding,
+            client_id,
+            want_session_id,
+            want_ack,
+            raw_tickers,
         } => {
+            if !is_sendable_unicast(&udp_target) {
+                info!("rejecting non-unicast udp target {udp_target}");
+                reply_err(&mut stream, "E_BAD_TARGET", "");
+                return Ok(());
+            }
+
+            // ENC=: запрошенная кодировка, если сервер её поддерживает
+            let (wire, encoding) = match encoding {
+                None => (wire, None),
+                Some(requested) => match negotiate_encoding(requested) {
+                    Some((wire, enc)) => (wire, Some(enc)),
+                    None => {
+                        reply_err(
+                            &mut stream,
+                            "E_UNSUPPORTED_ENCODING",
+                            format_args!(
+                                "encoding {requested} is not supported (supported: {})",
+                                SUPPORTED_ENCODINGS
+                                    .iter()
+                                    .map(|e| e.as_str())
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            ),
+                        );
+                        return Ok(());
+                    }
+                },
+            };
+
+            let tickers = match groups.expand(tickers) {
+                Ok(t) => t,
+                Err(name) => {
+                    reply_err(
+                        &mut stream,
+                        "E_UNKNOWN_GROUP",
+                        format_args!("unknown group: {name}"),
+                    );
+                    return Ok(());
+                }
+            };
+
+            let sub = {
+                let known = match known_tickers.read() {
+                    Ok(g) => g,
+                    Err(poisoned) => poisoned.into_inner(),
+                };
+                resolve_subscription(&tickers, &known, None, None)
+            };
+            if !sub.unknown.is_empty() || !sub.rejected.is_empty() {
+                info!(
+                    "{udp_target}: unknown tickers {:?}, rejected tickers {:?} (requested {:?})",
+                    sub.unknown, sub.rejected, raw_tickers.0
+                );
+            }
+            let tickers_hs = sub.accepted;
+            // в отчёт сессии о недоставленных тикерах
+            let mut unresolved: Vec<String> = sub.unknown.into_iter().collect();
+            unresolved.sort_unstable();
+            if tickers_hs.is_empty() {
+                reply_err(
+                    &mut stream,
+                    "E_NO_TICKERS",
+                    "no known tickers match request",
+                );
+                return Ok(());
+            }
+
+            // свой сокет на сессию: ошибки отправки не задевают другие сессии,
+            // сокет закрывается вместе с сессией
+            let udp = match UdpSocket::bind(SocketAddr::new(udp_bind_ip, 0)) {
+                Ok(s) => Arc::new(s),
+                Err(e) => {
+                    reply_err(&mut stream, "E_INTERNAL", "udp socket unavailable");
+                    return Err(e).context("bind session udp socket");
+                }
+            };
+            // неподдержка уже отмечена warning при старте
+            if let Err(e) = udp_tuning.apply(&udp) {
+                debug!("session udp socket tuning failed: {e}");
+            }
+
             let cid = curr_client_id.fetch_add(1, Ordering::Relaxed);
 
-            let rx = match hub.add_client(cid) {
-                Ok(rx) => rx,
+            // вернувшийся клиент: прежняя сессия отключается до add_client,
+            // чтобы переподключение не упиралось в --max-sessions
+            if let Some(id) = &client_id
+                && let Some(old) = clients.register(id, cid)
+            {
+                info!("client {id} reconnected: replacing session {old} with {cid}");
+                hub.remove_client(old);
+            }
+            let unregister = || {
+                if let Some(id) = &client_id {
+                    clients.unregister(id, cid);
+                }
+            };
+
+            let added = match &client_id {
+                Some(id) if priority_clients.contains(id) => {
+                    hub.add_client_weighted(cid, PRIORITY_CLIENT_WEIGHT)
+                }
+                _ => hub.add_client(cid),
+            };
+
+            let (rx, removals) = match added {
+                Ok(rx) => {
+                    // получатель TickerRemoved - до подписки на тикеры: иначе
+                    // тикер, убранный между этими вызовами, клиент не увидит
+                    let removals = hub.ticker_removals(cid);
+                    // спрос для --on-demand, адресаты TickerRemoved, WHOAMI
+                    hub.set_client_tickers(cid, tickers_hs.iter().cloned().collect());
+                    hub.set_client_target(cid, udp_target);
+                    (rx, removals)
+                }
+                Err(HubError::AtCapacity(max)) => {
+                    unregister();
+                    metrics.record_session_rejected();
+                    info!("rejecting {udp_target}: server full ({max} sessions)");
+                    let reply = format_err_reply(
+                        "E_SERVER_FULL",
+                        Some(SERVER_FULL_RETRY_AFTER),
+                        "server is at capacity",
+                    );
+                    let _ = stream.write_all(format!("{reply}\n").as_bytes());
+                    return Ok(());
+                }
                 Err(e) => {
-                    let msg = format!("ERR {e}\n");
-                    let _ = stream.write_all(msg.as_bytes());
+                    unregister();
+                    reply_err(&mut stream, "E_INTERNAL", e);
                     return Ok(());
                 }
             };
 
-            if let Err(e) = stream.write_all(b"OK\n") {
+            // режим --ping-token: выдаём токен, по которому будут приходить PingToken
+            let token = tokens.as_ref().map(|map| {
+                let token: SessionToken = rand::random();
+                write_lock(map).insert(token, udp_target);
+                token
+            });
+
+            let mut reply = OkReply::new()
+                .with_token(token)
+                .with_session_id(want_session_id.then_some(cid));
+            if let Some(enc) = encoding {
+                reply = reply.with_field(ENCODING_KEY, enc);
+            }
+            let reply = reply.to_line();
+
+            if let Err(e) = stream.write_all(reply.as_bytes()) {
                 hub.remove_client(cid);
+                unregister();
+                if let (Some(map), Some(token)) = (&tokens, token) {
+                    write_lock(map).remove(&token);
+                }
                 return Err(e.into());
             }
             stream.flush()?;
-            stream.shutdown(std::net::Shutdown::Both).ok();
+            stream.shutdown_both().ok();
+            metrics.record_session();
             drop(stream);
 
-            let tickers_hs: HashSet<String> = tickers.into_iter().collect();
+            // причина остановки уже залогирована как SessionEvent::Stopped
+            // клиентский RATE= переопределяет серверную настройку для этой сессии
+            let session_cfg = SessionConfig {
+                min_send_interval: max_rate_ms
+                    .map(|ms| Duration::from_millis(u64::from(ms)))
+                    .or(session_cfg.min_send_interval),
+                initial_burst: burst_ms
+                    .map(|ms| Duration::from_millis(u64::from(ms)))
+                    .or(session_cfg.initial_burst),
+                wire,
+                // ACK=1: checkpoint среди котировок, подтверждения - в ping
+                loss: want_ack.then(|| Arc::new(LossMonitor::new(CHECKPOINT_EVERY_QUOTES))),
+                removals,
+                unresolved,
+                ..session_cfg
+            };
 
-            let res = run_session(cid, rx, udp_target, udp, tickers_hs, last_ping, shutdown);
+            // клиент шлёт ping на адрес, с которого пришли котировки
+            let ping_stop = Arc::new(ShutdownFlag::default());
+            let ping_handle = {
+                let udp = udp.clone();
+                let last_ping = last_ping.clone();
+                let tokens = tokens.clone().unwrap_or_default();
+                let metrics = metrics.clone();
+                let ping_stop = ping_stop.clone();
+                let loss = session_cfg.loss.clone();
+                thread::spawn(move || {
+                    if let Err(e) = run_udp_ping_listener(
+                        udp,
+                        last_ping,
+                        tokens,
+                        enable_pong,
+                        loss,
+                        metrics,
+                        ping_stop,
+                    ) {
+                        warn!("session {cid} ping listener stopped: {e}");
+                    }
+                })
+            };
 
-            if let Err(e) = res {
-                warn!("session {cid} ended with error: {e}");
-            }
+            let summary = run_session(
+                cid,
+                rx,
+                udp_target,
+                udp,
+                tickers_hs,
+                last_ping,
+                shutdown,
+                &session_cfg,
+            );
+
+            metrics.record_session_end(summary.reason, summary.undelivered.len());
+
+            ping_stop.stop();
+            let _ = ping_handle.join();
 
             hub.remove_client(cid);
+            unregister();
+            if let (Some(map), Some(token)) = (&tokens, token) {
+                write_lock(map).remove(&token);
+            }
+        }
+        Command::Status => {
+            let reply = OkReply::new()
+                .with_field(SESSIONS_KEY, hub.client_count())
+                .to_line();
+            stream.write_all(reply.as_bytes())?;
+        }
+        Command::Debug(_) | Command::WhoAmI { .. } if !debug_commands => {
+            reply_err(&mut stream, "E_FORBIDDEN", "debug commands are disabled");
+        }
+        Command::Debug(DebugTopic::Pings) => {
+            stream.write_all(format_ping_ages(&last_ping, Instant::now()).as_bytes())?;
+        }
+        Command::WhoAmI { udp_target } => {
+            let sessions = hub.subscriptions_for(udp_target);
+            if sessions.is_empty() {
+                reply_err(
+                    &mut stream,
+                    "E_NO_SESSION",
+                    format_args!("no session streams to {udp_target}"),
+                );
+            } else {
+                stream.write_all(format_subscriptions(&sessions).as_bytes())?;
+            }
         }
     }
 
     Ok(())
 }
 
+/// Ответ на `DEBUG pings`: `OK pings=<n>`, затем по строке `<addr> age_ms=<ms>`
+fn format_ping_ages(last_ping: &LastPingMap, now: Instant) -> String {
+    let ages = ping_ages(last_ping, now);
+    let mut out = OkReply::new().with_field("pings", ages.len()).to_line();
+    for (addr, age) in ages {
+        out.push_str(&format!("{addr} age_ms={}\n", age.as_millis()));
+    }
+    out
+}
+
+/// Ответ на `WHOAMI`: `OK sessions=<n>`, затем по строке `id=<id> tickers=<t1,t2>`
+fn format_subscriptions(sessions: &[(ClientId, Vec<String>)]) -> String {
+    let mut out = OkReply::new()
+        .with_field(SESSIONS_KEY, sessions.len())
+        .to_line();
+    for (cid, tickers) in sessions {
+        out.push_str(&format!(
+            "{SESSION_ID_KEY}={cid} tickers={}\n",
+            tickers.join(",")
+        ));
+    }
+    out
+}
+
+/// Согласованная кодировка и формат UDP-котировок для неё;
+/// `None` - сервер не поддерживает `requested`
+fn negotiate_encoding(requested: Encoding) -> Option<(WireFormat, Encoding)> {
+    let enc = requested.negotiate(SUPPORTED_ENCODINGS)?;
+    Some((enc.wire_format()?, enc))
+}
+
+/// Ответ `ERR <code> <сообщение>` (пустое сообщение не пишется); ошибка записи
+/// не важна - соединение всё равно закрывается
+fn reply_err(stream: &mut impl Write, code: &str, message: impl std::fmt::Display) {
+    let reply = format_err_reply(code, None, &message.to_string());
+    let _ = stream.write_all(format!("{reply}\n").as_bytes());
+}
+
+fn write_lock(
+    map: &SessionTokenMap,
+) -> std::sync::RwLockWriteGuard<'_, std::collections::HashMap<SessionToken, SocketAddr>> {
+    match map.write() {
+        Ok(g) => g,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::collections::HashMap;
     use std::io::{Read, Write};
     use std::net::{TcpListener, TcpStream, UdpSocket};
-    use std::sync::{
-        Arc, RwLock,
-        atomic::{AtomicBool, AtomicU64},
-    };
-    use std::time::Duration;
+    use std::sync::{Arc, RwLock, atomic::AtomicU64};
+    use std::time::{Duration, Instant};
 
     fn connect_pair() -> (TcpStream, TcpStream) {
         let listener = TcpListener::bind("127.0.0.1:0").unwrap();
@@ -203,22 +686,54 @@ mod tests {
         String::from_utf8_lossy(&buf[..n]).to_string()
     }
 
+    // stopped=true => run_session не зависнет
+    fn mk_ctx(stopped: bool) -> ConnContext {
+        ConnContext {
+            hub: Arc::new(Hub::new()),
+            udp_bind_ip: IpAddr::from([127, 0, 0, 1]),
+            udp_tuning: UdpTuning::default(),
+            curr_client_id: Arc::new(AtomicU64::new(1)),
+            clients: Arc::new(ClientRegistry::default()),
+            last_ping: Arc::new(RwLock::new(HashMap::new())),
+            tokens: None,
+            enable_pong: false,
+            debug_commands: true,
+            known_tickers: Arc::new(RwLock::new(
+                ["AAPL", "BRK.A", "BRK.B", "TSLA"].map(String::from).into(),
+            )),
+            groups: Arc::new(
+                TickerGroups::parse(
+                    std::io::Cursor::new("brk = BRK.A, BRK.B\n"),
+                    TickerCase::Upper,
+                )
+                .unwrap(),
+            ),
+            allow_cidrs: Arc::new(Vec::new()),
+            priority_clients: Arc::new(HashSet::new()),
+            ticker_case: TickerCase::Upper,
+            session_cfg: SessionConfig::default(),
+            metrics: Arc::new(ServerMetrics::default()),
+            accept: AcceptConfig::default(),
+            shutdown: Arc::new({
+                let flag = ShutdownFlag::default();
+                if stopped {
+                    flag.stop();
+                }
+                flag
+            }),
+        }
+    }
+
     #[test]
     fn handle_conn_writes_err_on_garbage_command() {
         let (mut client, server) = connect_pair();
         client.write_all(b"GARBAGE\n").unwrap();
 
-        let hub = Arc::new(Hub::new());
-        let udp = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
-        let last_ping: LastPingMap = Arc::new(RwLock::new(HashMap::new()));
-        let shutdown = Arc::new(AtomicBool::new(false));
-        let cid = Arc::new(AtomicU64::new(1));
-
-        handle_conn(server, hub, cid, udp, last_ping, shutdown).unwrap();
+        handle_conn(server, mk_ctx(false)).unwrap();
 
         let reply = read_reply(client);
         assert!(
-            reply.starts_with("ERR "),
+            reply.starts_with("ERR E_BAD_COMMAND "),
             "expected ERR reply, got: {reply:?}"
         );
         assert!(
@@ -227,6 +742,145 @@ mod tests {
         );
     }
 
+    #[test]
+    fn whoami_reports_filter_of_active_session() {
+        let ctx = mk_ctx(false);
+        let shutdown = ctx.shutdown.clone();
+
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let target = recv_sock.local_addr().unwrap();
+        let (mut client, server) = connect_pair();
+        client
+            .write_all(format!("STREAM udp://{target} AAPL,BRK*,MSFT\n").as_bytes())
+            .unwrap();
+        let h = {
+            let ctx = ctx.clone();
+            std::thread::spawn(move || handle_conn(server, ctx))
+        };
+        assert_eq!(read_reply(client), "OK\n");
+
+        let (mut client, server) = connect_pair();
+        client
+            .write_all(format!("WHOAMI udp://{target}\n").as_bytes())
+            .unwrap();
+        handle_conn(server, ctx.clone()).unwrap();
+        // MSFT серверу неизвестен и в подписку не попал
+        assert_eq!(
+            read_reply(client),
+            "OK sessions=1\nid=1 tickers=AAPL,BRK.A,BRK.B\n"
+        );
+
+        let (mut client, server) = connect_pair();
+        client.write_all(b"WHOAMI udp://127.0.0.1:9\n").unwrap();
+        handle_conn(server, ctx.clone()).unwrap();
+        assert_eq!(
+            read_reply(client),
+            "ERR E_NO_SESSION no session streams to 127.0.0.1:9\n"
+        );
+
+        let (mut client, server) = connect_pair();
+        client
+            .write_all(format!("WHOAMI udp://{target}\n").as_bytes())
+            .unwrap();
+        let gated = ConnContext {
+            debug_commands: false,
+            ..ctx
+        };
+        handle_conn(server, gated).unwrap();
+        assert_eq!(
+            read_reply(client),
+            "ERR E_FORBIDDEN debug commands are disabled\n"
+        );
+
+        shutdown.stop();
+        h.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn handle_conn_counts_unknown_requested_tickers_as_undelivered() {
+        let ctx = mk_ctx(false);
+        let (hub, metrics, shutdown) = (ctx.hub.clone(), ctx.metrics.clone(), ctx.shutdown.clone());
+
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        recv_sock
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let (mut client, server) = connect_pair();
+        // MSFT серверу неизвестен: в фильтр не попадает, но запрошен
+        let cmd = format!(
+            "STREAM udp://{} AAPL,MSFT\n",
+            recv_sock.local_addr().unwrap()
+        );
+        client.write_all(cmd.as_bytes()).unwrap();
+        let h = std::thread::spawn(move || handle_conn(server, ctx));
+        assert_eq!(read_reply(client), "OK\n");
+
+        hub.broadcast(quote_core::StockQuote {
+            ticker: "AAPL".to_string(),
+            price: 100,
+            volume: 1,
+            timestamp_ms: 1,
+            side: None,
+            sent_ms: None,
+        });
+        let mut buf = [0u8; 256];
+        recv_sock.recv(&mut buf).expect("quote must arrive");
+
+        shutdown.stop();
+        h.join().unwrap().unwrap();
+
+        assert!(
+            metrics
+                .render_prometheus()
+                .contains("\nquote_server_tickers_undelivered_total 1\n")
+        );
+    }
+
+    #[test]
+    fn stream_enc_negotiates_supported_encoding_and_rejects_others() {
+        let ctx = mk_ctx(false);
+        let (hub, shutdown) = (ctx.hub.clone(), ctx.shutdown.clone());
+
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        recv_sock
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let (mut client, server) = connect_pair();
+        let cmd = format!(
+            "STREAM udp://{} AAPL ENC=fixed\n",
+            recv_sock.local_addr().unwrap()
+        );
+        client.write_all(cmd.as_bytes()).unwrap();
+        let h = std::thread::spawn(move || handle_conn(server, ctx));
+        assert_eq!(read_reply(client), "OK enc=fixed\n");
+
+        hub.broadcast(quote_core::StockQuote {
+            ticker: "AAPL".to_string(),
+            price: 100,
+            volume: 1,
+            timestamp_ms: 1,
+            side: None,
+            sent_ms: None,
+        });
+        let mut buf = [0u8; 256];
+        let n = recv_sock.recv(&mut buf).expect("quote must arrive");
+        assert_eq!(buf[0], quote_core::wire::fixed::FIXED_WIRE_VERSION);
+        assert_eq!(n, quote_core::wire::fixed::FIXED_QUOTE_LEN);
+
+        shutdown.stop();
+        h.join().unwrap().unwrap();
+
+        let (mut client, server) = connect_pair();
+        client
+            .write_all(b"STREAM udp://127.0.0.1:34254 AAPL ENC=batch\n")
+            .unwrap();
+        handle_conn(server, mk_ctx(true)).unwrap();
+        assert_eq!(
+            read_reply(client),
+            "ERR E_UNSUPPORTED_ENCODING encoding batch is not supported (supported: v1, fixed)\n"
+        );
+    }
+
     #[test]
     fn handle_conn_writes_ok_on_stream_and_exits_fast_on_shutdown() {
         let (mut client, server) = connect_pair();
@@ -236,19 +890,442 @@ mod tests {
             .write_all(b"STREAM udp://127.0.0.1:34254 AAPL\n")
             .unwrap();
 
-        let hub = Arc::new(Hub::new());
-        let udp = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
-        let last_ping: LastPingMap = Arc::new(RwLock::new(HashMap::new()));
+        handle_conn(server, mk_ctx(true)).unwrap();
 
-        // shutdown=true => run_session не зависнет
-        let shutdown = Arc::new(AtomicBool::new(true));
+        let reply = read_reply(client);
+        assert_eq!(reply, "OK\n");
+    }
 
-        let cid = Arc::new(AtomicU64::new(1));
+    #[test]
+    fn preserve_case_lowercase_subscription_receives_lowercase_quotes() {
+        let ctx = ConnContext {
+            ticker_case: TickerCase::Preserve,
+            known_tickers: Arc::new(RwLock::new(["btcUSD", "BTCUSD"].map(String::from).into())),
+            ..mk_ctx(false)
+        };
+        let (hub, shutdown) = (ctx.hub.clone(), ctx.shutdown.clone());
 
-        handle_conn(server, hub, cid, udp, last_ping, shutdown).unwrap();
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        recv_sock
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let (mut client, server) = connect_pair();
+        let cmd = format!("STREAM udp://{} btcUSD\n", recv_sock.local_addr().unwrap());
+        client.write_all(cmd.as_bytes()).unwrap();
+        let h = std::thread::spawn(move || handle_conn(server, ctx));
+        assert_eq!(read_reply(client), "OK\n");
+
+        // генератор и фильтр сессии сравнивают тикеры без смены регистра
+        for ticker in ["BTCUSD", "btcUSD"] {
+            hub.broadcast(quote_core::StockQuote {
+                ticker: ticker.to_string(),
+                price: 100_0000,
+                volume: 1,
+                timestamp_ms: 1,
+                side: None,
+                sent_ms: None,
+            });
+        }
+
+        let mut buf = [0u8; 256];
+        let n = recv_sock.recv(&mut buf).expect("quote must arrive");
+        match quote_core::wire::decode(&buf[..n]).unwrap() {
+            quote_core::wire::UdpPacketV1::Quote(q) => assert_eq!(q.ticker, "btcUSD"),
+            other => panic!("expected quote, got {other:?}"),
+        }
+        recv_sock
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        assert!(recv_sock.recv(&mut buf).is_err(), "BTCUSD must not match");
+
+        shutdown.stop();
+        h.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn concurrent_sessions_send_from_distinct_udp_sockets() {
+        let ctx = mk_ctx(false);
+        let (hub, shutdown) = (ctx.hub.clone(), ctx.shutdown.clone());
+
+        let mut targets = Vec::new();
+        let mut handles = Vec::new();
+        for _ in 0..2 {
+            let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+            recv_sock
+                .set_read_timeout(Some(Duration::from_secs(2)))
+                .unwrap();
+            let (mut client, server) = connect_pair();
+            let cmd = format!("STREAM udp://{} AAPL\n", recv_sock.local_addr().unwrap());
+            client.write_all(cmd.as_bytes()).unwrap();
+
+            let ctx = ctx.clone();
+            handles.push(std::thread::spawn(move || handle_conn(server, ctx)));
+            assert_eq!(read_reply(client), "OK\n");
+            targets.push(recv_sock);
+        }
+
+        hub.broadcast(quote_core::StockQuote {
+            ticker: "AAPL".to_string(),
+            price: 100_0000,
+            volume: 1,
+            timestamp_ms: 1,
+            side: None,
+            sent_ms: None,
+        });
+
+        let mut buf = [0u8; 256];
+        let senders: Vec<SocketAddr> = targets
+            .iter()
+            .map(|s| s.recv_from(&mut buf).expect("quote must arrive").1)
+            .collect();
+        assert_ne!(senders[0].port(), senders[1].port());
+
+        shutdown.stop();
+        for h in handles {
+            h.join().unwrap().unwrap();
+        }
+    }
+
+    #[test]
+    fn handle_conn_echoes_session_id_on_request() {
+        let ctx = mk_ctx(true);
+        ctx.curr_client_id.store(42, Ordering::Relaxed);
+
+        let (mut client, server) = connect_pair();
+        client
+            .write_all(b"STREAM udp://127.0.0.1:34254 AAPL SID=1\n")
+            .unwrap();
+        handle_conn(server, ctx).unwrap();
+
+        assert_eq!(read_reply(client), "OK id=42\n");
+    }
+
+    #[test]
+    fn handle_conn_replies_with_token_and_releases_it_after_session() {
+        let (mut client, server) = connect_pair();
+        client
+            .write_all(b"STREAM udp://127.0.0.1:34254 AAPL\n")
+            .unwrap();
+
+        let tokens: SessionTokenMap = Arc::new(RwLock::new(HashMap::new()));
+        let ctx = ConnContext {
+            tokens: Some(tokens.clone()),
+            ..mk_ctx(true)
+        };
+
+        handle_conn(server, ctx).unwrap();
 
         let reply = read_reply(client);
-        assert_eq!(reply, "OK\n");
+        let token = reply
+            .strip_prefix("OK ")
+            .and_then(|t| t.trim_end().parse::<SessionToken>().ok());
+        assert!(token.is_some(), "expected OK <token>, got: {reply:?}");
+
+        assert!(
+            tokens.read().unwrap().is_empty(),
+            "token must be released when session stops"
+        );
+    }
+
+    #[test]
+    fn handle_conn_rejects_when_server_full_and_accepts_after_slot_freed() {
+        let ctx = ConnContext {
+            hub: Arc::new(Hub::with_max_clients(1)),
+            ..mk_ctx(true)
+        };
+
+        // единственный слот занят "живой" сессией
+        let _busy = ctx.hub.add_client(1000).unwrap();
+
+        let stream_once = || {
+            let (mut client, server) = connect_pair();
+            client
+                .write_all(b"STREAM udp://127.0.0.1:34254 AAPL\n")
+                .unwrap();
+            handle_conn(server, ctx.clone()).unwrap();
+            read_reply(client)
+        };
+
+        assert_eq!(
+            stream_once(),
+            format!(
+                "ERR E_SERVER_FULL retry_after_ms={} server is at capacity\n",
+                SERVER_FULL_RETRY_AFTER.as_millis()
+            )
+        );
+
+        // сессия завершилась -> слот освободился
+        assert!(ctx.hub.remove_client(1000));
+        assert_eq!(stream_once(), "OK\n");
+    }
+
+    #[test]
+    fn handle_conn_replies_to_status_with_session_count() {
+        let ctx = mk_ctx(false);
+        let _busy = ctx.hub.add_client(1000).unwrap();
+
+        let (mut client, server) = connect_pair();
+        client.write_all(b"STATUS\n").unwrap();
+
+        handle_conn(server, ctx).unwrap();
+
+        assert_eq!(read_reply(client), "OK sessions=1\n");
+    }
+
+    #[test]
+    fn handle_conn_debug_pings_lists_ping_age_per_address() {
+        let ctx = mk_ctx(false);
+        let now = Instant::now();
+        {
+            let mut map = ctx.last_ping.write().unwrap();
+            map.insert("127.0.0.1:7002".parse().unwrap(), now);
+            map.insert(
+                "127.0.0.1:7001".parse().unwrap(),
+                now - Duration::from_millis(1500),
+            );
+        }
+
+        let reply = format_ping_ages(&ctx.last_ping, now);
+        assert_eq!(
+            reply,
+            "OK pings=2\n127.0.0.1:7001 age_ms=1500\n127.0.0.1:7002 age_ms=0\n"
+        );
+
+        let (mut client, server) = connect_pair();
+        client.write_all(b"DEBUG pings\n").unwrap();
+        handle_conn(server, ctx).unwrap();
+
+        let reply = read_reply(client);
+        let lines: Vec<&str> = reply.lines().collect();
+        assert_eq!(lines[0], "OK pings=2");
+        assert!(lines[1].starts_with("127.0.0.1:7001 age_ms="), "{reply:?}");
+        assert!(lines[2].starts_with("127.0.0.1:7002 age_ms="), "{reply:?}");
+    }
+
+    #[test]
+    fn handle_conn_rejects_debug_without_flag() {
+        let ctx = ConnContext {
+            debug_commands: false,
+            ..mk_ctx(false)
+        };
+        let (mut client, server) = connect_pair();
+        client.write_all(b"DEBUG pings\n").unwrap();
+        handle_conn(server, ctx).unwrap();
+
+        assert_eq!(
+            read_reply(client),
+            "ERR E_FORBIDDEN debug commands are disabled\n"
+        );
+    }
+
+    #[test]
+    fn tcp_listener_exits_promptly_after_shutdown() {
+        let ctx = mk_ctx(false);
+        let shutdown = ctx.shutdown.clone();
+        let (done_tx, done_rx) = crossbeam_channel::bounded(1);
+
+        let h = std::thread::spawn(move || {
+            let res = run_tcp_listener("127.0.0.1:0".parse().unwrap(), ctx);
+            let _ = done_tx.send(Instant::now());
+            res
+        });
+        // listener успел войти в accept
+        std::thread::sleep(Duration::from_millis(100));
+
+        let signaled = Instant::now();
+        shutdown.begin_drain();
+        let exited = done_rx
+            .recv_timeout(Duration::from_secs(2))
+            .expect("listener must exit");
+
+        assert!(
+            exited.duration_since(signaled) < Duration::from_millis(250),
+            "listener exit took {:?}",
+            exited.duration_since(signaled)
+        );
+        h.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn listener_with_custom_backlog_accepts_connections() {
+        let listener = bind_tcp_listener("127.0.0.1:0".parse().unwrap(), Some(1)).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        client.write_all(b"x").unwrap();
+        let mut buf = [0u8; 1];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"x");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn uds_listener_with_custom_backoff_and_backlog_serves_commands() {
+        use std::os::unix::net::UnixStream;
+
+        let path = std::env::temp_dir().join(format!("quote-poll-{}.sock", std::process::id()));
+        let ctx = ConnContext {
+            accept: AcceptConfig {
+                error_backoff: Duration::from_millis(5),
+                backlog: Some(4),
+            },
+            ..mk_ctx(false)
+        };
+        let shutdown = ctx.shutdown.clone();
+        let h = {
+            let path = path.clone();
+            std::thread::spawn(move || run_uds_listener(&path, ctx))
+        };
+
+        let mut client = (0..50)
+            .find_map(|_| {
+                UnixStream::connect(&path).ok().or_else(|| {
+                    std::thread::sleep(Duration::from_millis(20));
+                    None
+                })
+            })
+            .expect("control socket is not listening");
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        client.write_all(b"STATUS\n").unwrap();
+        let mut buf = [0u8; 64];
+        let n = client.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"OK sessions=0\n");
+
+        // accept блокирующий: выход по пробуждению, а не по опросу
+        let signaled = Instant::now();
+        shutdown.stop();
+        h.join().unwrap().unwrap();
+        assert!(
+            signaled.elapsed() < Duration::from_millis(250),
+            "listener exit took {:?}",
+            signaled.elapsed()
+        );
+        assert!(!path.exists(), "socket file is removed on exit");
+    }
+
+    #[test]
+    fn failed_accept_backs_off_before_retry() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        // нет входящих соединений: accept завершается ошибкой WouldBlock
+        let err = listener.accept().unwrap_err();
+
+        let started = Instant::now();
+        backoff_after_accept_error(&err, Duration::from_millis(30));
+        assert!(started.elapsed() >= Duration::from_millis(30));
+    }
+
+    #[test]
+    fn admit_peer_checks_allow_cidr() {
+        let allowed: Vec<Cidr> = vec![
+            "10.0.0.0/8".parse().unwrap(),
+            "127.0.0.0/8".parse().unwrap(),
+        ];
+        let (client, mut server) = connect_pair();
+        let peer = server.peer_addr().unwrap();
+        assert!(admit_peer(&mut server, peer, &allowed));
+
+        // соединение не тронуто: команда обрабатывается как обычно
+        let mut client = client;
+        client.write_all(b"STATUS\n").unwrap();
+        handle_conn(server, mk_ctx(false)).unwrap();
+        assert_eq!(read_reply(client), "OK sessions=0\n");
+
+        let foreign: Vec<Cidr> = vec!["10.0.0.0/8".parse().unwrap()];
+        let (client, mut server) = connect_pair();
+        let peer = server.peer_addr().unwrap();
+        assert!(!admit_peer(&mut server, peer, &foreign));
+        assert_eq!(read_reply(client), "ERR E_FORBIDDEN address not allowed\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn uds_listener_completes_stream_handshake() {
+        use std::os::unix::net::UnixStream;
+
+        let path = std::env::temp_dir().join(format!("quote-ctl-{}.sock", std::process::id()));
+        let ctx = mk_ctx(false);
+        let shutdown = ctx.shutdown.clone();
+
+        let h = {
+            let path = path.clone();
+            std::thread::spawn(move || run_uds_listener(&path, ctx))
+        };
+
+        let mut client = (0..50)
+            .find_map(|_| {
+                UnixStream::connect(&path).ok().or_else(|| {
+                    std::thread::sleep(Duration::from_millis(20));
+                    None
+                })
+            })
+            .expect("control socket is not listening");
+        client
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        client
+            .write_all(b"STREAM udp://127.0.0.1:34254 AAPL\n")
+            .unwrap();
+
+        let mut buf = [0u8; 64];
+        let n = client.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"OK\n");
+
+        shutdown.stop();
+        h.join().unwrap().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn handle_conn_expands_groups_and_rejects_unknown_group() {
+        let (mut client, server) = connect_pair();
+        client
+            .write_all(b"STREAM udp://127.0.0.1:34254 @brk\n")
+            .unwrap();
+        handle_conn(server, mk_ctx(true)).unwrap();
+        assert_eq!(read_reply(client), "OK\n");
+
+        let (mut client, server) = connect_pair();
+        client
+            .write_all(b"STREAM udp://127.0.0.1:34254 AAPL,@energy\n")
+            .unwrap();
+        handle_conn(server, mk_ctx(true)).unwrap();
+        assert_eq!(
+            read_reply(client),
+            "ERR E_UNKNOWN_GROUP unknown group: ENERGY\n"
+        );
+    }
+
+    #[test]
+    fn handle_conn_tracks_clients_with_different_ids_on_same_port_separately() {
+        let ctx = mk_ctx(true);
+
+        // живая сессия клиента A на порту 34254
+        let _a_rx = ctx.hub.add_client(1000).unwrap();
+        ctx.clients.register("A", 1000);
+
+        let stream_with_id = |id: &str| {
+            let (mut client, server) = connect_pair();
+            let cmd = format!("STREAM udp://127.0.0.1:34254 AAPL ID={id}\n");
+            client.write_all(cmd.as_bytes()).unwrap();
+            handle_conn(server, ctx.clone()).unwrap();
+            read_reply(client)
+        };
+
+        // другой клиент на том же порту не трогает сессию A
+        assert_eq!(stream_with_id("B"), "OK\n");
+        assert!(ctx.hub.remove_client(1000), "session of A must survive B");
+        let _a_rx = ctx.hub.add_client(1000).unwrap();
+
+        // вернувшийся A вытесняет свою прежнюю сессию
+        assert_eq!(stream_with_id("A"), "OK\n");
+        assert!(
+            !ctx.hub.remove_client(1000),
+            "previous session of A must be replaced"
+        );
     }
 
     #[test]
@@ -256,13 +1333,55 @@ mod tests {
         let (client, server) = connect_pair();
         drop(client); // клиент сразу закрыл соединение => EOF
 
-        let hub = Arc::new(Hub::new());
-        let udp = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
-        let last_ping: LastPingMap = Arc::new(RwLock::new(HashMap::new()));
-        let shutdown = Arc::new(AtomicBool::new(false));
-        let cid = Arc::new(AtomicU64::new(1));
-
         // просто проверяем, что не паникует и корректно завершается
-        handle_conn(server, hub, cid, udp, last_ping, shutdown).unwrap();
+        handle_conn(server, mk_ctx(false)).unwrap();
+    }
+
+    #[test]
+    fn handle_conn_writes_err_when_pattern_matches_nothing() {
+        let (mut client, server) = connect_pair();
+        client
+            .write_all(b"STREAM udp://127.0.0.1:34254 ZZZ*,MSFT\n")
+            .unwrap();
+
+        handle_conn(server, mk_ctx(true)).unwrap();
+
+        assert_eq!(
+            read_reply(client),
+            "ERR E_NO_TICKERS no known tickers match request\n"
+        );
+    }
+
+    #[test]
+    fn is_sendable_unicast_rejects_broadcast_multicast_unspecified() {
+        for bad in [
+            "255.255.255.255:1",
+            "224.0.0.1:1",
+            "0.0.0.0:1",
+            "[ff02::1]:1",
+            "[::]:1",
+        ] {
+            let addr: SocketAddr = bad.parse().unwrap();
+            assert!(!is_sendable_unicast(&addr), "{bad} must be rejected");
+        }
+
+        for good in ["127.0.0.1:1", "192.168.1.10:1", "[::1]:1"] {
+            let addr: SocketAddr = good.parse().unwrap();
+            assert!(is_sendable_unicast(&addr), "{good} must be accepted");
+        }
+    }
+
+    #[test]
+    fn handle_conn_rejects_multicast_target() {
+        let (mut client, server) = connect_pair();
+        client
+            .write_all(b"STREAM udp://239.1.2.3:34254 AAPL\n")
+            .unwrap();
+
+        let ctx = mk_ctx(true);
+        handle_conn(server, ctx.clone()).unwrap();
+
+        assert_eq!(read_reply(client), "ERR E_BAD_TARGET\n");
+        assert!(!ctx.hub.remove_client(1), "no session must be created");
     }
 }
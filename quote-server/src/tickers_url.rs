@@ -0,0 +1,212 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use quote_core::tickers::TickerCase;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub(crate) enum TickersUrlError {
+    #[error("only http:// urls are supported: {0}")]
+    UnsupportedScheme(String),
+
+    #[error("invalid tickers url: {0}")]
+    InvalidUrl(String),
+
+    #[error("tickers url request timed out after {0:?}")]
+    Timeout(Duration),
+
+    #[error("tickers url returned HTTP {0}")]
+    BadStatus(u16),
+
+    #[error("malformed HTTP response")]
+    MalformedResponse,
+
+    #[error("tickers url io error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Загружает список тикеров минимальным HTTP/1.0 GET (без TLS и chunked).
+/// Тело ответа разбирается через `read_tickers_auto_with_case` (построчно или
+/// JSON-массив) с той же нормализацией регистра, что и тикеры STREAM.
+pub(crate) fn fetch_tickers(
+    url: &str,
+    timeout: Duration,
+    case: TickerCase,
+) -> Result<Vec<String>, TickersUrlError> {
+    let (host, path) = split_url(url)?;
+
+    let addr = host
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| TickersUrlError::InvalidUrl(url.to_string()))?;
+
+    let mut stream =
+        TcpStream::connect_timeout(&addr, timeout).map_err(|e| timeout_err(e, timeout))?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let req = format!("GET {path} HTTP/1.0\r\nHost: {host}\r\nConnection: close\r\n\r\n");
+    stream
+        .write_all(req.as_bytes())
+        .map_err(|e| timeout_err(e, timeout))?;
+
+    let mut resp = Vec::new();
+    stream
+        .read_to_end(&mut resp)
+        .map_err(|e| timeout_err(e, timeout))?;
+
+    let body = parse_response(&resp)?;
+    Ok(quote_core::tickers::read_tickers_auto_with_case(
+        body, case,
+    )?)
+}
+
+/// "http://host:port/path" -> ("host:port", "/path")
+fn split_url(url: &str) -> Result<(String, String), TickersUrlError> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| TickersUrlError::UnsupportedScheme(url.to_string()))?;
+
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    if authority.is_empty() {
+        return Err(TickersUrlError::InvalidUrl(url.to_string()));
+    }
+
+    let host = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{authority}:80")
+    };
+
+    Ok((host, path.to_string()))
+}
+
+/// Проверяет статус и возвращает тело ответа
+fn parse_response(resp: &[u8]) -> Result<&[u8], TickersUrlError> {
+    let header_end = resp
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or(TickersUrlError::MalformedResponse)?;
+
+    let head =
+        std::str::from_utf8(&resp[..header_end]).map_err(|_| TickersUrlError::MalformedResponse)?;
+    let status_line = head.lines().next().unwrap_or("");
+
+    // "HTTP/1.1 200 OK"
+    let code: u16 = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|c| c.parse().ok())
+        .ok_or(TickersUrlError::MalformedResponse)?;
+
+    if code != 200 {
+        return Err(TickersUrlError::BadStatus(code));
+    }
+
+    Ok(&resp[header_end + 4..])
+}
+
+fn timeout_err(e: io::Error, timeout: Duration) -> TickersUrlError {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => TickersUrlError::Timeout(timeout),
+        _ => TickersUrlError::Io(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Поднимает одноразовый HTTP-сервер, отвечающий `response`
+    fn serve_once(response: &'static str) -> (String, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let h = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            // дочитываем заголовки запроса
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                line.clear();
+            }
+
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+
+        (format!("http://{addr}/tickers.txt"), h)
+    }
+
+    #[test]
+    fn fetch_tickers_reads_list_from_loopback() {
+        let (url, h) =
+            serve_once("HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\naapl\n# c\ntsla\n");
+
+        let got = fetch_tickers(&url, Duration::from_secs(2), TickerCase::Upper).unwrap();
+        assert_eq!(got, vec!["AAPL", "TSLA"]);
+
+        h.join().unwrap();
+    }
+
+    #[test]
+    fn fetch_tickers_reads_json_list_from_loopback() {
+        let (url, h) = serve_once("HTTP/1.1 200 OK\r\n\r\n[\"msft\", \"AAPL\"]");
+
+        let got = fetch_tickers(&url, Duration::from_secs(2), TickerCase::Upper).unwrap();
+        assert_eq!(got, vec!["AAPL", "MSFT"]);
+
+        h.join().unwrap();
+    }
+
+    #[test]
+    fn fetch_tickers_preserves_case_for_json_list() {
+        let (url, h) = serve_once("HTTP/1.1 200 OK\r\n\r\n[\"btcUSD\", \"AAPL\"]");
+
+        let got = fetch_tickers(&url, Duration::from_secs(2), TickerCase::Preserve).unwrap();
+        assert_eq!(got, vec!["AAPL", "btcUSD"]);
+
+        h.join().unwrap();
+    }
+
+    #[test]
+    fn fetch_tickers_rejects_non_200() {
+        let (url, h) = serve_once("HTTP/1.0 404 Not Found\r\n\r\nnope");
+
+        let err = fetch_tickers(&url, Duration::from_secs(2), TickerCase::Upper).unwrap_err();
+        assert!(matches!(err, TickersUrlError::BadStatus(404)));
+
+        h.join().unwrap();
+    }
+
+    #[test]
+    fn fetch_tickers_times_out_on_silent_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        // соединение принимается ОС (backlog), но ответа не будет
+        let err = fetch_tickers(&url, Duration::from_millis(100), TickerCase::Upper).unwrap_err();
+        assert!(matches!(err, TickersUrlError::Timeout(_)));
+
+        drop(listener);
+    }
+
+    #[test]
+    fn split_url_validates_scheme_and_defaults_port() {
+        assert!(matches!(
+            split_url("https://example.com/x"),
+            Err(TickersUrlError::UnsupportedScheme(_))
+        ));
+        assert_eq!(
+            split_url("http://example.com").unwrap(),
+            ("example.com:80".to_string(), "/".to_string())
+        );
+    }
+}
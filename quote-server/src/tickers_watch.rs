@@ -0,0 +1,198 @@
+use crate::generator::GeneratorCmd;
+use crate::hub::Hub;
+use crate::shutdown::ShutdownFlag;
+use crossbeam_channel::Sender;
+use log::{info, warn};
+use quote_core::tickers::TickerCase;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+/// Тикеры, на которые можно подписаться; обновляются при перезагрузке файла
+pub(crate) type KnownTickers = Arc<RwLock<HashSet<String>>>;
+
+/// Шаг сна между проверками shutdown
+const SHUTDOWN_POLL_STEP: Duration = Duration::from_millis(100);
+
+/// Следит за mtime файла тикеров (`--watch-tickers`)
+pub(crate) struct TickersWatch {
+    path: PathBuf,
+    case: TickerCase,
+    limit: Option<usize>,
+    /// Добавлять встроенные тикеры (`--with-default-tickers`)
+    with_defaults: bool,
+    last_modified: Option<SystemTime>,
+}
+
+impl TickersWatch {
+    /// Текущий mtime считается уже загруженным
+    pub(crate) fn new(path: PathBuf, case: TickerCase, limit: Option<usize>) -> Self {
+        let last_modified = modified(&path);
+        Self {
+            path,
+            case,
+            limit,
+            with_defaults: false,
+            last_modified,
+        }
+    }
+
+    /// Как при старте: к файлу добавляются встроенные тикеры
+    pub(crate) fn with_default_tickers(mut self, enabled: bool) -> Self {
+        self.with_defaults = enabled;
+        self
+    }
+
+    /// Some(тикеры) - файл изменился и прочитан полностью.
+    ///
+    /// Если файл не читается, пуст или менялся во время чтения (запись не
+    /// закончена), mtime не запоминается - файл перечитается при следующем опросе.
+    pub(crate) fn poll(&mut self) -> Option<Vec<String>> {
+        let before = modified(&self.path)?;
+        if Some(before) == self.last_modified {
+            return None;
+        }
+
+        let loaded = crate::config::load_server_tickers(Some(self.path.clone()), self.case)
+            .and_then(|v| {
+                if self.with_defaults && !v.is_empty() {
+                    crate::config::merge_with_default_tickers(&v, self.case)
+                } else {
+                    Ok(v)
+                }
+            });
+        let tickers = match loaded {
+            Ok(v) if !v.is_empty() => v,
+            Ok(_) => {
+                warn!(
+                    "tickers file {:?} is empty, keeping current tickers",
+                    self.path
+                );
+                return None;
+            }
+            Err(e) => {
+                warn!("tickers file {:?} reload failed: {e}", self.path);
+                return None;
+            }
+        };
+
+        if modified(&self.path) != Some(before) {
+            return None;
+        }
+
+        self.last_modified = Some(before);
+        Some(quote_core::tickers::limit_tickers(tickers, self.limit))
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Поток перезагрузки тикеров: опрос файла раз в `interval`, новый набор -
+/// в `known` (для новых подписок) и генератору (`GeneratorCmd::SetTickers`).
+/// Сессии, подписанные на удалённые тикеры, получают `TickerRemoved` через `hub`.
+pub(crate) fn run_tickers_watcher(
+    mut watch: TickersWatch,
+    interval: Duration,
+    known: KnownTickers,
+    hub: Arc<Hub>,
+    gen_tx: Sender<GeneratorCmd>,
+    shutdown: Arc<ShutdownFlag>,
+) {
+    while !shutdown.is_shutting_down() {
+        let mut slept = Duration::ZERO;
+        while slept < interval && !shutdown.is_shutting_down() {
+            std::thread::sleep(SHUTDOWN_POLL_STEP);
+            slept += SHUTDOWN_POLL_STEP;
+        }
+
+        let Some(tickers) = watch.poll() else {
+            continue;
+        };
+        info!("tickers file changed: {} tickers", tickers.len());
+
+        let removed: Vec<String> = {
+            let mut known = match known.write() {
+                Ok(g) => g,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            let next: HashSet<String> = tickers.iter().cloned().collect();
+            let removed = known.difference(&next).cloned().collect();
+            *known = next;
+            removed
+        };
+        for ticker in &removed {
+            let sessions = hub.remove_ticker(ticker);
+            if sessions > 0 {
+                info!("ticker {ticker} removed: notified {sessions} sessions");
+            }
+        }
+
+        if gen_tx.send(GeneratorCmd::SetTickers(tickers)).is_err() {
+            break; // генератор уже остановлен
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::QuoteGenerator;
+    use std::fs::File;
+    use std::io::Write;
+
+    fn write_tickers(path: &PathBuf, body: &str, mtime: SystemTime) {
+        let mut f = File::create(path).unwrap();
+        f.write_all(body.as_bytes()).unwrap();
+        f.set_modified(mtime).unwrap();
+    }
+
+    #[test]
+    fn changed_file_updates_generator_tickers() {
+        let path = std::env::temp_dir().join(format!("tickers-watch-{}.txt", std::process::id()));
+        let t0 = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        write_tickers(&path, "AAPL\nMSFT\n", t0);
+
+        let mut watch = TickersWatch::new(path.clone(), TickerCase::Upper, None);
+        let mut g = QuoteGenerator::with_seed(
+            vec!["AAPL".to_string(), "MSFT".to_string()],
+            Default::default(),
+            1,
+        );
+        assert_eq!(watch.poll(), None);
+
+        write_tickers(&path, "msft\ntsla # new\n", t0 + Duration::from_secs(5));
+        let tickers = watch.poll().unwrap();
+        assert_eq!(tickers, vec!["MSFT", "TSLA"]);
+
+        assert_eq!(g.set_tickers(&tickers), (1, 1));
+        assert_eq!(g.tickers(), vec!["MSFT", "TSLA"]);
+
+        // тот же mtime - повторной перезагрузки нет
+        assert_eq!(watch.poll(), None);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn empty_file_is_retried_on_next_poll() {
+        let path =
+            std::env::temp_dir().join(format!("tickers-watch-empty-{}.txt", std::process::id()));
+        let t0 = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        write_tickers(&path, "AAPL\n", t0);
+
+        let mut watch = TickersWatch::new(path.clone(), TickerCase::Upper, None);
+
+        let t1 = t0 + Duration::from_secs(5);
+        write_tickers(&path, "", t1);
+        assert_eq!(watch.poll(), None);
+
+        // дописали с тем же mtime - файл всё равно перечитывается
+        write_tickers(&path, "TSLA\n", t1);
+        assert_eq!(watch.poll(), Some(vec!["TSLA".to_string()]));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
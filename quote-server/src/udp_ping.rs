@@ -6,43 +6,115 @@ use std::time::{Duration, Instant};
 
 use log::{debug, warn};
 
-use quote_core::wire::{UdpPacketV1, decode};
+use quote_core::WireError;
+use quote_core::wire::{PacketKind, UdpPacketV1, WIRE_VERSION, decode, encode_v1, peek_kind};
+
+use crate::config::SessionToken;
+use crate::loss::LossMonitor;
+use crate::metrics::ServerMetrics;
+use crate::shutdown::ShutdownFlag;
 
 pub(crate) type LastPingMap = Arc<RwLock<HashMap<SocketAddr, Instant>>>;
 
-/// Один поток на весь сервер:
-/// - читает UDP пакеты (recv_from) с общего сокета
-/// - принимает только Ping
-/// - обновляет last_ping[src_addr] = Instant::now()
+/// token -> объявленный клиентом udp_target
+pub(crate) type SessionTokenMap = Arc<RwLock<HashMap<SessionToken, SocketAddr>>>;
+
+/// Поток на общий сокет сервера и по потоку на сокет каждой сессии
+/// (клиент пингует адрес, с которого пришли котировки):
+/// - читает UDP пакеты (recv_from) с сокета
+/// - принимает только Ping / PingToken
+/// - Ping: обновляет last_ping[src_addr] = Instant::now()
+/// - PingToken: обновляет last_ping[udp_target сессии], независимо от src_addr
+/// - PingAck: как Ping (с токеном - как PingToken) + `ack_seq` в `loss` сессии
+/// - при `enable_pong` отвечает на ping пакетом Pong на адрес источника
 pub(crate) fn run_udp_ping_listener(
     udp: Arc<UdpSocket>,
     last_ping: LastPingMap,
-    shutdown: Arc<std::sync::atomic::AtomicBool>,
+    tokens: SessionTokenMap,
+    enable_pong: bool,
+    loss: Option<Arc<LossMonitor>>,
+    metrics: Arc<ServerMetrics>,
+    shutdown: Arc<ShutdownFlag>,
 ) -> anyhow::Result<()> {
     udp.set_read_timeout(Some(Duration::from_millis(200)))?;
 
     let mut buf = vec![0u8; 2048];
+    let pong = encode_v1(&UdpPacketV1::Pong)?;
+    let send_pong = |src: SocketAddr| {
+        if enable_pong && let Err(e) = udp.send_to(&pong, src) {
+            debug!("failed to send pong to {src}: {e}");
+        }
+    };
 
-    while !shutdown.load(std::sync::atomic::Ordering::Relaxed) {
+    while !shutdown.is_stopped() {
         match udp.recv_from(&mut buf) {
             Ok((n, src)) => {
+                let pkt = &buf[..n];
+                // всё, кроме ping, отсекаем по заголовку, не разбирая payload
+                if let Ok(kind) = peek_kind(pkt)
+                    && !matches!(
+                        kind,
+                        PacketKind::Ping | PacketKind::PingToken | PacketKind::PingAck
+                    )
+                {
+                    debug!("Ignoring {kind:?} packet from {src}");
+                    continue;
+                }
+
                 // decode проверяет версию + postcard payload
-                match decode(&buf[..n]) {
+                match decode(pkt) {
                     Ok(UdpPacketV1::Ping) => {
                         // обновляем last ping для src (IP:port клиента)
-                        let mut map = match last_ping.write() {
-                            Ok(g) => g,
-                            Err(poisoned) => {
-                                warn!("last_ping map lock poisoned; continuing");
-                                poisoned.into_inner()
+                        touch(&last_ping, src);
+                        debug!("Ping from {src}");
+                        send_pong(src);
+                    }
+                    Ok(UdpPacketV1::PingToken(token)) => {
+                        match token_target(&tokens, token) {
+                            Some(target) => {
+                                touch(&last_ping, target);
+                                debug!("Ping from {src} for session target {target}");
                             }
+                            None => debug!("Ping with unknown token from {src}"),
+                        }
+                        send_pong(src);
+                    }
+                    Ok(UdpPacketV1::PingAck { token, ack_seq }) => {
+                        let target = match token {
+                            Some(token) => token_target(&tokens, token),
+                            None => Some(src),
                         };
-                        map.insert(src, Instant::now());
-                        debug!("Ping from {src}");
+                        match target {
+                            Some(target) => {
+                                touch(&last_ping, target);
+                                debug!("Ping from {src} for {target}, ack_seq={ack_seq}");
+                                if let Some(loss) = &loss {
+                                    loss.on_ack(ack_seq);
+                                }
+                            }
+                            None => debug!("Ping with unknown token from {src}"),
+                        }
+                        send_pong(src);
                     }
                     Ok(UdpPacketV1::Quote(_)) => {
                         // по протоколу клиент не должен слать Quote на сервер
                     }
+                    Ok(other) => {
+                        // вариант из более новой версии протокола - игнорируем
+                        debug!("Ignoring unsupported UDP packet from {src}: {other}");
+                    }
+                    Err(WireError::UnsupportedWireVersion(ver)) => {
+                        // клиент другой версии: громко один раз, дальше - только метрика
+                        if metrics.record_wire_version_mismatch() == 1 {
+                            warn!(
+                                "WIRE VERSION MISMATCH: {src} sent v{ver}, server speaks v{WIRE_VERSION}; \
+                                 mixed client/server versions in the fleet? \
+                                 (further mismatches are counted in quote_server_wire_version_mismatch_total)"
+                            );
+                        } else {
+                            debug!("wire version mismatch from {src}: v{ver}");
+                        }
+                    }
                     Err(e) => {
                         // не валим сервер из-за мусора в UDP
                         warn!("Bad UDP packet from {src}: {e}");
@@ -61,3 +133,254 @@ pub(crate) fn run_udp_ping_listener(
 
     Ok(())
 }
+
+/// udp_target сессии по токену `PingToken`
+fn token_target(tokens: &SessionTokenMap, token: SessionToken) -> Option<SocketAddr> {
+    let map = match tokens.read() {
+        Ok(g) => g,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    map.get(&token).copied()
+}
+
+fn touch(last_ping: &LastPingMap, addr: SocketAddr) {
+    let mut map = match last_ping.write() {
+        Ok(g) => g,
+        Err(poisoned) => {
+            warn!("last_ping map lock poisoned; continuing");
+            poisoned.into_inner()
+        }
+    };
+    map.insert(addr, Instant::now());
+}
+
+/// Возраст последнего ping на момент `now` по каждому адресу (по возрастанию адреса)
+pub(crate) fn ping_ages(last_ping: &LastPingMap, now: Instant) -> Vec<(SocketAddr, Duration)> {
+    let map = match last_ping.read() {
+        Ok(g) => g,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut ages: Vec<(SocketAddr, Duration)> = map
+        .iter()
+        .map(|(addr, t)| (*addr, now.saturating_duration_since(*t)))
+        .collect();
+    ages.sort_unstable_by_key(|(addr, _)| *addr);
+    ages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn ping_ack_refreshes_source_and_records_ack_seq() {
+        let udp = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+        let server_addr = udp.local_addr().unwrap();
+        let last_ping: LastPingMap = Arc::new(RwLock::new(HashMap::new()));
+        let loss = Arc::new(LossMonitor::new(1));
+        for _ in 0..4 {
+            loss.on_quote_sent();
+        }
+
+        let shutdown = Arc::new(ShutdownFlag::default());
+        let h = {
+            let (udp, last_ping, loss, shutdown) = (
+                udp.clone(),
+                last_ping.clone(),
+                loss.clone(),
+                shutdown.clone(),
+            );
+            thread::spawn(move || {
+                run_udp_ping_listener(
+                    udp,
+                    last_ping,
+                    Default::default(),
+                    false,
+                    Some(loss),
+                    Default::default(),
+                    shutdown,
+                )
+            })
+        };
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let src = client.local_addr().unwrap();
+        let bytes = encode_v1(&UdpPacketV1::PingAck {
+            token: None,
+            ack_seq: 3,
+        })
+        .unwrap();
+        client.send_to(&bytes, server_addr).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !last_ping.read().unwrap().contains_key(&src) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        shutdown.stop();
+        h.join().unwrap().unwrap();
+
+        assert!(last_ping.read().unwrap().contains_key(&src));
+        assert_eq!(loss.estimate().acked, 3);
+        assert_eq!(loss.estimate().loss_ratio(), Some(0.25));
+    }
+
+    #[test]
+    fn ping_token_from_other_source_keeps_session_target_alive() {
+        let udp = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+        let server_addr = udp.local_addr().unwrap();
+
+        // объявленный клиентом адрес; пинги будут приходить с другого
+        let advertised: SocketAddr = "127.0.0.1:40001".parse().unwrap();
+        let other: SocketAddr = "127.0.0.1:40002".parse().unwrap();
+
+        let last_ping: LastPingMap = Arc::new(RwLock::new(HashMap::new()));
+        let tokens: SessionTokenMap = Arc::new(RwLock::new(HashMap::new()));
+        tokens.write().unwrap().insert(7, advertised);
+        tokens.write().unwrap().insert(8, other);
+
+        let shutdown = Arc::new(ShutdownFlag::default());
+        let h = {
+            let (udp, last_ping, tokens, shutdown) = (
+                udp.clone(),
+                last_ping.clone(),
+                tokens.clone(),
+                shutdown.clone(),
+            );
+            thread::spawn(move || {
+                run_udp_ping_listener(
+                    udp,
+                    last_ping,
+                    tokens,
+                    false,
+                    None,
+                    Default::default(),
+                    shutdown,
+                )
+            })
+        };
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let src = client.local_addr().unwrap();
+        let bytes = encode_v1(&UdpPacketV1::PingToken(7)).unwrap();
+        client.send_to(&bytes, server_addr).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !last_ping.read().unwrap().contains_key(&advertised) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        shutdown.stop();
+        h.join().unwrap().unwrap();
+
+        let map = last_ping.read().unwrap();
+        assert!(
+            map.contains_key(&advertised),
+            "session target must be refreshed"
+        );
+        assert!(
+            !map.contains_key(&other),
+            "other session must not be touched"
+        );
+        assert!(!map.contains_key(&src), "token ping must not key on source");
+    }
+
+    #[test]
+    fn wrong_wire_version_is_counted_and_listener_keeps_running() {
+        let udp = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+        let server_addr = udp.local_addr().unwrap();
+
+        let last_ping: LastPingMap = Arc::new(RwLock::new(HashMap::new()));
+        let metrics = Arc::new(ServerMetrics::default());
+        let shutdown = Arc::new(ShutdownFlag::default());
+        let h = {
+            let (udp, last_ping, metrics, shutdown) = (
+                udp.clone(),
+                last_ping.clone(),
+                metrics.clone(),
+                shutdown.clone(),
+            );
+            thread::spawn(move || {
+                run_udp_ping_listener(
+                    udp,
+                    last_ping,
+                    Default::default(),
+                    false,
+                    None,
+                    metrics,
+                    shutdown,
+                )
+            })
+        };
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let src = client.local_addr().unwrap();
+
+        let mut future = encode_v1(&UdpPacketV1::Ping).unwrap();
+        future[0] = 42; // неизвестная версия (не v1 и не fixed)
+        client.send_to(&future, server_addr).unwrap();
+        client.send_to(&future, server_addr).unwrap();
+        // следом нормальный ping - слушатель должен быть жив
+        client
+            .send_to(&encode_v1(&UdpPacketV1::Ping).unwrap(), server_addr)
+            .unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !last_ping.read().unwrap().contains_key(&src) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        shutdown.stop();
+        h.join().unwrap().unwrap();
+
+        assert!(last_ping.read().unwrap().contains_key(&src));
+        assert!(
+            metrics
+                .render_prometheus()
+                .contains("quote_server_wire_version_mismatch_total 2\n")
+        );
+    }
+
+    #[test]
+    fn ping_yields_pong_to_source_when_enabled() {
+        let udp = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+        let server_addr = udp.local_addr().unwrap();
+
+        let last_ping: LastPingMap = Arc::new(RwLock::new(HashMap::new()));
+        let tokens: SessionTokenMap = Arc::new(RwLock::new(HashMap::new()));
+        let shutdown = Arc::new(ShutdownFlag::default());
+        let h = {
+            let (udp, shutdown) = (udp.clone(), shutdown.clone());
+            thread::spawn(move || {
+                run_udp_ping_listener(
+                    udp,
+                    last_ping,
+                    tokens,
+                    true,
+                    None,
+                    Default::default(),
+                    shutdown,
+                )
+            })
+        };
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        client
+            .send_to(&encode_v1(&UdpPacketV1::Ping).unwrap(), server_addr)
+            .unwrap();
+
+        let mut buf = [0u8; 64];
+        let res = client.recv_from(&mut buf);
+
+        shutdown.stop();
+        h.join().unwrap().unwrap();
+
+        let (n, from) = res.expect("pong must arrive");
+        assert_eq!(from, server_addr);
+        assert_eq!(decode(&buf[..n]).unwrap(), UdpPacketV1::Pong);
+    }
+}
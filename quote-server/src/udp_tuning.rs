@@ -0,0 +1,139 @@
+use std::io;
+use std::net::UdpSocket;
+
+/// Сетевые параметры UDP-сокетов, с которых уходят котировки
+/// (`--udp-ttl`, `--udp-tos`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct UdpTuning {
+    /// IP TTL (для IPv6 - hop limit)
+    pub(crate) ttl: Option<u32>,
+    /// Байт ToS / Traffic Class: DSCP в старших 6 битах (EF = 46 -> 184)
+    pub(crate) tos: Option<u8>,
+}
+
+impl UdpTuning {
+    /// Применяет заданные параметры; ошибка - ОС или платформа их не поддерживает
+    pub(crate) fn apply(&self, sock: &UdpSocket) -> io::Result<()> {
+        let ipv6 = sock.local_addr()?.is_ipv6();
+        if let Some(ttl) = self.ttl {
+            if ipv6 {
+                set_ipv6_hops(sock, ttl)?;
+            } else {
+                sock.set_ttl(ttl)?;
+            }
+        }
+        if let Some(tos) = self.tos {
+            set_tos(sock, tos, ipv6)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn setsockopt_int(
+    sock: &UdpSocket,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: u32,
+) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let value = value as libc::c_int;
+    // SAFETY: дескриптор жив, пока жив `sock`; значение - c_int нужного размера
+    let rc = unsafe {
+        libc::setsockopt(
+            sock.as_raw_fd(),
+            level,
+            name,
+            (&value as *const libc::c_int).cast(),
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if rc == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(unix)]
+fn set_ipv6_hops(sock: &UdpSocket, hops: u32) -> io::Result<()> {
+    setsockopt_int(sock, libc::IPPROTO_IPV6, libc::IPV6_UNICAST_HOPS, hops)
+}
+
+#[cfg(unix)]
+fn set_tos(sock: &UdpSocket, tos: u8, ipv6: bool) -> io::Result<()> {
+    if ipv6 {
+        setsockopt_int(sock, libc::IPPROTO_IPV6, libc::IPV6_TCLASS, tos.into())
+    } else {
+        setsockopt_int(sock, libc::IPPROTO_IP, libc::IP_TOS, tos.into())
+    }
+}
+
+#[cfg(not(unix))]
+fn set_ipv6_hops(_sock: &UdpSocket, _hops: u32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "IPv6 hop limit is not supported on this platform",
+    ))
+}
+
+#[cfg(not(unix))]
+fn set_tos(_sock: &UdpSocket, _tos: u8, _ipv6: bool) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "ToS is not supported on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_tuning_changes_nothing() {
+        let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let before = sock.ttl().unwrap();
+        UdpTuning::default().apply(&sock).unwrap();
+        assert_eq!(sock.ttl().unwrap(), before);
+    }
+
+    #[test]
+    fn ttl_is_applied_to_ipv4_socket() {
+        let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let tuning = UdpTuning {
+            ttl: Some(7),
+            tos: None,
+        };
+        tuning.apply(&sock).unwrap();
+        assert_eq!(sock.ttl().unwrap(), 7);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn tos_is_applied_to_ipv4_socket() {
+        use std::os::fd::AsRawFd;
+
+        let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let tuning = UdpTuning {
+            ttl: None,
+            tos: Some(184),
+        };
+        tuning.apply(&sock).unwrap();
+
+        let mut value: libc::c_int = 0;
+        let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+        // SAFETY: буфер - c_int, len - его размер
+        let rc = unsafe {
+            libc::getsockopt(
+                sock.as_raw_fd(),
+                libc::IPPROTO_IP,
+                libc::IP_TOS,
+                (&mut value as *mut libc::c_int).cast(),
+                &mut len,
+            )
+        };
+        assert_eq!(rc, 0);
+        assert_eq!(value, 184);
+    }
+}